@@ -11,5 +11,14 @@ struct RoomLayout: Codable {
     let roomType: RoomType
     let edges: [Line]
     let wallPolygons: [Polygon]
+    /// Size of the layout estimation output, which all the coordinates are relative to.
+    /// Missing in layouts saved before it was stored, which are all 512x512.
+    let layoutWidth: Int?
+    let layoutHeight: Int?
+    /// How much the wall polygons can be trusted, from 0 to 1.
+    /// Missing in layouts saved before layouts were validated.
+    let confidence: Float?
+    /// Bit flags of problems found in wall polygons, see `LAYOUT_ISSUE_*` constants
+    let issues: Int?
 }
 
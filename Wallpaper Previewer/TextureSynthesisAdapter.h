@@ -3,6 +3,34 @@
 #include <stdint.h>
 #include <stdlib.h>
 
+#define LAYOUT_ISSUE_NO_WALLS (1 << 0)
+
+#define LAYOUT_ISSUE_SELF_INTERSECTING (1 << 1)
+
+#define LAYOUT_ISSUE_INVERTED (1 << 2)
+
+#define LAYOUT_ISSUE_NOT_CONVEX (1 << 3)
+
+#define LAYOUT_ISSUE_TOO_SMALL (1 << 4)
+
+#define LAYOUT_ISSUE_OFF_SCREEN (1 << 5)
+
+#define LAYOUT_ISSUE_DISCONNECTED_WALLS (1 << 6)
+
+#define LAYOUT_ISSUE_IMPLAUSIBLE_VERTICAL_EDGES (1 << 7)
+
+#define LAYOUT_ISSUE_WEAK_CORNER_EVIDENCE (1 << 8)
+
+/**
+ * Maximum number of room layout hypotheses returned at once
+ */
+#define MAX_ROOM_LAYOUT_HYPOTHESES 3
+
+/**
+ * Smooths room layouts estimated independently for each frame of the camera view or video.
+ */
+typedef struct LayoutTracker LayoutTracker;
+
 typedef struct ImageInfo {
   const uint8_t *data;
   uintptr_t count;
@@ -48,8 +76,136 @@ typedef struct RoomLayoutData {
    * Indicates how many actual wall polygons are stored in [polygons] (at most 3)
    */
   uint8_t num_wall_polygons;
+  /**
+   * Width of the layout estimation output, which all the coordinates are relative to
+   */
+  uint32_t layout_width;
+  /**
+   * Height of the layout estimation output, which all the coordinates are relative to
+   */
+  uint32_t layout_height;
+  /**
+   * How much the wall polygons can be trusted, from 0 (garbage) to 1
+   */
+  float confidence;
+  /**
+   * Bit flags of problems found in wall polygons, see `LAYOUT_ISSUE_*` constants
+   */
+  uint32_t issues;
 } RoomLayoutData;
 
+/**
+ * Most probable room layouts, best first.
+ */
+typedef struct RoomLayoutHypotheses {
+  struct RoomLayoutData layouts[MAX_ROOM_LAYOUT_HYPOTHESES];
+  /**
+   * Probabilities of room types of [layouts]
+   */
+  float probabilities[MAX_ROOM_LAYOUT_HYPOTHESES];
+  /**
+   * Indicates how many actual layouts are stored in [layouts]
+   */
+  uint8_t num_layouts;
+} RoomLayoutHypotheses;
+
+/**
+ * Door, window or other opening in the wall, given either by its corners on the room image or
+ * by its rectangle on the wall plane.
+ */
+typedef struct WallOpeningData {
+  /**
+   * Index of the wall polygon the opening is in
+   */
+  uint8_t wall_index;
+  /**
+   * Whether the opening is given by [wall_plane_rect] rather than [image_quad]
+   */
+  bool is_in_wall_plane;
+  /**
+   * Corners of the opening in layout space
+   */
+  struct LayoutWallPolygon image_quad;
+  /**
+   * Left, top, width and height of the opening in meters from the top left wall corner
+   */
+  float wall_plane_rect[4];
+} WallOpeningData;
+
+/**
+ * Wallpaper area of the walls, in square meters.
+ */
+typedef struct WallpaperAreaData {
+  float walls;
+  float openings;
+  /**
+   * Walls less openings
+   */
+  float net;
+} WallpaperAreaData;
+
+/**
+ * Room corner of equirectangular panorama in HorizonNet format, in panorama pixels.
+ */
+typedef struct PanoramaCornerData {
+  /**
+   * Column of the corner
+   */
+  float x;
+  /**
+   * Row where the wall meets the ceiling
+   */
+  float ceiling_y;
+  /**
+   * Row where the wall meets the floor
+   */
+  float floor_y;
+} PanoramaCornerData;
+
+/**
+ * Depth map of the room photo in meters along the optical axis, row by row, 0 where unknown.
+ */
+typedef struct DepthMapInfo {
+  const float *data;
+  uintptr_t width;
+  uintptr_t height;
+} DepthMapInfo;
+
+/**
+ * Room layout estimated from the depth map, with true wall sizes.
+ */
+typedef struct DepthRoomLayoutData {
+  struct RoomLayoutData layout;
+  /**
+   * Width of each wall polygon in meters
+   */
+  float wall_widths[3];
+  /**
+   * Height of each wall polygon in meters
+   */
+  float wall_heights[3];
+} DepthRoomLayoutData;
+
+/**
+ * Room layout smoothed over the frames of the camera view or video.
+ */
+typedef struct TrackedRoomLayoutData {
+  struct RoomLayoutData layout;
+  /**
+   * Id of each wall polygon, which stays the same across frames
+   */
+  uint32_t wall_ids[3];
+} TrackedRoomLayoutData;
+
+/**
+ * Radial lens profile, see [LensProfile]. `k2` is ignored by the division model.
+ */
+typedef struct LensProfileData {
+  bool is_division_model;
+  float k1;
+  float k2;
+} LensProfileData;
+
 typedef struct SegmentationMap {
   const float *data;
   uintptr_t height;
@@ -83,6 +239,140 @@ struct ImageInfo generate_preview(const struct ImageInfo *room_image,
                                   const struct ImageInfo *wallpaper_tile_image,
                                   struct RoomLayoutData room_layout);
 
+/**
+ * Same as [generate_preview], but keeps memory used by preview compositing within
+ * `memory_budget` bytes. Preview image is downscaled if it does not fit into the budget.
+ */
+struct ImageInfo generate_preview_with_memory_budget(const struct ImageInfo *room_image,
+                                                     const struct ImageInfo *wall_mask_image,
+                                                     const struct ImageInfo *wallpaper_tile_image,
+                                                     struct RoomLayoutData room_layout,
+                                                     uintptr_t memory_budget);
+
+/**
+ * Same as [generate_preview], but also renders relief of embossed, vinyl or grasscloth
+ * wallpapers. `relief_image` is either tangent space normal map or height map (when
+ * `relief_is_height_map` is `true`), which tiles the same way as the wallpaper tile.
+ */
+struct ImageInfo generate_preview_with_relief(const struct ImageInfo *room_image,
+                                              const struct ImageInfo *wall_mask_image,
+                                              const struct ImageInfo *wallpaper_tile_image,
+                                              const struct ImageInfo *relief_image,
+                                              bool relief_is_height_map,
+                                              float relief_strength,
+                                              struct RoomLayoutData room_layout);
+
+/**
+ * Same as [generate_preview], but removes wall-mounted objects before applying wallpaper,
+ * so that they get wallpapered too.
+ *
+ * If `removal_mask_image` is `null`, non-wall regions fully enclosed by wall are removed.
+ * Otherwise, white pixels of the mask mark regions to remove.
+ *
+ * # Safety `removal_mask_image` must be either `null` or a valid pointer
+ */
+struct ImageInfo generate_preview_with_object_removal(const struct ImageInfo *room_image,
+                                                      const struct ImageInfo *wall_mask_image,
+                                                      const struct ImageInfo *wallpaper_tile_image,
+                                                      struct RoomLayoutData room_layout,
+                                                      const struct ImageInfo *removal_mask_image);
+
+/**
+ * Exports wall UV, wall id, coverage and shading maps along with JSON descriptor into
+ * `output_dir`. Returns `true` on success.
+ *
+ * # Safety `output_dir` must be a valid null-terminated UTF-8 string
+ */
+bool export_wall_maps(const struct ImageInfo *room_image,
+                      const struct ImageInfo *wall_mask_image,
+                      struct RoomLayoutData room_layout,
+                      const char *output_dir);
+
+/**
+ * Exports reconstructed walls, floor and ceiling as OBJ mesh with MTL materials into
+ * `output_dir`. Surfaces are textured with the wallpaper tile at its physical size, assuming
+ * camera is `camera_height_meters` above the floor. Visible floor and ceiling are textured with
+ * the room photo if `project_photo` is `true`. Returns `true` on success.
+ *
+ * # Safety `room_image` and `wallpaper_tile_image` must not be `null`, `output_dir` must be a
+ * valid null-terminated UTF-8 string
+ */
+bool export_room_mesh(const struct ImageInfo *room_image,
+                      const struct ImageInfo *wallpaper_tile_image,
+                      struct RoomLayoutData room_layout,
+                      float camera_height_meters,
+                      bool project_photo,
+                      const char *output_dir);
+
+/**
+ * Creates equirectangular preview of the 360° room panorama, with wallpaper continuing around
+ * all the walls given by `corners`. `wall_mask_image` limits wallpaper to wall pixels, unless it
+ * is `null`. Panorama is returned unchanged if the corners do not describe a room.
+ *
+ * # Safety `room_image` and `wallpaper_tile_image` must not be `null`, `wall_mask_image` must be
+ * either `null` or a valid pointer, `corners` must point to `num_corners` corners
+ */
+struct ImageInfo generate_panorama_preview(const struct ImageInfo *room_image,
+                                           const struct ImageInfo *wall_mask_image,
+                                           const struct ImageInfo *wallpaper_tile_image,
+                                           const struct PanoramaCornerData *corners,
+                                           uintptr_t num_corners);
+
+/**
+ * Estimates lens distortion of the room image from the curvature of edges along the layout
+ * lines. Returns profile without distortion if the edges are straight.
+ *
+ * # Safety `room_image` must not be `null`
+ */
+struct LensProfileData estimate_room_lens_profile(const struct ImageInfo *room_image,
+                                                  struct RoomLayoutData room_layout);
+
+/**
+ * Same as [generate_preview], but wallpaper is applied to the room image undistorted with
+ * `lens_profile`, and then distorted back onto the original room image.
+ *
+ * # Safety `room_image`, `wall_mask_image` and `wallpaper_tile_image` must not be `null`
+ */
+struct ImageInfo generate_preview_with_lens_profile(const struct ImageInfo *room_image,
+                                                    const struct ImageInfo *wall_mask_image,
+                                                    const struct ImageInfo *wallpaper_tile_image,
+                                                    struct RoomLayoutData room_layout,
+                                                    struct LensProfileData lens_profile);
+
+/**
+ * Draws the room layout over the room image for an in-app debug screen: walls with their
+ * indices, layout lines, labelled corners, room type and confidence. Room image is padded so that
+ * corners outside of it are visible.
+ *
+ * # Safety `room_image` must not be `null`
+ */
+struct ImageInfo generate_layout_debug_image(const struct ImageInfo *room_image,
+                                             struct RoomLayoutData room_layout);
+
+/**
+ * Creates tracker of room layouts estimated for the frames of the camera view or video. It must
+ * be released with [release_layout_tracker].
+ */
+struct LayoutTracker *create_layout_tracker(void);
+
+/**
+ * # Safety `tracker` must be created with [create_layout_tracker] and not used afterwards
+ */
+void release_layout_tracker(struct LayoutTracker *tracker);
+
+/**
+ * Adds room layout of the next frame to the tracker and returns the smoothed layout. Room type
+ * and lines describe the smoothed wall polygons. `homography` is either `null` or 3x3 row-major
+ * matrix mapping layout space of the previous frame onto layout space of this one. Layout is
+ * returned untracked if `tracker` is `null`.
+ *
+ * # Safety `tracker` must be either `null` or created with [create_layout_tracker], `homography`
+ * must be either `null` or point to 9 values
+ */
+struct TrackedRoomLayoutData track_room_layout(struct LayoutTracker *tracker,
+                                               struct RoomLayoutData room_layout,
+                                               const float *homography);
+
 const uint8_t *synthesize_texture(const struct ImageInfo *sample_info, uint32_t input_resize);
 
 const uint8_t *rust_process_data(const struct ImageInfo *image_info);
@@ -92,6 +382,101 @@ void process_segmentation_map(const struct SegmentationMap *segmentation_map);
 int shipping_rust_addition(int a, int b);
 
 /**
+ * Parses room layout estimation results. Returns a layout without walls, with zero confidence
+ * and [LAYOUT_ISSUE_NO_WALLS] issue, if results can not be parsed.
+ *
  * # Safety `results` must not be `null`
  */
 struct RoomLayoutData process_room_layout_estimation_results(const struct RoomLayoutEstimationResults *results);
+
+/**
+ * Same as [process_room_layout_estimation_results], but parses results as each of the most
+ * probable room types and returns at most `k` (up to [MAX_ROOM_LAYOUT_HYPOTHESES]) layouts,
+ * ranked by room type probability and geometric validity.
+ *
+ * # Safety `results` must not be `null`
+ */
+struct RoomLayoutHypotheses process_room_layout_estimation_results_top_k(const struct RoomLayoutEstimationResults *results,
+                                                                         uint8_t k);
+
+/**
+ * Estimates room layout from the wall segmentation mask and the room photo, when layout
+ * estimation results can not be parsed. Layout size is the mask size.
+ *
+ * # Safety `room_image` and `wall_mask_image` must not be `null`
+ */
+struct RoomLayoutData estimate_room_layout_from_mask(const struct ImageInfo *room_image,
+                                                     const struct ImageInfo *wall_mask_image);
+
+/**
+ * Estimates room layout by fitting wall planes to the depth map within the wall mask, instead of
+ * parsing layout estimation results. Depth map must have the same field of view as the room
+ * photo, and layout size is the depth map size.
+ *
+ * # Safety `wall_mask_image` must not be `null`, `depth_map` data must hold `width * height`
+ * values
+ */
+struct DepthRoomLayoutData estimate_room_layout_from_depth(const struct ImageInfo *wall_mask_image,
+                                                           struct DepthMapInfo depth_map,
+                                                           float horizontal_fov_degrees);
+
+/**
+ * Snaps layout lines to edges of the full resolution room image and recomputes the wall
+ * polygons. Returned layout size is the room image size. Layout is returned unchanged if its
+ * room type is unknown or it has fewer lines than the room type needs.
+ *
+ * # Safety `room_image` must not be `null`
+ */
+struct RoomLayoutData refine_room_layout(const struct ImageInfo *room_image,
+                                         struct RoomLayoutData room_layout);
+
+/**
+ * Snaps opening drawn on the room image to the perspective of its wall. Both [image_quad] and
+ * [wall_plane_rect] of the returned opening describe the snapped opening, which is clipped to
+ * the wall. Wall sizes are taken from the layout estimated by [estimate_room_layout_from_depth].
+ * Opening is returned unchanged if it is outside of its wall.
+ */
+struct WallOpeningData snap_wall_opening(struct DepthRoomLayoutData room_layout,
+                                         struct WallOpeningData opening);
+
+/**
+ * Removes wall openings from the wall mask, so that previews generated with the returned mask
+ * never cover them with wallpaper. Returned image is grayscale, same as the given mask. Wall
+ * sizes are taken from the layout estimated by [estimate_room_layout_from_depth].
+ *
+ * # Safety `wall_mask_image` must not be `null`, `openings` must point to `num_openings`
+ * openings
+ */
+struct ImageInfo cut_wall_openings(const struct ImageInfo *wall_mask_image,
+                                   struct DepthRoomLayoutData room_layout,
+                                   const struct WallOpeningData *openings,
+                                   uintptr_t num_openings);
+
+/**
+ * Estimates how much wallpaper the walls need, less their openings, from the wall sizes
+ * measured by [estimate_room_layout_from_depth]. Area is zero if the layout has no walls.
+ *
+ * # Safety `openings` must point to `num_openings` openings
+ */
+struct WallpaperAreaData compute_wallpaper_area(struct DepthRoomLayoutData room_layout,
+                                                const struct WallOpeningData *openings,
+                                                uintptr_t num_openings);
+
+/**
+ * Moves wall polygon corner (0 - top left, 1 - top right, 2 - bottom right, 3 - bottom left)
+ * and recomputes all the wall polygons, so that corners shared by neighbouring walls stay
+ * shared. Layout is returned unchanged if the corner can not be moved.
+ */
+struct RoomLayoutData move_layout_corner(struct RoomLayoutData room_layout,
+                                         uint8_t wall_index,
+                                         uint8_t corner_index,
+                                         struct LayoutPoint position);
+
+/**
+ * Moves start (or end, if `is_line_end` is `true`) of the layout line together with ends of
+ * other lines meeting at it, and recomputes all the wall polygons.
+ */
+struct RoomLayoutData move_layout_line_endpoint(struct RoomLayoutData room_layout,
+                                                uint8_t line_index,
+                                                bool is_line_end,
+                                                struct LayoutPoint position);
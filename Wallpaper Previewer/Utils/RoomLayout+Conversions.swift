@@ -50,7 +50,34 @@ extension RoomLayoutData {
             wallPolygons.append(self.wall_polygons.2.model)
         }
         
-        return RoomLayout(roomType: roomType, edges: edges, wallPolygons: wallPolygons)
+        return RoomLayout(
+            roomType: roomType,
+            edges: edges,
+            wallPolygons: wallPolygons,
+            layoutWidth: Int(self.layout_width),
+            layoutHeight: Int(self.layout_height),
+            confidence: self.confidence,
+            issues: Int(self.issues)
+        )
+    }
+}
+
+extension RoomLayoutHypotheses {
+    
+    /// Room layouts with probabilities of their room types, best first
+    var models: [(layout: RoomLayout, probability: Float)] {
+        var models = [(layout: RoomLayout, probability: Float)]()
+        // Swift doesn't support tuple indexing with variable, so there is need for such manual work
+        if self.num_layouts > 0 {
+            models.append((self.layouts.0.model, self.probabilities.0))
+        }
+        if self.num_layouts > 1 {
+            models.append((self.layouts.1.model, self.probabilities.1))
+        }
+        if self.num_layouts > 2 {
+            models.append((self.layouts.2.model, self.probabilities.2))
+        }
+        return models
     }
 }
 
@@ -159,7 +186,11 @@ extension RoomLayout {
             num_lines: UInt8(num_lines),
             room_type: UInt8(self.roomType.rawValue),
             wall_polygons: polygons,
-            num_wall_polygons: UInt8(self.wallPolygons.count)
+            num_wall_polygons: UInt8(self.wallPolygons.count),
+            layout_width: UInt32(self.layoutWidth ?? 512),
+            layout_height: UInt32(self.layoutHeight ?? 512),
+            confidence: self.confidence ?? 1,
+            issues: UInt32(self.issues ?? 0)
         )
     }
 }
@@ -139,7 +139,7 @@ extension PreviewGenerationScreen {
             previewGenerationStatus = .layout
             
             let roomLayout: RoomLayout
-            switch await prepareRoomLayout(roomPhotoFile: roomPhotoFile) {
+            switch await prepareRoomLayout(roomPhotoFile: roomPhotoFile, roomWallMask: segmentationImage) {
             case .success(let layout):
                 roomLayout = layout
                 print("Successfully prepared room layout: \(roomLayout)")
@@ -289,7 +289,10 @@ extension PreviewGenerationScreen {
             return .success(wallpaperTile)
         }
         
-        private func prepareRoomLayout(roomPhotoFile: MediaFile) async -> Result<RoomLayout, PreviewError> {
+        private func prepareRoomLayout(
+            roomPhotoFile: MediaFile,
+            roomWallMask: UIImage
+        ) async -> Result<RoomLayout, PreviewError> {
             // Check if we have cached room layout for this photo to avoid unnecessary computation
             let cachedRoomLayout = fileHelper.loadRoomLayout(id: roomPhotoFile.id)
             if case .success(let roomLayout) = cachedRoomLayout, roomLayout != nil {
@@ -387,9 +390,26 @@ extension PreviewGenerationScreen {
                 type_: typeArrayInfo
             )
             
-            let roomLayout = process_room_layout_estimation_results(&roomLayoutEstimationResults).model
+            var roomLayout = process_room_layout_estimation_results(&roomLayoutEstimationResults).model
             
             print("Processed room layout: \(roomLayout)")
+            let confidence = roomLayout.confidence ?? 0
+            if confidence < 0.5 {
+                print("Room layout is unreliable (confidence: \(confidence), issues: \(roomLayout.issues ?? 0)), will estimate it from the wall mask")
+                let maskRoomLayout = roomPhotoImage.withUnsafeRgbaImageInfoPointer { roomImageInfoPtr in
+                    roomWallMask.withUnsafeGrayImageInfoPointer { roomWallMaskImageInfoPtr in
+                        estimate_room_layout_from_mask(roomImageInfoPtr, roomWallMaskImageInfoPtr)
+                    }
+                }.model
+                print("Estimated room layout from the wall mask: \(maskRoomLayout)")
+                if (maskRoomLayout.confidence ?? 0) > confidence {
+                    roomLayout = maskRoomLayout
+                }
+            }
+            
+            if roomLayout.wallPolygons.isEmpty {
+                return .failure(PreviewError(message: "Could not find any walls in the room photo"))
+            }
             
             switch fileHelper.saveRoomLayout(id: roomPhotoFile.id, roomLayout: roomLayout) {
             case .success(let roomLayoutFile):
@@ -0,0 +1,150 @@
+use clap::Parser;
+use ndarray::{Array1, Array2};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use texture_synthesis_adapter::evaluation::{evaluate_layout, EvaluationReport, LayoutEvaluation};
+use texture_synthesis_adapter::hypotheses::room_type_probabilities;
+use texture_synthesis_adapter::layout::{RoomLayout, RoomType};
+
+/// Evaluates room layouts computed from layout estimation results against the ground truth,
+/// with LSUN pixel and corner errors
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path to the layout estimation results directory with `indices.npy`, `lines_<index>.json`
+    /// and `type_<index>.npy` files
+    #[arg(long, value_name = "DIR")]
+    predictions: PathBuf,
+    /// Path to the ground truth directory with `layout_<index>.json` room layouts
+    #[arg(long, value_name = "DIR")]
+    ground_truth: PathBuf,
+    /// Path to the JSON report
+    #[arg(long, value_name = "FILE")]
+    output: PathBuf,
+    /// Size of the layout estimation output
+    #[arg(long, default_value_t = 512)]
+    layout_size: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinesData {
+    lines: Vec<((i32, i32), (i32, i32))>,
+}
+
+fn read_estimated_layout(
+    predictions_dir: &Path,
+    index: i32,
+    layout_size: u32,
+) -> Option<RoomLayout> {
+    let lines_file_path = predictions_dir.join(format!("lines_{index}.json"));
+    let lines_file = match File::open(&lines_file_path) {
+        Ok(lines_file) => lines_file,
+        Err(e) => {
+            println!("Could not open {lines_file_path:?}: {e}");
+            return None;
+        }
+    };
+    let lines_data: LinesData = match serde_json::from_reader(BufReader::new(lines_file)) {
+        Ok(lines_data) => lines_data,
+        Err(e) => {
+            println!("Could not parse {lines_file_path:?}: {e}");
+            return None;
+        }
+    };
+
+    let type_file_path = predictions_dir.join(format!("type_{index}.npy"));
+    let type_array: Array2<f32> = match ndarray_npy::read_npy(&type_file_path) {
+        Ok(type_array) => type_array,
+        Err(e) => {
+            println!("Could not read {type_file_path:?}: {e}");
+            return None;
+        }
+    };
+    let probabilities = room_type_probabilities(&type_array);
+    let room_type = (0..probabilities.len())
+        .max_by(|a, b| probabilities[*a].total_cmp(&probabilities[*b]))? as u8;
+    let room_type = match RoomType::try_from(room_type) {
+        Ok(room_type) => room_type,
+        Err(e) => {
+            println!("Skipping layout {index}: {e}");
+            return None;
+        }
+    };
+
+    let layout = RoomLayout::from_lines(room_type, lines_data.lines, layout_size, layout_size);
+    if layout.is_none() {
+        println!("Skipping layout {index}: too few lines for room type {room_type:?}");
+    }
+    layout
+}
+
+fn read_ground_truth_layout(ground_truth_dir: &Path, index: i32) -> Option<RoomLayout> {
+    let layout_file_path = ground_truth_dir.join(format!("layout_{index}.json"));
+    let layout_file = match File::open(&layout_file_path) {
+        Ok(layout_file) => layout_file,
+        Err(e) => {
+            println!("Could not open {layout_file_path:?}: {e}");
+            return None;
+        }
+    };
+    match serde_json::from_reader(BufReader::new(layout_file)) {
+        Ok(layout) => Some(layout),
+        Err(e) => {
+            println!("Could not parse {layout_file_path:?}: {e}");
+            None
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let indices: Array1<i32> = ndarray_npy::read_npy(cli.predictions.join("indices.npy"))
+        .expect("Could not read layout indices");
+
+    let mut evaluations: Vec<LayoutEvaluation> = vec![];
+    for index in indices.iter() {
+        let Some(estimated) = read_estimated_layout(&cli.predictions, *index, cli.layout_size)
+        else {
+            continue;
+        };
+        let Some(ground_truth) = read_ground_truth_layout(&cli.ground_truth, *index) else {
+            continue;
+        };
+        let evaluation = evaluate_layout(&estimated, &ground_truth);
+        println!(
+            "Layout {index}: room type {} (estimated {}), pixel error {:.4}, corner error {:.4}",
+            evaluation.room_type,
+            evaluation.estimated_room_type,
+            evaluation.pixel_error,
+            evaluation.corner_error,
+        );
+        evaluations.push(evaluation);
+    }
+
+    let report = EvaluationReport::new(&evaluations);
+    println!("Room type | Layouts | Pixel error | Corner error | Room type accuracy");
+    for (room_type, summary) in &report.by_room_type {
+        println!(
+            "{room_type:>9} | {:>7} | {:>11.4} | {:>12.4} | {:>18.4}",
+            summary.num_layouts,
+            summary.pixel_error,
+            summary.corner_error,
+            summary.room_type_accuracy,
+        );
+    }
+    println!(
+        "{:>9} | {:>7} | {:>11.4} | {:>12.4} | {:>18.4}",
+        "All",
+        report.overall.num_layouts,
+        report.overall.pixel_error,
+        report.overall.corner_error,
+        report.overall.room_type_accuracy,
+    );
+
+    let report_file = File::create(&cli.output).expect("Could not create report file");
+    serde_json::to_writer_pretty(BufWriter::new(report_file), &report)
+        .expect("Could not write report");
+}
@@ -0,0 +1,48 @@
+use clap::Parser;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use texture_synthesis_adapter::debug::{draw_layout, DrawLayoutOptions};
+use texture_synthesis_adapter::layout::RoomLayout;
+
+/// Draws room layout over the room image for debugging
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path to the room image
+    #[arg(long, value_name = "FILE")]
+    room_image: PathBuf,
+    /// Path to the room layout JSON, as stored by the app
+    #[arg(long, value_name = "FILE")]
+    layout: PathBuf,
+    /// Path to the output image
+    #[arg(long, value_name = "FILE")]
+    output: PathBuf,
+    /// Padding around the room image in pixels, just enough for all the corners by default
+    #[arg(long)]
+    padding: Option<u32>,
+    /// Opacity of wall fills, from 0 to 1
+    #[arg(long, default_value_t = 0.35)]
+    fill_opacity: f32,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let room_image = image::open(&cli.room_image)
+        .expect("Could not open room image")
+        .into_rgb8();
+    let layout_file = File::open(&cli.layout).expect("Could not open room layout");
+    let layout: RoomLayout =
+        serde_json::from_reader(BufReader::new(layout_file)).expect("Could not parse room layout");
+
+    let options = DrawLayoutOptions {
+        padding: cli.padding,
+        fill_opacity: cli.fill_opacity,
+        ..Default::default()
+    };
+    let overlay_image = draw_layout(&room_image, &layout, &options);
+    overlay_image
+        .save(&cli.output)
+        .expect("Could not save overlay image");
+}
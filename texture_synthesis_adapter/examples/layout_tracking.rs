@@ -0,0 +1,108 @@
+use clap::Parser;
+use imageproc::geometric_transformations::Projection;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use texture_synthesis_adapter::debug::{draw_layout, DrawLayoutOptions};
+use texture_synthesis_adapter::layout::RoomLayout;
+use texture_synthesis_adapter::tracking::{LayoutTracker, LayoutTrackerOptions};
+
+/// Smooths room layouts estimated independently for each video frame
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Path to the directory with `layout_<frame>.json` room layouts, frames numbered from 0
+    #[arg(long, value_name = "DIR")]
+    layouts: PathBuf,
+    /// Path to the JSON array with row-major 3x3 homography of each frame, which maps layout
+    /// space of the previous frame onto layout space of this one
+    #[arg(long, value_name = "FILE")]
+    homographies: Option<PathBuf>,
+    /// Path to the directory with `frame_<frame>.png` video frames, to draw smoothed layouts over
+    #[arg(long, value_name = "DIR")]
+    frames: Option<PathBuf>,
+    /// Path to the directory for smoothed `layout_<frame>.json` layouts and `overlay_<frame>.png`
+    /// images
+    #[arg(long, value_name = "DIR")]
+    output_dir: PathBuf,
+    /// How far corners move between frames, in layout pixels, beyond what homographies explain
+    #[arg(long, default_value_t = 2.0)]
+    process_noise: f32,
+    /// How far estimated corners are from the true ones, in layout pixels
+    #[arg(long, default_value_t = 6.0)]
+    measurement_noise: f32,
+    /// Number of frames a new room type has to be estimated in before it is trusted
+    #[arg(long, default_value_t = 3)]
+    room_type_switch_frames: u32,
+}
+
+fn read_layout(path: &Path) -> Option<RoomLayout> {
+    let layout_file = File::open(path).ok()?;
+    match serde_json::from_reader(BufReader::new(layout_file)) {
+        Ok(layout) => Some(layout),
+        Err(e) => {
+            println!("Could not parse {path:?}: {e}");
+            None
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let homographies: Vec<[f32; 9]> = match &cli.homographies {
+        Some(path) => {
+            let homographies_file = File::open(path).expect("Could not open homographies");
+            serde_json::from_reader(BufReader::new(homographies_file))
+                .expect("Could not parse homographies")
+        }
+        None => vec![],
+    };
+    std::fs::create_dir_all(&cli.output_dir).expect("Could not create output directory");
+
+    let mut tracker = LayoutTracker::new(LayoutTrackerOptions {
+        process_noise: cli.process_noise,
+        measurement_noise: cli.measurement_noise,
+        room_type_switch_frames: cli.room_type_switch_frames,
+        ..Default::default()
+    });
+    for frame in 0.. {
+        let Some(layout) = read_layout(&cli.layouts.join(format!("layout_{frame}.json"))) else {
+            println!("Tracked {frame} frames");
+            break;
+        };
+        let homography = homographies
+            .get(frame)
+            .and_then(|matrix| Projection::from_matrix(*matrix));
+
+        let tracked_layout = tracker.update(&layout, homography.as_ref());
+        let wall_ids: Vec<u32> = tracked_layout.walls.iter().map(|wall| wall.id).collect();
+        println!(
+            "Frame {frame}: room type {} (estimated {}), walls {wall_ids:?}",
+            u8::from(tracked_layout.room_type),
+            u8::from(layout.room_type),
+        );
+        let Some(smoothed_layout) = tracked_layout.room_layout() else {
+            continue;
+        };
+
+        let layout_file = File::create(cli.output_dir.join(format!("layout_{frame}.json")))
+            .expect("Could not create layout file");
+        serde_json::to_writer(BufWriter::new(layout_file), &smoothed_layout)
+            .expect("Could not write layout");
+
+        if let Some(frames_dir) = &cli.frames {
+            let frame_image = image::open(frames_dir.join(format!("frame_{frame}.png")))
+                .expect("Could not open video frame")
+                .into_rgb8();
+            let overlay_image = draw_layout(
+                &frame_image,
+                &smoothed_layout,
+                &DrawLayoutOptions::default(),
+            );
+            overlay_image
+                .save(cli.output_dir.join(format!("overlay_{frame}.png")))
+                .expect("Could not save overlay image");
+        }
+    }
+}
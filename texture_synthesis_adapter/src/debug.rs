@@ -0,0 +1,441 @@
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use imageproc::drawing::{
+    draw_filled_circle_mut, draw_filled_rect_mut, draw_line_segment_mut, draw_polygon_mut,
+};
+use imageproc::point::Point as PolygonPoint;
+use imageproc::rect::Rect;
+
+use crate::layout::lsun::{Line, Point};
+use crate::layout::RoomLayout;
+use crate::polygons::WallPolygon;
+
+/// Padding fits vertices at most this share of the room image size away from the image
+const MAX_PADDING_SHARE: f32 = 0.5;
+/// Labels are scaled up by one for each this many pixels of the room image size
+const LABEL_SCALE_IMAGE_SIZE: u32 = 256;
+const PADDING_COLOR: Rgb<u8> = Rgb([40, 40, 40]);
+const LINE_COLOR: Rgb<u8> = Rgb([255, 230, 0]);
+const TEXT_COLOR: Rgb<u8> = Rgb([255, 255, 255]);
+const TEXT_BACKGROUND_COLOR: Rgb<u8> = Rgb([0, 0, 0]);
+const WALL_COLORS: [Rgb<u8>; 5] = [
+    Rgb([230, 25, 75]),
+    Rgb([60, 180, 75]),
+    Rgb([0, 130, 200]),
+    Rgb([245, 130, 48]),
+    Rgb([145, 30, 180]),
+];
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// How [draw_layout] renders the layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawLayoutOptions {
+    /// Padding around the room image in pixels, or just enough for vertices outside of the image
+    /// if `None`
+    pub padding: Option<u32>,
+    /// Opacity of wall fills, from 0 to 1
+    pub fill_opacity: f32,
+    /// Whether to draw layout lines in addition to wall polygons
+    pub draw_lines: bool,
+    /// Whether to label wall polygon corners
+    pub label_corners: bool,
+}
+
+impl Default for DrawLayoutOptions {
+    fn default() -> Self {
+        Self {
+            padding: None,
+            fill_opacity: 0.35,
+            draw_lines: true,
+            label_corners: true,
+        }
+    }
+}
+
+/// Draws the layout over the room image for debugging: translucent walls with their indices,
+/// layout lines, labelled wall corners, room type and confidence. Layout is scaled to the room
+/// image, which is padded so that vertices outside of it are visible.
+pub fn draw_layout(
+    room_image: &RgbImage,
+    layout: &RoomLayout,
+    options: &DrawLayoutOptions,
+) -> RgbImage {
+    let (width, height) = room_image.dimensions();
+    if width == 0 || height == 0 {
+        println!("Can not draw layout over an empty room image");
+        let padding = options.padding.unwrap_or(0);
+        return RgbImage::from_pixel(width + 2 * padding, height + 2 * padding, PADDING_COLOR);
+    }
+    let scale_x = width as f32 / layout.layout_width as f32;
+    let scale_y = height as f32 / layout.layout_height as f32;
+    let to_image = |point: Point| (point.0 as f32 * scale_x, point.1 as f32 * scale_y);
+    let label_scale = (width.max(height) / LABEL_SCALE_IMAGE_SIZE).max(1);
+
+    let padding = options.padding.unwrap_or_else(|| {
+        let vertices = layout
+            .wall_polygons
+            .iter()
+            .flat_map(polygon_vertices)
+            .chain(layout.lines.iter().flat_map(|(start, end)| [*start, *end]));
+        let overshoot = vertices
+            .map(|point| {
+                let (x, y) = to_image(point);
+                [-x, -y, x - (width - 1) as f32, y - (height - 1) as f32]
+                    .into_iter()
+                    .fold(0.0, f32::max)
+            })
+            .fold(0.0, f32::max);
+        let max_padding = MAX_PADDING_SHARE * width.max(height) as f32;
+        // Room for corner labels
+        let margin = if overshoot > 0.0 {
+            (4 * GLYPH_HEIGHT * label_scale) as f32
+        } else {
+            0.0
+        };
+        (overshoot + margin).min(max_padding).ceil() as u32
+    });
+    let mut canvas = RgbImage::from_pixel(width + 2 * padding, height + 2 * padding, PADDING_COLOR);
+    image::imageops::overlay(&mut canvas, room_image, padding as i64, padding as i64);
+    let to_canvas = |point: Point| {
+        let (x, y) = to_image(point);
+        (x + padding as f32, y + padding as f32)
+    };
+
+    for (wall, polygon) in layout.wall_polygons.iter().enumerate() {
+        let vertices = polygon_vertices(polygon).map(to_canvas);
+        fill_polygon(
+            &mut canvas,
+            &vertices,
+            wall_color(wall),
+            options.fill_opacity,
+        );
+        for idx in 0..vertices.len() {
+            let next = (idx + 1) % vertices.len();
+            draw_thick_line(
+                &mut canvas,
+                vertices[idx],
+                vertices[next],
+                label_scale,
+                wall_color(wall),
+            );
+        }
+    }
+    if options.draw_lines {
+        for (start, end) in &layout.lines {
+            draw_thick_line(
+                &mut canvas,
+                to_canvas(*start),
+                to_canvas(*end),
+                label_scale,
+                LINE_COLOR,
+            );
+        }
+    }
+
+    for (wall, polygon) in layout.wall_polygons.iter().enumerate() {
+        let vertices = polygon_vertices(polygon).map(to_canvas);
+        let center = (
+            vertices.iter().map(|vertex| vertex.0).sum::<f32>() / vertices.len() as f32,
+            vertices.iter().map(|vertex| vertex.1).sum::<f32>() / vertices.len() as f32,
+        );
+        if options.label_corners {
+            for (vertex, name) in vertices.iter().zip(["TL", "TR", "BR", "BL"]) {
+                draw_filled_circle_mut(
+                    &mut canvas,
+                    (vertex.0.round() as i32, vertex.1.round() as i32),
+                    (2 * label_scale) as i32,
+                    wall_color(wall),
+                );
+                // Labels are inside the wall, so that labels of shared corners do not overlap
+                let text = format!("{wall}{name}");
+                let (text_width, text_height) = text_size(&text, label_scale);
+                let x = if center.0 >= vertex.0 {
+                    vertex.0 + (3 * label_scale) as f32
+                } else {
+                    vertex.0 - (3 * label_scale + text_width) as f32
+                };
+                let y = if center.1 >= vertex.1 {
+                    vertex.1 + (3 * label_scale) as f32
+                } else {
+                    vertex.1 - (3 * label_scale + text_height) as f32
+                };
+                draw_label(
+                    &mut canvas,
+                    &text,
+                    (x.round() as i32, y.round() as i32),
+                    label_scale,
+                );
+            }
+        }
+        let text = format!("W{wall}");
+        let (text_width, text_height) = text_size(&text, 2 * label_scale);
+        draw_label(
+            &mut canvas,
+            &text,
+            (
+                (center.0 - text_width as f32 / 2.0).round() as i32,
+                (center.1 - text_height as f32 / 2.0).round() as i32,
+            ),
+            2 * label_scale,
+        );
+    }
+
+    let mut header = format!("TYPE {}", u8::from(layout.room_type));
+    if let Some(confidence) = layout.confidence {
+        header.push_str(&format!(" CONF {confidence:.2}"));
+    }
+    let margin = (2 * label_scale) as i32;
+    draw_label(&mut canvas, &header, (margin, margin), 2 * label_scale);
+    canvas
+}
+
+/// Draws raw lines over the room image padded by `padding` pixels on every side.
+pub fn draw_lines_on_padded_image(src_image: &RgbImage, lines: &[Line], padding: u32) -> RgbImage {
+    let new_width = src_image.width() + 2 * padding;
+    let new_height = src_image.height() + 2 * padding;
+
+    let mut padded_image = RgbImage::from_pixel(new_width, new_height, Rgb([0, 0, 0]));
+
+    image::imageops::overlay(&mut padded_image, src_image, padding as i64, padding as i64);
+
+    let padding = padding as f32;
+    for line in lines {
+        let (p1, p2) = line;
+
+        let x1 = p1.0 as f32 + padding;
+        let y1 = p1.1 as f32 + padding;
+        let x2 = p2.0 as f32 + padding;
+        let y2 = p2.1 as f32 + padding;
+
+        draw_line_segment_mut(&mut padded_image, (x1, y1), (x2, y2), Rgb([255, 0, 0]));
+    }
+
+    padded_image
+}
+
+fn polygon_vertices(polygon: &WallPolygon) -> [Point; 4] {
+    [
+        polygon.top_left,
+        polygon.top_right,
+        polygon.bottom_right,
+        polygon.bottom_left,
+    ]
+}
+
+fn wall_color(wall: usize) -> Rgb<u8> {
+    WALL_COLORS[wall % WALL_COLORS.len()]
+}
+
+/// Blends the color over the polygon with the given opacity.
+fn fill_polygon(image: &mut RgbImage, vertices: &[(f32, f32)], color: Rgb<u8>, opacity: f32) {
+    let mut points: Vec<PolygonPoint<i32>> = vec![];
+    for vertex in vertices {
+        let point = PolygonPoint::new(vertex.0.round() as i32, vertex.1.round() as i32);
+        if points.last() != Some(&point) {
+            points.push(point);
+        }
+    }
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    // Degenerate polygons have no area to fill
+    if points.len() < 3 {
+        return;
+    }
+
+    let mut mask = GrayImage::new(image.width(), image.height());
+    draw_polygon_mut(&mut mask, &points, Luma([255]));
+    let opacity = opacity.clamp(0.0, 1.0);
+    for (pixel, mask_pixel) in image.pixels_mut().zip(mask.pixels()) {
+        if mask_pixel.0[0] == 0 {
+            continue;
+        }
+        for (channel, color_channel) in pixel.0.iter_mut().zip(color.0) {
+            *channel =
+                (*channel as f32 * (1.0 - opacity) + color_channel as f32 * opacity).round() as u8;
+        }
+    }
+}
+
+fn draw_thick_line(
+    image: &mut RgbImage,
+    start: (f32, f32),
+    end: (f32, f32),
+    thickness: u32,
+    color: Rgb<u8>,
+) {
+    let half = (thickness as f32 - 1.0) / 2.0;
+    for dy in 0..thickness {
+        for dx in 0..thickness {
+            let (dx, dy) = (dx as f32 - half, dy as f32 - half);
+            draw_line_segment_mut(
+                image,
+                (start.0 + dx, start.1 + dy),
+                (end.0 + dx, end.1 + dy),
+                color,
+            );
+        }
+    }
+}
+
+/// Size of the text drawn by [draw_label], background included.
+fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let length = text.chars().count() as u32;
+    let width = (length * (GLYPH_WIDTH + 1) + 1) * scale;
+    let height = (GLYPH_HEIGHT + 2) * scale;
+    (width, height)
+}
+
+/// Draws the text with the built-in font on dark background, with its top left corner at the
+/// position. Each font pixel is `scale` pixels wide.
+fn draw_label(image: &mut RgbImage, text: &str, position: (i32, i32), scale: u32) {
+    let (width, height) = text_size(text, scale);
+    draw_filled_rect_mut(
+        image,
+        Rect::at(position.0, position.1).of_size(width, height),
+        TEXT_BACKGROUND_COLOR,
+    );
+    for (idx, character) in text.chars().enumerate() {
+        let glyph_x = position.0 + ((idx as u32 * (GLYPH_WIDTH + 1) + 1) * scale) as i32;
+        let glyph_y = position.1 + scale as i32;
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                draw_filled_rect_mut(
+                    image,
+                    Rect::at(
+                        glyph_x + (column * scale) as i32,
+                        glyph_y + (row as u32 * scale) as i32,
+                    )
+                    .of_size(scale, scale),
+                    TEXT_COLOR,
+                );
+            }
+        }
+    }
+}
+
+/// Rows of the 3x5 glyph of the character, most significant bit on the left. Characters
+/// without glyphs are blank.
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match character.to_ascii_uppercase() {
+        '0' | 'O' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b111, 0b100, 0b100, 0b100, 0b111],
+        'E' => [0b111, 0b100, 0b111, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b111, 0b100, 0b100],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'P' => [0b111, 0b101, 0b111, 0b100, 0b100],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'W' => [0b101, 0b101, 0b101, 0b111, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use crate::debug::{draw_label, draw_layout, DrawLayoutOptions, PADDING_COLOR, TEXT_COLOR};
+    use crate::layout::{RoomLayout, RoomType};
+
+    fn type_5_layout() -> RoomLayout {
+        RoomLayout::from_lines(
+            RoomType::Type5,
+            vec![
+                ((294, 167), (13, 0)),
+                ((294, 167), (511, 85)),
+                ((294, 167), (306, 343)),
+                ((306, 343), (0, 491)),
+                ((306, 343), (511, 410)),
+            ],
+            512,
+            512,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn pads_image_to_fit_vertices_outside_of_it() {
+        let room_image = RgbImage::from_pixel(256, 256, Rgb([128, 128, 128]));
+        let layout = type_5_layout();
+        // Left wall corner is above and to the left of the image
+        assert!(layout.wall_polygons[0].top_left.0 < 0);
+
+        let overlay = draw_layout(&room_image, &layout, &DrawLayoutOptions::default());
+        let unpadded = draw_layout(
+            &room_image,
+            &layout,
+            &DrawLayoutOptions {
+                padding: Some(0),
+                ..Default::default()
+            },
+        );
+
+        assert!(overlay.width() > room_image.width());
+        assert_eq!(overlay.width() - 256, overlay.height() - 256);
+        assert_eq!(unpadded.dimensions(), room_image.dimensions());
+    }
+
+    #[test]
+    fn returns_padding_for_empty_image() {
+        let options = DrawLayoutOptions {
+            padding: Some(4),
+            ..Default::default()
+        };
+
+        let overlay = draw_layout(&RgbImage::new(0, 0), &type_5_layout(), &options);
+
+        assert_eq!(overlay.dimensions(), (8, 8));
+        assert!(overlay.pixels().all(|pixel| *pixel == PADDING_COLOR));
+    }
+
+    #[test]
+    fn fills_walls_with_translucent_colors() {
+        let room_image = RgbImage::from_pixel(512, 512, Rgb([128, 128, 128]));
+        let options = DrawLayoutOptions {
+            padding: Some(0),
+            label_corners: false,
+            ..Default::default()
+        };
+
+        let overlay = draw_layout(&room_image, &type_5_layout(), &options);
+
+        // Inside of the left wall, away from the lines and labels
+        let wall_pixel = overlay.get_pixel(150, 300);
+        assert_ne!(*wall_pixel, Rgb([128, 128, 128]));
+        assert!(wall_pixel.0[0] > wall_pixel.0[1], "{wall_pixel:?}");
+        // Ceiling is not covered
+        assert_eq!(*overlay.get_pixel(300, 40), Rgb([128, 128, 128]));
+    }
+
+    #[test]
+    fn draws_labels_with_built_in_font() {
+        let mut image = RgbImage::new(20, 10);
+
+        draw_label(&mut image, "1", (0, 0), 1);
+
+        // Stem of the digit, one pixel from the label border
+        for y in 1..6 {
+            assert_eq!(*image.get_pixel(2, y), TEXT_COLOR, "{y}");
+        }
+        assert_eq!(*image.get_pixel(1, 1), Rgb([0, 0, 0]));
+        assert_eq!(*image.get_pixel(1, 2), TEXT_COLOR);
+    }
+}
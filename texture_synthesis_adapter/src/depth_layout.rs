@@ -0,0 +1,569 @@
+use std::path::Path;
+
+use image::{GrayImage, ImageBuffer, Luma};
+
+use crate::polygons::WallPolygon;
+use crate::preview::{prepare_wall_mask, WALL_PIXEL};
+use crate::reconstruction::{
+    add, cross, dot, normalized, scaled, smallest_eigenvector, sub, Vector3,
+};
+
+/// Room photos show at most three walls
+const MAX_WALLS: usize = 3;
+/// Wall pixels are sampled on a grid, so that at most this many points are used
+const MAX_SAMPLED_POINTS: u32 = 20_000;
+/// Surface normals are not estimated across depth jumps larger than this share of the depth
+const MAX_NORMAL_DEPTH_JUMP_SHARE: f64 = 0.05;
+/// Weight of the upright camera assumption in the estimation of the up direction. It only
+/// matters when all the walls are parallel.
+const UPRIGHT_CAMERA_PRIOR: f64 = 0.01;
+const RANSAC_ITERATIONS: usize = 256;
+const RANDOM_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+/// Wall points are at most this far from their plane, in meters plus share of their depth
+const PLANE_TOLERANCE_METERS: f64 = 0.03;
+const PLANE_TOLERANCE_DEPTH_SHARE: f64 = 0.01;
+/// Planes supported by a smaller share of wall points are furniture or noise
+const MIN_WALL_POINTS_SHARE: f64 = 0.08;
+/// Share of wall points beyond each end of the wall which are treated as outliers
+const EXTENT_OUTLIER_SHARE: f64 = 0.01;
+/// Neighbouring walls meeting at a smaller angle are not joined at a corner
+const MIN_CORNER_ANGLE_DEGREES: f64 = 20.0;
+/// Neighbouring walls are joined at a corner only if it is this close to both of their ends
+const MAX_CORNER_GAP_METERS: f64 = 0.5;
+
+/// Depth map of the room photo, with the same field of view as the photo.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthMap {
+    /// Distance along the optical axis in meters, 0 where unknown
+    pub depth: ImageBuffer<Luma<f32>, Vec<f32>>,
+    /// Focal length in depth map pixels
+    pub focal_length: f64,
+}
+
+impl DepthMap {
+    pub fn new(depth: ImageBuffer<Luma<f32>, Vec<f32>>, horizontal_fov_degrees: f64) -> Self {
+        let focal_length =
+            depth.width() as f64 / 2.0 / (horizontal_fov_degrees.to_radians() / 2.0).tan();
+        Self {
+            depth,
+            focal_length,
+        }
+    }
+
+    /// Depth map from depth in millimeters, as stored in 16-bit depth PNGs.
+    pub fn from_millimeters(
+        depth: &ImageBuffer<Luma<u16>, Vec<u16>>,
+        horizontal_fov_degrees: f64,
+    ) -> Self {
+        let depth = ImageBuffer::from_fn(depth.width(), depth.height(), |x, y| {
+            Luma([depth.get_pixel(x, y).0[0] as f32 / 1000.0])
+        });
+        Self::new(depth, horizontal_fov_degrees)
+    }
+
+    /// Opens 16-bit depth PNG with depth in millimeters.
+    pub fn open(path: &Path, horizontal_fov_degrees: f64) -> Option<Self> {
+        match image::open(path) {
+            Ok(image) => Some(Self::from_millimeters(
+                &image.into_luma16(),
+                horizontal_fov_degrees,
+            )),
+            Err(error) => {
+                println!("Could not open depth map {path:?}: {error}");
+                None
+            }
+        }
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (
+            self.depth.width() as f64 / 2.0,
+            self.depth.height() as f64 / 2.0,
+        )
+    }
+
+    /// Camera space point at the pixel centre, `None` where depth is unknown. Camera looks along
+    /// `z`, with `x` to the right and `y` down.
+    fn point(&self, x: u32, y: u32) -> Option<Vector3> {
+        let depth = self.depth.get_pixel(x, y).0[0] as f64;
+        if depth.is_nan() || depth <= 0.0 {
+            return None;
+        }
+        let (center_x, center_y) = self.center();
+        Some([
+            (x as f64 + 0.5 - center_x) * depth / self.focal_length,
+            (y as f64 + 0.5 - center_y) * depth / self.focal_length,
+            depth,
+        ])
+    }
+
+    fn project(&self, point: Vector3) -> (i32, i32) {
+        let (center_x, center_y) = self.center();
+        let depth = point[2].max(f64::EPSILON);
+        (
+            (center_x + point[0] * self.focal_length / depth).round() as i32,
+            (center_y + point[1] * self.focal_length / depth).round() as i32,
+        )
+    }
+}
+
+/// Walls of the room found in the depth map, with their true sizes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthLayout {
+    /// Walls from left to right, in depth map coordinates, i.e. layout size is the depth map size
+    pub polygons: Vec<WallPolygon>,
+    /// Width and height of each wall in meters
+    pub wall_sizes: Vec<(f32, f32)>,
+}
+
+/// Estimates room layout by fitting vertical planes to wall pixels of the depth map, without
+/// layout estimation results. Walls span from the lowest to the highest wall point, since floor
+/// and ceiling edges may be hidden. Returns `None` if no wall planes are found.
+pub fn estimate_layout_from_depth(
+    depth_map: &DepthMap,
+    mask_image: &GrayImage,
+) -> Option<DepthLayout> {
+    let (width, height) = depth_map.depth.dimensions();
+    let mask_image = prepare_wall_mask(mask_image, width, height);
+    let stride = ((width as f64 * height as f64 / MAX_SAMPLED_POINTS as f64)
+        .sqrt()
+        .ceil() as u32)
+        .max(1);
+    let is_wall = |x: u32, y: u32| *mask_image.get_pixel(x, y) == WALL_PIXEL;
+
+    let mut points = vec![];
+    let mut normal_moments = [[0.0; 3]; 3];
+    let mut normals_count = 0;
+    for y in (0..height).step_by(stride as usize) {
+        for x in (0..width).step_by(stride as usize) {
+            if !is_wall(x, y) {
+                continue;
+            }
+            let Some(point) = depth_map.point(x, y) else {
+                continue;
+            };
+            points.push(point);
+
+            // Normal from the next samples to the right and below on the same surface
+            let neighbour = |x: u32, y: u32| -> Option<Vector3> {
+                if x >= width || y >= height || !is_wall(x, y) {
+                    return None;
+                }
+                let neighbour = depth_map.point(x, y)?;
+                let jump = (neighbour[2] - point[2]).abs();
+                (jump <= MAX_NORMAL_DEPTH_JUMP_SHARE * point[2]).then_some(neighbour)
+            };
+            if let (Some(right), Some(below)) = (neighbour(x + stride, y), neighbour(x, y + stride))
+            {
+                let normal = normalized(cross(sub(right, point), sub(below, point)));
+                for (row, moments) in normal_moments.iter_mut().enumerate() {
+                    for (column, moment) in moments.iter_mut().enumerate() {
+                        *moment += normal[row] * normal[column];
+                    }
+                }
+                normals_count += 1;
+            }
+        }
+    }
+    if points.is_empty() {
+        println!("No wall pixels with known depth");
+        return None;
+    }
+
+    let floor_plan = FloorPlan::new(estimate_up(normal_moments, normals_count));
+    let plan_points: Vec<PlanPoint> = points
+        .iter()
+        .map(|point| PlanPoint {
+            position: floor_plan.position(*point),
+            height: dot(*point, floor_plan.up),
+            tolerance: PLANE_TOLERANCE_METERS + PLANE_TOLERANCE_DEPTH_SHARE * point[2],
+        })
+        .collect();
+
+    // Walls are lines on the floor plan, which are fitted one by one to the remaining points
+    let min_wall_points = ((MIN_WALL_POINTS_SHARE * points.len() as f64).ceil() as usize).max(2);
+    let mut remaining: Vec<&PlanPoint> = plan_points.iter().collect();
+    let mut random = Random(RANDOM_SEED);
+    let mut walls = vec![];
+    while walls.len() < MAX_WALLS {
+        let Some(line) = fit_wall_line(&remaining, &mut random) else {
+            break;
+        };
+        let (inliers, outliers): (Vec<&PlanPoint>, Vec<&PlanPoint>) = remaining
+            .iter()
+            .partition(|point| line.distance(point.position) <= point.tolerance);
+        if inliers.len() < min_wall_points {
+            break;
+        }
+        walls.push(PlanWall::new(line, &inliers));
+        remaining = outliers;
+    }
+    if walls.is_empty() {
+        println!("No wall planes found in the depth map");
+        return None;
+    }
+
+    walls.sort_by(|a, b| a.bearing().total_cmp(&b.bearing()));
+    for idx in 1..walls.len() {
+        let (left, right) = (&walls[idx - 1], &walls[idx]);
+        if let Some(corner) = left.line.intersection(&right.line) {
+            let is_close = distance(corner, left.right) <= MAX_CORNER_GAP_METERS
+                && distance(corner, right.left) <= MAX_CORNER_GAP_METERS;
+            if is_close && corner.1 > 0.0 {
+                walls[idx - 1].right = corner;
+                walls[idx].left = corner;
+            }
+        }
+    }
+
+    // Floor and ceiling are shared by all the walls
+    let mut heights: Vec<f64> = walls
+        .iter()
+        .flat_map(|wall| wall.heights.iter().cloned())
+        .collect();
+    heights.sort_by(f64::total_cmp);
+    let bottom = percentile(&heights, EXTENT_OUTLIER_SHARE);
+    let top = percentile(&heights, 1.0 - EXTENT_OUTLIER_SHARE);
+
+    let project =
+        |position: (f64, f64), height: f64| depth_map.project(floor_plan.point(position, height));
+    let polygons = walls
+        .iter()
+        .map(|wall| WallPolygon {
+            top_left: project(wall.left, top),
+            top_right: project(wall.right, top),
+            bottom_right: project(wall.right, bottom),
+            bottom_left: project(wall.left, bottom),
+        })
+        .collect();
+    let wall_sizes = walls
+        .iter()
+        .map(|wall| {
+            (
+                distance(wall.left, wall.right) as f32,
+                (top - bottom) as f32,
+            )
+        })
+        .collect();
+    Some(DepthLayout {
+        polygons,
+        wall_sizes,
+    })
+}
+
+/// Up direction in camera space, along which wall normals do not vary. Upright camera is assumed
+/// where the normals do not tell.
+fn estimate_up(normal_moments: [[f64; 3]; 3], normals_count: usize) -> Vector3 {
+    let camera_up = [0.0, -1.0, 0.0];
+    let mut matrix = [[0.0; 3]; 3];
+    for (row, values) in matrix.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            let identity = if row == column { 1.0 } else { 0.0 };
+            let moment = normal_moments[row][column] / normals_count.max(1) as f64;
+            *value =
+                moment + UPRIGHT_CAMERA_PRIOR * (identity - camera_up[row] * camera_up[column]);
+        }
+    }
+    let up = normalized(smallest_eigenvector(matrix));
+    if dot(up, camera_up) < 0.0 {
+        scaled(up, -1.0)
+    } else {
+        up
+    }
+}
+
+/// Horizontal plane through the camera, with `x` to the right and `y` forward.
+struct FloorPlan {
+    up: Vector3,
+    right: Vector3,
+    forward: Vector3,
+}
+
+impl FloorPlan {
+    fn new(up: Vector3) -> Self {
+        let forward = normalized(add([0.0, 0.0, 1.0], scaled(up, -up[2])));
+        Self {
+            up,
+            right: cross(forward, up),
+            forward,
+        }
+    }
+
+    fn position(&self, point: Vector3) -> (f64, f64) {
+        (dot(point, self.right), dot(point, self.forward))
+    }
+
+    fn point(&self, position: (f64, f64), height: f64) -> Vector3 {
+        add(
+            add(
+                scaled(self.right, position.0),
+                scaled(self.forward, position.1),
+            ),
+            scaled(self.up, height),
+        )
+    }
+}
+
+struct PlanPoint {
+    position: (f64, f64),
+    height: f64,
+    /// Largest distance from the wall plane
+    tolerance: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PlanLine {
+    origin: (f64, f64),
+    /// Unit direction
+    direction: (f64, f64),
+}
+
+impl PlanLine {
+    fn distance(&self, position: (f64, f64)) -> f64 {
+        let (x, y) = (position.0 - self.origin.0, position.1 - self.origin.1);
+        (x * self.direction.1 - y * self.direction.0).abs()
+    }
+
+    fn coordinate(&self, position: (f64, f64)) -> f64 {
+        (position.0 - self.origin.0) * self.direction.0
+            + (position.1 - self.origin.1) * self.direction.1
+    }
+
+    fn at(&self, coordinate: f64) -> (f64, f64) {
+        (
+            self.origin.0 + coordinate * self.direction.0,
+            self.origin.1 + coordinate * self.direction.1,
+        )
+    }
+
+    /// `None` for lines meeting at an angle too small for a room corner.
+    fn intersection(&self, other: &PlanLine) -> Option<(f64, f64)> {
+        let sine = self.direction.0 * other.direction.1 - self.direction.1 * other.direction.0;
+        if sine.abs() < MIN_CORNER_ANGLE_DEGREES.to_radians().sin() {
+            return None;
+        }
+        let offset = (
+            other.origin.0 - self.origin.0,
+            other.origin.1 - self.origin.1,
+        );
+        let coordinate = (offset.0 * other.direction.1 - offset.1 * other.direction.0) / sine;
+        Some(self.at(coordinate))
+    }
+}
+
+/// Wall segment on the floor plan, with ends ordered from left to right as seen from the camera.
+struct PlanWall {
+    line: PlanLine,
+    left: (f64, f64),
+    right: (f64, f64),
+    heights: Vec<f64>,
+}
+
+impl PlanWall {
+    fn new(line: PlanLine, points: &[&PlanPoint]) -> Self {
+        let mut coordinates: Vec<f64> = points
+            .iter()
+            .map(|point| line.coordinate(point.position))
+            .collect();
+        coordinates.sort_by(f64::total_cmp);
+        let start = line.at(percentile(&coordinates, EXTENT_OUTLIER_SHARE));
+        let end = line.at(percentile(&coordinates, 1.0 - EXTENT_OUTLIER_SHARE));
+        let (left, right) = if bearing(start) <= bearing(end) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        Self {
+            line,
+            left,
+            right,
+            heights: points.iter().map(|point| point.height).collect(),
+        }
+    }
+
+    fn bearing(&self) -> f64 {
+        bearing((
+            (self.left.0 + self.right.0) / 2.0,
+            (self.left.1 + self.right.1) / 2.0,
+        ))
+    }
+}
+
+/// Line through the most points found with RANSAC, refined by least squares.
+fn fit_wall_line(points: &[&PlanPoint], random: &mut Random) -> Option<PlanLine> {
+    if points.len() < 2 {
+        return None;
+    }
+    let mut best: Option<(usize, PlanLine)> = None;
+    for _ in 0..RANSAC_ITERATIONS {
+        let first = points[random.below(points.len())].position;
+        let second = points[random.below(points.len())].position;
+        let length = distance(first, second);
+        if length < PLANE_TOLERANCE_METERS {
+            continue;
+        }
+        let line = PlanLine {
+            origin: first,
+            direction: ((second.0 - first.0) / length, (second.1 - first.1) / length),
+        };
+        let inliers_count = points
+            .iter()
+            .filter(|point| line.distance(point.position) <= point.tolerance)
+            .count();
+        if best.is_none_or(|(best_count, _)| inliers_count > best_count) {
+            best = Some((inliers_count, line));
+        }
+    }
+    let (_, line) = best?;
+
+    let inliers: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|point| line.distance(point.position) <= point.tolerance)
+        .map(|point| point.position)
+        .collect();
+    let count = inliers.len() as f64;
+    let mean_x = inliers.iter().map(|point| point.0).sum::<f64>() / count;
+    let mean_y = inliers.iter().map(|point| point.1).sum::<f64>() / count;
+    let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+    for (x, y) in &inliers {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        xx += dx * dx;
+        xy += dx * dy;
+        yy += dy * dy;
+    }
+    let angle = 0.5 * (2.0 * xy).atan2(xx - yy);
+    Some(PlanLine {
+        origin: (mean_x, mean_y),
+        direction: (angle.cos(), angle.sin()),
+    })
+}
+
+/// Angle of the floor plan position to the right of the camera view direction.
+fn bearing(position: (f64, f64)) -> f64 {
+    position.0.atan2(position.1)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).hypot(a.1 - b.1)
+}
+
+/// Value at the share of sorted values.
+fn percentile(sorted: &[f64], share: f64) -> f64 {
+    let idx = (share * (sorted.len() - 1) as f64).round() as usize;
+    sorted[idx]
+}
+
+/// Deterministic xorshift generator, so that the same depth map always gives the same layout.
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, ImageBuffer, Luma};
+
+    use crate::depth_layout::{estimate_layout_from_depth, DepthMap};
+
+    const WIDTH: u32 = 320;
+    const HEIGHT: u32 = 240;
+    const FOCAL_LENGTH: f64 = 200.0;
+    /// Camera is 1.2 meters above the floor and 1.3 meters below the ceiling
+    const FLOOR_Y: f64 = 1.2;
+    const CEILING_Y: f64 = -1.3;
+
+    /// Depth map and wall mask of the room with the front wall 3 meters ahead and, optionally,
+    /// the left wall 1.5 meters to the left of the upright camera.
+    fn render_room(has_left_wall: bool) -> (DepthMap, GrayImage) {
+        let mut depth = ImageBuffer::new(WIDTH, HEIGHT);
+        let mut mask_image = GrayImage::new(WIDTH, HEIGHT);
+        for (x, y, pixel) in depth.enumerate_pixels_mut() {
+            let ray_x = (x as f64 + 0.5 - WIDTH as f64 / 2.0) / FOCAL_LENGTH;
+            let ray_y = (y as f64 + 0.5 - HEIGHT as f64 / 2.0) / FOCAL_LENGTH;
+            let mut wall_depth = 3.0;
+            if has_left_wall && ray_x < 0.0 {
+                wall_depth = f64::min(wall_depth, -1.5 / ray_x);
+            }
+            let height_y = ray_y * wall_depth;
+            let (depth, is_wall) = if height_y > FLOOR_Y {
+                (FLOOR_Y / ray_y, false)
+            } else if height_y < CEILING_Y {
+                (CEILING_Y / ray_y, false)
+            } else {
+                (wall_depth, true)
+            };
+            *pixel = Luma([depth as f32]);
+            if is_wall {
+                mask_image.put_pixel(x, y, Luma([255]));
+            }
+        }
+        let depth_map = DepthMap {
+            depth,
+            focal_length: FOCAL_LENGTH,
+        };
+        (depth_map, mask_image)
+    }
+
+    fn project(x: f64, y: f64, z: f64) -> (f64, f64) {
+        (
+            WIDTH as f64 / 2.0 + x * FOCAL_LENGTH / z,
+            HEIGHT as f64 / 2.0 + y * FOCAL_LENGTH / z,
+        )
+    }
+
+    fn assert_close(actual: (i32, i32), expected: (f64, f64)) {
+        assert!(
+            (actual.0 as f64 - expected.0).abs() <= 5.0
+                && (actual.1 as f64 - expected.1).abs() <= 5.0,
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn fits_walls_meeting_at_corner() {
+        let (depth_map, mask_image) = render_room(true);
+
+        let layout = estimate_layout_from_depth(&depth_map, &mask_image).unwrap();
+
+        assert_eq!(layout.polygons.len(), 2);
+        let (left, front) = (&layout.polygons[0], &layout.polygons[1]);
+        assert_close(left.top_right, project(-1.5, CEILING_Y, 3.0));
+        assert_close(left.bottom_right, project(-1.5, FLOOR_Y, 3.0));
+        assert_close(front.top_left, project(-1.5, CEILING_Y, 3.0));
+        assert_close(front.bottom_right, project(2.4, FLOOR_Y, 3.0));
+
+        let (front_width, front_height) = layout.wall_sizes[1];
+        assert!((front_width - 3.9).abs() < 0.1, "{front_width}");
+        assert!((front_height - 2.5).abs() < 0.1, "{front_height}");
+    }
+
+    #[test]
+    fn single_wall_is_upright() {
+        let (depth_map, mask_image) = render_room(false);
+
+        let layout = estimate_layout_from_depth(&depth_map, &mask_image).unwrap();
+
+        assert_eq!(layout.polygons.len(), 1);
+        let wall = &layout.polygons[0];
+        assert_close(wall.top_left, project(-2.4, CEILING_Y, 3.0));
+        assert_close(wall.bottom_right, project(2.4, FLOOR_Y, 3.0));
+        let (width, height) = layout.wall_sizes[0];
+        assert!((width - 4.8).abs() < 0.15, "{width}");
+        assert!((height - 2.5).abs() < 0.1, "{height}");
+    }
+
+    #[test]
+    fn no_layout_without_walls() {
+        let (depth_map, _) = render_room(true);
+
+        let layout = estimate_layout_from_depth(&depth_map, &GrayImage::new(WIDTH, HEIGHT));
+
+        assert_eq!(layout, None);
+    }
+}
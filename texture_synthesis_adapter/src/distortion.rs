@@ -0,0 +1,501 @@
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use imageproc::geometric_transformations::{warp_with, Interpolation};
+
+use crate::layout::lsun::Line;
+use crate::material::WallpaperMaterial;
+use crate::polygons::compute_wall_polygons;
+use crate::preview::{create_preview, prepare_wall_mask, WALL_PIXEL};
+use crate::refinement::Gradients;
+
+/// Strongest barrel or pincushion distortion considered by the estimation
+const MAX_DIVISION_COEFFICIENT: f64 = 0.5;
+const COEFFICIENT_SEARCH_STEPS: usize = 200;
+/// Half-width of the band searched across layout lines for their curved edges, as a share of the
+/// image diagonal
+const EDGE_SEARCH_BAND_SHARE: f64 = 0.03;
+const LINE_SAMPLES: usize = 48;
+/// Share of the line length at both ends which is not sampled, since other edges meet there
+const LINE_END_MARGIN: f64 = 0.05;
+/// Sobel response across the line of pixels belonging to its edge
+const MIN_EDGE_GRADIENT: f64 = 100.0;
+/// Lines with fewer edge samples are too weak to measure their curvature
+const MIN_LINE_EDGE_SAMPLES: usize = 12;
+/// Distortion is corrected only if it makes the edges at least this much straighter
+const MIN_STRAIGHTENING_GAIN: f64 = 0.2;
+const RADIUS_INVERSION_ITERATIONS: usize = 20;
+/// Photo border is sampled this many times per side to fit the undistorted photo into its frame
+const BORDER_SAMPLES: usize = 32;
+
+/// Radial lens distortion around the image centre. Radii are relative to half of the image
+/// diagonal, and negative coefficients give barrel distortion of wide-angle lenses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LensProfile {
+    /// Brown–Conrady model, `distorted = r * (1 + k1 * r^2 + k2 * r^4)`
+    BrownConrady { k1: f64, k2: f64 },
+    /// Division model, `r = distorted / (1 + k1 * distorted^2)`
+    Division { k1: f64 },
+}
+
+impl LensProfile {
+    fn undistort_radius(&self, distorted: f64) -> f64 {
+        match *self {
+            LensProfile::BrownConrady { k1, k2 } => {
+                let mut radius = distorted;
+                for _ in 0..RADIUS_INVERSION_ITERATIONS {
+                    let radius_squared = radius * radius;
+                    radius = distorted / (1.0 + k1 * radius_squared + k2 * radius_squared.powi(2));
+                }
+                radius
+            }
+            LensProfile::Division { k1 } => distorted / (1.0 + k1 * distorted * distorted),
+        }
+    }
+
+    fn distort_radius(&self, radius: f64) -> f64 {
+        match *self {
+            LensProfile::BrownConrady { k1, k2 } => {
+                let radius_squared = radius * radius;
+                radius * (1.0 + k1 * radius_squared + k2 * radius_squared.powi(2))
+            }
+            LensProfile::Division { k1 } => {
+                // Smaller root of `k1 * r * distorted^2 - distorted + r = 0`
+                if (k1 * radius).abs() < f64::EPSILON {
+                    return radius;
+                }
+                let discriminant = (1.0 - 4.0 * k1 * radius * radius).max(0.0);
+                (1.0 - discriminant.sqrt()) / (2.0 * k1 * radius)
+            }
+        }
+    }
+}
+
+/// Mapping between the distorted photo and its undistorted image of the same size. Undistorted
+/// image is zoomed out, so that the whole photo fits into it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Undistortion {
+    pub profile: LensProfile,
+    pub width: u32,
+    pub height: u32,
+    /// Undistorted image shrink, at least 1
+    zoom: f64,
+}
+
+impl Undistortion {
+    pub fn new(profile: LensProfile, width: u32, height: u32) -> Self {
+        let mut undistortion = Self {
+            profile,
+            width,
+            height,
+            zoom: 1.0,
+        };
+        let (width, height) = (width as f64, height as f64);
+        let border_points = (0..=BORDER_SAMPLES).flat_map(|i| {
+            let share = i as f64 / BORDER_SAMPLES as f64;
+            [
+                (share * width, 0.0),
+                (share * width, height),
+                (0.0, share * height),
+                (width, share * height),
+            ]
+        });
+        let zoom = border_points
+            .map(|point| {
+                let (x, y) = undistortion.undistort_point(point);
+                f64::max(
+                    (x - width / 2.0).abs() / (width / 2.0),
+                    (y - height / 2.0).abs() / (height / 2.0),
+                )
+            })
+            .fold(1.0, f64::max);
+        undistortion.zoom = zoom;
+        undistortion
+    }
+
+    /// Half of the image diagonal, which lens profile radii are relative to.
+    fn unit(&self) -> f64 {
+        (self.width as f64).hypot(self.height as f64) / 2.0
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (self.width as f64 / 2.0, self.height as f64 / 2.0)
+    }
+
+    /// Position of the photo point in the undistorted image.
+    pub fn undistort_point(&self, point: (f64, f64)) -> (f64, f64) {
+        let (center_x, center_y) = self.center();
+        let (x, y) = (point.0 - center_x, point.1 - center_y);
+        let distorted = x.hypot(y) / self.unit();
+        if distorted < f64::EPSILON {
+            return point;
+        }
+        let scale = self.profile.undistort_radius(distorted) / distorted / self.zoom;
+        (center_x + x * scale, center_y + y * scale)
+    }
+
+    /// Position of the undistorted image point in the photo.
+    pub fn distort_point(&self, point: (f64, f64)) -> (f64, f64) {
+        let (center_x, center_y) = self.center();
+        let (x, y) = (point.0 - center_x, point.1 - center_y);
+        let radius = x.hypot(y) * self.zoom / self.unit();
+        if radius < f64::EPSILON {
+            return point;
+        }
+        let scale = self.profile.distort_radius(radius) / radius * self.zoom;
+        (center_x + x * scale, center_y + y * scale)
+    }
+
+    pub fn undistort_image(&self, image: &RgbImage) -> RgbImage {
+        self.assert_size(image.width(), image.height());
+        warp_with(
+            image,
+            |x, y| to_f32(self.distort_point((x as f64, y as f64))),
+            Interpolation::Bilinear,
+            Rgb([0, 0, 0]),
+        )
+    }
+
+    /// Same as [Self::undistort_image], but keeps mask values intact.
+    pub fn undistort_mask(&self, mask_image: &GrayImage) -> GrayImage {
+        self.assert_size(mask_image.width(), mask_image.height());
+        warp_with(
+            mask_image,
+            |x, y| to_f32(self.distort_point((x as f64, y as f64))),
+            Interpolation::Nearest,
+            Luma([0]),
+        )
+    }
+
+    pub fn distort_image(&self, image: &RgbImage) -> RgbImage {
+        self.assert_size(image.width(), image.height());
+        warp_with(
+            image,
+            |x, y| to_f32(self.undistort_point((x as f64, y as f64))),
+            Interpolation::Bilinear,
+            Rgb([0, 0, 0]),
+        )
+    }
+
+    pub fn undistort_line(&self, line: &Line) -> Line {
+        let undistort = |(x, y): (i32, i32)| {
+            let (x, y) = self.undistort_point((x as f64, y as f64));
+            (x.round() as i32, y.round() as i32)
+        };
+        (undistort(line.0), undistort(line.1))
+    }
+
+    fn assert_size(&self, width: u32, height: u32) {
+        assert_eq!(
+            (width, height),
+            (self.width, self.height),
+            "Image size does not match the undistortion size"
+        );
+    }
+}
+
+/// Estimates division model lens profile from the curvature of room edges along the layout
+/// lines, which are straight approximations of those edges. `None` if the edges are straight
+/// enough or too weak to measure.
+pub fn estimate_lens_profile(
+    room_image: &GrayImage,
+    lines: &[Line],
+    layout_width: u32,
+    layout_height: u32,
+) -> Option<LensProfile> {
+    let (width, height) = (room_image.width() as f64, room_image.height() as f64);
+    let scale = (width / layout_width as f64, height / layout_height as f64);
+    let gradients = Gradients::new(room_image);
+    let band = EDGE_SEARCH_BAND_SHARE * width.hypot(height);
+
+    let curves: Vec<Vec<(f64, f64)>> = lines
+        .iter()
+        .filter_map(|(start, end)| {
+            let start = (start.0 as f64 * scale.0, start.1 as f64 * scale.1);
+            let end = (end.0 as f64 * scale.0, end.1 as f64 * scale.1);
+            let length = (end.0 - start.0).hypot(end.1 - start.1);
+            if length < 2.0 * band {
+                return None;
+            }
+            let normal = (-(end.1 - start.1) / length, (end.0 - start.0) / length);
+
+            // Strongest edge across the line at each sample
+            let mut edge_points = vec![];
+            for sample in 0..LINE_SAMPLES {
+                let share = LINE_END_MARGIN
+                    + (1.0 - 2.0 * LINE_END_MARGIN) * sample as f64 / (LINE_SAMPLES - 1) as f64;
+                let point = (
+                    start.0 + share * (end.0 - start.0),
+                    start.1 + share * (end.1 - start.1),
+                );
+                let strongest_edge = (-(band as i32)..=band as i32)
+                    .filter_map(|offset| {
+                        let candidate = (
+                            point.0 + offset as f64 * normal.0,
+                            point.1 + offset as f64 * normal.1,
+                        );
+                        let (gradient_x, gradient_y) = gradients.at(candidate)?;
+                        Some((
+                            (gradient_x * normal.0 + gradient_y * normal.1).abs(),
+                            candidate,
+                        ))
+                    })
+                    .max_by(|a, b| a.0.total_cmp(&b.0));
+                if let Some((response, edge_point)) = strongest_edge {
+                    if response >= MIN_EDGE_GRADIENT {
+                        edge_points.push(edge_point);
+                    }
+                }
+            }
+            (edge_points.len() >= MIN_LINE_EDGE_SAMPLES).then_some(edge_points)
+        })
+        .collect();
+    if curves.is_empty() {
+        return None;
+    }
+
+    let center = (width / 2.0, height / 2.0);
+    let unit = width.hypot(height) / 2.0;
+    let crookedness = |profile: LensProfile| -> f64 {
+        curves
+            .iter()
+            .map(|curve| {
+                let points: Vec<(f64, f64)> = curve
+                    .iter()
+                    .map(|point| {
+                        let (x, y) = ((point.0 - center.0) / unit, (point.1 - center.1) / unit);
+                        let distorted = x.hypot(y);
+                        if distorted < f64::EPSILON {
+                            return (x, y);
+                        }
+                        let scale = profile.undistort_radius(distorted) / distorted;
+                        (x * scale, y * scale)
+                    })
+                    .collect();
+                let (across, along) = principal_variances(&points);
+                across / along.max(f64::EPSILON)
+            })
+            .sum()
+    };
+
+    let straight_crookedness = crookedness(LensProfile::Division { k1: 0.0 });
+    let (k1, best_crookedness) = (0..=COEFFICIENT_SEARCH_STEPS)
+        .map(|step| {
+            let k1 = MAX_DIVISION_COEFFICIENT
+                * (2.0 * step as f64 / COEFFICIENT_SEARCH_STEPS as f64 - 1.0);
+            (k1, crookedness(LensProfile::Division { k1 }))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))?;
+    if best_crookedness >= (1.0 - MIN_STRAIGHTENING_GAIN) * straight_crookedness {
+        return None;
+    }
+    Some(LensProfile::Division { k1 })
+}
+
+/// Same as [create_preview], but corrects lens distortion of the room image first. Layout lines
+/// are undistorted along with the room image and wall polygons are recomputed from them, so that
+/// walls are planar in the undistorted space, and wallpapered walls are distorted back onto the
+/// room image. Room image is returned unchanged if lines do not fit the room type.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_preview_with_lens_profile(
+    room_image: RgbImage,
+    mask_image: GrayImage,
+    material: WallpaperMaterial,
+    lines: &[Line],
+    room_type: u8,
+    layout_width: u32,
+    layout_height: u32,
+    profile: LensProfile,
+) -> RgbImage {
+    let (width, height) = room_image.dimensions();
+    let undistortion = Undistortion::new(profile, width, height);
+    let mask_image = prepare_wall_mask(&mask_image, width, height);
+
+    let scale_x = width as f32 / layout_width as f32;
+    let scale_y = height as f32 / layout_height as f32;
+    let scale = |(x, y): (i32, i32)| {
+        (
+            (x as f32 * scale_x).round() as i32,
+            (y as f32 * scale_y).round() as i32,
+        )
+    };
+    let lines: Vec<Line> = lines
+        .iter()
+        .map(|(start, end)| undistortion.undistort_line(&(scale(*start), scale(*end))))
+        .collect();
+    let Some(polygons) = compute_wall_polygons(&lines, width as i32, height as i32, room_type)
+    else {
+        println!("Can not create preview for room type {room_type}");
+        return room_image;
+    };
+    let undistorted_preview = create_preview(
+        undistortion.undistort_image(&room_image),
+        undistortion.undistort_mask(&mask_image),
+        material,
+        polygons,
+        width,
+        height,
+    );
+
+    // Preview can be rescaled, so the result is composited at the preview size
+    let (preview_width, preview_height) = undistorted_preview.dimensions();
+    let preview_undistortion = Undistortion {
+        width: preview_width,
+        height: preview_height,
+        ..undistortion
+    };
+    let distorted_preview = preview_undistortion.distort_image(&undistorted_preview);
+    let mut preview_image = if (preview_width, preview_height) == (width, height) {
+        room_image
+    } else {
+        image::imageops::resize(
+            &room_image,
+            preview_width,
+            preview_height,
+            image::imageops::FilterType::Lanczos3,
+        )
+    };
+    let mask_image = prepare_wall_mask(&mask_image, preview_width, preview_height);
+    for (x, y, pixel) in preview_image.enumerate_pixels_mut() {
+        if *mask_image.get_pixel(x, y) == WALL_PIXEL {
+            *pixel = *distorted_preview.get_pixel(x, y);
+        }
+    }
+    preview_image
+}
+
+/// Variances of the points across and along their principal direction.
+fn principal_variances(points: &[(f64, f64)]) -> (f64, f64) {
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|point| point.0).sum::<f64>() / count;
+    let mean_y = points.iter().map(|point| point.1).sum::<f64>() / count;
+    let (mut xx, mut xy, mut yy) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        xx += dx * dx / count;
+        xy += dx * dy / count;
+        yy += dy * dy / count;
+    }
+    let half_trace = (xx + yy) / 2.0;
+    let spread = (((xx - yy) / 2.0).powi(2) + xy * xy).sqrt();
+    (half_trace - spread, half_trace + spread)
+}
+
+fn to_f32(point: (f64, f64)) -> (f32, f32) {
+    (point.0 as f32, point.1 as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    use crate::distortion::{
+        create_preview_with_lens_profile, estimate_lens_profile, LensProfile, Undistortion,
+    };
+    use crate::layout::lsun::Line;
+
+    #[test]
+    fn distortion_inverts_undistortion() {
+        for profile in [
+            LensProfile::BrownConrady { k1: -0.2, k2: 0.05 },
+            LensProfile::Division { k1: -0.3 },
+            LensProfile::Division { k1: 0.1 },
+        ] {
+            let undistortion = Undistortion::new(profile, 640, 480);
+            for point in [(0.0, 0.0), (320.0, 240.0), (600.0, 50.0), (100.0, 470.0)] {
+                let undistorted = undistortion.undistort_point(point);
+                let distorted = undistortion.distort_point(undistorted);
+
+                assert!(
+                    (distorted.0 - point.0).abs() < 0.01,
+                    "{profile:?} {point:?}"
+                );
+                assert!(
+                    (distorted.1 - point.1).abs() < 0.01,
+                    "{profile:?} {point:?}"
+                );
+                // Whole photo fits into the undistorted image
+                assert!((-0.01..=640.01).contains(&undistorted.0), "{undistorted:?}");
+                assert!((-0.01..=480.01).contains(&undistorted.1), "{undistorted:?}");
+            }
+        }
+    }
+
+    /// Photo of straight ceiling and left wall edges bent by the lens, with layout lines between
+    /// the ends of the bent edges.
+    fn distorted_room(profile: LensProfile) -> (GrayImage, Vec<Line>) {
+        let (width, height) = (640, 480);
+        let undistortion = Undistortion::new(profile, width, height);
+        // Bright ceiling and left wall, in the undistorted image
+        let undistorted = RgbImage::from_fn(width, height, |x, y| {
+            if y < 120 || x < 140 {
+                Rgb([220, 220, 220])
+            } else {
+                Rgb([60, 60, 60])
+            }
+        });
+        let photo =
+            image::DynamicImage::from(undistortion.distort_image(&undistorted)).into_luma8();
+
+        let to_photo = |point: (f64, f64)| {
+            let (x, y) = undistortion.distort_point(point);
+            (x.round() as i32, y.round() as i32)
+        };
+        let lines = vec![
+            (to_photo((140.0, 120.0)), to_photo((600.0, 120.0))),
+            (to_photo((140.0, 120.0)), to_photo((140.0, 440.0))),
+        ];
+        (photo, lines)
+    }
+
+    #[test]
+    fn estimates_lens_profile_from_bent_edges() {
+        let (photo, lines) = distorted_room(LensProfile::Division { k1: -0.25 });
+
+        let profile = estimate_lens_profile(&photo, &lines, 640, 480).unwrap();
+
+        let LensProfile::Division { k1 } = profile else {
+            panic!("{profile:?}");
+        };
+        assert!((k1 + 0.25).abs() < 0.05, "{k1}");
+    }
+
+    #[test]
+    fn straight_edges_need_no_correction() {
+        let (photo, lines) = distorted_room(LensProfile::Division { k1: 0.0 });
+
+        assert_eq!(estimate_lens_profile(&photo, &lines, 640, 480), None);
+    }
+
+    #[test]
+    fn keeps_room_image_outside_of_walls() {
+        let room_image = RgbImage::from_fn(64, 48, |x, y| Rgb([x as u8 * 4, y as u8 * 5, 100]));
+        let mask_image =
+            GrayImage::from_fn(64, 48, |x, _| if x < 32 { Luma([255]) } else { Luma([0]) });
+        let tile = RgbImage::from_pixel(16, 16, Rgb([250, 0, 0]));
+        // Single wall above the floor line at the bottom of the image
+        let lines = [((0, 500), (511, 500))];
+
+        let preview = create_preview_with_lens_profile(
+            room_image.clone(),
+            mask_image,
+            tile.into(),
+            &lines,
+            9,
+            512,
+            512,
+            LensProfile::Division { k1: -0.2 },
+        );
+
+        let resized_room_image = image::imageops::resize(
+            &room_image,
+            preview.width(),
+            preview.height(),
+            image::imageops::FilterType::Lanczos3,
+        );
+        let (width, height) = preview.dimensions();
+        for (x, y) in [(width * 3 / 4, height / 2), (width - 1, 0)] {
+            assert_eq!(preview.get_pixel(x, y), resized_room_image.get_pixel(x, y));
+        }
+        let wall_pixel = preview.get_pixel(width / 4, height / 2).0;
+        assert!(wall_pixel[0] > wall_pixel[1] + 100, "{wall_pixel:?}");
+    }
+}
@@ -0,0 +1,189 @@
+use crate::layout::lsun::{Line, Point};
+use crate::polygons::{room_topology, Corner, EdgeLine, LineEnd, Side};
+
+/// Endpoints of different lines closer than this are the same layout junction
+const JUNCTION_TOLERANCE: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CornerPosition {
+    TopLeft,
+    TopRight,
+    BottomRight,
+    BottomLeft,
+}
+
+/// Edit of the room layout made by user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutEdit {
+    /// Moves wall polygon corner. Corners at line endpoints move the whole junction, while
+    /// corners where a detected line is extended to the border rotate that line around its
+    /// other end.
+    MoveCorner {
+        wall: usize,
+        corner: CornerPosition,
+        to: Point,
+    },
+    /// Moves line endpoint together with endpoints of other lines meeting at it.
+    MoveLineEndpoint {
+        line: usize,
+        end: LineEnd,
+        to: Point,
+    },
+}
+
+/// Applies the edit to detected layout lines, so that wall polygons recomputed from them stay
+/// consistent. Returns `None` if the edit can not be applied, e.g. if the corner lies on an
+/// invisible line, which is estimated rather than detected.
+pub fn edit_layout_lines(lines: &[Line], room_type: u8, edit: LayoutEdit) -> Option<Vec<Line>> {
+    let (line, end, to) = match edit {
+        LayoutEdit::MoveCorner { wall, corner, to } => {
+            let topology = room_topology(room_type)?;
+            let wall = topology.walls.get(wall)?;
+            let corner = match corner {
+                CornerPosition::TopLeft => wall.top_left,
+                CornerPosition::TopRight => wall.top_right,
+                CornerPosition::BottomRight => wall.bottom_right,
+                CornerPosition::BottomLeft => wall.bottom_left,
+            };
+            match corner {
+                Corner::Endpoint(line, end) => (line, end, to),
+                Corner::Meet(EdgeLine::Detected(line), border) => {
+                    let (start_point, end_point) = *lines.get(line)?;
+                    // Line goes through the moved corner from its end closest to the border
+                    let start_is_outer = match border.side {
+                        Side::Left => start_point.0 <= end_point.0,
+                        Side::Right => start_point.0 > end_point.0,
+                    };
+                    let end = if start_is_outer {
+                        LineEnd::Start
+                    } else {
+                        LineEnd::End
+                    };
+                    (line, end, to)
+                }
+                Corner::Meet(edge, _) => {
+                    println!("Can not move corner of estimated edge line {edge:?}");
+                    return None;
+                }
+            }
+        }
+        LayoutEdit::MoveLineEndpoint { line, end, to } => (line, end, to),
+    };
+
+    let (start_point, end_point) = *lines.get(line)?;
+    let from = match end {
+        LineEnd::Start => start_point,
+        LineEnd::End => end_point,
+    };
+
+    let mut edited_lines = lines.to_vec();
+    for (line_idx, (start_point, end_point)) in edited_lines.iter_mut().enumerate() {
+        if is_same_junction(*start_point, from) {
+            *start_point = to;
+        }
+        if is_same_junction(*end_point, from) {
+            *end_point = to;
+        }
+        // Moved endpoint itself might be further than tolerance from another one at the junction
+        if line_idx == line {
+            match end {
+                LineEnd::Start => *start_point = to,
+                LineEnd::End => *end_point = to,
+            }
+        }
+    }
+    Some(edited_lines)
+}
+
+pub(crate) fn is_same_junction(a: Point, b: Point) -> bool {
+    ((a.0 - b.0) as f64).hypot((a.1 - b.1) as f64) <= JUNCTION_TOLERANCE
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editing::{edit_layout_lines, CornerPosition, LayoutEdit};
+    use crate::polygons::{compute_wall_polygons, LineEnd};
+
+    fn room_type_5_lines() -> Vec<((i32, i32), (i32, i32))> {
+        vec![
+            ((294, 167), (13, 0)),
+            ((294, 167), (511, 85)),
+            ((294, 167), (306, 343)),
+            ((306, 343), (0, 491)),
+            ((306, 343), (511, 410)),
+        ]
+    }
+
+    #[test]
+    fn moving_shared_corner_moves_it_for_both_walls() {
+        let lines = room_type_5_lines();
+        let edit = LayoutEdit::MoveCorner {
+            wall: 1,
+            corner: CornerPosition::TopLeft,
+            to: (280, 150),
+        };
+
+        let edited_lines = edit_layout_lines(&lines, 5, edit).unwrap();
+        let polygons = compute_wall_polygons(&edited_lines, 512, 512, 5).unwrap();
+
+        assert_eq!(edited_lines[0].0, (280, 150));
+        assert_eq!(edited_lines[1].0, (280, 150));
+        assert_eq!(edited_lines[2].0, (280, 150));
+        assert_eq!(polygons[0].top_right, (280, 150));
+        assert_eq!(polygons[1].top_left, (280, 150));
+        // Bottom junction stays in place
+        assert_eq!(polygons[0].bottom_right, (306, 343));
+        assert_eq!(polygons[1].bottom_left, (306, 343));
+    }
+
+    #[test]
+    fn moving_border_corner_rotates_line_around_junction() {
+        let lines = room_type_5_lines();
+        let edit = LayoutEdit::MoveCorner {
+            wall: 0,
+            corner: CornerPosition::TopLeft,
+            to: (1, 60),
+        };
+
+        let edited_lines = edit_layout_lines(&lines, 5, edit).unwrap();
+        let polygons = compute_wall_polygons(&edited_lines, 512, 512, 5).unwrap();
+
+        assert_eq!(edited_lines[0], ((294, 167), (1, 60)));
+        // Corner is re-intersected with the border along the line through the moved corner
+        let (x, y) = polygons[0].top_left;
+        let cross = (x - 294) * (60 - 167) - (y - 167) * (1 - 294);
+        let distance_to_line = cross.abs() as f64 / (293f64).hypot(107.0);
+        assert!(distance_to_line < 1.5);
+        assert!(x <= 1);
+        assert_eq!(polygons[0].top_right, (294, 167));
+    }
+
+    #[test]
+    fn moving_line_endpoint_keeps_junction_shared() {
+        let lines = room_type_5_lines();
+        let edit = LayoutEdit::MoveLineEndpoint {
+            line: 2,
+            end: LineEnd::End,
+            to: (310, 360),
+        };
+
+        let edited_lines = edit_layout_lines(&lines, 5, edit).unwrap();
+
+        assert_eq!(edited_lines[2].1, (310, 360));
+        assert_eq!(edited_lines[3].0, (310, 360));
+        assert_eq!(edited_lines[4].0, (310, 360));
+        assert_eq!(edited_lines[0], lines[0]);
+    }
+
+    #[test]
+    fn does_not_move_corner_of_estimated_line() {
+        let lines = vec![((200, 400), (200, 100)), ((200, 400), (0, 450))];
+        let edit = LayoutEdit::MoveCorner {
+            wall: 0,
+            corner: CornerPosition::TopLeft,
+            to: (0, 30),
+        };
+
+        assert!(edit_layout_lines(&lines, 4, edit).is_none());
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use image::{GrayImage, Luma};
+use imageproc::drawing::draw_polygon_mut;
+use imageproc::point::Point as PolygonPoint;
+use serde::Serialize;
+
+use crate::layout::lsun::Point;
+use crate::layout::RoomLayout;
+use crate::polygons::WallPolygon;
+
+pub const SURFACE_LABEL_CEILING: u8 = 1;
+pub const SURFACE_LABEL_FLOOR: u8 = 2;
+/// Walls are labelled with their index plus this
+pub const SURFACE_LABEL_FIRST_WALL: u8 = 3;
+
+/// Errors of an estimated room layout compared to the ground truth layout, as in the LSUN room
+/// layout challenge.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutEvaluation {
+    /// Ground truth room type
+    pub room_type: u8,
+    pub estimated_room_type: u8,
+    /// Share of pixels labelled with a wrong surface, with surfaces matched to overlap the most
+    pub pixel_error: f32,
+    /// Mean distance between matched layout corners as a share of the image diagonal. Corners
+    /// without a match count as a whole diagonal away.
+    pub corner_error: f32,
+}
+
+/// Compares wall polygons and layout line ends of the estimated layout to the ground truth.
+/// Estimated layout is scaled to the size of the ground truth layout.
+pub fn evaluate_layout(estimated: &RoomLayout, ground_truth: &RoomLayout) -> LayoutEvaluation {
+    let (width, height) = (ground_truth.layout_width, ground_truth.layout_height);
+    let scale_x = width as f32 / estimated.layout_width as f32;
+    let scale_y = height as f32 / estimated.layout_height as f32;
+    let scale = |point: Point| {
+        (
+            (point.0 as f32 * scale_x).round() as i32,
+            (point.1 as f32 * scale_y).round() as i32,
+        )
+    };
+    let estimated_polygons: Vec<WallPolygon> = estimated
+        .wall_polygons
+        .iter()
+        .map(|polygon| WallPolygon {
+            top_left: scale(polygon.top_left),
+            top_right: scale(polygon.top_right),
+            bottom_right: scale(polygon.bottom_right),
+            bottom_left: scale(polygon.bottom_left),
+        })
+        .collect();
+    let estimated_corners: Vec<Point> = layout_corners(estimated).into_iter().map(scale).collect();
+
+    let pixel_error = pixel_error(
+        &surface_labels(&estimated_polygons, width, height),
+        &surface_labels(&ground_truth.wall_polygons, width, height),
+    );
+    let corner_error = corner_error(
+        &estimated_corners,
+        &layout_corners(ground_truth),
+        width,
+        height,
+    );
+
+    LayoutEvaluation {
+        room_type: ground_truth.room_type.into(),
+        estimated_room_type: estimated.room_type.into(),
+        pixel_error,
+        corner_error,
+    }
+}
+
+/// Labels each pixel with the surface it belongs to, see `SURFACE_LABEL_*` constants. Pixels
+/// above walls are ceiling and below are floor, and columns without walls are split in half.
+pub fn surface_labels(polygons: &[WallPolygon], width: u32, height: u32) -> GrayImage {
+    let mut labels = GrayImage::new(width, height);
+    for (wall, polygon) in polygons.iter().enumerate() {
+        let mut points: Vec<PolygonPoint<i32>> = [
+            polygon.top_left,
+            polygon.top_right,
+            polygon.bottom_right,
+            polygon.bottom_left,
+        ]
+        .iter()
+        .map(|(x, y)| PolygonPoint::new(*x, *y))
+        .collect();
+        // Polygon drawing rejects closed polygons, which degenerate walls would become
+        points.dedup();
+        if points.len() < 3 || points.first() == points.last() {
+            continue;
+        }
+        draw_polygon_mut(
+            &mut labels,
+            &points,
+            Luma([SURFACE_LABEL_FIRST_WALL + wall as u8]),
+        );
+    }
+
+    for x in 0..width {
+        let wall_rows: Vec<u32> = (0..height)
+            .filter(|y| labels.get_pixel(x, *y).0[0] >= SURFACE_LABEL_FIRST_WALL)
+            .collect();
+        let first_wall_row = wall_rows.first().copied().unwrap_or(height / 2);
+        for y in 0..height {
+            let label = labels.get_pixel_mut(x, y);
+            if label.0[0] >= SURFACE_LABEL_FIRST_WALL {
+                continue;
+            }
+            label.0[0] = if y < first_wall_row {
+                SURFACE_LABEL_CEILING
+            } else {
+                SURFACE_LABEL_FLOOR
+            };
+        }
+    }
+    labels
+}
+
+/// Share of pixels whose labels differ, after matching estimated labels to ground truth labels
+/// so that they overlap the most.
+fn pixel_error(estimated_labels: &GrayImage, ground_truth_labels: &GrayImage) -> f32 {
+    let mut overlaps: HashMap<(u8, u8), u64> = HashMap::new();
+    for (estimated, ground_truth) in estimated_labels.pixels().zip(ground_truth_labels.pixels()) {
+        *overlaps
+            .entry((ground_truth.0[0], estimated.0[0]))
+            .or_default() += 1;
+    }
+    let ground_truth_labels: Vec<u8> = overlaps
+        .keys()
+        .map(|(label, _)| *label)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let estimated_labels: Vec<u8> = overlaps
+        .keys()
+        .map(|(_, label)| *label)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let num_pixels: u64 = overlaps.values().sum();
+    if num_pixels == 0 {
+        return 0.0;
+    }
+    let matched = max_overlap(
+        &ground_truth_labels,
+        &estimated_labels,
+        &mut vec![false; estimated_labels.len()],
+        &overlaps,
+    );
+    1.0 - matched as f32 / num_pixels as f32
+}
+
+/// Largest overlap of one to one matching of the labels. There are only a few surfaces, so all
+/// the matchings are tried.
+fn max_overlap(
+    ground_truth_labels: &[u8],
+    estimated_labels: &[u8],
+    used: &mut [bool],
+    overlaps: &HashMap<(u8, u8), u64>,
+) -> u64 {
+    let Some((label, rest)) = ground_truth_labels.split_first() else {
+        return 0;
+    };
+    // Ground truth surface may have no match
+    let mut best = max_overlap(rest, estimated_labels, used, overlaps);
+    for (idx, estimated_label) in estimated_labels.iter().enumerate() {
+        if used[idx] {
+            continue;
+        }
+        let overlap = overlaps
+            .get(&(*label, *estimated_label))
+            .copied()
+            .unwrap_or(0);
+        if overlap == 0 {
+            continue;
+        }
+        used[idx] = true;
+        best = best.max(overlap + max_overlap(rest, estimated_labels, used, overlaps));
+        used[idx] = false;
+    }
+    best
+}
+
+/// Unique ends of the layout lines, which are the LSUN layout keypoints.
+fn layout_corners(layout: &RoomLayout) -> Vec<Point> {
+    let mut corners: Vec<Point> = layout
+        .lines
+        .iter()
+        .flat_map(|(start, end)| [*start, *end])
+        .collect();
+    corners.sort();
+    corners.dedup();
+    corners
+}
+
+/// Mean distance between closest pairs of corners as a share of the image diagonal.
+fn corner_error(
+    estimated_corners: &[Point],
+    ground_truth_corners: &[Point],
+    width: u32,
+    height: u32,
+) -> f32 {
+    let num_corners = estimated_corners.len().max(ground_truth_corners.len());
+    if num_corners == 0 {
+        return 0.0;
+    }
+    let diagonal = (width as f32).hypot(height as f32);
+
+    let mut pairs: Vec<(f32, usize, usize)> = vec![];
+    for (estimated_idx, estimated) in estimated_corners.iter().enumerate() {
+        for (ground_truth_idx, ground_truth) in ground_truth_corners.iter().enumerate() {
+            let distance = ((estimated.0 - ground_truth.0) as f32)
+                .hypot((estimated.1 - ground_truth.1) as f32)
+                .min(diagonal);
+            pairs.push((distance, estimated_idx, ground_truth_idx));
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut estimated_used = vec![false; estimated_corners.len()];
+    let mut ground_truth_used = vec![false; ground_truth_corners.len()];
+    let mut num_matched = 0;
+    let mut total_distance = 0.0;
+    for (distance, estimated_idx, ground_truth_idx) in pairs {
+        if estimated_used[estimated_idx] || ground_truth_used[ground_truth_idx] {
+            continue;
+        }
+        estimated_used[estimated_idx] = true;
+        ground_truth_used[ground_truth_idx] = true;
+        num_matched += 1;
+        total_distance += distance;
+    }
+    total_distance += (num_corners - num_matched) as f32 * diagonal;
+    total_distance / (num_corners as f32 * diagonal)
+}
+
+/// Mean errors over a set of evaluated layouts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorSummary {
+    pub num_layouts: usize,
+    pub pixel_error: f32,
+    pub corner_error: f32,
+    /// Share of layouts with the room type estimated right
+    pub room_type_accuracy: f32,
+}
+
+impl ErrorSummary {
+    pub fn new(evaluations: &[LayoutEvaluation]) -> Self {
+        if evaluations.is_empty() {
+            return Self::default();
+        }
+        let num_layouts = evaluations.len();
+        let mean = |error: fn(&LayoutEvaluation) -> f32| {
+            evaluations.iter().map(error).sum::<f32>() / num_layouts as f32
+        };
+        Self {
+            num_layouts,
+            pixel_error: mean(|evaluation| evaluation.pixel_error),
+            corner_error: mean(|evaluation| evaluation.corner_error),
+            room_type_accuracy: mean(|evaluation| {
+                (evaluation.room_type == evaluation.estimated_room_type) as u8 as f32
+            }),
+        }
+    }
+}
+
+/// Errors over the whole dataset and for each ground truth room type.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvaluationReport {
+    pub overall: ErrorSummary,
+    pub by_room_type: BTreeMap<u8, ErrorSummary>,
+}
+
+impl EvaluationReport {
+    pub fn new(evaluations: &[LayoutEvaluation]) -> Self {
+        let mut by_room_type: BTreeMap<u8, Vec<LayoutEvaluation>> = BTreeMap::new();
+        for evaluation in evaluations {
+            by_room_type
+                .entry(evaluation.room_type)
+                .or_default()
+                .push(*evaluation);
+        }
+        Self {
+            overall: ErrorSummary::new(evaluations),
+            by_room_type: by_room_type
+                .into_iter()
+                .map(|(room_type, evaluations)| (room_type, ErrorSummary::new(&evaluations)))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{
+        evaluate_layout, surface_labels, EvaluationReport, LayoutEvaluation, SURFACE_LABEL_CEILING,
+        SURFACE_LABEL_FIRST_WALL, SURFACE_LABEL_FLOOR,
+    };
+    use crate::layout::{RoomLayout, RoomType};
+
+    fn type_5_layout(corner_x: i32) -> RoomLayout {
+        RoomLayout::from_lines(
+            RoomType::Type5,
+            vec![
+                ((corner_x, 167), (13, 0)),
+                ((corner_x, 167), (511, 85)),
+                ((corner_x, 167), (corner_x + 12, 343)),
+                ((corner_x + 12, 343), (0, 491)),
+                ((corner_x + 12, 343), (511, 410)),
+            ],
+            512,
+            512,
+        )
+        .unwrap()
+    }
+
+    fn evaluation(room_type: u8, estimated_room_type: u8, pixel_error: f32) -> LayoutEvaluation {
+        LayoutEvaluation {
+            room_type,
+            estimated_room_type,
+            pixel_error,
+            corner_error: pixel_error / 2.0,
+        }
+    }
+
+    #[test]
+    fn labels_ceiling_walls_and_floor() {
+        let layout = type_5_layout(294);
+
+        let labels = surface_labels(&layout.wall_polygons, 512, 512);
+
+        assert_eq!(labels.get_pixel(300, 20).0[0], SURFACE_LABEL_CEILING);
+        assert_eq!(labels.get_pixel(150, 250).0[0], SURFACE_LABEL_FIRST_WALL);
+        assert_eq!(
+            labels.get_pixel(420, 250).0[0],
+            SURFACE_LABEL_FIRST_WALL + 1
+        );
+        assert_eq!(labels.get_pixel(300, 500).0[0], SURFACE_LABEL_FLOOR);
+    }
+
+    #[test]
+    fn same_layout_has_no_errors() {
+        let layout = type_5_layout(294);
+        let mut downscaled = layout.clone();
+        for polygon in &mut downscaled.wall_polygons {
+            for point in [
+                &mut polygon.top_left,
+                &mut polygon.top_right,
+                &mut polygon.bottom_right,
+                &mut polygon.bottom_left,
+            ] {
+                *point = (point.0 / 2, point.1 / 2);
+            }
+        }
+        downscaled.lines = vec![];
+        downscaled.layout_width = 256;
+        downscaled.layout_height = 256;
+
+        let evaluation = evaluate_layout(&layout, &layout);
+        let downscaled_evaluation = evaluate_layout(&downscaled, &layout);
+
+        assert_eq!(evaluation.pixel_error, 0.0);
+        assert_eq!(evaluation.corner_error, 0.0);
+        assert!(downscaled_evaluation.pixel_error < 0.01);
+        // Layout without lines has no corners to match
+        assert_eq!(downscaled_evaluation.corner_error, 1.0);
+    }
+
+    #[test]
+    fn errors_grow_with_corner_offset() {
+        let ground_truth = type_5_layout(294);
+
+        let near = evaluate_layout(&type_5_layout(284), &ground_truth);
+        let far = evaluate_layout(&type_5_layout(244), &ground_truth);
+
+        assert!(near.pixel_error > 0.0);
+        assert!(near.pixel_error < far.pixel_error);
+        // Two of the corners moved
+        let diagonal = 512.0 * 2f32.sqrt();
+        let expected_corner_error = 2.0 * 10.0 / (6.0 * diagonal);
+        assert!(
+            (near.corner_error - expected_corner_error).abs() < 1e-4,
+            "{}",
+            near.corner_error
+        );
+        assert!(near.corner_error < far.corner_error);
+    }
+
+    #[test]
+    fn summarizes_errors_by_room_type() {
+        let evaluations = [
+            evaluation(5, 5, 0.1),
+            evaluation(5, 4, 0.3),
+            evaluation(0, 0, 0.4),
+        ];
+
+        let report = EvaluationReport::new(&evaluations);
+
+        assert_eq!(report.overall.num_layouts, 3);
+        assert!((report.overall.pixel_error - 0.8 / 3.0).abs() < 1e-6);
+        assert!((report.overall.room_type_accuracy - 2.0 / 3.0).abs() < 1e-6);
+        let type_5 = report.by_room_type[&5];
+        assert_eq!(type_5.num_layouts, 2);
+        assert!((type_5.pixel_error - 0.2).abs() < 1e-6);
+        assert!((type_5.corner_error - 0.1).abs() < 1e-6);
+        assert_eq!(type_5.room_type_accuracy, 0.5);
+        assert_eq!(report.by_room_type[&0].num_layouts, 1);
+    }
+}
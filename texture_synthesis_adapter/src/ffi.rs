@@ -1,13 +1,36 @@
 // TODO: move whatever we export to Objective-C here.
 
-use crate::polygons::{compute_wall_polygons, WallPolygon};
-use crate::preview::create_preview;
+use crate::debug::{draw_layout, DrawLayoutOptions};
+use crate::depth_layout::{estimate_layout_from_depth, DepthMap};
+use crate::distortion::{create_preview_with_lens_profile, estimate_lens_profile, LensProfile};
+use crate::editing::{edit_layout_lines, CornerPosition, LayoutEdit};
+use crate::hypotheses::estimate_room_layout_hypotheses;
+use crate::layout::lsun::{parse_lsun_results, Line, Point};
+use crate::layout::{RoomLayout, RoomType};
+use crate::mask_layout::{estimate_wall_polygons_from_mask, lsun_layout_from_polygons};
+use crate::material::WallpaperMaterial;
+use crate::mesh_export::{build_room_mesh, MeshOptions};
+use crate::object_removal::{detect_enclosed_objects, remove_objects};
+use crate::openings::{
+    cut_openings, estimate_wallpaper_area, opening_corners, snap_opening, OpeningOutline,
+    WallOpening, WallPlaneRect,
+};
+use crate::panorama::{create_panorama_preview, PanoramaCorner};
+use crate::polygons::{compute_wall_polygons, LineEnd, WallPolygon};
+use crate::preview::{create_preview, create_preview_with_memory_budget};
+use crate::reconstruction::reconstruct_room;
+use crate::refinement::refine_layout_lines;
+use crate::tracking::LayoutTracker;
+use crate::validation::{validate_layout, LayoutIssue, LayoutValidation};
+use crate::wall_maps::compute_wall_maps;
 use crate::{polygons, GeneratorProgressLogger};
-use image::{DynamicImage, GrayImage, RgbImage, Rgba, RgbaImage};
-use lsun_res_parser::{parse_lsun_results, Point, RoomLayoutInfo};
+use image::{DynamicImage, GrayImage, ImageBuffer, RgbImage, Rgba, RgbaImage};
+use imageproc::geometric_transformations::Projection;
 use ndarray::{Array2, Array3, Axis, ShapeBuilder};
 use ndarray_stats::QuantileExt;
-use std::os::raw::c_int;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
 use std::{mem, ptr, slice};
 use texture_synthesis as ts;
 use texture_synthesis::session::{GeneratorProgress, ProgressUpdate};
@@ -38,6 +61,22 @@ impl From<RgbaImage> for ImageInfo {
     }
 }
 
+impl From<GrayImage> for ImageInfo {
+    fn from(image: GrayImage) -> Self {
+        let width = image.width() as usize;
+        let height = image.height() as usize;
+        let buffer = image.into_raw().into_boxed_slice();
+        let count = buffer.len();
+        let data = Box::into_raw(buffer) as *const u8;
+        Self {
+            data,
+            count,
+            width,
+            height,
+        }
+    }
+}
+
 impl ImageInfo {
     pub fn rgba_image(&self) -> RgbaImage {
         let data_slice = unsafe { slice::from_raw_parts(self.data, self.count) };
@@ -68,8 +107,11 @@ pub struct MLMultiArray2DInfo {
     pub strides: [usize; 2],
 }
 
+/// Maximum number of room layout hypotheses returned at once
+pub const MAX_ROOM_LAYOUT_HYPOTHESES: usize = 3;
+
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct RoomLayoutData {
     /// Identified room layout lines
     pub lines: [LayoutLine; 8],
@@ -81,6 +123,41 @@ pub struct RoomLayoutData {
     pub wall_polygons: [LayoutWallPolygon; 3],
     /// Indicates how many actual wall polygons are stored in [polygons] (at most 3)
     pub num_wall_polygons: u8,
+    /// Width of the layout estimation output, which all the coordinates are relative to
+    pub layout_width: u32,
+    /// Height of the layout estimation output, which all the coordinates are relative to
+    pub layout_height: u32,
+    /// How much the wall polygons can be trusted, from 0 (garbage) to 1
+    pub confidence: f32,
+    /// Bit flags of problems found in wall polygons, see `LAYOUT_ISSUE_*` constants
+    pub issues: u32,
+}
+
+/// Most probable room layouts, best first.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RoomLayoutHypotheses {
+    pub layouts: [RoomLayoutData; MAX_ROOM_LAYOUT_HYPOTHESES],
+    /// Probabilities of room types of [layouts]
+    pub probabilities: [f32; MAX_ROOM_LAYOUT_HYPOTHESES],
+    /// Indicates how many actual layouts are stored in [layouts]
+    pub num_layouts: u8,
+}
+
+impl RoomLayoutData {
+    pub fn lines(&self) -> Vec<Line> {
+        self.lines[..self.num_lines as usize]
+            .iter()
+            .map(|line| (line.start.to_point(), line.end.to_point()))
+            .collect()
+    }
+
+    pub fn wall_polygons(&self) -> Vec<WallPolygon> {
+        self.wall_polygons[..self.num_wall_polygons as usize]
+            .iter()
+            .map(|polygon| WallPolygon::from(*polygon))
+            .collect()
+    }
 }
 
 #[repr(C)]
@@ -112,6 +189,162 @@ pub struct LayoutWallPolygon {
     pub bottom_left: LayoutPoint,
 }
 
+/// Door, window or other opening in the wall, given either by its corners on the room image or
+/// by its rectangle on the wall plane.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct WallOpeningData {
+    /// Index of the wall polygon the opening is in
+    pub wall_index: u8,
+    /// Whether the opening is given by [wall_plane_rect] rather than [image_quad]
+    pub is_in_wall_plane: bool,
+    /// Corners of the opening in layout space
+    pub image_quad: LayoutWallPolygon,
+    /// Left, top, width and height of the opening in meters from the top left wall corner
+    pub wall_plane_rect: [f32; 4],
+}
+
+impl From<WallOpeningData> for WallOpening {
+    fn from(opening: WallOpeningData) -> Self {
+        let outline = if opening.is_in_wall_plane {
+            let [left, top, width, height] = opening.wall_plane_rect;
+            OpeningOutline::WallPlane(WallPlaneRect {
+                left,
+                top,
+                width,
+                height,
+            })
+        } else {
+            let quad = opening.image_quad;
+            OpeningOutline::Image(
+                [
+                    quad.top_left,
+                    quad.top_right,
+                    quad.bottom_right,
+                    quad.bottom_left,
+                ]
+                .map(|point| (point.x as f32, point.y as f32)),
+            )
+        };
+        Self {
+            wall: opening.wall_index as usize,
+            outline,
+        }
+    }
+}
+
+/// Wallpaper area of the walls, in square meters.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct WallpaperAreaData {
+    pub walls: f32,
+    pub openings: f32,
+    /// Walls less openings
+    pub net: f32,
+}
+
+/// Room corner of equirectangular panorama in HorizonNet format, in panorama pixels.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PanoramaCornerData {
+    /// Column of the corner
+    pub x: f32,
+    /// Row where the wall meets the ceiling
+    pub ceiling_y: f32,
+    /// Row where the wall meets the floor
+    pub floor_y: f32,
+}
+
+impl From<PanoramaCornerData> for PanoramaCorner {
+    fn from(corner: PanoramaCornerData) -> Self {
+        Self {
+            x: corner.x,
+            ceiling_y: corner.ceiling_y,
+            floor_y: corner.floor_y,
+        }
+    }
+}
+
+/// Depth map of the room photo in meters along the optical axis, row by row, 0 where unknown.
+#[repr(C)]
+pub struct DepthMapInfo {
+    pub data: *const f32,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Room layout estimated from the depth map, with true wall sizes.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct DepthRoomLayoutData {
+    pub layout: RoomLayoutData,
+    /// Width of each wall polygon in meters
+    pub wall_widths: [f32; 3],
+    /// Height of each wall polygon in meters
+    pub wall_heights: [f32; 3],
+}
+
+impl DepthRoomLayoutData {
+    pub fn wall_sizes(&self) -> Vec<(f32, f32)> {
+        let num_walls = self.layout.num_wall_polygons as usize;
+        self.wall_widths[..num_walls]
+            .iter()
+            .zip(&self.wall_heights[..num_walls])
+            .map(|(width, height)| (*width, *height))
+            .collect()
+    }
+}
+
+/// Room layout smoothed over the frames of the camera view or video.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TrackedRoomLayoutData {
+    pub layout: RoomLayoutData,
+    /// Id of each wall polygon, which stays the same across frames
+    pub wall_ids: [u32; 3],
+}
+
+/// Radial lens profile, see [LensProfile]. `k2` is ignored by the division model.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct LensProfileData {
+    pub is_division_model: bool,
+    pub k1: f32,
+    pub k2: f32,
+}
+
+impl From<LensProfileData> for LensProfile {
+    fn from(profile: LensProfileData) -> Self {
+        if profile.is_division_model {
+            LensProfile::Division {
+                k1: profile.k1 as f64,
+            }
+        } else {
+            LensProfile::BrownConrady {
+                k1: profile.k1 as f64,
+                k2: profile.k2 as f64,
+            }
+        }
+    }
+}
+
+impl From<LensProfile> for LensProfileData {
+    fn from(profile: LensProfile) -> Self {
+        match profile {
+            LensProfile::BrownConrady { k1, k2 } => Self {
+                is_division_model: false,
+                k1: k1 as f32,
+                k2: k2 as f32,
+            },
+            LensProfile::Division { k1 } => Self {
+                is_division_model: true,
+                k1: k1 as f32,
+                k2: 0.0,
+            },
+        }
+    }
+}
+
 impl MLMultiArray2DInfo {
     pub fn array(&self) -> Array2<f32> {
         let shape = (self.shape[0], self.shape[1]).strides((self.strides[0], self.strides[1]));
@@ -180,12 +413,483 @@ pub unsafe extern "C" fn generate_preview(
     let room_image = DynamicImage::from(room_image).into_rgb8();
     let wallpaper_tile_image = DynamicImage::from(wallpaper_tile_image).into_rgb8();
 
-    let preview_image = create_preview(room_image, wall_mask_image, wallpaper_tile_image, polygons);
+    let preview_image = create_preview(
+        room_image,
+        wall_mask_image,
+        wallpaper_tile_image.into(),
+        polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+    );
     let preview_image = DynamicImage::from(preview_image).into_rgba8();
 
     ImageInfo::from(preview_image)
 }
 
+/// Same as [generate_preview], but keeps memory used by preview compositing within
+/// `memory_budget` bytes. Preview image is downscaled if it does not fit into the budget.
+#[no_mangle]
+pub unsafe extern "C" fn generate_preview_with_memory_budget(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+    wallpaper_tile_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+    memory_budget: usize,
+) -> ImageInfo {
+    let room_image = ptr::read(room_image).rgba_image();
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let wallpaper_tile_image = ptr::read(wallpaper_tile_image).rgba_image();
+
+    let polygons: Vec<WallPolygon> = (0..room_layout.num_wall_polygons)
+        .map(|i| room_layout.wall_polygons[i as usize].into())
+        .collect();
+
+    let room_image = DynamicImage::from(room_image).into_rgb8();
+    let wallpaper_tile_image = DynamicImage::from(wallpaper_tile_image).into_rgb8();
+
+    let preview_image = create_preview_with_memory_budget(
+        room_image,
+        wall_mask_image,
+        wallpaper_tile_image.into(),
+        polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+        memory_budget,
+    );
+    let preview_image = DynamicImage::from(preview_image).into_rgba8();
+
+    ImageInfo::from(preview_image)
+}
+
+/// Same as [generate_preview], but also renders relief of embossed, vinyl or grasscloth
+/// wallpapers. `relief_image` is either tangent space normal map or height map (when
+/// `relief_is_height_map` is `true`), which tiles the same way as the wallpaper tile.
+#[no_mangle]
+pub unsafe extern "C" fn generate_preview_with_relief(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+    wallpaper_tile_image: *const ImageInfo,
+    relief_image: *const ImageInfo,
+    relief_is_height_map: bool,
+    relief_strength: f32,
+    room_layout: RoomLayoutData,
+) -> ImageInfo {
+    let room_image = ptr::read(room_image).rgba_image();
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let wallpaper_tile_image = ptr::read(wallpaper_tile_image).rgba_image();
+    let relief_image = ptr::read(relief_image).rgba_image();
+
+    let polygons: Vec<WallPolygon> = (0..room_layout.num_wall_polygons)
+        .map(|i| room_layout.wall_polygons[i as usize].into())
+        .collect();
+
+    let room_image = DynamicImage::from(room_image).into_rgb8();
+    let wallpaper_tile_image = DynamicImage::from(wallpaper_tile_image).into_rgb8();
+    let material = if relief_is_height_map {
+        let height_map = DynamicImage::from(relief_image).into_luma8();
+        WallpaperMaterial::with_height_map(wallpaper_tile_image, height_map, relief_strength)
+    } else {
+        let normal_map = DynamicImage::from(relief_image).into_rgb8();
+        WallpaperMaterial::with_normal_map(wallpaper_tile_image, normal_map, relief_strength)
+    };
+
+    let preview_image = create_preview(
+        room_image,
+        wall_mask_image,
+        material,
+        polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+    );
+    let preview_image = DynamicImage::from(preview_image).into_rgba8();
+
+    ImageInfo::from(preview_image)
+}
+
+/// Same as [generate_preview], but removes wall-mounted objects before applying wallpaper,
+/// so that they get wallpapered too.
+///
+/// If `removal_mask_image` is `null`, non-wall regions fully enclosed by wall are removed.
+/// Otherwise, white pixels of the mask mark regions to remove.
+///
+/// # Safety `removal_mask_image` must be either `null` or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn generate_preview_with_object_removal(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+    wallpaper_tile_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+    removal_mask_image: *const ImageInfo,
+) -> ImageInfo {
+    let room_image = ptr::read(room_image).rgba_image();
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let wallpaper_tile_image = ptr::read(wallpaper_tile_image).rgba_image();
+
+    let removal_mask_image = if removal_mask_image.is_null() {
+        detect_enclosed_objects(&wall_mask_image)
+    } else {
+        ptr::read(removal_mask_image).gray_image()
+    };
+
+    let polygons: Vec<WallPolygon> = (0..room_layout.num_wall_polygons)
+        .map(|i| room_layout.wall_polygons[i as usize].into())
+        .collect();
+
+    let room_image = DynamicImage::from(room_image).into_rgb8();
+    let wallpaper_tile_image = DynamicImage::from(wallpaper_tile_image).into_rgb8();
+
+    let (room_image, wall_mask_image) =
+        remove_objects(&room_image, &wall_mask_image, &removal_mask_image);
+
+    let preview_image = create_preview(
+        room_image,
+        wall_mask_image,
+        wallpaper_tile_image.into(),
+        polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+    );
+    let preview_image = DynamicImage::from(preview_image).into_rgba8();
+
+    ImageInfo::from(preview_image)
+}
+
+/// Exports wall UV, wall id, coverage and shading maps along with JSON descriptor into
+/// `output_dir`. Returns `true` on success.
+///
+/// # Safety `output_dir` must be a valid null-terminated UTF-8 string
+#[no_mangle]
+pub unsafe extern "C" fn export_wall_maps(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+    output_dir: *const c_char,
+) -> bool {
+    let room_image = ptr::read(room_image).rgba_image();
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let output_dir = match CStr::from_ptr(output_dir).to_str() {
+        Ok(output_dir) => output_dir,
+        Err(e) => {
+            println!("Invalid output directory: {e}");
+            return false;
+        }
+    };
+
+    let polygons: Vec<WallPolygon> = (0..room_layout.num_wall_polygons)
+        .map(|i| room_layout.wall_polygons[i as usize].into())
+        .collect();
+
+    let room_image = DynamicImage::from(room_image).into_rgb8();
+    let wall_maps = compute_wall_maps(
+        &room_image,
+        &wall_mask_image,
+        polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+    );
+
+    match wall_maps.save(Path::new(output_dir)) {
+        Ok(_) => true,
+        Err(e) => {
+            println!("Could not export wall maps: {e}");
+            false
+        }
+    }
+}
+
+/// Exports reconstructed walls, floor and ceiling as OBJ mesh with MTL materials into
+/// `output_dir`. Surfaces are textured with the wallpaper tile at its physical size, assuming
+/// camera is `camera_height_meters` above the floor. Visible floor and ceiling are textured with
+/// the room photo if `project_photo` is `true`. Returns `true` on success.
+///
+/// # Safety `room_image` and `wallpaper_tile_image` must not be `null`, `output_dir` must be a
+/// valid null-terminated UTF-8 string
+#[no_mangle]
+pub unsafe extern "C" fn export_room_mesh(
+    room_image: *const ImageInfo,
+    wallpaper_tile_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+    camera_height_meters: f32,
+    project_photo: bool,
+    output_dir: *const c_char,
+) -> bool {
+    let output_dir = match CStr::from_ptr(output_dir).to_str() {
+        Ok(output_dir) => output_dir,
+        Err(e) => {
+            println!("Invalid output directory: {e}");
+            return false;
+        }
+    };
+    let Ok(room_type) = RoomType::try_from(room_layout.room_type) else {
+        println!("Unknown room type: {}", room_layout.room_type);
+        return false;
+    };
+    let room_image = DynamicImage::from(ptr::read(room_image).rgba_image()).into_rgb8();
+    let wallpaper_tile_image =
+        DynamicImage::from(ptr::read(wallpaper_tile_image).rgba_image()).into_rgb8();
+
+    let polygons = room_layout.wall_polygons();
+    let room = reconstruct_room(
+        &polygons,
+        room_type,
+        room_layout.layout_width,
+        room_layout.layout_height,
+    );
+    let options = MeshOptions {
+        camera_height_meters,
+        project_photo,
+    };
+    let mesh = build_room_mesh(
+        &room,
+        &polygons,
+        room_type,
+        room_layout.layout_width,
+        room_layout.layout_height,
+        options,
+    );
+
+    let wallpaper = WallpaperMaterial::from(wallpaper_tile_image);
+    match mesh.save_obj(Path::new(output_dir), &wallpaper, Some(&room_image)) {
+        Ok(_) => true,
+        Err(e) => {
+            println!("Could not export room mesh: {e}");
+            false
+        }
+    }
+}
+
+/// Creates equirectangular preview of the 360° room panorama, with wallpaper continuing around
+/// all the walls given by `corners`. `wall_mask_image` limits wallpaper to wall pixels, unless it
+/// is `null`. Panorama is returned unchanged if the corners do not describe a room.
+///
+/// # Safety `room_image` and `wallpaper_tile_image` must not be `null`, `wall_mask_image` must be
+/// either `null` or a valid pointer, `corners` must point to `num_corners` corners
+#[no_mangle]
+pub unsafe extern "C" fn generate_panorama_preview(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+    wallpaper_tile_image: *const ImageInfo,
+    corners: *const PanoramaCornerData,
+    num_corners: usize,
+) -> ImageInfo {
+    let room_image = DynamicImage::from(ptr::read(room_image).rgba_image()).into_rgb8();
+    let wall_mask_image = if wall_mask_image.is_null() {
+        None
+    } else {
+        Some(ptr::read(wall_mask_image).gray_image())
+    };
+    let wallpaper_tile_image =
+        DynamicImage::from(ptr::read(wallpaper_tile_image).rgba_image()).into_rgb8();
+    let corners: Vec<PanoramaCorner> = if corners.is_null() {
+        vec![]
+    } else {
+        slice::from_raw_parts(corners, num_corners)
+            .iter()
+            .map(|corner| PanoramaCorner::from(*corner))
+            .collect()
+    };
+
+    let preview_image = create_panorama_preview(
+        room_image,
+        wall_mask_image.as_ref(),
+        &wallpaper_tile_image.into(),
+        &corners,
+    );
+    let preview_image = DynamicImage::from(preview_image).into_rgba8();
+
+    ImageInfo::from(preview_image)
+}
+
+/// Estimates lens distortion of the room image from the curvature of edges along the layout
+/// lines. Returns profile without distortion if the edges are straight.
+///
+/// # Safety `room_image` must not be `null`
+#[no_mangle]
+pub unsafe extern "C" fn estimate_room_lens_profile(
+    room_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+) -> LensProfileData {
+    let room_image = DynamicImage::from(ptr::read(room_image).rgba_image()).into_luma8();
+
+    estimate_lens_profile(
+        &room_image,
+        &room_layout.lines(),
+        room_layout.layout_width,
+        room_layout.layout_height,
+    )
+    .unwrap_or(LensProfile::Division { k1: 0.0 })
+    .into()
+}
+
+/// Same as [generate_preview], but wallpaper is applied to the room image undistorted with
+/// `lens_profile`, and then distorted back onto the original room image.
+///
+/// # Safety `room_image`, `wall_mask_image` and `wallpaper_tile_image` must not be `null`
+#[no_mangle]
+pub unsafe extern "C" fn generate_preview_with_lens_profile(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+    wallpaper_tile_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+    lens_profile: LensProfileData,
+) -> ImageInfo {
+    let room_image = DynamicImage::from(ptr::read(room_image).rgba_image()).into_rgb8();
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let wallpaper_tile_image =
+        DynamicImage::from(ptr::read(wallpaper_tile_image).rgba_image()).into_rgb8();
+
+    let preview_image = create_preview_with_lens_profile(
+        room_image,
+        wall_mask_image,
+        wallpaper_tile_image.into(),
+        &room_layout.lines(),
+        room_layout.room_type,
+        room_layout.layout_width,
+        room_layout.layout_height,
+        lens_profile.into(),
+    );
+    let preview_image = DynamicImage::from(preview_image).into_rgba8();
+
+    ImageInfo::from(preview_image)
+}
+
+/// Draws the room layout over the room image for an in-app debug screen: walls with their
+/// indices, layout lines, labelled corners, room type and confidence. Room image is padded so that
+/// corners outside of it are visible.
+///
+/// # Safety `room_image` must not be `null`
+#[no_mangle]
+pub unsafe extern "C" fn generate_layout_debug_image(
+    room_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+) -> ImageInfo {
+    let room_image = DynamicImage::from(ptr::read(room_image).rgba_image()).into_rgb8();
+    let room_type = match RoomType::try_from(room_layout.room_type) {
+        Ok(room_type) => room_type,
+        Err(e) => {
+            println!("Could not draw room layout: {e}");
+            return ImageInfo::from(DynamicImage::from(room_image).into_rgba8());
+        }
+    };
+    let layout = RoomLayout {
+        room_type,
+        lines: room_layout.lines(),
+        wall_polygons: room_layout.wall_polygons(),
+        layout_width: room_layout.layout_width,
+        layout_height: room_layout.layout_height,
+        confidence: Some(room_layout.confidence),
+        issues: Some(room_layout.issues),
+    };
+
+    let debug_image = draw_layout(&room_image, &layout, &DrawLayoutOptions::default());
+    let debug_image = DynamicImage::from(debug_image).into_rgba8();
+
+    ImageInfo::from(debug_image)
+}
+
+/// Creates tracker of room layouts estimated for the frames of the camera view or video. It must
+/// be released with [release_layout_tracker].
+#[no_mangle]
+pub extern "C" fn create_layout_tracker() -> *mut LayoutTracker {
+    Box::into_raw(Box::default())
+}
+
+/// # Safety `tracker` must be created with [create_layout_tracker] and not used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn release_layout_tracker(tracker: *mut LayoutTracker) {
+    if !tracker.is_null() {
+        let _ = Box::from_raw(tracker);
+    }
+}
+
+/// Adds room layout of the next frame to the tracker and returns the smoothed layout. Room type
+/// and lines describe the smoothed wall polygons. `homography` is either `null` or 3x3 row-major
+/// matrix mapping layout space of the previous frame onto layout space of this one. Layout is
+/// returned untracked if `tracker` is `null`.
+///
+/// # Safety `tracker` must be either `null` or created with [create_layout_tracker], `homography`
+/// must be either `null` or point to 9 values
+#[no_mangle]
+pub unsafe extern "C" fn track_room_layout(
+    tracker: *mut LayoutTracker,
+    room_layout: RoomLayoutData,
+    homography: *const f32,
+) -> TrackedRoomLayoutData {
+    if tracker.is_null() {
+        println!("Can not track room layout without a tracker");
+        return TrackedRoomLayoutData {
+            layout: room_layout,
+            ..Default::default()
+        };
+    }
+    let tracker = &mut *tracker;
+    let room_type = match RoomType::try_from(room_layout.room_type) {
+        Ok(room_type) => room_type,
+        Err(e) => {
+            println!("Could not track room layout: {e}");
+            return TrackedRoomLayoutData {
+                layout: room_layout,
+                ..Default::default()
+            };
+        }
+    };
+    let homography = if homography.is_null() {
+        None
+    } else {
+        let matrix = slice::from_raw_parts(homography, 9).try_into().unwrap();
+        let homography = Projection::from_matrix(matrix);
+        if homography.is_none() {
+            println!("Ignoring singular homography: {matrix:?}");
+        }
+        homography
+    };
+    let layout = RoomLayout {
+        room_type,
+        lines: room_layout.lines(),
+        wall_polygons: room_layout.wall_polygons(),
+        layout_width: room_layout.layout_width,
+        layout_height: room_layout.layout_height,
+        confidence: None,
+        issues: None,
+    };
+
+    let tracked_layout = tracker.update(&layout, homography.as_ref());
+    let Some(layout) = tracked_layout.room_layout() else {
+        return TrackedRoomLayoutData {
+            layout: RoomLayoutData {
+                layout_width: tracked_layout.layout_width,
+                layout_height: tracked_layout.layout_height,
+                confidence: 0.0,
+                issues: LayoutIssue::NoWalls.flag(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+    };
+
+    let validation = validate_layout(
+        &layout.wall_polygons,
+        layout.layout_width,
+        layout.layout_height,
+        None,
+    );
+    let mut tracked_room_layout = TrackedRoomLayoutData {
+        layout: room_layout_data(
+            &layout.lines,
+            layout.room_type.into(),
+            &layout.wall_polygons,
+            layout.layout_width,
+            layout.layout_height,
+            &validation,
+        ),
+        ..Default::default()
+    };
+    for (idx, wall) in tracked_layout.walls.iter().enumerate() {
+        tracked_room_layout.wall_ids[idx] = wall.id;
+    }
+    tracked_room_layout
+}
+
 #[no_mangle]
 pub extern "C" fn synthesize_texture(
     sample_info: *const ImageInfo,
@@ -317,6 +1021,9 @@ pub extern "C" fn shipping_rust_addition(a: c_int, b: c_int) -> c_int {
     a + b
 }
 
+/// Parses room layout estimation results. Returns a layout without walls, with zero confidence
+/// and [LAYOUT_ISSUE_NO_WALLS] issue, if results can not be parsed.
+///
 /// # Safety `results` must not be `null`
 #[no_mangle]
 pub unsafe extern "C" fn process_room_layout_estimation_results(
@@ -334,6 +1041,7 @@ pub unsafe extern "C" fn process_room_layout_estimation_results(
 
     let edges_array = results_ref.edges.array();
     println!("Edges array shape: {:?}", edges_array.shape());
+    let (layout_width, layout_height) = layout_size(&edges_array);
 
     let corners_array = results_ref.corners.array();
     println!("Corners array shape: {:?}", corners_array.shape());
@@ -341,21 +1049,402 @@ pub unsafe extern "C" fn process_room_layout_estimation_results(
     let corners_flip_array = results_ref.corners_flip.array();
     println!("Flipped array shape: {:?}", corners_flip_array.shape());
 
-    let parse_result =
-        parse_lsun_results(edges_array, corners_array, corners_flip_array, type_array).unwrap();
+    let parse_result = match parse_lsun_results(
+        edges_array,
+        corners_array.clone(),
+        corners_flip_array,
+        type_array,
+    ) {
+        Ok(parse_result) => parse_result,
+        Err(e) => {
+            println!("Could not parse room layout estimation results: {e:?}");
+            return RoomLayoutData {
+                layout_width,
+                layout_height,
+                confidence: 0.0,
+                issues: LayoutIssue::NoWalls.flag(),
+                ..Default::default()
+            };
+        }
+    };
     println!("Parse result: {parse_result:?}");
 
     // TODO: extract polygons from parse_result
 
+    // Validation reports layouts without walls
     let polygons = compute_wall_polygons(
         &parse_result.lines,
-        // TODO: provide from function args
-        512,
-        // TODO: provide from function args
-        512,
+        layout_width as i32,
+        layout_height as i32,
+        parse_result.room_type,
+    )
+    .unwrap_or_default();
+
+    let validation = validate_layout(
+        &polygons,
+        layout_width,
+        layout_height,
+        Some(&corners_array),
+    );
+    println!(
+        "Layout confidence: {}, issues: {:?}",
+        validation.confidence, validation.issues
+    );
+
+    room_layout_data(
+        &parse_result.lines,
         parse_result.room_type,
+        &polygons,
+        layout_width,
+        layout_height,
+        &validation,
+    )
+}
+
+/// Same as [process_room_layout_estimation_results], but parses results as each of the most
+/// probable room types and returns at most `k` (up to [MAX_ROOM_LAYOUT_HYPOTHESES]) layouts,
+/// ranked by room type probability and geometric validity.
+///
+/// # Safety `results` must not be `null`
+#[no_mangle]
+pub unsafe extern "C" fn process_room_layout_estimation_results_top_k(
+    results: *const RoomLayoutEstimationResults,
+    k: u8,
+) -> RoomLayoutHypotheses {
+    let results_ref = unsafe { &*results };
+
+    let edges_array = results_ref.edges.array();
+    let (layout_width, layout_height) = layout_size(&edges_array);
+    let hypotheses = estimate_room_layout_hypotheses(
+        &edges_array,
+        &results_ref.corners.array(),
+        &results_ref.corners_flip.array(),
+        &results_ref.type_.array(),
+        layout_width,
+        layout_height,
+        usize::min(k as usize, MAX_ROOM_LAYOUT_HYPOTHESES),
+    );
+
+    let mut room_layout_hypotheses = RoomLayoutHypotheses {
+        layouts: Default::default(),
+        probabilities: Default::default(),
+        num_layouts: hypotheses.len() as u8,
+    };
+    for (idx, hypothesis) in hypotheses.iter().enumerate() {
+        println!(
+            "Room type {} hypothesis: probability {}, confidence {}",
+            hypothesis.room_type, hypothesis.probability, hypothesis.validation.confidence
+        );
+        room_layout_hypotheses.layouts[idx] = room_layout_data(
+            &hypothesis.lines,
+            hypothesis.room_type,
+            &hypothesis.polygons,
+            layout_width,
+            layout_height,
+            &hypothesis.validation,
+        );
+        room_layout_hypotheses.probabilities[idx] = hypothesis.probability;
+    }
+    room_layout_hypotheses
+}
+
+/// Estimates room layout from the wall segmentation mask and the room photo, when layout
+/// estimation results can not be parsed. Layout size is the mask size.
+///
+/// # Safety `room_image` and `wall_mask_image` must not be `null`
+#[no_mangle]
+pub unsafe extern "C" fn estimate_room_layout_from_mask(
+    room_image: *const ImageInfo,
+    wall_mask_image: *const ImageInfo,
+) -> RoomLayoutData {
+    let room_image = ptr::read(room_image).rgba_image();
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let room_image = DynamicImage::from(room_image).into_rgb8();
+    let (layout_width, layout_height) = wall_mask_image.dimensions();
+
+    let polygons = estimate_wall_polygons_from_mask(&wall_mask_image, Some(&room_image));
+    let Some((room_type, lines)) = lsun_layout_from_polygons(&polygons) else {
+        println!("Could not estimate room layout from the wall mask");
+        return RoomLayoutData {
+            layout_width,
+            layout_height,
+            confidence: 0.0,
+            issues: LayoutIssue::NoWalls.flag(),
+            ..Default::default()
+        };
+    };
+    println!("Estimated room type {room_type} layout from the wall mask: {polygons:?}");
+
+    let validation = validate_layout(&polygons, layout_width, layout_height, None);
+    room_layout_data(
+        &lines,
+        room_type,
+        &polygons,
+        layout_width,
+        layout_height,
+        &validation,
+    )
+}
+
+/// Estimates room layout by fitting wall planes to the depth map within the wall mask, instead of
+/// parsing layout estimation results. Depth map must have the same field of view as the room
+/// photo, and layout size is the depth map size.
+///
+/// # Safety `wall_mask_image` must not be `null`, `depth_map` data must hold `width * height`
+/// values
+#[no_mangle]
+pub unsafe extern "C" fn estimate_room_layout_from_depth(
+    wall_mask_image: *const ImageInfo,
+    depth_map: DepthMapInfo,
+    horizontal_fov_degrees: f32,
+) -> DepthRoomLayoutData {
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let (layout_width, layout_height) = (depth_map.width as u32, depth_map.height as u32);
+    let depth = slice::from_raw_parts(depth_map.data, depth_map.width * depth_map.height);
+    let depth = ImageBuffer::from_raw(layout_width, layout_height, depth.to_vec()).unwrap();
+    let depth_map = DepthMap::new(depth, horizontal_fov_degrees as f64);
+
+    let no_walls_layout = DepthRoomLayoutData {
+        layout: RoomLayoutData {
+            layout_width,
+            layout_height,
+            confidence: 0.0,
+            issues: LayoutIssue::NoWalls.flag(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let Some(depth_layout) = estimate_layout_from_depth(&depth_map, &wall_mask_image) else {
+        return no_walls_layout;
+    };
+    let polygons = depth_layout.polygons;
+    let Some((room_type, lines)) = lsun_layout_from_polygons(&polygons) else {
+        println!("Could not convert depth layout to LSUN layout: {polygons:?}");
+        return no_walls_layout;
+    };
+    println!("Estimated room type {room_type} layout from the depth map: {polygons:?}");
+
+    let validation = validate_layout(&polygons, layout_width, layout_height, None);
+    let mut depth_room_layout = DepthRoomLayoutData {
+        layout: room_layout_data(
+            &lines,
+            room_type,
+            &polygons,
+            layout_width,
+            layout_height,
+            &validation,
+        ),
+        ..Default::default()
+    };
+    for (idx, (width, height)) in depth_layout.wall_sizes.into_iter().enumerate() {
+        depth_room_layout.wall_widths[idx] = width;
+        depth_room_layout.wall_heights[idx] = height;
+    }
+    depth_room_layout
+}
+
+/// Snaps layout lines to edges of the full resolution room image and recomputes the wall
+/// polygons. Returned layout size is the room image size. Layout is returned unchanged if its
+/// room type is unknown or it has fewer lines than the room type needs.
+///
+/// # Safety `room_image` must not be `null`
+#[no_mangle]
+pub unsafe extern "C" fn refine_room_layout(
+    room_image: *const ImageInfo,
+    room_layout: RoomLayoutData,
+) -> RoomLayoutData {
+    let lines = room_layout.lines();
+    let room_image = ptr::read(room_image).rgba_image();
+    let room_image = DynamicImage::from(room_image).into_luma8();
+    let (layout_width, layout_height) = room_image.dimensions();
+    let refined_lines = refine_layout_lines(
+        &room_image,
+        &lines,
+        room_layout.room_type,
+        room_layout.layout_width,
+        room_layout.layout_height,
     );
+    println!("Refined layout lines: {refined_lines:?}");
+
+    let Some(polygons) = compute_wall_polygons(
+        &refined_lines,
+        layout_width as i32,
+        layout_height as i32,
+        room_layout.room_type,
+    ) else {
+        println!(
+            "Can not refine layout of room type {}",
+            room_layout.room_type
+        );
+        return room_layout;
+    };
+    let validation = validate_layout(&polygons, layout_width, layout_height, None);
+    room_layout_data(
+        &refined_lines,
+        room_layout.room_type,
+        &polygons,
+        layout_width,
+        layout_height,
+        &validation,
+    )
+}
 
+/// Snaps opening drawn on the room image to the perspective of its wall. Both [image_quad] and
+/// [wall_plane_rect] of the returned opening describe the snapped opening, which is clipped to
+/// the wall. Wall sizes are taken from the layout estimated by [estimate_room_layout_from_depth].
+/// Opening is returned unchanged if it is outside of its wall.
+#[no_mangle]
+pub extern "C" fn snap_wall_opening(
+    room_layout: DepthRoomLayoutData,
+    opening: WallOpeningData,
+) -> WallOpeningData {
+    let polygons = room_layout.layout.wall_polygons();
+    let wall_sizes = room_layout.wall_sizes();
+    let wall_opening = WallOpening::from(opening);
+    let (Some(rect), Some(corners)) = (
+        snap_opening(&polygons, &wall_sizes, &wall_opening),
+        opening_corners(&polygons, &wall_sizes, &wall_opening),
+    ) else {
+        println!("Could not snap opening: {opening:?}");
+        return opening;
+    };
+
+    let point = |(x, y): (f32, f32)| LayoutPoint {
+        x: x.round() as i32,
+        y: y.round() as i32,
+    };
+    WallOpeningData {
+        image_quad: LayoutWallPolygon {
+            top_left: point(corners[0]),
+            top_right: point(corners[1]),
+            bottom_right: point(corners[2]),
+            bottom_left: point(corners[3]),
+        },
+        wall_plane_rect: [rect.left, rect.top, rect.width, rect.height],
+        ..opening
+    }
+}
+
+/// Removes wall openings from the wall mask, so that previews generated with the returned mask
+/// never cover them with wallpaper. Returned image is grayscale, same as the given mask. Wall
+/// sizes are taken from the layout estimated by [estimate_room_layout_from_depth].
+///
+/// # Safety `wall_mask_image` must not be `null`, `openings` must point to `num_openings`
+/// openings
+#[no_mangle]
+pub unsafe extern "C" fn cut_wall_openings(
+    wall_mask_image: *const ImageInfo,
+    room_layout: DepthRoomLayoutData,
+    openings: *const WallOpeningData,
+    num_openings: usize,
+) -> ImageInfo {
+    let wall_mask_image = ptr::read(wall_mask_image).gray_image();
+    let openings = wall_openings(openings, num_openings);
+
+    let wall_mask_image = cut_openings(
+        &wall_mask_image,
+        &room_layout.layout.wall_polygons(),
+        &room_layout.wall_sizes(),
+        &openings,
+        room_layout.layout.layout_width,
+        room_layout.layout.layout_height,
+    );
+    ImageInfo::from(wall_mask_image)
+}
+
+/// Estimates how much wallpaper the walls need, less their openings, from the wall sizes
+/// measured by [estimate_room_layout_from_depth]. Area is zero if the layout has no walls.
+///
+/// # Safety `openings` must point to `num_openings` openings
+#[no_mangle]
+pub unsafe extern "C" fn compute_wallpaper_area(
+    room_layout: DepthRoomLayoutData,
+    openings: *const WallOpeningData,
+    num_openings: usize,
+) -> WallpaperAreaData {
+    let openings = wall_openings(openings, num_openings);
+    let Some(area) = estimate_wallpaper_area(
+        &room_layout.layout.wall_polygons(),
+        &room_layout.wall_sizes(),
+        &openings,
+    ) else {
+        return WallpaperAreaData::default();
+    };
+    WallpaperAreaData {
+        walls: area.walls,
+        openings: area.openings,
+        net: area.net(),
+    }
+}
+
+unsafe fn wall_openings(openings: *const WallOpeningData, num_openings: usize) -> Vec<WallOpening> {
+    if openings.is_null() || num_openings == 0 {
+        return vec![];
+    }
+    slice::from_raw_parts(openings, num_openings)
+        .iter()
+        .map(|opening| WallOpening::from(*opening))
+        .collect()
+}
+
+/// Moves wall polygon corner (0 - top left, 1 - top right, 2 - bottom right, 3 - bottom left)
+/// and recomputes all the wall polygons, so that corners shared by neighbouring walls stay
+/// shared. Layout is returned unchanged if the corner can not be moved.
+#[no_mangle]
+pub extern "C" fn move_layout_corner(
+    room_layout: RoomLayoutData,
+    wall_index: u8,
+    corner_index: u8,
+    position: LayoutPoint,
+) -> RoomLayoutData {
+    let corner = match corner_index {
+        0 => CornerPosition::TopLeft,
+        1 => CornerPosition::TopRight,
+        2 => CornerPosition::BottomRight,
+        3 => CornerPosition::BottomLeft,
+        _ => {
+            println!("Invalid corner index: {corner_index}");
+            return room_layout;
+        }
+    };
+    let edit = LayoutEdit::MoveCorner {
+        wall: wall_index as usize,
+        corner,
+        to: position.to_point(),
+    };
+    apply_layout_edit(room_layout, edit)
+}
+
+/// Moves start (or end, if `is_line_end` is `true`) of the layout line together with ends of
+/// other lines meeting at it, and recomputes all the wall polygons.
+#[no_mangle]
+pub extern "C" fn move_layout_line_endpoint(
+    room_layout: RoomLayoutData,
+    line_index: u8,
+    is_line_end: bool,
+    position: LayoutPoint,
+) -> RoomLayoutData {
+    let edit = LayoutEdit::MoveLineEndpoint {
+        line: line_index as usize,
+        end: if is_line_end {
+            LineEnd::End
+        } else {
+            LineEnd::Start
+        },
+        to: position.to_point(),
+    };
+    apply_layout_edit(room_layout, edit)
+}
+
+/// Packs layout lines, wall polygons and their validation results for FFI.
+fn room_layout_data(
+    lines: &[Line],
+    room_type: u8,
+    polygons: &[WallPolygon],
+    layout_width: u32,
+    layout_height: u32,
+    validation: &LayoutValidation,
+) -> RoomLayoutData {
     let mut wall_polygons: [LayoutWallPolygon; 3] = Default::default();
     for (idx, polygon) in polygons.iter().enumerate() {
         // TODO: could use `From` impl
@@ -384,8 +1473,8 @@ pub unsafe extern "C" fn process_room_layout_estimation_results(
     }
     let num_wall_polygons = polygons.len() as u8;
 
-    let mut lines: [LayoutLine; 8] = Default::default();
-    for (idx, (start, end)) in parse_result.lines.iter().enumerate() {
+    let mut layout_lines: [LayoutLine; 8] = Default::default();
+    for (idx, (start, end)) in lines.iter().enumerate() {
         let line = LayoutLine {
             start: LayoutPoint {
                 x: start.0,
@@ -393,15 +1482,167 @@ pub unsafe extern "C" fn process_room_layout_estimation_results(
             },
             end: LayoutPoint { x: end.0, y: end.1 },
         };
-        lines[idx] = line;
+        layout_lines[idx] = line;
     }
-    let num_lines = parse_result.lines.len() as u8;
+    let num_lines = lines.len() as u8;
 
     RoomLayoutData {
-        lines,
+        lines: layout_lines,
         num_lines,
-        room_type: parse_result.room_type,
+        room_type,
         wall_polygons,
         num_wall_polygons,
+        layout_width,
+        layout_height,
+        confidence: validation.confidence,
+        issues: validation.issue_flags(),
+    }
+}
+
+/// Recomputes wall polygons of the layout after user edit of its lines.
+fn apply_layout_edit(room_layout: RoomLayoutData, edit: LayoutEdit) -> RoomLayoutData {
+    let lines = room_layout.lines();
+    let Some(edited_lines) = edit_layout_lines(&lines, room_layout.room_type, edit) else {
+        println!("Could not apply layout edit: {edit:?}");
+        return room_layout;
+    };
+
+    let Some(polygons) = compute_wall_polygons(
+        &edited_lines,
+        room_layout.layout_width as i32,
+        room_layout.layout_height as i32,
+        room_layout.room_type,
+    ) else {
+        return room_layout;
+    };
+    let validation = validate_layout(
+        &polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+        None,
+    );
+    room_layout_data(
+        &edited_lines,
+        room_layout.room_type,
+        &polygons,
+        room_layout.layout_width,
+        room_layout.layout_height,
+        &validation,
+    )
+}
+
+/// Size of the layout estimation output, which is given by the last two axes of the
+/// `(channels, height, width)` edges array.
+fn layout_size(edges_array: &Array3<f32>) -> (u32, u32) {
+    let shape = edges_array.shape();
+    (shape[2] as u32, shape[1] as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::evaluate_layout;
+    use crate::ffi::{
+        create_layout_tracker, estimate_room_layout_from_mask, layout_size, move_layout_corner,
+        release_image_buffer, release_layout_tracker, room_layout_data, track_room_layout,
+        ImageInfo, LayoutPoint, RoomLayoutData,
+    };
+    use crate::layout::{RoomLayout, RoomType};
+    use crate::polygons::compute_wall_polygons;
+    use crate::synthetic::SyntheticRoom;
+    use crate::validation::validate_layout;
+    use image::DynamicImage;
+    use ndarray::Array3;
+    use std::ptr;
+
+    fn room_type_5_layout() -> RoomLayoutData {
+        let lines = vec![
+            ((294, 167), (13, 0)),
+            ((294, 167), (511, 85)),
+            ((294, 167), (306, 343)),
+            ((306, 343), (0, 491)),
+            ((306, 343), (511, 410)),
+        ];
+        let polygons = compute_wall_polygons(&lines, 512, 512, 5).unwrap();
+        let validation = validate_layout(&polygons, 512, 512, None);
+        room_layout_data(&lines, 5, &polygons, 512, 512, &validation)
+    }
+
+    #[test]
+    fn takes_layout_size_from_edges_array_shape() {
+        let edges_array = Array3::<f32>::zeros((3, 320, 240));
+        assert_eq!(layout_size(&edges_array), (240, 320));
+    }
+
+    #[test]
+    fn recomputes_neighbour_polygons_after_moving_shared_corner() {
+        let room_layout = room_type_5_layout();
+
+        let edited_layout =
+            move_layout_corner(room_layout, 0, 2, LayoutPoint { x: 300, y: 360 });
+
+        assert_eq!(edited_layout.num_wall_polygons, 2);
+        assert_eq!(edited_layout.lines()[2].1, (300, 360));
+        let left_wall = edited_layout.wall_polygons[0];
+        let right_wall = edited_layout.wall_polygons[1];
+        assert_eq!(left_wall.bottom_right.to_point(), (300, 360));
+        assert_eq!(right_wall.bottom_left.to_point(), (300, 360));
+        assert_eq!(edited_layout.issues, 0);
+    }
+
+    #[test]
+    fn estimates_room_layout_of_synthetic_room_from_wall_mask() {
+        let room = SyntheticRoom::new(RoomType::Type0)
+            .with_furniture()
+            .render();
+        let room_image = ImageInfo::from(DynamicImage::from(room.photo).into_rgba8());
+        let wall_mask_image = ImageInfo {
+            data: room.wall_mask.as_ptr(),
+            count: room.wall_mask.len(),
+            width: room.wall_mask.width() as usize,
+            height: room.wall_mask.height() as usize,
+        };
+
+        let room_layout = unsafe { estimate_room_layout_from_mask(&room_image, &wall_mask_image) };
+        release_image_buffer(room_image.data, room_image.count);
+
+        assert_eq!(room_layout.room_type, 0);
+        assert_eq!(room_layout.num_wall_polygons, 3);
+        let estimated_layout = RoomLayout {
+            wall_polygons: room_layout.wall_polygons(),
+            ..room.layout.clone()
+        };
+        let evaluation = evaluate_layout(&estimated_layout, &room.layout);
+        assert!(evaluation.pixel_error < 0.01, "{}", evaluation.pixel_error);
+    }
+
+    #[test]
+    fn tracks_room_layout_with_homography() {
+        let tracker = create_layout_tracker();
+        let homography: [f32; 9] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let room_layout = room_type_5_layout();
+        let expected_polygons = room_layout.wall_polygons();
+
+        let mut tracked_layout = Default::default();
+        for _ in 0..3 {
+            tracked_layout =
+                unsafe { track_room_layout(tracker, room_type_5_layout(), homography.as_ptr()) };
+        }
+        unsafe { release_layout_tracker(tracker) };
+
+        assert_eq!(tracked_layout.wall_ids[..2], [0, 1]);
+        assert_eq!(tracked_layout.layout.wall_polygons(), expected_polygons);
+    }
+
+    #[test]
+    fn returns_untracked_layout_without_tracker() {
+        let room_layout = room_type_5_layout();
+        let expected_polygons = room_layout.wall_polygons();
+
+        let tracked_layout =
+            unsafe { track_room_layout(ptr::null_mut(), room_layout, ptr::null()) };
+
+        assert_eq!(tracked_layout.layout.room_type, 5);
+        assert_eq!(tracked_layout.layout.wall_polygons(), expected_polygons);
+        assert_eq!(tracked_layout.wall_ids, [0; 3]);
     }
 }
@@ -0,0 +1,187 @@
+use ndarray::{Array2, Array3, Axis};
+
+use crate::layout::lsun::{parse_lsun_results, Line};
+use crate::polygons::{compute_wall_polygons, WallPolygon};
+use crate::validation::{validate_layout, LayoutValidation};
+
+/// How many more room types than requested hypotheses are parsed, since geometric validity may
+/// rank less probable room types higher
+const EXTRA_CANDIDATE_ROOM_TYPES: usize = 2;
+/// Probabilities of room types should sum to 1 within this tolerance, otherwise they are
+/// treated as logits
+const PROBABILITY_SUM_TOLERANCE: f32 = 1e-3;
+
+/// One of the alternative room layouts estimated from the same layout estimation outputs.
+#[derive(Clone, Debug)]
+pub struct RoomLayoutHypothesis {
+    pub room_type: u8,
+    /// Probability of the room type given by the layout estimation
+    pub probability: f32,
+    pub lines: Vec<Line>,
+    pub polygons: Vec<WallPolygon>,
+    pub validation: LayoutValidation,
+}
+
+impl RoomLayoutHypothesis {
+    /// Combined room type probability and geometric validity of the layout.
+    pub fn score(&self) -> f32 {
+        self.probability * self.validation.confidence
+    }
+}
+
+/// Room type probabilities averaged over the rows of `(rows, room types)` type array.
+pub fn room_type_probabilities(type_array: &Array2<f32>) -> Vec<f32> {
+    let mean_scores = type_array.mean_axis(Axis(0)).unwrap().to_vec();
+    let sum: f32 = mean_scores.iter().sum();
+    let are_probabilities = mean_scores.iter().all(|score| (0.0..=1.0).contains(score))
+        && (sum - 1.0).abs() < PROBABILITY_SUM_TOLERANCE;
+    if are_probabilities {
+        return mean_scores;
+    }
+
+    // Softmax of logits
+    let max_score = mean_scores.iter().cloned().fold(f32::MIN, f32::max);
+    let exps: Vec<f32> = mean_scores
+        .iter()
+        .map(|score| (score - max_score).exp())
+        .collect();
+    let exps_sum: f32 = exps.iter().sum();
+    exps.iter().map(|exp| exp / exps_sum).collect()
+}
+
+/// Parses layout estimation outputs as each of the most probable room types, and returns `k`
+/// best layouts ranked by [RoomLayoutHypothesis::score].
+pub fn estimate_room_layout_hypotheses(
+    edges_array: &Array3<f32>,
+    corners_array: &Array3<f32>,
+    corners_flip_array: &Array3<f32>,
+    type_array: &Array2<f32>,
+    layout_width: u32,
+    layout_height: u32,
+    k: usize,
+) -> Vec<RoomLayoutHypothesis> {
+    let probabilities = room_type_probabilities(type_array);
+    let mut room_types: Vec<usize> = (0..probabilities.len()).collect();
+    room_types.sort_by(|a, b| probabilities[*b].total_cmp(&probabilities[*a]));
+    room_types.truncate(k + EXTRA_CANDIDATE_ROOM_TYPES);
+
+    let mut hypotheses = vec![];
+    for room_type in room_types {
+        // Parser commits to the most probable room type, so make it the only probable one
+        let mut room_type_array = Array2::<f32>::zeros(type_array.raw_dim());
+        room_type_array.column_mut(room_type).fill(1.0);
+
+        let parse_result = match parse_lsun_results(
+            edges_array.clone(),
+            corners_array.clone(),
+            corners_flip_array.clone(),
+            room_type_array,
+        ) {
+            Ok(parse_result) => parse_result,
+            Err(e) => {
+                println!("Could not parse layout as room type {room_type}: {e:?}");
+                continue;
+            }
+        };
+
+        let Some(polygons) = compute_wall_polygons(
+            &parse_result.lines,
+            layout_width as i32,
+            layout_height as i32,
+            parse_result.room_type,
+        ) else {
+            continue;
+        };
+        let validation = validate_layout(
+            &polygons,
+            layout_width,
+            layout_height,
+            Some(corners_array),
+        );
+        hypotheses.push(RoomLayoutHypothesis {
+            room_type: parse_result.room_type,
+            probability: probabilities[room_type],
+            lines: parse_result.lines,
+            polygons,
+            validation,
+        });
+    }
+
+    rank_hypotheses(hypotheses, k)
+}
+
+/// `k` best hypotheses, best first.
+fn rank_hypotheses(
+    mut hypotheses: Vec<RoomLayoutHypothesis>,
+    k: usize,
+) -> Vec<RoomLayoutHypothesis> {
+    hypotheses.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    hypotheses.truncate(k);
+    hypotheses
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hypotheses::{rank_hypotheses, room_type_probabilities, RoomLayoutHypothesis};
+    use crate::validation::LayoutValidation;
+    use ndarray::{array, Array2};
+
+    fn hypothesis(room_type: u8, probability: f32, confidence: f32) -> RoomLayoutHypothesis {
+        RoomLayoutHypothesis {
+            room_type,
+            probability,
+            lines: vec![],
+            polygons: vec![],
+            validation: LayoutValidation {
+                confidence,
+                issues: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn averages_room_type_probabilities() {
+        let type_array = array![[0.2, 0.7, 0.1], [0.4, 0.5, 0.1]];
+
+        let probabilities = room_type_probabilities(&type_array);
+
+        assert_eq!(probabilities.len(), 3);
+        assert!((probabilities[0] - 0.3).abs() < 1e-6);
+        assert!((probabilities[1] - 0.6).abs() < 1e-6);
+        assert!((probabilities[2] - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn converts_logits_to_probabilities() {
+        let mut type_array = Array2::<f32>::zeros((1, 11));
+        type_array[[0, 5]] = 3.0;
+        type_array[[0, 2]] = -1.0;
+
+        let probabilities = room_type_probabilities(&type_array);
+
+        assert!((probabilities.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        let most_probable = probabilities
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .unwrap()
+            .0;
+        assert_eq!(most_probable, 5);
+        assert!(probabilities[2] < probabilities[0]);
+    }
+
+    #[test]
+    fn ranks_hypotheses_by_probability_and_validity() {
+        let hypotheses = vec![
+            // Most probable, but geometrically broken
+            hypothesis(5, 0.5, 0.1),
+            hypothesis(0, 0.3, 1.0),
+            hypothesis(3, 0.2, 0.9),
+        ];
+
+        let ranked = rank_hypotheses(hypotheses, 2);
+
+        let room_types: Vec<u8> = ranked.iter().map(|hypothesis| hypothesis.room_type).collect();
+        assert_eq!(room_types, vec![0, 3]);
+    }
+}
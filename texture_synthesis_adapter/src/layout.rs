@@ -0,0 +1,360 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::polygons::{compute_wall_polygons, room_topology, RoomTopology, WallPolygon};
+use lsun::{Line, Point};
+
+pub mod lsun;
+
+/// Size of layouts saved before the layout size was stored
+pub const LEGACY_LAYOUT_SIZE: u32 = 512;
+
+/// LSUN room type, serialized as its number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum RoomType {
+    /// Three walls with visible ceiling and floor
+    Type0,
+    /// Three walls with visible floor
+    Type1,
+    /// Three walls with visible ceiling
+    Type2,
+    /// Two walls with visible ceiling
+    Type3,
+    /// Two walls with visible floor
+    Type4,
+    /// Two walls with visible ceiling and floor
+    Type5,
+    /// Single wall with visible ceiling and floor
+    Type6,
+    /// Three walls with neither ceiling nor floor visible
+    Type7,
+    /// Single wall with visible ceiling
+    Type8,
+    /// Single wall with visible floor
+    Type9,
+    /// Two walls with neither ceiling nor floor visible
+    Type10,
+}
+
+impl RoomType {
+    pub const ALL: [RoomType; 11] = [
+        RoomType::Type0,
+        RoomType::Type1,
+        RoomType::Type2,
+        RoomType::Type3,
+        RoomType::Type4,
+        RoomType::Type5,
+        RoomType::Type6,
+        RoomType::Type7,
+        RoomType::Type8,
+        RoomType::Type9,
+        RoomType::Type10,
+    ];
+
+    /// Number of layout lines the layout estimation outputs for this room type.
+    pub fn num_lines(&self) -> usize {
+        match self {
+            RoomType::Type0 => 8,
+            RoomType::Type1 | RoomType::Type2 | RoomType::Type5 => 5,
+            RoomType::Type3 | RoomType::Type4 => 3,
+            RoomType::Type6 | RoomType::Type7 => 2,
+            RoomType::Type8 | RoomType::Type9 | RoomType::Type10 => 1,
+        }
+    }
+
+    pub fn num_walls(&self) -> usize {
+        match self {
+            RoomType::Type0 | RoomType::Type1 | RoomType::Type2 | RoomType::Type7 => 3,
+            RoomType::Type3 | RoomType::Type4 | RoomType::Type5 | RoomType::Type10 => 2,
+            RoomType::Type6 | RoomType::Type8 | RoomType::Type9 => 1,
+        }
+    }
+
+    pub fn is_ceiling_visible(&self) -> bool {
+        matches!(
+            self,
+            RoomType::Type0
+                | RoomType::Type2
+                | RoomType::Type3
+                | RoomType::Type5
+                | RoomType::Type6
+                | RoomType::Type8
+        )
+    }
+
+    pub fn is_floor_visible(&self) -> bool {
+        matches!(
+            self,
+            RoomType::Type0
+                | RoomType::Type1
+                | RoomType::Type4
+                | RoomType::Type5
+                | RoomType::Type6
+                | RoomType::Type9
+        )
+    }
+
+    /// How wall polygons of this room type are built from layout lines.
+    pub fn topology(&self) -> RoomTopology {
+        room_topology(u8::from(*self)).unwrap()
+    }
+}
+
+impl From<RoomType> for u8 {
+    fn from(room_type: RoomType) -> Self {
+        room_type as u8
+    }
+}
+
+impl TryFrom<u8> for RoomType {
+    type Error = UnknownRoomType;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        RoomType::ALL
+            .get(value as usize)
+            .copied()
+            .ok_or(UnknownRoomType(value))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownRoomType(pub u8);
+
+impl fmt::Display for UnknownRoomType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown room type: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownRoomType {}
+
+/// Room layout as stored by the app.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "RoomLayoutJson", into = "RoomLayoutJson")]
+pub struct RoomLayout {
+    pub room_type: RoomType,
+    /// Layout lines in LSUN order
+    pub lines: Vec<Line>,
+    pub wall_polygons: Vec<WallPolygon>,
+    /// Size of the layout estimation output, which all the coordinates are relative to
+    pub layout_width: u32,
+    pub layout_height: u32,
+    /// How much the wall polygons can be trusted, from 0 to 1, if layout was validated
+    pub confidence: Option<f32>,
+    /// Bit flags of problems found in wall polygons, see `LAYOUT_ISSUE_*` constants
+    pub issues: Option<u32>,
+}
+
+impl RoomLayout {
+    /// Layout with wall polygons computed from the lines. `None` if there are fewer lines than
+    /// the room type needs.
+    pub fn from_lines(
+        room_type: RoomType,
+        lines: Vec<Line>,
+        layout_width: u32,
+        layout_height: u32,
+    ) -> Option<Self> {
+        let wall_polygons = compute_wall_polygons(
+            &lines,
+            layout_width as i32,
+            layout_height as i32,
+            room_type.into(),
+        )?;
+        Some(Self {
+            room_type,
+            lines,
+            wall_polygons,
+            layout_width,
+            layout_height,
+            confidence: None,
+            issues: None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct PointJson {
+    x: i32,
+    y: i32,
+}
+
+impl From<Point> for PointJson {
+    fn from(point: Point) -> Self {
+        Self {
+            x: point.0,
+            y: point.1,
+        }
+    }
+}
+
+impl From<PointJson> for Point {
+    fn from(point: PointJson) -> Self {
+        (point.x, point.y)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct LineJson {
+    start: PointJson,
+    end: PointJson,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolygonJson {
+    top_left: PointJson,
+    top_right: PointJson,
+    bottom_right: PointJson,
+    bottom_left: PointJson,
+}
+
+/// JSON layout of the `RoomLayout` Swift model.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RoomLayoutJson {
+    room_type: RoomType,
+    edges: Vec<LineJson>,
+    wall_polygons: Vec<PolygonJson>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout_width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    layout_height: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    issues: Option<u32>,
+}
+
+impl From<RoomLayoutJson> for RoomLayout {
+    fn from(layout: RoomLayoutJson) -> Self {
+        Self {
+            room_type: layout.room_type,
+            lines: layout
+                .edges
+                .into_iter()
+                .map(|line| (line.start.into(), line.end.into()))
+                .collect(),
+            wall_polygons: layout
+                .wall_polygons
+                .into_iter()
+                .map(|polygon| WallPolygon {
+                    top_left: polygon.top_left.into(),
+                    top_right: polygon.top_right.into(),
+                    bottom_right: polygon.bottom_right.into(),
+                    bottom_left: polygon.bottom_left.into(),
+                })
+                .collect(),
+            layout_width: layout.layout_width.unwrap_or(LEGACY_LAYOUT_SIZE),
+            layout_height: layout.layout_height.unwrap_or(LEGACY_LAYOUT_SIZE),
+            confidence: layout.confidence,
+            issues: layout.issues,
+        }
+    }
+}
+
+impl From<RoomLayout> for RoomLayoutJson {
+    fn from(layout: RoomLayout) -> Self {
+        Self {
+            room_type: layout.room_type,
+            edges: layout
+                .lines
+                .into_iter()
+                .map(|(start, end)| LineJson {
+                    start: start.into(),
+                    end: end.into(),
+                })
+                .collect(),
+            wall_polygons: layout
+                .wall_polygons
+                .into_iter()
+                .map(|polygon| PolygonJson {
+                    top_left: polygon.top_left.into(),
+                    top_right: polygon.top_right.into(),
+                    bottom_right: polygon.bottom_right.into(),
+                    bottom_left: polygon.bottom_left.into(),
+                })
+                .collect(),
+            layout_width: Some(layout.layout_width),
+            layout_height: Some(layout.layout_height),
+            confidence: layout.confidence,
+            issues: layout.issues,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::{RoomLayout, RoomType, UnknownRoomType, LEGACY_LAYOUT_SIZE};
+
+    #[test]
+    fn room_type_metadata_matches_topology() {
+        for room_type in RoomType::ALL {
+            let topology = room_type.topology();
+
+            assert_eq!(topology.walls.len(), room_type.num_walls(), "{room_type:?}");
+            assert!(
+                topology.num_lines() <= room_type.num_lines(),
+                "{room_type:?}"
+            );
+            assert_eq!(RoomType::try_from(u8::from(room_type)), Ok(room_type));
+        }
+        assert_eq!(RoomType::try_from(11), Err(UnknownRoomType(11)));
+    }
+
+    #[test]
+    fn reads_layout_saved_by_app() {
+        let json = r#"{
+            "roomType": 6,
+            "edges": [
+                {"start": {"x": 0, "y": 100}, "end": {"x": 511, "y": 110}},
+                {"start": {"x": 0, "y": 400}, "end": {"x": 511, "y": 390}}
+            ],
+            "wallPolygons": [{
+                "topLeft": {"x": 0, "y": 100},
+                "topRight": {"x": 511, "y": 110},
+                "bottomRight": {"x": 511, "y": 390},
+                "bottomLeft": {"x": 0, "y": 400}
+            }]
+        }"#;
+
+        let layout: RoomLayout = serde_json::from_str(json).unwrap();
+
+        assert_eq!(layout.room_type, RoomType::Type6);
+        assert_eq!(layout.lines[1], ((0, 400), (511, 390)));
+        assert_eq!(layout.wall_polygons[0].top_right, (511, 110));
+        assert_eq!(layout.layout_width, LEGACY_LAYOUT_SIZE);
+        assert_eq!(layout.confidence, None);
+    }
+
+    #[test]
+    fn writes_layout_in_app_format() {
+        let mut layout = RoomLayout::from_lines(
+            RoomType::Type6,
+            vec![((0, 100), (511, 110)), ((0, 400), (511, 390))],
+            512,
+            384,
+        )
+        .unwrap();
+        layout.issues = Some(0);
+
+        let json = serde_json::to_value(&layout).unwrap();
+
+        assert_eq!(json["roomType"], 6);
+        assert_eq!(json["edges"][0]["end"]["x"], 511);
+        assert_eq!(json["wallPolygons"][0]["bottomLeft"]["y"], 400);
+        assert_eq!(json["layoutHeight"], 384);
+        assert_eq!(json["issues"], 0);
+        assert!(json.get("confidence").is_none());
+        let read_layout: RoomLayout = serde_json::from_value(json).unwrap();
+        assert_eq!(read_layout, layout);
+    }
+
+    #[test]
+    fn rejects_unknown_room_type() {
+        let json = r#"{"roomType": 12, "edges": [], "wallPolygons": []}"#;
+
+        assert!(serde_json::from_str::<RoomLayout>(json).is_err());
+    }
+}
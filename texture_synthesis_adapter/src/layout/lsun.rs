@@ -0,0 +1,683 @@
+//! Decoding of LSUN room layout estimation outputs into layout lines.
+//!
+//! Layout estimation gives edge and corner heatmaps, corner heatmaps of the horizontally flipped
+//! image and room type scores. Room corners are peaks of the corner heatmaps, and lines leaving
+//! them follow the edge heatmap up to the image border. Rooms without visible corners are bounded
+//! by the strongest edges crossing the whole image. Heatmap channels are merged, since lines are
+//! told apart by their direction and position.
+//!
+//! Lines are returned in the order described by [crate::polygons::topology], in heatmap pixels.
+
+use ndarray::{s, Array2, Array3, Axis};
+use ndarray_stats::QuantileExt;
+
+/// Layout image point.
+pub type Point = (i32, i32);
+/// Layout line from start to end.
+pub type Line = (Point, Point);
+
+/// Corner peaks this close to the image border are points where edges leave the image, rather
+/// than room corners
+const BORDER_PEAK_MARGIN: usize = 2;
+const COARSE_ANGLE_STEP_DEGREES: f64 = 1.0;
+const FINE_ANGLE_STEP_DEGREES: f64 = 0.05;
+/// Step of the coarse search of lines crossing the whole image, in heatmap pixels
+const COARSE_OFFSET_STEP: i32 = 8;
+/// Lines crossing the whole image may pass by its corners this share of the image size away
+const MAX_LINE_OVERHANG_SHARE: f64 = 0.5;
+/// Lines crossing the whole image are at least this share of the image size apart in the middle
+const MIN_LINE_SEPARATION_SHARE: f64 = 0.1;
+
+/// Room layout decoded from layout estimation outputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomLayoutInfo {
+    /// Layout lines in LSUN order
+    pub lines: Vec<Line>,
+    pub room_type: u8,
+}
+
+/// Thresholds of layout estimation outputs decoding.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LsunDecoderOptions {
+    /// Smallest corner heatmap peak taken as a room corner
+    pub corner_threshold: f32,
+    /// Weaker corner peaks closer than this many heatmap pixels to a stronger one are suppressed
+    pub nms_radius: usize,
+    /// Smallest mean edge heatmap value along a layout line
+    pub edge_threshold: f32,
+}
+
+impl Default for LsunDecoderOptions {
+    fn default() -> Self {
+        Self {
+            corner_threshold: 0.2,
+            nms_radius: 8,
+            edge_threshold: 0.1,
+        }
+    }
+}
+
+/// Decodes `(channels, height, width)` edge and corner heatmaps and `(rows, room types)` room
+/// type scores with the default options.
+pub fn parse_lsun_results(
+    edges: Array3<f32>,
+    corners: Array3<f32>,
+    corners_flip: Array3<f32>,
+    type_: Array2<f32>,
+) -> Result<RoomLayoutInfo, String> {
+    decode_lsun_results(
+        &edges,
+        &corners,
+        &corners_flip,
+        &type_,
+        &LsunDecoderOptions::default(),
+    )
+}
+
+/// Decodes layout lines of the most probable room type.
+pub fn decode_lsun_results(
+    edges: &Array3<f32>,
+    corners: &Array3<f32>,
+    corners_flip: &Array3<f32>,
+    type_: &Array2<f32>,
+    options: &LsunDecoderOptions,
+) -> Result<RoomLayoutInfo, String> {
+    let room_type = type_
+        .mean_axis(Axis(0))
+        .ok_or("Room type scores are empty")?
+        .argmax()
+        .map_err(|error| format!("Room type scores are invalid: {error}"))?
+        as u8;
+
+    let (_, height, width) = edges.dim();
+    for (name, heatmap) in [("corners", corners), ("corners_flip", corners_flip)] {
+        let (_, heatmap_height, heatmap_width) = heatmap.dim();
+        if (heatmap_width, heatmap_height) != (width, height) {
+            return Err(format!(
+                "Size of {name} {heatmap_width}x{heatmap_height} differs from edges {width}x{height}"
+            ));
+        }
+    }
+    if width < 2 || height < 2 {
+        return Err(format!("Heatmaps {width}x{height} are too small"));
+    }
+
+    let mut flipped_corner_map = corners_flip.fold_axis(Axis(0), f32::MIN, |a, b| a.max(*b));
+    flipped_corner_map.invert_axis(Axis(1));
+    let corner_map =
+        (corners.fold_axis(Axis(0), f32::MIN, |a, b| a.max(*b)) + flipped_corner_map) / 2.0;
+    let decoder = Decoder {
+        edge_map: edges.sum_axis(Axis(0)),
+        corner_map,
+        options: *options,
+    };
+
+    use Heading::*;
+    let lines = match room_type {
+        0 => {
+            let [top_left, bottom_left, top_right, bottom_right] = decoder.corner_quad()?;
+            vec![
+                decoder.ray(top_left, CeilingLeft)?,
+                decoder.ray(bottom_left, FloorLeft)?,
+                decoder.ray(bottom_right, FloorRight)?,
+                decoder.ray(top_right, CeilingRight)?,
+                (top_left, bottom_left),
+                (bottom_left, bottom_right),
+                (bottom_right, top_right),
+                (top_right, top_left),
+            ]
+        }
+        1 => {
+            let [left, right] = decoder.corners_by_x()?;
+            vec![
+                decoder.ray(left, Up)?,
+                decoder.ray(left, FloorLeft)?,
+                (left, right),
+                decoder.ray(right, Up)?,
+                decoder.ray(right, FloorRight)?,
+            ]
+        }
+        2 => {
+            let [left, right] = decoder.corners_by_x()?;
+            vec![
+                decoder.ray(left, CeilingLeft)?,
+                decoder.ray(left, Down)?,
+                (left, right),
+                decoder.ray(right, CeilingRight)?,
+                decoder.ray(right, Down)?,
+            ]
+        }
+        3 => {
+            let [corner] = decoder.corners_by_x()?;
+            vec![
+                decoder.ray(corner, CeilingLeft)?,
+                decoder.ray(corner, Down)?,
+                decoder.ray(corner, CeilingRight)?,
+            ]
+        }
+        4 => {
+            let [corner] = decoder.corners_by_x()?;
+            vec![
+                decoder.ray(corner, FloorLeft)?,
+                decoder.ray(corner, Up)?,
+                decoder.ray(corner, FloorRight)?,
+            ]
+        }
+        5 => {
+            let [mut top, mut bottom] = decoder.corners_by_x()?;
+            if top.1 > bottom.1 {
+                (top, bottom) = (bottom, top);
+            }
+            vec![
+                decoder.ray(top, CeilingLeft)?,
+                decoder.ray(top, CeilingRight)?,
+                (top, bottom),
+                decoder.ray(bottom, FloorLeft)?,
+                decoder.ray(bottom, FloorRight)?,
+            ]
+        }
+        6 => decoder.crossing_lines(2, false)?,
+        // Wall corners go from the floor up
+        7 => decoder
+            .crossing_lines(2, true)?
+            .into_iter()
+            .map(|(start, end)| (end, start))
+            .collect(),
+        8 | 9 => decoder.crossing_lines(1, false)?,
+        10 => decoder.crossing_lines(1, true)?,
+        _ => return Err(format!("Unknown room type: {room_type}")),
+    };
+    Ok(RoomLayoutInfo { lines, room_type })
+}
+
+/// Direction of the line leaving a room corner.
+#[derive(Clone, Copy, Debug)]
+enum Heading {
+    CeilingLeft,
+    CeilingRight,
+    FloorLeft,
+    FloorRight,
+    Up,
+    Down,
+}
+
+impl Heading {
+    /// Range of line angles in degrees, clockwise from the image X axis.
+    fn angles(&self) -> (f64, f64) {
+        match self {
+            Heading::CeilingLeft => (165.0, 265.0),
+            Heading::CeilingRight => (275.0, 375.0),
+            Heading::FloorLeft => (95.0, 195.0),
+            Heading::FloorRight => (-15.0, 85.0),
+            Heading::Up => (240.0, 300.0),
+            Heading::Down => (60.0, 120.0),
+        }
+    }
+}
+
+struct Decoder {
+    /// Sum of edge heatmap channels
+    edge_map: Array2<f32>,
+    /// Largest value of corner heatmap channels, averaged with the flipped image
+    corner_map: Array2<f32>,
+    options: LsunDecoderOptions,
+}
+
+impl Decoder {
+    fn width(&self) -> usize {
+        self.edge_map.dim().1
+    }
+
+    fn height(&self) -> usize {
+        self.edge_map.dim().0
+    }
+
+    /// Local maxima of the corner heatmap above the threshold, strongest first.
+    fn corner_peaks(&self) -> Vec<(Point, f32)> {
+        let (height, width) = self.corner_map.dim();
+        let radius = self.options.nms_radius;
+        let mut peaks = vec![];
+        for ((y, x), value) in self.corner_map.indexed_iter() {
+            if *value < self.options.corner_threshold
+                || x < BORDER_PEAK_MARGIN
+                || y < BORDER_PEAK_MARGIN
+                || x + BORDER_PEAK_MARGIN >= width
+                || y + BORDER_PEAK_MARGIN >= height
+            {
+                continue;
+            }
+            let (top, left) = (y.saturating_sub(radius), x.saturating_sub(radius));
+            let window = self.corner_map.slice(s![
+                top..(y + radius + 1).min(height),
+                left..(x + radius + 1).min(width)
+            ]);
+            // Plateaus keep their first pixel
+            let is_peak = window.indexed_iter().all(|((window_y, window_x), other)| {
+                other < value || (other == value && (top + window_y, left + window_x) >= (y, x))
+            });
+            if is_peak {
+                peaks.push(((x as i32, y as i32), *value));
+            }
+        }
+        peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+        peaks
+    }
+
+    /// `N` strongest room corners from left to right.
+    fn corners_by_x<const N: usize>(&self) -> Result<[Point; N], String> {
+        let peaks = self.corner_peaks();
+        if peaks.len() < N {
+            return Err(format!("Found {} of {N} room corners", peaks.len()));
+        }
+        let mut corners: Vec<Point> = peaks[..N].iter().map(|(point, _)| *point).collect();
+        corners.sort();
+        Ok(corners.try_into().unwrap())
+    }
+
+    /// Top left, bottom left, top right and bottom right corners of the middle wall.
+    fn corner_quad(&self) -> Result<[Point; 4], String> {
+        let [a, b, c, d] = self.corners_by_x()?;
+        let (top_left, bottom_left) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+        let (top_right, bottom_right) = if c.1 <= d.1 { (c, d) } else { (d, c) };
+        Ok([top_left, bottom_left, top_right, bottom_right])
+    }
+
+    /// Line along the strongest edge leaving the corner in the heading, up to the image border.
+    fn ray(&self, corner: Point, heading: Heading) -> Result<Line, String> {
+        let (start, end) = heading.angles();
+        let (coarse_angle, _) = self.best_ray(corner, start, end, COARSE_ANGLE_STEP_DEGREES);
+        let (angle, score) = self.best_ray(
+            corner,
+            coarse_angle - COARSE_ANGLE_STEP_DEGREES,
+            coarse_angle + COARSE_ANGLE_STEP_DEGREES,
+            FINE_ANGLE_STEP_DEGREES,
+        );
+        if score < self.options.edge_threshold as f64 {
+            return Err(format!("No edge leaves corner {corner:?} {heading:?}"));
+        }
+
+        let direction = (angle.to_radians().cos(), angle.to_radians().sin());
+        let distance = self.exit_distance(corner, direction);
+        let end = (
+            (corner.0 as f64 + distance * direction.0)
+                .round()
+                .clamp(0.0, (self.width() - 1) as f64) as i32,
+            (corner.1 as f64 + distance * direction.1)
+                .round()
+                .clamp(0.0, (self.height() - 1) as f64) as i32,
+        );
+        Ok((corner, end))
+    }
+
+    /// Angle with the largest mean edge heatmap value along the ray, and that value.
+    fn best_ray(&self, corner: Point, start: f64, end: f64, step: f64) -> (f64, f64) {
+        let steps = ((end - start) / step).round() as usize;
+        (0..=steps)
+            .map(|idx| {
+                let angle = start + idx as f64 * step;
+                (angle, self.ray_score(corner, angle))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    }
+
+    fn ray_score(&self, corner: Point, angle: f64) -> f64 {
+        let direction = (angle.to_radians().cos(), angle.to_radians().sin());
+        let length = self.exit_distance(corner, direction);
+        // Edges meet at the corner, so its surroundings do not tell the direction
+        let start = self.options.nms_radius.max(1);
+        let mut sum = 0.0;
+        let mut count = 0;
+        for distance in start..=length.floor() as usize {
+            let x = corner.0 as f64 + distance as f64 * direction.0;
+            let y = corner.1 as f64 + distance as f64 * direction.1;
+            sum += self.edge_map[[y.round() as usize, x.round() as usize]] as f64;
+            count += 1;
+        }
+        if count == 0 {
+            return 0.0;
+        }
+        sum / count as f64
+    }
+
+    /// Distance from the point inside the image to the image border along the direction.
+    fn exit_distance(&self, point: Point, direction: (f64, f64)) -> f64 {
+        let distance_along = |position: f64, delta: f64, size: usize| {
+            if delta > f64::EPSILON {
+                ((size - 1) as f64 - position) / delta
+            } else if delta < -f64::EPSILON {
+                -position / delta
+            } else {
+                f64::INFINITY
+            }
+        };
+        f64::min(
+            distance_along(point.0 as f64, direction.0, self.width()),
+            distance_along(point.1 as f64, direction.1, self.height()),
+        )
+        .max(0.0)
+    }
+
+    /// `count` strongest edge lines crossing the image from left to right (or from top to bottom
+    /// if `is_vertical`), in the same order, cut by the image borders.
+    fn crossing_lines(&self, count: usize, is_vertical: bool) -> Result<Vec<Line>, String> {
+        // Lines go along the image X axis in a transposed heatmap
+        let edge_map = if is_vertical {
+            self.edge_map.t()
+        } else {
+            self.edge_map.view()
+        };
+        let (across_size, along_size) = edge_map.dim();
+        let last_along = (along_size - 1) as f64;
+        // Mean edge value along the line through `(0, first)` and `(last_along, last)`
+        let score = |first: i32, last: i32| -> f64 {
+            let sum: f32 = (0..along_size)
+                .filter_map(|along| {
+                    let across = first as f64 + (last - first) as f64 * along as f64 / last_along;
+                    let across = across.round();
+                    (across >= 0.0 && across < across_size as f64)
+                        .then(|| edge_map[[across as usize, along]])
+                })
+                .sum();
+            sum as f64 / along_size as f64
+        };
+
+        let overhang = (MAX_LINE_OVERHANG_SHARE * across_size as f64) as i32;
+        let offsets: Vec<i32> = (-overhang..across_size as i32 + overhang)
+            .step_by(COARSE_OFFSET_STEP as usize)
+            .collect();
+        let mut candidates: Vec<(f64, i32, i32)> = offsets
+            .iter()
+            .flat_map(|first| offsets.iter().map(move |last| (*first, *last)))
+            .map(|(first, last)| (score(first, last), first, last))
+            .collect();
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let min_separation = MIN_LINE_SEPARATION_SHARE * across_size as f64;
+        let middle = |first: i32, last: i32| (first + last) as f64 / 2.0;
+        let mut lines: Vec<(i32, i32)> = vec![];
+        for (_, first, last) in candidates {
+            if lines.len() == count {
+                break;
+            }
+            let is_separate = lines
+                .iter()
+                .all(|line| (middle(line.0, line.1) - middle(first, last)).abs() >= min_separation);
+            if !is_separate {
+                continue;
+            }
+            // Refine around the coarse line
+            let refined = (-COARSE_OFFSET_STEP..=COARSE_OFFSET_STEP)
+                .flat_map(|first_delta| {
+                    (-COARSE_OFFSET_STEP..=COARSE_OFFSET_STEP)
+                        .map(move |last_delta| (first + first_delta, last + last_delta))
+                })
+                .map(|(first, last)| (score(first, last), first, last))
+                .max_by(|a, b| a.0.total_cmp(&b.0))
+                .unwrap();
+            if refined.0 < self.options.edge_threshold as f64 {
+                break;
+            }
+            lines.push((refined.1, refined.2));
+        }
+        if lines.len() < count {
+            return Err(format!("Found {} of {count} edges", lines.len()));
+        }
+        lines.sort_by(|a, b| middle(a.0, a.1).total_cmp(&middle(b.0, b.1)));
+
+        lines
+            .into_iter()
+            .map(|(first, last)| {
+                // Part of the line within the image
+                let last_across = (across_size - 1) as f64;
+                let (mut start, mut end) = (0.0, last_along);
+                if first != last {
+                    let slope = (last - first) as f64 / last_along;
+                    let at_zero = -first as f64 / slope;
+                    let at_last = (last_across - first as f64) / slope;
+                    start = f64::max(start, at_zero.min(at_last));
+                    end = f64::min(end, at_zero.max(at_last));
+                } else if first < 0 || first as f64 > last_across {
+                    start = f64::INFINITY;
+                }
+                if start > end {
+                    return Err(format!("Edge {first}..{last} does not cross the image"));
+                }
+                let point = |along: f64| {
+                    let across = first as f64 + (last - first) as f64 * along / last_along;
+                    let along = along.round() as i32;
+                    let across = across.round().clamp(0.0, last_across) as i32;
+                    if is_vertical {
+                        (across, along)
+                    } else {
+                        (along, across)
+                    }
+                };
+                Ok((point(start), point(end)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::Path;
+
+    use ndarray::{Array2, Array3};
+    use ndarray_npy::read_npy;
+
+    use crate::layout::lsun::{
+        decode_lsun_results, parse_lsun_results, Decoder, Line, LsunDecoderOptions,
+    };
+    use crate::layout::RoomLayout;
+
+    const SIZE: usize = 512;
+    const NUM_ROOM_TYPES: usize = 11;
+
+    /// Layout estimation outputs which have the given lines on the edge heatmap and their shared
+    /// ends on the corner heatmaps.
+    fn render_results(
+        lines: &[Line],
+        room_type: usize,
+    ) -> (Array3<f32>, Array3<f32>, Array3<f32>, Array2<f32>) {
+        let mut edges = Array3::<f32>::zeros((3, SIZE, SIZE));
+        for (idx, (start, end)) in lines.iter().enumerate() {
+            let (dx, dy) = ((end.0 - start.0) as f32, (end.1 - start.1) as f32);
+            let length_squared = dx * dx + dy * dy;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let (px, py) = (x as f32 - start.0 as f32, y as f32 - start.1 as f32);
+                    let t = ((px * dx + py * dy) / length_squared).clamp(0.0, 1.0);
+                    let distance_squared = (px - t * dx).powi(2) + (py - t * dy).powi(2);
+                    let value = (-distance_squared / 4.0).exp();
+                    let edge = &mut edges[[idx % 3, y, x]];
+                    *edge = edge.max(value);
+                }
+            }
+        }
+
+        let ends: Vec<_> = lines
+            .iter()
+            .flat_map(|(start, end)| [*start, *end])
+            .collect();
+        let mut room_corners: Vec<_> = ends
+            .iter()
+            .filter(|corner| ends.iter().filter(|end| end == corner).count() >= 2)
+            .collect();
+        room_corners.sort();
+        room_corners.dedup();
+        let mut corners = Array3::<f32>::zeros((8, SIZE, SIZE));
+        let mut corners_flip = Array3::<f32>::zeros((8, SIZE, SIZE));
+        for corner in room_corners {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let distance_squared =
+                        (x as f32 - corner.0 as f32).powi(2) + (y as f32 - corner.1 as f32).powi(2);
+                    let value = (-distance_squared / 18.0).exp();
+                    corners[[0, y, x]] += value;
+                    corners_flip[[1, y, SIZE - 1 - x]] += value;
+                }
+            }
+        }
+
+        let mut type_ = Array2::<f32>::zeros((4, NUM_ROOM_TYPES));
+        type_.column_mut(room_type).fill(1.0);
+        (edges, corners, corners_flip, type_)
+    }
+
+    fn assert_lines_close(actual: &[Line], expected: &[Line], room_type: usize) {
+        assert_eq!(actual.len(), expected.len(), "{room_type}: {actual:?}");
+        for (actual, expected) in actual.iter().zip(expected) {
+            for (actual, expected) in [(actual.0, expected.0), (actual.1, expected.1)] {
+                assert!(
+                    (actual.0 - expected.0).abs() <= 3 && (actual.1 - expected.1).abs() <= 3,
+                    "{room_type}: {actual:?} != {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_layout_saved_by_app() {
+        let file = File::open("./fixtures/rooms/layout_2.json").unwrap();
+        let layout: RoomLayout = serde_json::from_reader(BufReader::new(file)).unwrap();
+        let room_type = u8::from(layout.room_type) as usize;
+        let (edges, corners, corners_flip, type_) = render_results(&layout.lines, room_type);
+
+        let info = parse_lsun_results(edges, corners, corners_flip, type_).unwrap();
+
+        assert_eq!(info.room_type, 5);
+        assert_lines_close(&info.lines, &layout.lines, room_type);
+    }
+
+    /// Outputs of the layout estimation network for `fixtures/rooms/2.png` decode to the layout
+    /// which `lsun_res_parser` parsed from them
+    #[test]
+    #[ignore = "needs edges_2.npy, corners_2.npy, corners_flip_2.npy and type_2.npy network outputs in fixtures/rooms"]
+    fn decodes_network_outputs_same_as_lsun_res_parser() {
+        let fixtures_dir = Path::new("./fixtures/rooms");
+        let edges: Array3<f32> = read_npy(fixtures_dir.join("edges_2.npy")).unwrap();
+        let corners: Array3<f32> = read_npy(fixtures_dir.join("corners_2.npy")).unwrap();
+        let corners_flip: Array3<f32> = read_npy(fixtures_dir.join("corners_flip_2.npy")).unwrap();
+        let type_: Array2<f32> = read_npy(fixtures_dir.join("type_2.npy")).unwrap();
+        let file = File::open(fixtures_dir.join("layout_2.json")).unwrap();
+        let layout: RoomLayout = serde_json::from_reader(BufReader::new(file)).unwrap();
+
+        let info = parse_lsun_results(edges, corners, corners_flip, type_).unwrap();
+
+        assert_eq!(info.room_type, u8::from(layout.room_type));
+        assert_eq!(info.lines, layout.lines);
+    }
+
+    #[test]
+    fn decodes_layouts_of_all_room_types() {
+        // Lines of layout estimation results parsed for each room type
+        let layouts: [Vec<Line>; NUM_ROOM_TYPES] = [
+            vec![
+                ((116, 100), (72, 0)),
+                ((116, 396), (64, 511)),
+                ((344, 370), (511, 479)),
+                ((342, 133), (496, 0)),
+                ((116, 100), (116, 396)),
+                ((116, 396), (344, 370)),
+                ((344, 370), (342, 133)),
+                ((342, 133), (116, 100)),
+            ],
+            vec![
+                ((153, 365), (154, 0)),
+                ((153, 365), (24, 511)),
+                ((153, 365), (441, 375)),
+                ((441, 375), (446, 0)),
+                ((441, 375), (510, 439)),
+            ],
+            vec![
+                ((101, 35), (86, 0)),
+                ((101, 35), (93, 511)),
+                ((101, 35), (336, 42)),
+                ((336, 42), (365, 0)),
+                ((336, 42), (336, 511)),
+            ],
+            vec![
+                ((31, 110), (0, 97)),
+                ((31, 110), (20, 511)),
+                ((31, 110), (511, 0)),
+            ],
+            vec![
+                ((482, 471), (1, 422)),
+                ((482, 471), (504, 0)),
+                ((482, 471), (491, 511)),
+            ],
+            vec![
+                ((294, 167), (13, 0)),
+                ((294, 167), (511, 85)),
+                ((294, 167), (306, 343)),
+                ((306, 343), (0, 491)),
+                ((306, 343), (511, 410)),
+            ],
+            vec![((142, 0), (511, 75)), ((2, 511), (511, 346))],
+            vec![((51, 511), (0, 0)), ((417, 511), (419, 0))],
+            vec![((0, 127), (511, 95))],
+            vec![((0, 311), (511, 305))],
+            vec![((292, 0), (312, 511))],
+        ];
+
+        for (room_type, lines) in layouts.iter().enumerate() {
+            let (edges, corners, corners_flip, type_) = render_results(lines, room_type);
+
+            let info = parse_lsun_results(edges, corners, corners_flip, type_).unwrap();
+
+            assert_eq!(info.room_type as usize, room_type);
+            assert_lines_close(&info.lines, lines, room_type);
+        }
+    }
+
+    #[test]
+    fn weak_corners_are_not_decoded() {
+        let lines = vec![
+            ((294, 167), (13, 0)),
+            ((294, 167), (511, 85)),
+            ((294, 167), (306, 343)),
+            ((306, 343), (0, 491)),
+            ((306, 343), (511, 410)),
+        ];
+        let (edges, mut corners, mut corners_flip, type_) = render_results(&lines, 5);
+        corners *= 0.5;
+        corners_flip *= 0.5;
+        let options = LsunDecoderOptions {
+            corner_threshold: 0.6,
+            ..Default::default()
+        };
+
+        let result = decode_lsun_results(&edges, &corners, &corners_flip, &type_, &options);
+
+        assert_eq!(result, Err("Found 0 of 2 room corners".to_string()));
+    }
+
+    #[test]
+    fn suppresses_weaker_corners_nearby() {
+        let mut corner_map = Array2::<f32>::zeros((32, 32));
+        corner_map[[10, 10]] = 0.9;
+        corner_map[[10, 14]] = 0.5;
+        corner_map[[25, 25]] = 0.3;
+        corner_map[[25, 5]] = 0.1;
+        let decoder = |nms_radius| Decoder {
+            edge_map: Array2::zeros((32, 32)),
+            corner_map: corner_map.clone(),
+            options: LsunDecoderOptions {
+                nms_radius,
+                ..Default::default()
+            },
+        };
+
+        assert_eq!(
+            decoder(8).corner_peaks(),
+            vec![((10, 10), 0.9), ((25, 25), 0.3)]
+        );
+        assert_eq!(
+            decoder(2).corner_peaks(),
+            vec![((10, 10), 0.9), ((14, 10), 0.5), ((25, 25), 0.3)]
+        );
+    }
+}
@@ -1,6 +1,26 @@
+pub mod debug;
+pub mod depth_layout;
+pub mod distortion;
+pub mod editing;
+pub mod evaluation;
 mod ffi;
+pub mod hypotheses;
+pub mod layout;
+pub mod mask_layout;
+pub mod material;
+pub mod mesh_export;
+pub mod object_removal;
+pub mod openings;
+pub mod panorama;
 pub mod polygons;
 pub mod preview;
+pub mod reconstruction;
+pub mod refinement;
+#[cfg(test)]
+mod synthetic;
+pub mod tracking;
+pub mod validation;
+pub mod wall_maps;
 
 use image::{DynamicImage, RgbImage, Rgba, RgbaImage};
 use imageproc::drawing::draw_filled_rect_mut;
@@ -61,7 +81,7 @@ mod tests {
 
     #[test]
     fn test_image() {
-        let image = ImageReader::open("./assets/lenna.png")
+        let image = ImageReader::open("./fixtures/wallpaper1.jpg")
             .unwrap()
             .decode()
             .unwrap();
@@ -70,10 +90,11 @@ mod tests {
         println!("Color type: {:?}", image.color());
         let num_bytes = image.as_bytes().len();
         println!("Image has {} bytes", num_bytes);
+        assert_eq!(num_bytes, (image.width() * image.height() * 3) as usize);
 
         // Reconstruct image from bytes
-        // let mut image2 = RgbImage::new(image.width(), image.height());
-        let image2 = RgbImage::from_raw(image.width(), image.height(), image.into_bytes()).unwrap();
-        image2.save("./assets/image2.jpg").unwrap();
+        let image2 =
+            RgbImage::from_raw(image.width(), image.height(), image.clone().into_bytes()).unwrap();
+        assert_eq!(image2, image.into_rgb8());
     }
 }
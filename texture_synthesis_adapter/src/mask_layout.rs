@@ -0,0 +1,660 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, RgbImage};
+use imageproc::contours::{find_contours, BorderType};
+use imageproc::geometry::approximate_polygon_dp;
+use imageproc::hough::{detect_lines, LineDetectionOptions, PolarLine};
+use imageproc::point::Point as ContourPoint;
+
+use crate::layout::lsun::{Line, Point};
+use crate::polygons::WallPolygon;
+use crate::preview::prepare_wall_mask;
+
+/// Room layouts have at most three visible walls
+const MAX_WALLS: usize = 3;
+/// Contours shorter than this share of the mask perimeter are segmentation noise
+const MIN_CONTOUR_LENGTH_SHARE: f64 = 0.05;
+/// Tolerance of mask boundary simplification relative to mask width
+const SIMPLIFICATION_EPSILON_SHARE: f64 = 0.01;
+/// Boundary direction around a kink is measured over this share of mask width
+const KINK_REACH_SHARE: f64 = 0.05;
+/// Boundary should turn by at least this angle at a wall corner
+const MIN_CORNER_ANGLE_DEGREES: f64 = 10.0;
+/// Ceiling and floor kinks of the same wall corner can be this share of mask width apart
+const CORNER_MATCH_TOLERANCE_SHARE: f64 = 0.05;
+/// Boundary points closer than this share of mask width to a wall corner are not used for
+/// fitting wall edges, since segmentation rounds the corners
+const FIT_MARGIN_SHARE: f64 = 0.02;
+/// Boundary points retreating further than this share of mask height from a fitted wall edge
+/// are occluded by furniture
+const OCCLUSION_TOLERANCE_SHARE: f64 = 0.01;
+const FIT_ITERATIONS: usize = 3;
+/// Vertical edges of the room photo can be tilted by this angle
+const MAX_VERTICAL_EDGE_TILT_DEGREES: u32 = 10;
+/// Vertical edge of the room photo should be supported by this share of mask height
+const VERTICAL_EDGE_VOTE_SHARE: f64 = 0.2;
+const VERTICAL_EDGE_SUPPRESSION_RADIUS: u32 = 8;
+/// Wall corners snap to vertical edges of the room photo within this share of mask width
+const SNAP_TOLERANCE_SHARE: f64 = 0.02;
+const CANNY_LOW_THRESHOLD: f32 = 20.0;
+const CANNY_HIGH_THRESHOLD: f32 = 50.0;
+
+/// Estimates wall polygons from the wall segmentation mask alone, for photos whose layout
+/// estimation results can not be parsed. Concave corners of the mask boundary, where both
+/// ceiling and floor edges turn, are taken as vertical edges between walls. If the room photo
+/// is given, these are snapped to straight vertical edges found in it.
+///
+/// Polygons are in mask coordinates, i.e. layout size is the mask size. Returns no polygons if
+/// the mask has no walls.
+pub fn estimate_wall_polygons_from_mask(
+    mask_image: &GrayImage,
+    room_image: Option<&RgbImage>,
+) -> Vec<WallPolygon> {
+    let (width, height) = mask_image.dimensions();
+    let mask_image = prepare_wall_mask(mask_image, width, height);
+    let Some(profile) = BoundaryProfile::from_mask(&mask_image) else {
+        println!("No walls found in the mask");
+        return vec![];
+    };
+
+    let mut corners = profile.find_wall_corners();
+    let mut polygons = match profile.fit_wall_polygons(&corners) {
+        Some(polygons) => polygons,
+        None => {
+            println!("Could not fit walls between {} mask corners", corners.len());
+            corners.clear();
+            match profile.fit_wall_polygons(&corners) {
+                Some(polygons) => polygons,
+                None => return vec![],
+            }
+        }
+    };
+
+    if let Some(room_image) = room_image {
+        let vertical_edges = detect_vertical_edges(room_image, width, height);
+        snap_to_vertical_edges(&mut polygons, &vertical_edges, width);
+    }
+    polygons
+        .iter()
+        .map(|polygon| WallPolygon {
+            top_left: rounded(polygon.top_left),
+            top_right: rounded(polygon.top_right),
+            bottom_right: rounded(polygon.bottom_right),
+            bottom_left: rounded(polygon.bottom_left),
+        })
+        .collect()
+}
+
+/// Room type and lines in LSUN order, from which [crate::polygons::compute_wall_polygons]
+/// computes walls like the given ones. Lines which are not used by wall polygons of the room
+/// type connect the corresponding junctions.
+pub fn lsun_layout_from_polygons(polygons: &[WallPolygon]) -> Option<(u8, Vec<Line>)> {
+    match polygons {
+        [wall] => Some((
+            6,
+            vec![
+                (wall.top_left, wall.top_right),
+                (wall.bottom_left, wall.bottom_right),
+            ],
+        )),
+        [left, right] => Some((
+            5,
+            vec![
+                (left.top_right, left.top_left),
+                (right.top_left, right.top_right),
+                (left.top_right, left.bottom_right),
+                (left.bottom_right, left.bottom_left),
+                (right.bottom_left, right.bottom_right),
+            ],
+        )),
+        [left, center, right] => Some((
+            0,
+            vec![
+                (left.top_right, left.top_left),
+                (left.bottom_right, left.bottom_left),
+                (right.bottom_left, right.bottom_right),
+                (right.top_left, right.top_right),
+                (center.top_left, center.bottom_left),
+                (center.top_left, center.top_right),
+                (center.bottom_right, center.top_right),
+                (center.bottom_left, center.bottom_right),
+            ],
+        )),
+        _ => None,
+    }
+}
+
+/// Wall edge `y = intercept + slope * x` in image coordinates.
+#[derive(Clone, Copy, Debug)]
+struct BoundaryLine {
+    intercept: f64,
+    slope: f64,
+}
+
+impl BoundaryLine {
+    /// Least squares fit.
+    fn fit(points: &[(f64, f64)]) -> Option<Self> {
+        let n = points.len() as f64;
+        if points.len() < 2 {
+            return None;
+        }
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let covariance: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+        let variance: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+        if variance == 0.0 {
+            return None;
+        }
+        let slope = covariance / variance;
+        Some(Self {
+            intercept: mean_y - slope * mean_x,
+            slope,
+        })
+    }
+
+    /// Fit which drops points retreating from the line into the wall by more than `tolerance`.
+    /// `outward` is `-1` for ceiling edges and `1` for floor edges.
+    fn fit_unoccluded(points: &[(f64, f64)], outward: f64, tolerance: f64) -> Option<Self> {
+        let mut inliers = points.to_vec();
+        for _ in 0..FIT_ITERATIONS {
+            let line = Self::fit(&inliers)?;
+            let kept: Vec<(f64, f64)> = inliers
+                .iter()
+                .copied()
+                .filter(|p| outward * (p.1 - line.y_at(p.0)) > -tolerance)
+                .collect();
+            if kept.len() == inliers.len() || kept.len() < 2 {
+                break;
+            }
+            inliers = kept;
+        }
+        Self::fit(&inliers)
+    }
+
+    fn y_at(&self, x: f64) -> f64 {
+        self.intercept + self.slope * x
+    }
+
+    fn point_at(&self, x: f64) -> (f64, f64) {
+        (x, self.y_at(x))
+    }
+
+    /// Intersection if it is within `tolerance` from `near_x`.
+    fn intersection_near(&self, other: &Self, near_x: f64, tolerance: f64) -> Option<(f64, f64)> {
+        let slope_diff = self.slope - other.slope;
+        if slope_diff.abs() < f64::EPSILON {
+            return None;
+        }
+        let x = (other.intercept - self.intercept) / slope_diff;
+        ((x - near_x).abs() <= tolerance).then(|| self.point_at(x))
+    }
+}
+
+/// Vertical edge between two walls, as x of its ceiling and floor ends.
+#[derive(Clone, Copy, Debug)]
+struct WallCorner {
+    top_x: f64,
+    bottom_x: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct FloatPolygon {
+    top_left: (f64, f64),
+    top_right: (f64, f64),
+    bottom_right: (f64, f64),
+    bottom_left: (f64, f64),
+}
+
+/// Topmost and bottommost wall pixel of each mask column.
+struct BoundaryProfile {
+    top: Vec<Option<f64>>,
+    bottom: Vec<Option<f64>>,
+    min_x: usize,
+    max_x: usize,
+    width: f64,
+    height: f64,
+}
+
+impl BoundaryProfile {
+    fn from_mask(mask_image: &GrayImage) -> Option<Self> {
+        let (width, height) = mask_image.dimensions();
+        let min_contour_length =
+            (MIN_CONTOUR_LENGTH_SHARE * 2.0 * (width + height) as f64) as usize;
+
+        let mut top: Vec<Option<f64>> = vec![None; width as usize];
+        let mut bottom: Vec<Option<f64>> = vec![None; width as usize];
+        // Contour tracing does not start at image border, so walls touching it need padding
+        let mut padded_mask = GrayImage::new(width + 2, height + 2);
+        image::imageops::replace(&mut padded_mask, mask_image, 1, 1);
+        let contours = find_contours::<i32>(&padded_mask);
+        let outer_contours = contours.iter().filter(|contour| {
+            contour.border_type == BorderType::Outer && contour.points.len() >= min_contour_length
+        });
+        for contour in outer_contours {
+            for point in &contour.points {
+                let x = point.x as usize - 1;
+                let y = (point.y - 1) as f64;
+                top[x] = Some(top[x].map_or(y, |top_y| top_y.min(y)));
+                bottom[x] = Some(bottom[x].map_or(y, |bottom_y| bottom_y.max(y)));
+            }
+        }
+
+        let min_x = top.iter().position(Option::is_some)?;
+        let max_x = top.iter().rposition(Option::is_some)?;
+        if min_x == max_x {
+            return None;
+        }
+        Some(Self {
+            top,
+            bottom,
+            min_x,
+            max_x,
+            width: width as f64,
+            height: height as f64,
+        })
+    }
+
+    /// Concave corners of the mask where both ceiling and floor edges turn, left to right.
+    fn find_wall_corners(&self) -> Vec<WallCorner> {
+        let top_kinks = self.concave_kinks(&self.top, 1.0);
+        let bottom_kinks = self.concave_kinks(&self.bottom, -1.0);
+        let match_tolerance = CORNER_MATCH_TOLERANCE_SHARE * self.width;
+
+        let mut candidates: Vec<(WallCorner, f64)> = top_kinks
+            .iter()
+            .filter_map(|&(top_x, top_turn)| {
+                bottom_kinks
+                    .iter()
+                    .filter(|(bottom_x, _)| (bottom_x - top_x).abs() <= match_tolerance)
+                    .min_by(|a, b| (a.0 - top_x).abs().total_cmp(&(b.0 - top_x).abs()))
+                    .map(|&(bottom_x, bottom_turn)| {
+                        (WallCorner { top_x, bottom_x }, top_turn + bottom_turn)
+                    })
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut corners: Vec<WallCorner> = vec![];
+        for (candidate, _) in candidates {
+            if corners.len() == MAX_WALLS - 1 {
+                break;
+            }
+            // Kinks of both edges should be on the same side of other corners
+            let is_consistent = corners.iter().all(|corner| {
+                (corner.top_x < candidate.top_x) == (corner.bottom_x < candidate.bottom_x)
+                    && (corner.top_x - candidate.top_x).abs() > match_tolerance
+            });
+            if is_consistent {
+                corners.push(candidate);
+            }
+        }
+        corners.sort_by(|a, b| a.top_x.total_cmp(&b.top_x));
+        corners
+    }
+
+    /// X and turn angle of kinks where the edge bends towards the wall, i.e. down for the
+    /// ceiling edge (`inward = 1`) and up for the floor edge (`inward = -1`).
+    fn concave_kinks(&self, edge: &[Option<f64>], inward: f64) -> Vec<(f64, f64)> {
+        let points: Vec<ContourPoint<i32>> = edge
+            .iter()
+            .enumerate()
+            .filter_map(|(x, y)| y.map(|y| ContourPoint::new(x as i32, y as i32)))
+            .collect();
+        let epsilon = SIMPLIFICATION_EPSILON_SHARE * self.width;
+        let vertices = approximate_polygon_dp(&points, epsilon, false);
+
+        let reach = (KINK_REACH_SHARE * self.width).max(1.0) as usize;
+        let mut kinks: Vec<(f64, f64)> = vec![];
+        for vertex in vertices {
+            let x = vertex.x as usize;
+            if x < self.min_x + reach || x + reach > self.max_x {
+                continue;
+            }
+            let (Some(before), Some(at), Some(after)) = (
+                value_near(edge, x - reach, reach),
+                value_near(edge, x, reach),
+                value_near(edge, x + reach, reach),
+            ) else {
+                continue;
+            };
+            let angle_in = (at.1 - before.1).atan2(at.0 - before.0);
+            let angle_out = (after.1 - at.1).atan2(after.0 - at.0);
+            let turn = inward * (angle_in - angle_out);
+            if turn.to_degrees() < MIN_CORNER_ANGLE_DEGREES {
+                continue;
+            }
+            // Simplification may leave several vertices around the same rounded corner
+            match kinks.last_mut() {
+                Some(last) if at.0 - last.0 < reach as f64 => {
+                    if turn > last.1 {
+                        *last = (at.0, turn);
+                    }
+                }
+                _ => kinks.push((at.0, turn)),
+            }
+        }
+        kinks
+    }
+
+    /// Quads of walls separated by the corners, with ceiling and floor edges fitted to the mask
+    /// boundary between them.
+    fn fit_wall_polygons(&self, corners: &[WallCorner]) -> Option<Vec<FloatPolygon>> {
+        let min_x = self.min_x as f64;
+        let max_x = self.max_x as f64;
+        let margin = FIT_MARGIN_SHARE * self.width;
+        let tolerance = OCCLUSION_TOLERANCE_SHARE * self.height;
+
+        let top_bounds: Vec<f64> = [min_x]
+            .into_iter()
+            .chain(corners.iter().map(|corner| corner.top_x))
+            .chain([max_x])
+            .collect();
+        let bottom_bounds: Vec<f64> = [min_x]
+            .into_iter()
+            .chain(corners.iter().map(|corner| corner.bottom_x))
+            .chain([max_x])
+            .collect();
+
+        let mut top_lines = vec![];
+        let mut bottom_lines = vec![];
+        for wall_idx in 0..=corners.len() {
+            let top_points = edge_points(&self.top, &top_bounds[wall_idx..=wall_idx + 1], margin);
+            top_lines.push(BoundaryLine::fit_unoccluded(&top_points, -1.0, tolerance)?);
+            let bottom_points = edge_points(
+                &self.bottom,
+                &bottom_bounds[wall_idx..=wall_idx + 1],
+                margin,
+            );
+            bottom_lines.push(BoundaryLine::fit_unoccluded(
+                &bottom_points,
+                1.0,
+                tolerance,
+            )?);
+        }
+
+        let match_tolerance = CORNER_MATCH_TOLERANCE_SHARE * self.width;
+        let mut top_points = vec![top_lines[0].point_at(min_x)];
+        let mut bottom_points = vec![bottom_lines[0].point_at(min_x)];
+        for (corner_idx, corner) in corners.iter().enumerate() {
+            let (left, right) = (corner_idx, corner_idx + 1);
+            let top = top_lines[left]
+                .intersection_near(&top_lines[right], corner.top_x, match_tolerance)
+                .unwrap_or_else(|| {
+                    let y = (top_lines[left].y_at(corner.top_x)
+                        + top_lines[right].y_at(corner.top_x))
+                        / 2.0;
+                    (corner.top_x, y)
+                });
+            let bottom = bottom_lines[left]
+                .intersection_near(&bottom_lines[right], corner.bottom_x, match_tolerance)
+                .unwrap_or_else(|| {
+                    let y = (bottom_lines[left].y_at(corner.bottom_x)
+                        + bottom_lines[right].y_at(corner.bottom_x))
+                        / 2.0;
+                    (corner.bottom_x, y)
+                });
+            top_points.push(top);
+            bottom_points.push(bottom);
+        }
+        top_points.push(top_lines[corners.len()].point_at(max_x));
+        bottom_points.push(bottom_lines[corners.len()].point_at(max_x));
+
+        let polygons = (0..=corners.len())
+            .map(|wall_idx| FloatPolygon {
+                top_left: top_points[wall_idx],
+                top_right: top_points[wall_idx + 1],
+                bottom_right: bottom_points[wall_idx + 1],
+                bottom_left: bottom_points[wall_idx],
+            })
+            .collect();
+        Some(polygons)
+    }
+}
+
+/// Closest defined edge point within `radius` columns from `x`.
+fn value_near(edge: &[Option<f64>], x: usize, radius: usize) -> Option<(f64, f64)> {
+    (0..=radius).find_map(|offset| {
+        let left = x
+            .checked_sub(offset)
+            .and_then(|x| Some((x, edge.get(x)?.as_ref()?)));
+        let right = edge.get(x + offset)?.as_ref().map(|y| (x + offset, y));
+        left.or(right).map(|(x, y)| (x as f64, *y))
+    })
+}
+
+/// Edge points between `bounds` except for the `margin` around them, or all points between
+/// bounds if the wall is too narrow.
+fn edge_points(edge: &[Option<f64>], bounds: &[f64], margin: f64) -> Vec<(f64, f64)> {
+    let (start, end) = (bounds[0], bounds[1]);
+    let points_between = |margin: f64| -> Vec<(f64, f64)> {
+        edge.iter()
+            .enumerate()
+            .filter_map(|(x, y)| Some((x as f64, (*y)?)))
+            .filter(|(x, _)| *x >= start + margin && *x <= end - margin)
+            .collect()
+    };
+    let points = points_between(margin);
+    if points.len() >= 2 {
+        points
+    } else {
+        points_between(0.0)
+    }
+}
+
+/// Near-vertical straight edges of the room photo resized to `width` x `height`.
+fn detect_vertical_edges(room_image: &RgbImage, width: u32, height: u32) -> Vec<PolarLine> {
+    let gray_image = DynamicImage::from(room_image.clone()).into_luma8();
+    let gray_image = image::imageops::resize(&gray_image, width, height, FilterType::Triangle);
+    let edges_image =
+        imageproc::edges::canny(&gray_image, CANNY_LOW_THRESHOLD, CANNY_HIGH_THRESHOLD);
+    let options = LineDetectionOptions {
+        vote_threshold: (VERTICAL_EDGE_VOTE_SHARE * height as f64) as u32,
+        suppression_radius: VERTICAL_EDGE_SUPPRESSION_RADIUS,
+    };
+    detect_lines(&edges_image, options)
+        .into_iter()
+        // Angle is the direction of line normal
+        .filter(|line| {
+            line.angle_in_degrees <= MAX_VERTICAL_EDGE_TILT_DEGREES
+                || line.angle_in_degrees >= 180 - MAX_VERTICAL_EDGE_TILT_DEGREES
+        })
+        .collect()
+}
+
+fn vertical_edge_x_at(line: &PolarLine, y: f64) -> f64 {
+    let angle = (line.angle_in_degrees as f64).to_radians();
+    (line.r as f64 - y * angle.sin()) / angle.cos()
+}
+
+/// Moves vertical edges shared by neighbouring walls onto the closest photo edge.
+fn snap_to_vertical_edges(polygons: &mut [FloatPolygon], vertical_edges: &[PolarLine], width: u32) {
+    let tolerance = SNAP_TOLERANCE_SHARE * width as f64;
+    for wall_idx in 1..polygons.len() {
+        let top = polygons[wall_idx].top_left;
+        let bottom = polygons[wall_idx].bottom_left;
+        let closest_edge = vertical_edges
+            .iter()
+            .map(|edge| {
+                let distance = (vertical_edge_x_at(edge, top.1) - top.0)
+                    .abs()
+                    .max((vertical_edge_x_at(edge, bottom.1) - bottom.0).abs());
+                (edge, distance)
+            })
+            .filter(|(_, distance)| *distance <= tolerance)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((edge, _)) = closest_edge {
+            let top = (vertical_edge_x_at(edge, top.1), top.1);
+            let bottom = (vertical_edge_x_at(edge, bottom.1), bottom.1);
+            polygons[wall_idx - 1].top_right = top;
+            polygons[wall_idx - 1].bottom_right = bottom;
+            polygons[wall_idx].top_left = top;
+            polygons[wall_idx].bottom_left = bottom;
+        }
+    }
+}
+
+fn rounded(point: (f64, f64)) -> Point {
+    (point.0.round() as i32, point.1.round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::evaluate_layout;
+    use crate::layout::{RoomLayout, RoomType};
+    use crate::mask_layout::{estimate_wall_polygons_from_mask, lsun_layout_from_polygons};
+    use crate::polygons::compute_wall_polygons;
+    use crate::synthetic::SyntheticRoom;
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    /// Mask of walls between ceiling and floor edges given as functions of x.
+    fn mask(
+        width: u32,
+        height: u32,
+        top: impl Fn(f64) -> f64,
+        bottom: impl Fn(f64) -> f64,
+    ) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x as f64, y as f64);
+            if y >= top(x) && y <= bottom(x) {
+                Luma([255])
+            } else {
+                Luma([0])
+            }
+        })
+    }
+
+    fn two_walls_mask() -> GrayImage {
+        mask(
+            512,
+            512,
+            |x| {
+                if x <= 300.0 {
+                    60.0 + 0.25 * x
+                } else {
+                    135.0 - 0.3 * (x - 300.0)
+                }
+            },
+            |x| {
+                if x <= 300.0 {
+                    470.0 - 0.2 * x
+                } else {
+                    410.0 + 0.25 * (x - 300.0)
+                }
+            },
+        )
+    }
+
+    fn assert_near(actual: (i32, i32), expected: (i32, i32), tolerance: i32) {
+        assert!(
+            (actual.0 - expected.0).abs() <= tolerance
+                && (actual.1 - expected.1).abs() <= tolerance,
+            "{actual:?} is not near {expected:?}"
+        );
+    }
+
+    #[test]
+    fn estimates_single_wall() {
+        let mask_image = mask(512, 512, |x| 100.0 + 0.1 * x, |_| 400.0);
+
+        let polygons = estimate_wall_polygons_from_mask(&mask_image, None);
+
+        assert_eq!(polygons.len(), 1);
+        assert_near(polygons[0].top_left, (0, 100), 2);
+        assert_near(polygons[0].top_right, (511, 151), 2);
+        assert_near(polygons[0].bottom_right, (511, 400), 2);
+        assert_near(polygons[0].bottom_left, (0, 400), 2);
+    }
+
+    #[test]
+    fn finds_corner_between_two_walls() {
+        let polygons = estimate_wall_polygons_from_mask(&two_walls_mask(), None);
+
+        assert_eq!(polygons.len(), 2);
+        assert_near(polygons[0].top_right, (300, 135), 3);
+        assert_near(polygons[0].bottom_right, (300, 410), 3);
+        assert_eq!(polygons[0].top_right, polygons[1].top_left);
+        assert_eq!(polygons[0].bottom_right, polygons[1].bottom_left);
+        assert_near(polygons[0].top_left, (0, 60), 3);
+        assert_near(polygons[1].bottom_right, (511, 463), 3);
+    }
+
+    #[test]
+    fn ignores_furniture_occluding_the_floor() {
+        let mut mask_image = two_walls_mask();
+        // Sofa in front of the left wall
+        for x in 80..220 {
+            for y in 330..512 {
+                mask_image.put_pixel(x, y, Luma([0]));
+            }
+        }
+
+        let polygons = estimate_wall_polygons_from_mask(&mask_image, None);
+
+        assert_eq!(polygons.len(), 2);
+        assert_near(polygons[0].bottom_left, (0, 470), 3);
+        assert_near(polygons[0].bottom_right, (300, 410), 3);
+    }
+
+    #[test]
+    fn snaps_corner_to_vertical_edge_of_room_photo() {
+        // Walls are differently lit, with the corner slightly off the mask kink
+        let room_image = RgbImage::from_fn(512, 512, |x, _| {
+            if x < 306 {
+                Rgb([200, 200, 200])
+            } else {
+                Rgb([120, 120, 120])
+            }
+        });
+
+        let polygons = estimate_wall_polygons_from_mask(&two_walls_mask(), Some(&room_image));
+
+        assert_eq!(polygons.len(), 2);
+        assert!((polygons[0].top_right.0 - 306).abs() <= 1);
+        assert!((polygons[0].bottom_right.0 - 306).abs() <= 1);
+        assert_eq!(polygons[0].top_right, polygons[1].top_left);
+    }
+
+    #[test]
+    fn converts_mask_walls_to_lsun_layout() {
+        let polygons = estimate_wall_polygons_from_mask(&two_walls_mask(), None);
+
+        let (room_type, lines) = lsun_layout_from_polygons(&polygons).unwrap();
+        let recomputed = compute_wall_polygons(&lines, 512, 512, room_type).unwrap();
+
+        assert_eq!(room_type, 5);
+        assert_eq!(recomputed[0].top_right, polygons[0].top_right);
+        assert_eq!(recomputed[0].bottom_right, polygons[0].bottom_right);
+        assert_eq!(recomputed[1].top_left, polygons[1].top_left);
+    }
+
+    #[test]
+    fn estimates_wall_polygons_of_synthetic_rooms() {
+        // Mask has to show both ceiling and floor edges of each wall, so the corner view is
+        // not furnished
+        let rooms = [
+            SyntheticRoom::new(RoomType::Type0).with_furniture(),
+            SyntheticRoom::new(RoomType::Type5),
+            SyntheticRoom::new(RoomType::Type6).with_furniture(),
+            SyntheticRoom::new(RoomType::Type9).with_furniture(),
+        ];
+
+        for room in rooms {
+            let rendered = room.render();
+
+            let polygons =
+                estimate_wall_polygons_from_mask(&rendered.wall_mask, Some(&rendered.photo));
+
+            assert_eq!(polygons.len(), rendered.layout.wall_polygons.len());
+            let estimated_layout = RoomLayout {
+                wall_polygons: polygons,
+                ..rendered.layout.clone()
+            };
+            let evaluation = evaluate_layout(&estimated_layout, &rendered.layout);
+            assert!(
+                evaluation.pixel_error < 0.01,
+                "{:?}: {}",
+                room.room_type,
+                evaluation.pixel_error
+            );
+        }
+    }
+}
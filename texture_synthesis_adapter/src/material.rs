@@ -0,0 +1,287 @@
+use image::imageops::FilterType;
+use image::{GrayImage, Rgb, RgbImage};
+use std::ops::Range;
+
+use crate::preview::NO_WALL_ID;
+
+/// Light elevation above the wall plane used for relief shading
+const LIGHT_ELEVATION_DEGREES: f32 = 45.0;
+/// Walls with weaker average brightness gradient are assumed to be lit from the front-top
+const MIN_LIGHT_GRADIENT: f32 = 0.05;
+const DEFAULT_RELIEF_STRENGTH: f32 = 1.0;
+/// How steep relief generated from height maps is
+const HEIGHT_MAP_BUMP_SCALE: f32 = 8.0;
+
+/// Wallpaper material, which consists of color tile and optional relief of embossed, vinyl or
+/// grasscloth wallpapers.
+#[derive(Clone)]
+pub struct WallpaperMaterial {
+    pub color: RgbImage,
+    /// Tangent space normal map (OpenGL convention, Y up), same size as [color]
+    pub normal_map: Option<RgbImage>,
+    /// How much relief affects the wallpaper brightness, 1.0 is physically based shading
+    pub relief_strength: f32,
+}
+
+impl From<RgbImage> for WallpaperMaterial {
+    fn from(color: RgbImage) -> Self {
+        Self {
+            color,
+            normal_map: None,
+            relief_strength: DEFAULT_RELIEF_STRENGTH,
+        }
+    }
+}
+
+impl WallpaperMaterial {
+    /// Material with relief given as tangent space normal map, tiled the same way as color.
+    pub fn with_normal_map(color: RgbImage, normal_map: RgbImage, relief_strength: f32) -> Self {
+        let normal_map = image::imageops::resize(
+            &normal_map,
+            color.width(),
+            color.height(),
+            FilterType::Triangle,
+        );
+        Self {
+            color,
+            normal_map: Some(normal_map),
+            relief_strength,
+        }
+    }
+
+    /// Material with relief given as height map, where white is the highest point.
+    pub fn with_height_map(color: RgbImage, height_map: GrayImage, relief_strength: f32) -> Self {
+        let height_map = image::imageops::resize(
+            &height_map,
+            color.width(),
+            color.height(),
+            FilterType::Triangle,
+        );
+        let normal_map = height_map_to_normal_map(&height_map);
+        Self {
+            color,
+            normal_map: Some(normal_map),
+            relief_strength,
+        }
+    }
+}
+
+/// Converts height map to tangent space normal map. Height map is treated as tiling, so normals
+/// at borders are computed using pixels from the opposite side.
+pub fn height_map_to_normal_map(height_map: &GrayImage) -> RgbImage {
+    let width = height_map.width() as i64;
+    let height = height_map.height() as i64;
+    let height_at = |x: i64, y: i64| {
+        let x = x.rem_euclid(width) as u32;
+        let y = y.rem_euclid(height) as u32;
+        height_map.get_pixel(x, y).0[0] as f32 / 255.0
+    };
+
+    let mut normal_map = RgbImage::new(height_map.width(), height_map.height());
+    for (x, y, normal_pixel) in normal_map.enumerate_pixels_mut() {
+        let (x, y) = (x as i64, y as i64);
+        let dx = (height_at(x + 1, y) - height_at(x - 1, y)) / 2.0;
+        let dy = (height_at(x, y + 1) - height_at(x, y - 1)) / 2.0;
+        // Image Y axis points down, while normal map Y axis points up
+        let normal = normalize((-dx * HEIGHT_MAP_BUMP_SCALE, dy * HEIGHT_MAP_BUMP_SCALE, 1.0));
+        *normal_pixel = encode_normal(normal);
+    }
+    normal_map
+}
+
+pub(crate) fn encode_normal(normal: (f32, f32, f32)) -> Rgb<u8> {
+    let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8;
+    Rgb([encode(normal.0), encode(normal.1), encode(normal.2)])
+}
+
+/// Decodes tangent space normal from normal map color in `[0; 1]` range, flipping its Y axis to
+/// point down the tile, same as tile image rows. See [orient_normal] for its image space normal.
+pub(crate) fn decode_normal(rgb: (f32, f32, f32)) -> (f32, f32, f32) {
+    normalize((rgb.0 * 2.0 - 1.0, -(rgb.1 * 2.0 - 1.0), rgb.2 * 2.0 - 1.0))
+}
+
+/// Rotates tangent space normal onto the wall in the image, where wallpaper X and Y axes point
+/// along `u_axis` and `v_axis`, to get its image space normal (Y down, Z towards the camera).
+pub(crate) fn orient_normal(
+    normal: (f32, f32, f32),
+    u_axis: (f32, f32),
+    v_axis: (f32, f32),
+) -> (f32, f32, f32) {
+    let u_axis = normalize((u_axis.0, u_axis.1, 0.0));
+    let v_axis = normalize((v_axis.0, v_axis.1, 0.0));
+    normalize((
+        normal.0 * u_axis.0 + normal.1 * v_axis.0,
+        normal.0 * u_axis.1 + normal.1 * v_axis.1,
+        normal.2,
+    ))
+}
+
+/// Estimates light direction for each wall in image space (Y down, Z towards the camera) from
+/// how wall brightness changes across the wall: light comes from the brighter side. Average
+/// brightness gradient of each wall is accumulated over image strips.
+pub(crate) struct LightGradients {
+    gradient_sums: Vec<(f32, f32)>,
+    gradient_counts: Vec<usize>,
+}
+
+impl LightGradients {
+    pub fn new(num_walls: usize) -> Self {
+        Self {
+            gradient_sums: vec![(0f32, 0f32); num_walls],
+            gradient_counts: vec![0usize; num_walls],
+        }
+    }
+
+    /// Adds gradients of the given rows. Rows right above and below them must be present in
+    /// the images too.
+    pub fn accumulate(
+        &mut self,
+        hsv_values_image: &GrayImage,
+        wall_ids: &GrayImage,
+        rows: Range<u32>,
+    ) {
+        let width = wall_ids.width();
+        for y in rows {
+            for x in 1..width.saturating_sub(1) {
+                let wall = wall_ids.get_pixel(x, y).0[0];
+                if wall == NO_WALL_ID {
+                    continue;
+                }
+                let is_same_wall = |x: u32, y: u32| wall_ids.get_pixel(x, y).0[0] == wall;
+                if !(is_same_wall(x - 1, y)
+                    && is_same_wall(x + 1, y)
+                    && is_same_wall(x, y - 1)
+                    && is_same_wall(x, y + 1))
+                {
+                    continue;
+                }
+
+                let value = |x: u32, y: u32| hsv_values_image.get_pixel(x, y).0[0] as f32;
+                let gradient_x = (value(x + 1, y) - value(x - 1, y)) / 2.0;
+                let gradient_y = (value(x, y + 1) - value(x, y - 1)) / 2.0;
+
+                let wall = wall as usize;
+                self.gradient_sums[wall].0 += gradient_x;
+                self.gradient_sums[wall].1 += gradient_y;
+                self.gradient_counts[wall] += 1;
+            }
+        }
+    }
+
+    pub fn light_directions(&self) -> Vec<(f32, f32, f32)> {
+        let elevation = LIGHT_ELEVATION_DEGREES.to_radians();
+        self.gradient_sums
+            .iter()
+            .zip(self.gradient_counts.iter())
+            .map(|(sum, count)| {
+                let count = usize::max(*count, 1) as f32;
+                let gradient = (sum.0 / count, sum.1 / count);
+                let gradient_length = f32::hypot(gradient.0, gradient.1);
+                let direction = if gradient_length < MIN_LIGHT_GRADIENT {
+                    // Assume ceiling light in front of the wall
+                    (0.0, -1.0)
+                } else {
+                    (gradient.0 / gradient_length, gradient.1 / gradient_length)
+                };
+                (
+                    direction.0 * elevation.cos(),
+                    direction.1 * elevation.cos(),
+                    elevation.sin(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Brightness multiplier of relief with the given image space normal, lit from light direction.
+/// Flat surface has multiplier of 1.0.
+pub(crate) fn relief_shading_factor(
+    normal: (f32, f32, f32),
+    light_direction: (f32, f32, f32),
+    relief_strength: f32,
+) -> f32 {
+    let flat_lighting = f32::max(light_direction.2, f32::EPSILON);
+    let lighting = f32::max(dot(normal, light_direction), 0.0);
+    1.0 + relief_strength * (lighting / flat_lighting - 1.0)
+}
+
+fn dot(a: (f32, f32, f32), b: (f32, f32, f32)) -> f32 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+fn normalize(v: (f32, f32, f32)) -> (f32, f32, f32) {
+    let length = dot(v, v).sqrt();
+    if length == 0.0 {
+        return (0.0, 0.0, 1.0);
+    }
+    (v.0 / length, v.1 / length, v.2 / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::material::{
+        decode_normal, height_map_to_normal_map, orient_normal, relief_shading_factor,
+        LightGradients,
+    };
+    use image::{GrayImage, Luma, Rgb};
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    #[test]
+    fn flat_height_map_produces_flat_normals() {
+        let height_map = GrayImage::from_pixel(8, 8, Luma([100]));
+        let normal_map = height_map_to_normal_map(&height_map);
+        assert_eq!(*normal_map.get_pixel(3, 3), Rgb([128, 128, 255]));
+    }
+
+    #[test]
+    fn relief_facing_light_gets_brighter() {
+        // Light comes from the left
+        let light_direction = (-FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2);
+        let flat_normal = decode_normal((0.5, 0.5, 1.0));
+        let left_facing_normal = (-0.5, 0.0, 0.866);
+        let right_facing_normal = (0.5, 0.0, 0.866);
+
+        let flat_factor = relief_shading_factor(flat_normal, light_direction, 1.0);
+        assert!((flat_factor - 1.0).abs() < 0.01);
+        assert!(relief_shading_factor(left_facing_normal, light_direction, 1.0) > 1.0);
+        assert!(relief_shading_factor(right_facing_normal, light_direction, 1.0) < 1.0);
+        assert_eq!(
+            relief_shading_factor(right_facing_normal, light_direction, 0.0),
+            1.0
+        );
+    }
+
+    #[test]
+    fn orients_normals_along_wall_axes() {
+        let right_facing_normal = (0.6, 0.0, 0.8);
+        // Wallpaper X axis runs up the image, e.g. on a wall rotated by 90 degrees
+        let normal = orient_normal(right_facing_normal, (0.0, -2.0), (3.0, 0.0));
+        assert!(normal.0.abs() < 1e-6, "{normal:?}");
+        assert!((normal.1 + 0.6).abs() < 1e-6, "{normal:?}");
+        assert!((normal.2 - 0.8).abs() < 1e-6, "{normal:?}");
+
+        // Frontal wall keeps the normal as is
+        let normal = orient_normal(right_facing_normal, (1.5, 0.0), (0.0, 1.5));
+        assert!((normal.0 - 0.6).abs() < 1e-6, "{normal:?}");
+        assert!(normal.1.abs() < 1e-6, "{normal:?}");
+    }
+
+    #[test]
+    fn estimates_light_from_brighter_side_of_the_wall() {
+        let mut hsv_values_image = GrayImage::new(32, 32);
+        for (x, _, pixel) in hsv_values_image.enumerate_pixels_mut() {
+            // Brighter on the right
+            *pixel = Luma([(100 + x * 4) as u8]);
+        }
+        let wall_ids = GrayImage::from_pixel(32, 32, Luma([0]));
+
+        let mut light_gradients = LightGradients::new(1);
+        light_gradients.accumulate(&hsv_values_image, &wall_ids, 1..31);
+        let light_directions = light_gradients.light_directions();
+
+        let light_direction = light_directions[0];
+        assert!(light_direction.0 > 0.5);
+        assert!(light_direction.1.abs() < 0.01);
+        assert!(light_direction.2 > 0.5);
+    }
+}
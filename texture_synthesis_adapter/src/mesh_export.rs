@@ -0,0 +1,610 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::RgbImage;
+
+use crate::layout::lsun::Point;
+use crate::layout::RoomType;
+use crate::material::WallpaperMaterial;
+use crate::polygons::WallPolygon;
+use crate::preview::{WALLPAPER_TILE_HEIGHT_METERS, WALLPAPER_TILE_WIDTH_METERS};
+use crate::reconstruction::{cross, dot, scaled, sub, RoomReconstruction, Vector3};
+
+const OBJ_FILE_NAME: &str = "room.obj";
+const MTL_FILE_NAME: &str = "room.mtl";
+const WALLPAPER_TEXTURE_FILE_NAME: &str = "wallpaper.png";
+const WALLPAPER_NORMAL_MAP_FILE_NAME: &str = "wallpaper_normal.png";
+const PHOTO_TEXTURE_FILE_NAME: &str = "photo.png";
+const WALLPAPER_MATERIAL_NAME: &str = "wallpaper";
+const PHOTO_MATERIAL_NAME: &str = "photo";
+
+/// Camera height above the floor, which gives the reconstruction its physical scale
+pub const DEFAULT_CAMERA_HEIGHT_METERS: f32 = 1.5;
+/// Texture coordinates are interpolated linearly within triangles, so triangles with the photo
+/// projected onto them are split into this many rows to keep the projection close to perspective
+const PHOTO_TESSELLATION: usize = 8;
+/// Floor and ceiling polygon corners closer than this are merged
+const MIN_CORNER_DISTANCE_METERS: f64 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceKind {
+    Wall(usize),
+    Floor,
+    Ceiling,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SurfaceMaterial {
+    /// Wallpaper tile repeated at its physical size
+    Wallpaper,
+    /// Room photo projected from the camera
+    Photo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshVertex {
+    /// Room coordinates in meters
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshSurface {
+    pub kind: SurfaceKind,
+    pub material: SurfaceMaterial,
+    /// Unit normal facing into the room
+    pub normal: [f32; 3],
+    pub vertices: Vec<MeshVertex>,
+    /// Vertex indices, counter-clockwise when seen from the room
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl MeshSurface {
+    pub fn name(&self) -> String {
+        match self.kind {
+            SurfaceKind::Wall(wall) => format!("wall_{wall}"),
+            SurfaceKind::Floor => "floor".to_string(),
+            SurfaceKind::Ceiling => "ceiling".to_string(),
+        }
+    }
+
+    fn new(kind: SurfaceKind, material: SurfaceMaterial, normal: Vector3) -> Self {
+        Self {
+            kind,
+            material,
+            normal: to_f32(normal),
+            vertices: vec![],
+            triangles: vec![],
+        }
+    }
+
+    /// Adds the triangle split into `tessellation` rows of smaller triangles, with texture
+    /// coordinates of each vertex given by `uv`.
+    fn add_triangle(
+        &mut self,
+        corners: [Vector3; 3],
+        tessellation: usize,
+        uv: impl Fn(Vector3) -> [f32; 2],
+    ) {
+        let [origin, first, second] = corners;
+        let first_step = scaled(sub(first, origin), 1.0 / tessellation as f64);
+        let second_step = scaled(sub(second, origin), 1.0 / tessellation as f64);
+        // Vertex (i, j) is `origin + i * first_step + j * second_step`, stored row by row
+        let mut row_starts = vec![];
+        for i in 0..=tessellation {
+            row_starts.push(self.vertices.len());
+            for j in 0..=tessellation - i {
+                let position = [0, 1, 2].map(|axis| {
+                    origin[axis] + i as f64 * first_step[axis] + j as f64 * second_step[axis]
+                });
+                self.vertices.push(MeshVertex {
+                    position: to_f32(position),
+                    uv: uv(position),
+                });
+            }
+        }
+        let vertex = |i: usize, j: usize| row_starts[i] + j;
+        let normal = self.normal.map(|value| value as f64);
+        let face_normal = cross(
+            sub(to_f64(self.vertices[vertex(1, 0)].position), origin),
+            sub(to_f64(self.vertices[vertex(0, 1)].position), origin),
+        );
+        let is_flipped = dot(face_normal, normal) < 0.0;
+        for i in 0..tessellation {
+            for j in 0..tessellation - i {
+                let mut triangles = vec![[vertex(i, j), vertex(i + 1, j), vertex(i, j + 1)]];
+                if i + j + 1 < tessellation {
+                    triangles.push([vertex(i + 1, j), vertex(i + 1, j + 1), vertex(i, j + 1)]);
+                }
+                for [a, b, c] in triangles {
+                    self.triangles
+                        .push(if is_flipped { [a, c, b] } else { [a, b, c] });
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeshOptions {
+    pub camera_height_meters: f32,
+    /// Whether visible floor and ceiling are textured with the room photo instead of wallpaper
+    pub project_photo: bool,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            camera_height_meters: DEFAULT_CAMERA_HEIGHT_METERS,
+            project_photo: false,
+        }
+    }
+}
+
+/// Box room mesh in meters, in room coordinates of [RoomReconstruction::to_room] with the floor
+/// at Y = 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomMesh {
+    pub surfaces: Vec<MeshSurface>,
+    pub camera_position: [f32; 3],
+}
+
+/// Builds the mesh of reconstructed walls, floor and ceiling. Walls span from floor to ceiling
+/// between their left and right polygon edges. Visible floor and ceiling are bounded by the walls
+/// and the image border, invisible ones by the walls and the camera.
+pub fn build_room_mesh(
+    room: &RoomReconstruction,
+    polygons: &[WallPolygon],
+    room_type: RoomType,
+    layout_width: u32,
+    layout_height: u32,
+    options: MeshOptions,
+) -> RoomMesh {
+    let meters = options.camera_height_meters as f64;
+    let to_room = |point: Vector3| scaled(room.to_room(point), meters);
+    let ray = |point: Point| room.camera.ray((point.0 as f64, point.1 as f64));
+    let ceiling_height = (1.0 + room.ceiling.offset) * meters;
+    let tile_width = WALLPAPER_TILE_WIDTH_METERS as f64;
+    let tile_height = WALLPAPER_TILE_HEIGHT_METERS as f64;
+
+    let mut surfaces = vec![];
+    // Bottom corners of the walls from left to right
+    let mut wall_corners: Vec<Vector3> = vec![];
+    let mut u_offset = 0.0;
+    for (wall, polygon) in polygons.iter().enumerate() {
+        let Some(plane) = room.walls.get(wall) else {
+            continue;
+        };
+        // Walls with even index run along room X axis, others along Z
+        let along = if wall.is_multiple_of(2) { 0 } else { 2 };
+        let across = 2 - along;
+        let side = |corners: [Point; 2]| {
+            let values: Vec<f64> = corners
+                .iter()
+                .filter_map(|corner| plane.intersect_ray(ray(*corner)))
+                .map(|point| to_room(point)[along])
+                .collect();
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+        let (Some(left), Some(right)) = (
+            side([polygon.top_left, polygon.bottom_left]),
+            side([polygon.top_right, polygon.bottom_right]),
+        ) else {
+            println!("Skipping wall {wall}, which is not in front of the camera");
+            continue;
+        };
+        let across_value = to_room(scaled(plane.normal, -plane.offset))[across];
+        let corner = |along_value: f64, height: f64| {
+            let mut corner = [0.0; 3];
+            corner[along] = along_value;
+            corner[1] = height;
+            corner[across] = across_value;
+            corner
+        };
+        let normal = sub(room.to_room(plane.normal), room.to_room([0.0; 3]));
+        let mut surface =
+            MeshSurface::new(SurfaceKind::Wall(wall), SurfaceMaterial::Wallpaper, normal);
+        let width = (right - left).abs();
+        // Wallpaper tiles start at the ceiling, with texture V pointing up
+        let wall_uv = |point: Vector3| {
+            [
+                ((u_offset + (point[along] - left).abs()) / tile_width) as f32,
+                (1.0 + (point[1] - ceiling_height) / tile_height) as f32,
+            ]
+        };
+        let (bottom_left, bottom_right) = (corner(left, 0.0), corner(right, 0.0));
+        let (top_left, top_right) = (corner(left, ceiling_height), corner(right, ceiling_height));
+        surface.add_triangle([bottom_left, bottom_right, top_right], 1, wall_uv);
+        surface.add_triangle([bottom_left, top_right, top_left], 1, wall_uv);
+        surfaces.push(surface);
+
+        wall_corners.push(bottom_left);
+        wall_corners.push(bottom_right);
+        u_offset += width;
+    }
+
+    let image_corners = [
+        (layout_width as i32, layout_height as i32),
+        (0, layout_height as i32),
+        (0, 0),
+        (layout_width as i32, 0),
+    ];
+    let horizontal_surfaces = [
+        (
+            SurfaceKind::Floor,
+            room_type.is_floor_visible(),
+            0.0,
+            &room.floor,
+            [image_corners[0], image_corners[1]],
+        ),
+        (
+            SurfaceKind::Ceiling,
+            room_type.is_ceiling_visible(),
+            ceiling_height,
+            &room.ceiling,
+            [image_corners[3], image_corners[2]],
+        ),
+    ];
+    for (kind, is_visible, height, plane, closing_image_corners) in horizontal_surfaces {
+        let mut corners: Vec<Vector3> = wall_corners
+            .iter()
+            .map(|corner| [corner[0], height, corner[2]])
+            .collect();
+        if is_visible {
+            // Image border from right to left closes the visible part
+            corners.extend(
+                closing_image_corners
+                    .iter()
+                    .filter_map(|corner| plane.intersect_ray(ray(*corner)))
+                    .map(to_room),
+            );
+        } else {
+            corners.push([0.0, height, 0.0]);
+        }
+        corners.dedup_by(|a, b| distance(*a, *b) < MIN_CORNER_DISTANCE_METERS);
+        if corners.len() < 3 {
+            continue;
+        }
+
+        let material = if is_visible && options.project_photo {
+            SurfaceMaterial::Photo
+        } else {
+            SurfaceMaterial::Wallpaper
+        };
+        let normal = if kind == SurfaceKind::Floor {
+            [0.0, 1.0, 0.0]
+        } else {
+            [0.0, -1.0, 0.0]
+        };
+        let mut surface = MeshSurface::new(kind, material, normal);
+        // Visible parts of the room are convex, so they are triangulated as a fan
+        for window in corners[1..].windows(2) {
+            let triangle = [corners[0], window[0], window[1]];
+            if cross(sub(triangle[1], triangle[0]), sub(triangle[2], triangle[0]))[1].abs()
+                < MIN_CORNER_DISTANCE_METERS.powi(2)
+            {
+                continue;
+            }
+            match material {
+                SurfaceMaterial::Wallpaper => {
+                    surface.add_triangle(triangle, 1, |point| {
+                        [
+                            (point[0] / tile_width) as f32,
+                            (point[2] / tile_height) as f32,
+                        ]
+                    });
+                }
+                SurfaceMaterial::Photo => {
+                    surface.add_triangle(triangle, PHOTO_TESSELLATION, |point| {
+                        let camera_point = room.to_camera(scaled(point, 1.0 / meters));
+                        let (x, y) = room
+                            .camera
+                            .project(camera_point)
+                            .unwrap_or(room.camera.principal_point);
+                        [
+                            (x / layout_width as f64) as f32,
+                            (1.0 - y / layout_height as f64) as f32,
+                        ]
+                    });
+                }
+            }
+        }
+        if !surface.triangles.is_empty() {
+            surfaces.push(surface);
+        }
+    }
+
+    RoomMesh {
+        surfaces,
+        camera_position: [0.0, options.camera_height_meters, 0.0],
+    }
+}
+
+impl RoomMesh {
+    /// Writes the mesh as Wavefront OBJ with MTL materials and PNG textures into the given
+    /// directory. `photo` is required if any surface has the photo projected onto it.
+    pub fn save_obj(
+        &self,
+        output_dir: &Path,
+        wallpaper: &WallpaperMaterial,
+        photo: Option<&RgbImage>,
+    ) -> std::io::Result<()> {
+        let uses_photo = self
+            .surfaces
+            .iter()
+            .any(|surface| surface.material == SurfaceMaterial::Photo);
+        if uses_photo && photo.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Room photo is required to texture the mesh",
+            ));
+        }
+        std::fs::create_dir_all(output_dir)?;
+
+        wallpaper
+            .color
+            .save(output_dir.join(WALLPAPER_TEXTURE_FILE_NAME))
+            .map_err(to_io_error)?;
+        if let Some(normal_map) = &wallpaper.normal_map {
+            normal_map
+                .save(output_dir.join(WALLPAPER_NORMAL_MAP_FILE_NAME))
+                .map_err(to_io_error)?;
+        }
+        if let (true, Some(photo)) = (uses_photo, photo) {
+            photo
+                .save(output_dir.join(PHOTO_TEXTURE_FILE_NAME))
+                .map_err(to_io_error)?;
+        }
+
+        let mut mtl_writer = BufWriter::new(File::create(output_dir.join(MTL_FILE_NAME))?);
+        writeln!(mtl_writer, "newmtl {WALLPAPER_MATERIAL_NAME}")?;
+        writeln!(mtl_writer, "Kd 1 1 1\nKs 0 0 0\nd 1\nillum 1")?;
+        writeln!(mtl_writer, "map_Kd {WALLPAPER_TEXTURE_FILE_NAME}")?;
+        if wallpaper.normal_map.is_some() {
+            writeln!(mtl_writer, "norm {WALLPAPER_NORMAL_MAP_FILE_NAME}")?;
+        }
+        if uses_photo {
+            // Photo is already lit
+            writeln!(mtl_writer, "\nnewmtl {PHOTO_MATERIAL_NAME}")?;
+            writeln!(mtl_writer, "Kd 1 1 1\nKs 0 0 0\nd 1\nillum 0")?;
+            writeln!(mtl_writer, "map_Kd {PHOTO_TEXTURE_FILE_NAME}")?;
+        }
+        mtl_writer.flush()?;
+
+        let mut obj_writer = BufWriter::new(File::create(output_dir.join(OBJ_FILE_NAME))?);
+        writeln!(obj_writer, "mtllib {MTL_FILE_NAME}")?;
+        // OBJ indices are global and start at 1
+        let mut vertex_offset = 1;
+        for (normal_index, surface) in self.surfaces.iter().enumerate() {
+            writeln!(obj_writer, "\no {}", surface.name())?;
+            let material_name = match surface.material {
+                SurfaceMaterial::Wallpaper => WALLPAPER_MATERIAL_NAME,
+                SurfaceMaterial::Photo => PHOTO_MATERIAL_NAME,
+            };
+            writeln!(obj_writer, "usemtl {material_name}")?;
+            for vertex in &surface.vertices {
+                let [x, y, z] = vertex.position;
+                writeln!(obj_writer, "v {x} {y} {z}")?;
+            }
+            for vertex in &surface.vertices {
+                let [u, v] = vertex.uv;
+                writeln!(obj_writer, "vt {u} {v}")?;
+            }
+            let [x, y, z] = surface.normal;
+            writeln!(obj_writer, "vn {x} {y} {z}")?;
+            for triangle in &surface.triangles {
+                let [a, b, c] = triangle.map(|index| index + vertex_offset);
+                let normal = normal_index + 1;
+                writeln!(
+                    obj_writer,
+                    "f {a}/{a}/{normal} {b}/{b}/{normal} {c}/{c}/{normal}"
+                )?;
+            }
+            vertex_offset += surface.vertices.len();
+        }
+        obj_writer.flush()?;
+
+        Ok(())
+    }
+}
+
+fn distance(a: Vector3, b: Vector3) -> f64 {
+    let difference = sub(a, b);
+    dot(difference, difference).sqrt()
+}
+
+fn to_f32(vector: Vector3) -> [f32; 3] {
+    vector.map(|value| value as f32)
+}
+
+fn to_f64(vector: [f32; 3]) -> Vector3 {
+    vector.map(|value| value as f64)
+}
+
+fn to_io_error(error: image::ImageError) -> std::io::Error {
+    std::io::Error::other(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::layout::RoomType;
+    use crate::material::WallpaperMaterial;
+    use crate::mesh_export::{
+        build_room_mesh, MeshOptions, RoomMesh, SurfaceKind, SurfaceMaterial,
+    };
+    use crate::polygons::compute_wall_polygons;
+    use crate::reconstruction::reconstruct_room;
+
+    /// Vertices, texture coordinates, faces and materials of OBJ objects, by object name.
+    struct ObjFile {
+        positions: Vec<[f32; 3]>,
+        uvs: Vec<[f32; 2]>,
+        objects: Vec<(String, String, Vec<[usize; 3]>)>,
+    }
+
+    fn read_obj(path: &Path) -> ObjFile {
+        let mut obj = ObjFile {
+            positions: vec![],
+            uvs: vec![],
+            objects: vec![],
+        };
+        for line in std::fs::read_to_string(path).unwrap().lines() {
+            let mut tokens = line.split_whitespace();
+            let values = |tokens: std::str::SplitWhitespace| -> Vec<f32> {
+                tokens.map(|token| token.parse().unwrap()).collect()
+            };
+            match tokens.next() {
+                Some("v") => {
+                    let values = values(tokens);
+                    obj.positions.push([values[0], values[1], values[2]]);
+                }
+                Some("vt") => {
+                    let values = values(tokens);
+                    obj.uvs.push([values[0], values[1]]);
+                }
+                Some("o") => {
+                    obj.objects
+                        .push((tokens.next().unwrap().to_string(), String::new(), vec![]))
+                }
+                Some("usemtl") => {
+                    obj.objects.last_mut().unwrap().1 = tokens.next().unwrap().to_string()
+                }
+                Some("f") => {
+                    let indices: Vec<usize> = tokens
+                        .map(|token| token.split('/').next().unwrap().parse().unwrap())
+                        .collect();
+                    obj.objects.last_mut().unwrap().2.push([
+                        indices[0] - 1,
+                        indices[1] - 1,
+                        indices[2] - 1,
+                    ]);
+                }
+                _ => {}
+            }
+        }
+        obj
+    }
+
+    fn front_wall_mesh(options: MeshOptions) -> RoomMesh {
+        let lines = vec![((0, 150), (511, 150)), ((0, 400), (511, 400))];
+        let polygons = compute_wall_polygons(&lines, 512, 512, 6).unwrap();
+        let room = reconstruct_room(&polygons, RoomType::Type6, 512, 512);
+        build_room_mesh(&room, &polygons, RoomType::Type6, 512, 512, options)
+    }
+
+    #[test]
+    fn builds_box_room_at_physical_scale() {
+        let mesh = front_wall_mesh(MeshOptions::default());
+
+        let kinds: Vec<SurfaceKind> = mesh.surfaces.iter().map(|surface| surface.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SurfaceKind::Wall(0),
+                SurfaceKind::Floor,
+                SurfaceKind::Ceiling
+            ]
+        );
+        let wall = &mesh.surfaces[0];
+        let heights: Vec<f32> = wall
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position[1])
+            .collect();
+        let ceiling_height = heights.iter().cloned().fold(0.0, f32::max);
+        // Ceiling edge is closer to the image centre than the floor edge, so the ceiling is less
+        // above the camera than the camera is above the floor
+        assert!(ceiling_height > 2.3 && ceiling_height < 2.9, "{heights:?}");
+        assert_eq!(heights.iter().cloned().fold(f32::INFINITY, f32::min), 0.0);
+        // Wall faces the camera, which looks along -Z
+        assert!(wall.normal[2] > 0.99, "{:?}", wall.normal);
+        assert!(wall.vertices.iter().all(|vertex| vertex.position[2] < -3.0));
+        assert!(mesh.surfaces[1]
+            .vertices
+            .iter()
+            .all(|vertex| vertex.position[1] == 0.0));
+    }
+
+    #[test]
+    fn projects_photo_onto_visible_floor_and_ceiling() {
+        let mesh = front_wall_mesh(MeshOptions {
+            project_photo: true,
+            ..MeshOptions::default()
+        });
+
+        let floor = &mesh.surfaces[1];
+        assert_eq!(floor.material, SurfaceMaterial::Photo);
+        assert_eq!(mesh.surfaces[0].material, SurfaceMaterial::Wallpaper);
+        // Visible floor spans the bottom of the photo, below the wall
+        for vertex in &floor.vertices {
+            let [u, v] = vertex.uv;
+            assert!((-1e-3..=1.001).contains(&u), "{u}");
+            assert!((-1e-3..=(1.0 - 400.0 / 512.0) + 1e-3).contains(&v), "{v}");
+        }
+    }
+
+    #[test]
+    fn round_trips_obj_file() {
+        let mesh = front_wall_mesh(MeshOptions {
+            project_photo: true,
+            ..MeshOptions::default()
+        });
+        let wallpaper = WallpaperMaterial::from(RgbImage::from_pixel(8, 8, Rgb([200, 10, 10])));
+        let photo = RgbImage::from_pixel(64, 64, Rgb([10, 200, 10]));
+        let output_dir = std::env::temp_dir().join(format!(
+            "room_mesh_round_trips_obj_file_{}",
+            std::process::id()
+        ));
+
+        assert!(mesh.save_obj(&output_dir, &wallpaper, None).is_err());
+        mesh.save_obj(&output_dir, &wallpaper, Some(&photo))
+            .unwrap();
+
+        let obj = read_obj(&output_dir.join("room.obj"));
+        let vertex_count: usize = mesh
+            .surfaces
+            .iter()
+            .map(|surface| surface.vertices.len())
+            .sum();
+        assert_eq!(obj.positions.len(), vertex_count);
+        assert_eq!(obj.uvs.len(), vertex_count);
+        assert_eq!(obj.objects.len(), mesh.surfaces.len());
+        let mut vertex_offset = 0;
+        for ((name, material, faces), surface) in obj.objects.iter().zip(&mesh.surfaces) {
+            assert_eq!(*name, surface.name());
+            let expected_material = match surface.material {
+                SurfaceMaterial::Wallpaper => "wallpaper",
+                SurfaceMaterial::Photo => "photo",
+            };
+            assert_eq!(material, expected_material);
+            assert_eq!(faces.len(), surface.triangles.len());
+            for (face, triangle) in faces.iter().zip(&surface.triangles) {
+                assert_eq!(*face, triangle.map(|index| index + vertex_offset));
+            }
+            for (index, vertex) in surface.vertices.iter().enumerate() {
+                assert_eq!(obj.positions[vertex_offset + index], vertex.position);
+                assert_eq!(obj.uvs[vertex_offset + index], vertex.uv);
+            }
+            vertex_offset += surface.vertices.len();
+        }
+
+        let mtl = std::fs::read_to_string(output_dir.join("room.mtl")).unwrap();
+        assert!(mtl.contains("map_Kd wallpaper.png"));
+        assert!(mtl.contains("map_Kd photo.png"));
+        let saved_photo = image::open(output_dir.join("photo.png"))
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(saved_photo, photo);
+        let saved_wallpaper = image::open(output_dir.join("wallpaper.png"))
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(saved_wallpaper, wallpaper.color);
+
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
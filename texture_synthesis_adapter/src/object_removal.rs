@@ -0,0 +1,290 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, Luma, RgbImage};
+use imageproc::region_labelling::{connected_components, Connectivity};
+use texture_synthesis as ts;
+use texture_synthesis::Dims;
+
+use crate::preview::{prepare_wall_mask, WALL_PIXEL};
+
+/// Pixel value marking regions which should be removed
+pub const REMOVAL_PIXEL: Luma<u8> = Luma([255]);
+const KEEP_PIXEL: Luma<u8> = Luma([0]);
+/// Largest side of the region crop passed to texture synthesis, bigger crops are downscaled
+const INPAINT_MAX_SIDE: u32 = 256;
+/// Context around each region which is used as inpainting example, relative to region size
+const INPAINT_CONTEXT_RATIO: f32 = 0.5;
+const INPAINT_MIN_CONTEXT: u32 = 16;
+/// Smaller regions are mask noise, which is not worth a texture synthesis session
+const MIN_REGION_PIXELS: u32 = 16;
+
+/// Bounding box and size of the connected region of the removal mask
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+    num_pixels: u32,
+}
+
+/// Detects non-wall blobs, such as picture frames, shelves and posters, which are fully enclosed
+/// by wall pixels and do not touch image borders.
+pub fn detect_enclosed_objects(mask_image: &GrayImage) -> GrayImage {
+    let width = mask_image.width();
+    let height = mask_image.height();
+    let mask_image = prepare_wall_mask(mask_image, width, height);
+
+    let mut non_wall_image = GrayImage::new(width, height);
+    for (x, y, pixel) in mask_image.enumerate_pixels() {
+        if *pixel != WALL_PIXEL {
+            *non_wall_image.get_pixel_mut(x, y) = REMOVAL_PIXEL;
+        }
+    }
+
+    let labels = connected_components(&non_wall_image, Connectivity::Eight, KEEP_PIXEL);
+    let num_labels = labels.pixels().map(|p| p.0[0]).max().unwrap_or(0) as usize;
+
+    // Objects touching image borders might continue outside of the photo and are not enclosed
+    let mut touches_border = vec![false; num_labels + 1];
+    for (x, y, label) in labels.enumerate_pixels() {
+        if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+            touches_border[label.0[0] as usize] = true;
+        }
+    }
+
+    let mut removal_mask = GrayImage::new(width, height);
+    for (x, y, label) in labels.enumerate_pixels() {
+        let label = label.0[0] as usize;
+        if label != 0 && !touches_border[label] {
+            *removal_mask.get_pixel_mut(x, y) = REMOVAL_PIXEL;
+        }
+    }
+
+    removal_mask
+}
+
+/// Fills regions marked in `removal_mask` with wall texture synthesized from their surroundings,
+/// and marks them as wall in the returned wall mask, so that they get wallpapered too. Tiny
+/// regions and regions which could not be inpainted are left unchanged.
+///
+/// Both masks are resized to room image size.
+pub fn remove_objects(
+    room_image: &RgbImage,
+    mask_image: &GrayImage,
+    removal_mask: &GrayImage,
+) -> (RgbImage, GrayImage) {
+    let width = room_image.width();
+    let height = room_image.height();
+
+    let removal_mask = image::imageops::resize(removal_mask, width, height, FilterType::Nearest);
+    let removal_mask = imageproc::contrast::threshold(&removal_mask, 127);
+    let mut mask_image = prepare_wall_mask(mask_image, width, height);
+    let mut room_image = room_image.clone();
+
+    // Inpaint each region separately, so that it is filled using only the wall around it
+    let labels = connected_components(&removal_mask, Connectivity::Eight, KEEP_PIXEL);
+    for (label, region) in label_regions(&labels).into_iter().enumerate() {
+        let Some(Region {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+            num_pixels,
+        }) = region
+        else {
+            continue;
+        };
+        if num_pixels < MIN_REGION_PIXELS {
+            continue;
+        }
+        let label = label as u32;
+
+        let region_width = max_x - min_x + 1;
+        let region_height = max_y - min_y + 1;
+        let context_x = u32::max(
+            (region_width as f32 * INPAINT_CONTEXT_RATIO) as u32,
+            INPAINT_MIN_CONTEXT,
+        );
+        let context_y = u32::max(
+            (region_height as f32 * INPAINT_CONTEXT_RATIO) as u32,
+            INPAINT_MIN_CONTEXT,
+        );
+        let crop_x = min_x.saturating_sub(context_x);
+        let crop_y = min_y.saturating_sub(context_y);
+        let crop_width = u32::min(max_x + context_x + 1, width) - crop_x;
+        let crop_height = u32::min(max_y + context_y + 1, height) - crop_y;
+
+        let mut crop_mask = GrayImage::new(crop_width, crop_height);
+        for (x, y, pixel) in crop_mask.enumerate_pixels_mut() {
+            let is_region = labels.get_pixel(crop_x + x, crop_y + y).0[0] == label;
+            // Texture synthesis fills black mask pixels and keeps white ones
+            *pixel = if is_region { Luma([0]) } else { Luma([255]) };
+        }
+        let crop_image =
+            image::imageops::crop_imm(&room_image, crop_x, crop_y, crop_width, crop_height)
+                .to_image();
+
+        let Some(inpainted_crop) = inpaint_region(&crop_image, &crop_mask) else {
+            continue;
+        };
+
+        for (x, y, mask_pixel) in crop_mask.enumerate_pixels() {
+            if mask_pixel.0[0] != 0 {
+                continue;
+            }
+            room_image.put_pixel(crop_x + x, crop_y + y, *inpainted_crop.get_pixel(x, y));
+            mask_image.put_pixel(crop_x + x, crop_y + y, WALL_PIXEL);
+        }
+    }
+
+    (room_image, mask_image)
+}
+
+fn inpaint_region(crop_image: &RgbImage, crop_mask: &GrayImage) -> Option<RgbImage> {
+    let crop_width = crop_image.width();
+    let crop_height = crop_image.height();
+
+    let scale = f32::min(
+        1.0,
+        INPAINT_MAX_SIDE as f32 / u32::max(crop_width, crop_height) as f32,
+    );
+    let dims = Dims::new(
+        u32::max((crop_width as f32 * scale) as u32, 1),
+        u32::max((crop_height as f32 * scale) as u32, 1),
+    );
+
+    let example_image = DynamicImage::from(image::imageops::resize(
+        crop_image,
+        dims.width,
+        dims.height,
+        FilterType::Triangle,
+    ));
+    let mask = DynamicImage::from(image::imageops::resize(
+        crop_mask,
+        dims.width,
+        dims.height,
+        FilterType::Nearest,
+    ));
+
+    let session = match ts::Session::builder()
+        .inpaint_example(
+            ts::ImageSource::Image(mask.clone()),
+            // Do not sample from the object itself
+            ts::Example::builder(ts::ImageSource::Image(example_image))
+                .set_sample_method(ts::ImageSource::Image(mask)),
+            dims,
+        )
+        .max_thread_count(num_cpus::get())
+        .build()
+    {
+        Ok(session) => session,
+        Err(e) => {
+            println!("Could not prepare inpainting session: {e}");
+            return None;
+        }
+    };
+    let inpainted = session.run(None).into_image().into_rgb8();
+
+    Some(image::imageops::resize(
+        &inpainted,
+        crop_width,
+        crop_height,
+        FilterType::Triangle,
+    ))
+}
+
+/// Region of each label, indexed by label, in a single pass over the labels.
+fn label_regions(labels: &image::ImageBuffer<Luma<u32>, Vec<u32>>) -> Vec<Option<Region>> {
+    let num_labels = labels.pixels().map(|p| p.0[0]).max().unwrap_or(0) as usize;
+    let mut regions: Vec<Option<Region>> = vec![None; num_labels + 1];
+    for (x, y, pixel) in labels.enumerate_pixels() {
+        let label = pixel.0[0] as usize;
+        if label == 0 {
+            continue;
+        }
+        regions[label] = Some(match regions[label] {
+            None => Region {
+                min_x: x,
+                min_y: y,
+                max_x: x,
+                max_y: y,
+                num_pixels: 1,
+            },
+            Some(region) => Region {
+                min_x: u32::min(region.min_x, x),
+                min_y: u32::min(region.min_y, y),
+                max_x: u32::max(region.max_x, x),
+                max_y: u32::max(region.max_y, y),
+                num_pixels: region.num_pixels + 1,
+            },
+        });
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::object_removal::{detect_enclosed_objects, remove_objects, REMOVAL_PIXEL};
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    fn wall_mask_with_objects() -> GrayImage {
+        let mut mask_image = GrayImage::from_pixel(64, 64, Luma([255]));
+        // Picture frame in the middle of the wall
+        for x in 20..30 {
+            for y in 20..30 {
+                mask_image.put_pixel(x, y, Luma([0]));
+            }
+        }
+        // Furniture standing on the floor, touching bottom border
+        for x in 40..60 {
+            for y in 50..64 {
+                mask_image.put_pixel(x, y, Luma([0]));
+            }
+        }
+        mask_image
+    }
+
+    #[test]
+    fn detects_only_objects_enclosed_by_wall() {
+        let removal_mask = detect_enclosed_objects(&wall_mask_with_objects());
+
+        assert_eq!(*removal_mask.get_pixel(25, 25), REMOVAL_PIXEL);
+        assert_eq!(*removal_mask.get_pixel(50, 60), Luma([0]));
+        assert_eq!(*removal_mask.get_pixel(5, 5), Luma([0]));
+    }
+
+    #[test]
+    fn fills_removed_objects_and_marks_them_as_wall() {
+        let mask_image = wall_mask_with_objects();
+        let mut room_image = RgbImage::from_pixel(64, 64, Rgb([200, 190, 180]));
+        for x in 20..30 {
+            for y in 20..30 {
+                room_image.put_pixel(x, y, Rgb([10, 10, 10]));
+            }
+        }
+        let removal_mask = detect_enclosed_objects(&mask_image);
+
+        let (inpainted_image, wall_mask) = remove_objects(&room_image, &mask_image, &removal_mask);
+
+        assert_eq!(*wall_mask.get_pixel(25, 25), Luma([255]));
+        assert_eq!(*wall_mask.get_pixel(50, 60), Luma([0]));
+        // Object is filled with the surrounding wall color
+        let filled_pixel = inpainted_image.get_pixel(25, 25);
+        assert!(filled_pixel.0[0] > 150);
+        assert_eq!(*inpainted_image.get_pixel(5, 5), Rgb([200, 190, 180]));
+    }
+
+    #[test]
+    fn leaves_tiny_regions_unchanged() {
+        let mask_image = GrayImage::from_pixel(64, 64, Luma([255]));
+        let room_image = RgbImage::from_pixel(64, 64, Rgb([200, 190, 180]));
+        let mut removal_mask = GrayImage::new(64, 64);
+        removal_mask.put_pixel(10, 10, REMOVAL_PIXEL);
+        removal_mask.put_pixel(40, 40, REMOVAL_PIXEL);
+
+        let (inpainted_image, wall_mask) = remove_objects(&room_image, &mask_image, &removal_mask);
+
+        assert_eq!(inpainted_image, room_image);
+        assert_eq!(wall_mask, mask_image);
+    }
+}
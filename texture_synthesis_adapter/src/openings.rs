@@ -0,0 +1,423 @@
+use image::{GrayImage, Luma};
+use imageproc::drawing::draw_polygon_mut;
+use imageproc::geometric_transformations::Projection;
+use imageproc::point::Point as PolygonPoint;
+
+use crate::polygons::WallPolygon;
+use crate::preview::{compute_wall_section_projection, scale_polygons, WallQuad};
+
+const NOT_WALL_PIXEL: Luma<u8> = Luma([0]);
+
+/// Rectangle on the wall plane, in meters from the top left corner of the wall.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallPlaneRect {
+    pub left: f32,
+    pub top: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl WallPlaneRect {
+    pub fn area(&self) -> f32 {
+        self.width * self.height
+    }
+
+    fn corners(&self) -> [(f32, f32); 4] {
+        let right = self.left + self.width;
+        let bottom = self.top + self.height;
+        [
+            (self.left, self.top),
+            (right, self.top),
+            (right, bottom),
+            (self.left, bottom),
+        ]
+    }
+
+    /// Part of the rectangle within `width` x `height` wall, `None` if they do not overlap.
+    fn clipped(&self, width: f32, height: f32) -> Option<Self> {
+        let left = self.left.max(0.0);
+        let top = self.top.max(0.0);
+        let right = (self.left + self.width).min(width);
+        let bottom = (self.top + self.height).min(height);
+        (right > left && bottom > top).then_some(Self {
+            left,
+            top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpeningOutline {
+    /// Top left, top right, bottom right and bottom left corners in layout space, e.g. as drawn
+    /// by the user. Corners are snapped to the wall perspective.
+    Image([(f32, f32); 4]),
+    WallPlane(WallPlaneRect),
+}
+
+/// Door, window or other opening in the wall, which should not be covered by wallpaper.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallOpening {
+    /// Index of the wall polygon the opening is in
+    pub wall: usize,
+    pub outline: OpeningOutline,
+}
+
+/// Wallpaper area of the walls, in square meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WallpaperArea {
+    pub walls: f32,
+    /// Area of openings within the walls. Overlapping openings are counted once per opening.
+    pub openings: f32,
+}
+
+impl WallpaperArea {
+    pub fn net(&self) -> f32 {
+        (self.walls - self.openings).max(0.0)
+    }
+}
+
+/// Wall planes of the layout with their true sizes, e.g. measured in the depth map by
+/// [crate::depth_layout::estimate_layout_from_depth].
+struct WallPlanes {
+    quads: Vec<WallQuad>,
+    /// Width and height of each wall in meters
+    sizes: Vec<(f32, f32)>,
+}
+
+impl WallPlanes {
+    /// `None` if there is not exactly one size for each wall.
+    fn new(polygons: &[WallPolygon], wall_sizes: &[(f32, f32)]) -> Option<Self> {
+        if wall_sizes.len() != polygons.len() {
+            println!(
+                "Got {} wall sizes for {} walls",
+                wall_sizes.len(),
+                polygons.len()
+            );
+            return None;
+        }
+        // Layout space is kept, only neighbouring wall corners are welded
+        let quads = scale_polygons(polygons, 1, 1, 1, 1);
+        Some(Self {
+            quads,
+            sizes: wall_sizes.to_vec(),
+        })
+    }
+
+    /// Projection from wall plane meters onto the wall in layout space.
+    fn projection(&self, wall: usize) -> Option<Projection> {
+        let quad = self.quads.get(wall)?;
+        let (width, height) = self.sizes[wall];
+        Some(compute_wall_section_projection(quad, 0.0, width, height))
+    }
+
+    /// Opening rectangle on its wall plane, clipped to the wall.
+    fn snap(&self, opening: &WallOpening) -> Option<WallPlaneRect> {
+        let (width, height) = *self.sizes.get(opening.wall)?;
+        let rect = match opening.outline {
+            OpeningOutline::WallPlane(rect) => rect,
+            OpeningOutline::Image(corners) => {
+                // Opening edges are horizontal and vertical on the wall plane
+                let to_wall_plane = self.projection(opening.wall)?.invert();
+                let wall_plane_corners = corners.map(|corner| to_wall_plane * corner);
+                let us = wall_plane_corners.map(|corner| corner.0);
+                let vs = wall_plane_corners.map(|corner| corner.1);
+                let left = us.iter().cloned().fold(f32::INFINITY, f32::min);
+                let right = us.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                let top = vs.iter().cloned().fold(f32::INFINITY, f32::min);
+                let bottom = vs.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                WallPlaneRect {
+                    left,
+                    top,
+                    width: right - left,
+                    height: bottom - top,
+                }
+            }
+        };
+        rect.clipped(width, height)
+    }
+}
+
+/// Opening as a rectangle on its wall plane, with image outlines snapped to the wall perspective.
+/// `wall_sizes` are width and height of each wall in meters. `None` if the wall does not exist or
+/// the opening is outside of it.
+pub fn snap_opening(
+    polygons: &[WallPolygon],
+    wall_sizes: &[(f32, f32)],
+    opening: &WallOpening,
+) -> Option<WallPlaneRect> {
+    WallPlanes::new(polygons, wall_sizes)?.snap(opening)
+}
+
+/// Top left, top right, bottom right and bottom left corners of the snapped opening in layout
+/// space.
+pub fn opening_corners(
+    polygons: &[WallPolygon],
+    wall_sizes: &[(f32, f32)],
+    opening: &WallOpening,
+) -> Option<[(f32, f32); 4]> {
+    let wall_planes = WallPlanes::new(polygons, wall_sizes)?;
+    let rect = wall_planes.snap(opening)?;
+    let to_image = wall_planes.projection(opening.wall)?;
+    Some(rect.corners().map(|corner| to_image * corner))
+}
+
+/// Removes openings from the wall segmentation mask, so that previews and wall maps computed with
+/// it never cover them with wallpaper. Openings are in layout space of `layout_width` x
+/// `layout_height` size, same as the polygons.
+pub fn cut_openings(
+    mask_image: &GrayImage,
+    polygons: &[WallPolygon],
+    wall_sizes: &[(f32, f32)],
+    openings: &[WallOpening],
+    layout_width: u32,
+    layout_height: u32,
+) -> GrayImage {
+    let mut mask_image = mask_image.clone();
+    let scale_x = mask_image.width() as f32 / layout_width as f32;
+    let scale_y = mask_image.height() as f32 / layout_height as f32;
+    for opening in openings {
+        let Some(corners) = opening_corners(polygons, wall_sizes, opening) else {
+            println!("Skipping opening outside of its wall: {opening:?}");
+            continue;
+        };
+        let mut polygon: Vec<PolygonPoint<i32>> = corners
+            .iter()
+            .map(|(x, y)| {
+                PolygonPoint::new((x * scale_x).round() as i32, (y * scale_y).round() as i32)
+            })
+            .collect();
+        // Polygon drawing rejects closed polygons, which degenerate openings would become
+        polygon.dedup();
+        if polygon.len() < 3 || polygon.first() == polygon.last() {
+            continue;
+        }
+        draw_polygon_mut(&mut mask_image, &polygon, NOT_WALL_PIXEL);
+    }
+    mask_image
+}
+
+/// Wallpaper area needed for the walls, less their openings. `wall_sizes` are width and height of
+/// each wall in meters, `None` if there is not exactly one size for each wall.
+pub fn estimate_wallpaper_area(
+    polygons: &[WallPolygon],
+    wall_sizes: &[(f32, f32)],
+    openings: &[WallOpening],
+) -> Option<WallpaperArea> {
+    let wall_planes = WallPlanes::new(polygons, wall_sizes)?;
+    let walls = wall_planes
+        .sizes
+        .iter()
+        .map(|(width, height)| width * height)
+        .sum();
+    let openings = openings
+        .iter()
+        .filter_map(|opening| wall_planes.snap(opening))
+        .map(|rect| rect.area())
+        .sum();
+    Some(WallpaperArea { walls, openings })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::openings::{
+        cut_openings, estimate_wallpaper_area, opening_corners, snap_opening, OpeningOutline,
+        WallOpening, WallPlaneRect,
+    };
+    use crate::polygons::WallPolygon;
+    use image::{GrayImage, Luma};
+
+    fn front_wall() -> Vec<WallPolygon> {
+        vec![WallPolygon {
+            top_left: (0, 100),
+            top_right: (511, 100),
+            bottom_right: (511, 400),
+            bottom_left: (0, 400),
+        }]
+    }
+
+    /// Width and height of the front wall in meters
+    const FRONT_WALL_SIZE: (f32, f32) = (4.0, 2.5);
+
+    fn two_walls() -> Vec<WallPolygon> {
+        vec![
+            WallPolygon {
+                top_left: (0, 50),
+                top_right: (300, 150),
+                bottom_right: (300, 350),
+                bottom_left: (0, 450),
+            },
+            WallPolygon {
+                top_left: (300, 150),
+                top_right: (511, 100),
+                bottom_right: (511, 400),
+                bottom_left: (300, 350),
+            },
+        ]
+    }
+
+    #[test]
+    fn snaps_drawn_opening_to_wall_perspective() {
+        let polygons = two_walls();
+        let door = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::WallPlane(WallPlaneRect {
+                left: 1.0,
+                top: 0.4,
+                width: 0.9,
+                height: 2.0,
+            }),
+        };
+        let wall_sizes = [(3.0, 2.5), (2.0, 2.5)];
+        let door_corners = opening_corners(&polygons, &wall_sizes, &door).unwrap();
+        // User draws the door roughly, e.g. with vertical edges slightly tilted
+        let drawn_door = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::Image([
+                (door_corners[0].0 + 2.0, door_corners[0].1),
+                (door_corners[1].0 - 1.0, door_corners[1].1 + 1.0),
+                door_corners[2],
+                door_corners[3],
+            ]),
+        };
+
+        let snapped = snap_opening(&polygons, &wall_sizes, &drawn_door).unwrap();
+
+        assert!((snapped.left - 1.0).abs() < 0.05, "{snapped:?}");
+        assert!((snapped.top - 0.4).abs() < 0.05, "{snapped:?}");
+        assert!((snapped.width - 0.9).abs() < 0.05, "{snapped:?}");
+        assert!((snapped.height - 2.0).abs() < 0.05, "{snapped:?}");
+    }
+
+    #[test]
+    fn clips_openings_to_their_wall() {
+        let window = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::WallPlane(WallPlaneRect {
+                left: -0.5,
+                top: 0.5,
+                width: 1.5,
+                height: 1.0,
+            }),
+        };
+        let missing_wall_window = WallOpening { wall: 3, ..window };
+
+        let snapped = snap_opening(&front_wall(), &[FRONT_WALL_SIZE], &window).unwrap();
+
+        assert_eq!(snapped.left, 0.0);
+        assert_eq!(snapped.width, 1.0);
+        assert!(snap_opening(&front_wall(), &[FRONT_WALL_SIZE], &missing_wall_window).is_none());
+    }
+
+    #[test]
+    fn cuts_openings_out_of_wall_mask() {
+        let polygons = front_wall();
+        let mask_image = GrayImage::from_pixel(1024, 1024, Luma([255]));
+        let window = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::Image([
+                (100.0, 150.0),
+                (200.0, 150.0),
+                (200.0, 250.0),
+                (100.0, 250.0),
+            ]),
+        };
+
+        let mask_image = cut_openings(
+            &mask_image,
+            &polygons,
+            &[FRONT_WALL_SIZE],
+            &[window],
+            512,
+            512,
+        );
+
+        assert_eq!(*mask_image.get_pixel(300, 400), Luma([0]));
+        assert_eq!(*mask_image.get_pixel(201, 301), Luma([0]));
+        assert_eq!(*mask_image.get_pixel(190, 400), Luma([255]));
+        assert_eq!(*mask_image.get_pixel(300, 510), Luma([255]));
+    }
+
+    #[test]
+    fn subtracts_openings_from_wallpaper_area() {
+        let door = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::WallPlane(WallPlaneRect {
+                left: 1.0,
+                top: 0.4,
+                width: 0.9,
+                height: 2.0,
+            }),
+        };
+
+        let area = estimate_wallpaper_area(&front_wall(), &[FRONT_WALL_SIZE], &[door]).unwrap();
+
+        assert!((area.walls - 10.0).abs() < 1e-3, "{area:?}");
+        assert!((area.openings - 1.8).abs() < 1e-3, "{area:?}");
+        assert!((area.net() - 8.2).abs() < 1e-3, "{area:?}");
+    }
+
+    #[test]
+    fn places_wall_plane_openings_by_wall_size() {
+        // Front wall spans 511 layout pixels horizontally and 300 vertically
+        let door = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::WallPlane(WallPlaneRect {
+                left: 1.0,
+                top: 0.5,
+                width: 1.0,
+                height: 2.0,
+            }),
+        };
+
+        let corners = opening_corners(&front_wall(), &[FRONT_WALL_SIZE], &door).unwrap();
+
+        let expected_corners = [
+            (127.75, 160.0),
+            (255.5, 160.0),
+            (255.5, 400.0),
+            (127.75, 400.0),
+        ];
+        for (corner, expected_corner) in corners.iter().zip(expected_corners) {
+            assert!((corner.0 - expected_corner.0).abs() < 0.01, "{corners:?}");
+            assert!((corner.1 - expected_corner.1).abs() < 0.01, "{corners:?}");
+        }
+    }
+
+    #[test]
+    fn needs_size_of_each_wall() {
+        let door = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::WallPlane(WallPlaneRect {
+                left: 1.0,
+                top: 0.4,
+                width: 0.9,
+                height: 2.0,
+            }),
+        };
+
+        assert!(estimate_wallpaper_area(&two_walls(), &[FRONT_WALL_SIZE], &[door]).is_none());
+        assert!(snap_opening(&two_walls(), &[], &door).is_none());
+    }
+
+    #[test]
+    fn keeps_mask_and_zero_area_for_layout_without_walls() {
+        let mask_image = GrayImage::from_pixel(64, 64, Luma([255]));
+        let window = WallOpening {
+            wall: 0,
+            outline: OpeningOutline::Image([
+                (100.0, 150.0),
+                (200.0, 150.0),
+                (200.0, 250.0),
+                (100.0, 250.0),
+            ]),
+        };
+
+        let cut_mask_image = cut_openings(&mask_image, &[], &[], &[window], 512, 512);
+        let area = estimate_wallpaper_area(&[], &[], &[window]).unwrap();
+
+        assert_eq!(cut_mask_image, mask_image);
+        assert_eq!(area.net(), 0.0);
+    }
+}
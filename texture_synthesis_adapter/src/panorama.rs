@@ -0,0 +1,333 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use image::{GrayImage, Luma, RgbImage};
+use rgb_hsv::rgb_to_hsv;
+
+use crate::material::WallpaperMaterial;
+use crate::preview::{
+    compute_wall_shading, prepare_wall_mask, sample_bilinear_wrapped, shade_wallpaper_pixel,
+    NO_WALL_ID, WALLPAPER_TILE_HEIGHT_METERS, WALLPAPER_TILE_WIDTH_METERS, WALL_HEIGHT_METERS,
+    WALL_PIXEL,
+};
+
+/// Walls with more corners than this can not be told apart in wall id maps
+const MAX_PANORAMA_CORNERS: usize = NO_WALL_ID as usize;
+
+/// Room corner in HorizonNet format: column of the corner and rows where the wall meets the
+/// ceiling and the floor, in equirectangular image pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PanoramaCorner {
+    pub x: f32,
+    pub ceiling_y: f32,
+    pub floor_y: f32,
+}
+
+/// Closed loop of walls around the camera, in meters. Camera is above the origin, and
+/// longitude 0 looks along +Y of the floor plan.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PanoramaRoom {
+    /// Floor plan positions of the corners, in order of increasing longitude. Wall `i` spans from
+    /// corner `i` to the next one.
+    pub corners: Vec<(f32, f32)>,
+    /// Longitudes of the corners, from -PI to PI
+    pub corner_longitudes: Vec<f32>,
+    pub camera_height: f32,
+    pub ceiling_height: f32,
+    /// Horizontal wallpaper offset at the start of each wall
+    wall_offsets: Vec<f32>,
+    /// Stretch of the wallpaper along the walls, which makes the whole perimeter a whole number
+    /// of tiles so that the pattern continues across the first corner
+    wallpaper_stretch: f32,
+}
+
+/// Wall hit by the view ray, with wallpaper coordinates in meters from the top of the wall.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PanoramaWallPoint {
+    pub wall: usize,
+    pub u: f32,
+    pub v: f32,
+}
+
+impl PanoramaRoom {
+    /// Reconstructs room from its corners in `width` x `height` equirectangular image. Room is
+    /// scaled to [WALL_HEIGHT_METERS] height, same as walls of perspective previews. `None` if
+    /// there are less than three corners or any floor boundary is not below the horizon.
+    pub fn new(corners: &[PanoramaCorner], width: u32, height: u32) -> Option<Self> {
+        if corners.len() < 3 || corners.len() > MAX_PANORAMA_CORNERS {
+            println!("Unsupported number of panorama corners: {}", corners.len());
+            return None;
+        }
+        let mut corners = corners.to_vec();
+        corners.sort_by(|a, b| a.x.total_cmp(&b.x));
+
+        // Camera is one unit above the floor until the room is scaled
+        let mut floor_plan = vec![];
+        let mut longitudes = vec![];
+        let mut ceiling_heights = vec![];
+        for corner in &corners {
+            let longitude = column_longitude(corner.x, width);
+            let floor_latitude = row_latitude(corner.floor_y, height);
+            if floor_latitude >= 0.0 {
+                println!("Floor boundary is not below the horizon: {corner:?}");
+                return None;
+            }
+            let distance = 1.0 / (-floor_latitude).tan();
+            floor_plan.push((distance * longitude.sin(), distance * longitude.cos()));
+            longitudes.push(longitude);
+            ceiling_heights.push(distance * row_latitude(corner.ceiling_y, height).tan());
+        }
+        let ceiling_height_above_camera =
+            ceiling_heights.iter().sum::<f32>() / ceiling_heights.len() as f32;
+        if ceiling_height_above_camera <= 0.0 {
+            println!("Ceiling boundary is not above the horizon");
+            return None;
+        }
+        let scale = WALL_HEIGHT_METERS / (1.0 + ceiling_height_above_camera);
+        let floor_plan: Vec<(f32, f32)> = floor_plan
+            .iter()
+            .map(|(x, y)| (x * scale, y * scale))
+            .collect();
+
+        let mut wall_offsets = vec![];
+        let mut perimeter = 0.0;
+        for (i, start) in floor_plan.iter().enumerate() {
+            let end = floor_plan[(i + 1) % floor_plan.len()];
+            wall_offsets.push(perimeter);
+            perimeter += (end.0 - start.0).hypot(end.1 - start.1);
+        }
+        let tile_count = (perimeter / WALLPAPER_TILE_WIDTH_METERS).round().max(1.0);
+        let wallpaper_stretch = tile_count * WALLPAPER_TILE_WIDTH_METERS / perimeter;
+
+        Some(Self {
+            corners: floor_plan,
+            corner_longitudes: longitudes,
+            camera_height: scale,
+            ceiling_height: WALL_HEIGHT_METERS,
+            wall_offsets,
+            wallpaper_stretch,
+        })
+    }
+
+    /// Wall seen in the given direction, `None` if it is the floor or the ceiling.
+    pub fn wall_point(&self, longitude: f32, latitude: f32) -> Option<PanoramaWallPoint> {
+        let wall = self.wall_at(longitude);
+        let start = self.corners[wall];
+        let end = self.corners[(wall + 1) % self.corners.len()];
+        let direction = (longitude.sin(), longitude.cos());
+
+        // Solve `distance * direction = start + share * (end - start)`
+        let edge = (end.0 - start.0, end.1 - start.1);
+        let determinant = direction.0 * -edge.1 + edge.0 * direction.1;
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let distance = (start.0 * -edge.1 + edge.0 * start.1) / determinant;
+        let share = (direction.0 * start.1 - direction.1 * start.0) / determinant;
+        if distance <= 0.0 {
+            return None;
+        }
+
+        let height = self.camera_height + distance * latitude.tan();
+        if !(0.0..=self.ceiling_height).contains(&height) {
+            return None;
+        }
+        let wall_length = edge.0.hypot(edge.1);
+        let u = (self.wall_offsets[wall] + share.clamp(0.0, 1.0) * wall_length)
+            * self.wallpaper_stretch;
+        Some(PanoramaWallPoint {
+            wall,
+            u,
+            v: self.ceiling_height - height,
+        })
+    }
+
+    /// Wall whose longitude span contains the longitude.
+    fn wall_at(&self, longitude: f32) -> usize {
+        let count = self.corner_longitudes.len();
+        (0..count)
+            .find(|&wall| {
+                let start = self.corner_longitudes[wall];
+                let mut end = self.corner_longitudes[(wall + 1) % count];
+                let mut longitude = longitude;
+                if end <= start {
+                    end += TAU;
+                }
+                if longitude < start {
+                    longitude += TAU;
+                }
+                longitude < end
+            })
+            .unwrap_or(count - 1)
+    }
+}
+
+/// Creates equirectangular preview of the room with all walls covered by wallpaper. Wallpaper
+/// follows the walls around the whole room, and shadows of the room image are transferred onto
+/// it same as in perspective previews. `mask_image` limits the wallpaper to wall pixels of the
+/// segmentation mask, if given.
+pub fn create_panorama_preview(
+    room_image: RgbImage,
+    mask_image: Option<&GrayImage>,
+    material: &WallpaperMaterial,
+    corners: &[PanoramaCorner],
+) -> RgbImage {
+    let width = room_image.width();
+    let height = room_image.height();
+    let Some(room) = PanoramaRoom::new(corners, width, height) else {
+        return room_image;
+    };
+    let mask_image = mask_image.map(|mask_image| prepare_wall_mask(mask_image, width, height));
+
+    let mut wall_ids = GrayImage::from_pixel(width, height, Luma([NO_WALL_ID]));
+    for y in 0..height {
+        let latitude = row_latitude(y as f32 + 0.5, height);
+        for x in 0..width {
+            if let Some(mask_image) = &mask_image {
+                if *mask_image.get_pixel(x, y) != WALL_PIXEL {
+                    continue;
+                }
+            }
+            let longitude = column_longitude(x as f32 + 0.5, width);
+            if let Some(wall_point) = room.wall_point(longitude, latitude) {
+                *wall_ids.get_pixel_mut(x, y) = Luma([wall_point.wall as u8]);
+            }
+        }
+    }
+    let wall_shading = compute_wall_shading(&room_image, &wall_ids, room.corners.len());
+
+    let pixels_per_meter = (
+        material.color.width() as f32 / WALLPAPER_TILE_WIDTH_METERS,
+        material.color.height() as f32 / WALLPAPER_TILE_HEIGHT_METERS,
+    );
+    let mut preview_image = room_image;
+    for y in 0..height {
+        // Wall points are computed again rather than kept for the whole image
+        let latitude = row_latitude(y as f32 + 0.5, height);
+        for x in 0..width {
+            if wall_ids.get_pixel(x, y).0[0] == NO_WALL_ID {
+                continue;
+            }
+            let longitude = column_longitude(x as f32 + 0.5, width);
+            let Some(wall_point) = room.wall_point(longitude, latitude) else {
+                continue;
+            };
+            let tile_point = (
+                wall_point.u * pixels_per_meter.0,
+                wall_point.v * pixels_per_meter.1,
+            );
+            let tile_pixel_hsv = rgb_to_hsv(sample_bilinear_wrapped(&material.color, tile_point));
+
+            let blackness_delta = wall_shading.blackness_delta(x, y, wall_point.wall);
+            shade_wallpaper_pixel(
+                preview_image.get_pixel_mut(x, y),
+                tile_pixel_hsv,
+                blackness_delta,
+            );
+        }
+    }
+
+    preview_image
+}
+
+/// Longitude of the equirectangular image column, from -PI at the left border to PI at the
+/// right one.
+fn column_longitude(x: f32, width: u32) -> f32 {
+    x / width as f32 * TAU - PI
+}
+
+/// Latitude of the equirectangular image row, from PI / 2 at the top to -PI / 2 at the bottom.
+fn row_latitude(y: f32, height: u32) -> f32 {
+    FRAC_PI_2 - y / height as f32 * PI
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    use image::{Rgb, RgbImage};
+
+    use crate::material::WallpaperMaterial;
+    use crate::panorama::{create_panorama_preview, PanoramaCorner, PanoramaRoom};
+    use crate::preview::{WALLPAPER_TILE_WIDTH_METERS, WALL_HEIGHT_METERS};
+
+    const WIDTH: u32 = 512;
+    const HEIGHT: u32 = 256;
+
+    /// Square room 4 x 4 camera heights, with ceiling 0.6 camera heights above the camera, seen
+    /// from its centre.
+    fn square_room_corners() -> Vec<PanoramaCorner> {
+        let distance = 2.0 * 2f32.sqrt();
+        [-3.0 * FRAC_PI_4, -FRAC_PI_4, FRAC_PI_4, 3.0 * FRAC_PI_4]
+            .iter()
+            .map(|longitude| {
+                let row = |latitude: f32| (FRAC_PI_2 - latitude) / PI * HEIGHT as f32;
+                PanoramaCorner {
+                    x: (longitude + PI) / (2.0 * PI) * WIDTH as f32,
+                    ceiling_y: row((0.6 / distance).atan()),
+                    floor_y: row(-(1.0 / distance).atan()),
+                }
+            })
+            .rev()
+            .collect()
+    }
+
+    #[test]
+    fn reconstructs_closed_loop_of_walls() {
+        let room = PanoramaRoom::new(&square_room_corners(), WIDTH, HEIGHT).unwrap();
+
+        // Room is 1.6 camera heights high, scaled to the wall height
+        let scale = WALL_HEIGHT_METERS / 1.6;
+        assert!((room.camera_height - scale).abs() < 1e-3, "{room:?}");
+        let expected_corners = [(-2.0, -2.0), (-2.0, 2.0), (2.0, 2.0), (2.0, -2.0)];
+        for (corner, expected) in room.corners.iter().zip(expected_corners) {
+            assert!((corner.0 - expected.0 * scale).abs() < 1e-3, "{room:?}");
+            assert!((corner.1 - expected.1 * scale).abs() < 1e-3, "{room:?}");
+        }
+
+        // Horizon is on the walls, poles are on the floor and ceiling
+        assert_eq!(room.wall_point(0.0, 0.0).unwrap().wall, 1);
+        assert_eq!(room.wall_point(PI - 0.01, 0.0).unwrap().wall, 3);
+        assert!(room.wall_point(0.0, 1.5).is_none());
+        assert!(room.wall_point(0.0, -1.5).is_none());
+    }
+
+    #[test]
+    fn wallpaper_continues_around_the_room() {
+        let room = PanoramaRoom::new(&square_room_corners(), WIDTH, HEIGHT).unwrap();
+        let first_corner = room.corner_longitudes[0];
+
+        let first_wall_start = room.wall_point(first_corner + 1e-4, 0.0).unwrap();
+        let last_wall_end = room.wall_point(first_corner - 1e-4, 0.0).unwrap();
+
+        assert_eq!(first_wall_start.wall, 0);
+        assert_eq!(last_wall_end.wall, 3);
+        assert!(first_wall_start.u < 0.01, "{first_wall_start:?}");
+        let remainder = last_wall_end.u % WALLPAPER_TILE_WIDTH_METERS;
+        let remainder = remainder.min(WALLPAPER_TILE_WIDTH_METERS - remainder);
+        assert!(remainder < 0.01, "{last_wall_end:?}");
+        // Neighbouring walls continue each other too
+        let second_corner = room.corner_longitudes[1];
+        let first_wall_end = room.wall_point(second_corner - 1e-4, 0.0).unwrap();
+        let second_wall_start = room.wall_point(second_corner + 1e-4, 0.0).unwrap();
+        assert!((first_wall_end.u - second_wall_start.u).abs() < 0.01);
+        assert!((first_wall_end.v - second_wall_start.v).abs() < 0.01);
+    }
+
+    #[test]
+    fn covers_only_walls_with_wallpaper() {
+        let room_image = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb([128, 128, 128]));
+        let material = WallpaperMaterial::from(RgbImage::from_pixel(16, 16, Rgb([200, 20, 20])));
+
+        let preview = create_panorama_preview(room_image, None, &material, &square_room_corners());
+
+        for x in [0, WIDTH / 4, WIDTH / 2, WIDTH - 1] {
+            let wall_pixel = preview.get_pixel(x, HEIGHT / 2).0;
+            assert!(
+                wall_pixel[0] > 150 && wall_pixel[1] < 50,
+                "{x}: {wall_pixel:?}"
+            );
+            assert_eq!(*preview.get_pixel(x, 2), Rgb([128, 128, 128]));
+            assert_eq!(*preview.get_pixel(x, HEIGHT - 3), Rgb([128, 128, 128]));
+        }
+    }
+}
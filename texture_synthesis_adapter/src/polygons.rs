@@ -1,9 +1,31 @@
 use crate::ffi::LayoutWallPolygon;
-use lsun_res_parser::{Line, Point};
-use polyfit_rs::polyfit_rs::polyfit;
+use crate::layout::lsun::{Line, Point};
+use projective::{HomogeneousLine, HomogeneousPoint};
+pub use topology::{
+    room_topology, Border, BorderDirection, Corner, EdgeLine, LineEnd, RoomTopology, Side,
+    WallTopology,
+};
+
+pub(crate) mod projective;
+pub mod topology;
+
+/// Wall corners further than this many image sizes away from the image are cut by image borders
+const MAX_CORNER_DISTANCE_IMAGE_SIZES: f64 = 10.0;
+/// Extra angle between ceiling (or floor) lines and the perpendicular to wall corner line
+/// when these lines are not visible.
+/// TODO: We selected 30 degrees because it gave most realistic results on parsed predictions
+///   results of LSUN val and train datasets.
+///   As well as after parsing other room types as type 7 and comparing results to actual lines (where these are visible)
+///   Bascially, the same technique as other papers used to generate missing room types form existing rooms.
+/// TODO: alternatively, we could also take into account line X position to adjust extra degrees
+///   as they most oftern are sharper the close line is to either border.
+///   However, since we anyways require user to keep floor and ceiling visible, so that
+///   edges could be estimated correctly, we won't perform any more advanced workarounds here
+///   in the current implementation.
+const INVISIBLE_LINE_EXTRA_ANGLE_DEGREES: f64 = 30.0;
 
 /// Convert line coordinates between image and geo space, and vice versa.
-fn convert_lines_coords_image_geo(lines: &Vec<Line>, height: i32) -> Vec<Line> {
+fn convert_lines_coords_image_geo(lines: &[Line], height: i32) -> Vec<Line> {
     lines
         .iter()
         .map(|(p1, p2)| {
@@ -14,64 +36,149 @@ fn convert_lines_coords_image_geo(lines: &Vec<Line>, height: i32) -> Vec<Line> {
         .collect()
 }
 
-fn convert_line_coords_image_geo(line: Line, height: i32) -> Line {
-    let (p1, p2) = line;
-    let p1: Point = (p1.0, height - 1 - p1.1);
-    let p2: Point = (p2.0, height - 1 - p2.1);
-    (p1, p2)
+fn convert_polygon_coords_image_geo(polygon: WallPolygon, height: i32) -> WallPolygon {
+    let convert_point = |point: Point| (point.0, height - 1 - point.1);
+    WallPolygon {
+        top_left: convert_point(polygon.top_left),
+        top_right: convert_point(polygon.top_right),
+        bottom_right: convert_point(polygon.bottom_right),
+        bottom_left: convert_point(polygon.bottom_left),
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
-struct LineSlopeInterceptForm {
-    pub slope: f32,
-    pub intercept: f32,
+/// Image bounds in geo space, where Y axis points up.
+struct ImageFrame {
+    max_x: f64,
+    max_y: f64,
 }
 
-fn compute_line_params(line: Line) -> LineSlopeInterceptForm {
-    let padding: f64 = if line.0 .0 == line.1 .0 { 0.00001 } else { 0. };
-    let line_monomials = polyfit(
-        &[line.0 .0 as f64, line.1 .0 as f64 + padding],
-        &[line.0 .1 as f64, line.1 .1 as f64 + padding],
-        1,
-    )
-    .unwrap();
+impl ImageFrame {
+    fn new(image_width: i32, image_height: i32) -> Self {
+        Self {
+            max_x: (image_width - 1) as f64,
+            max_y: (image_height - 1) as f64,
+        }
+    }
 
-    LineSlopeInterceptForm {
-        slope: line_monomials[1] as f32,
-        intercept: line_monomials[0] as f32,
+    fn top_left(&self) -> HomogeneousPoint {
+        HomogeneousPoint::new(0.0, self.max_y)
     }
-}
 
-fn compute_line_intercept_at_point(slope: f32, point: (f32, f32)) -> f32 {
-    // y = ax + b => b = y - ax
-    point.1 - slope * point.0
-}
+    fn top_right(&self) -> HomogeneousPoint {
+        HomogeneousPoint::new(self.max_x, self.max_y)
+    }
+
+    fn bottom_right(&self) -> HomogeneousPoint {
+        HomogeneousPoint::new(self.max_x, 0.0)
+    }
+
+    fn bottom_left(&self) -> HomogeneousPoint {
+        HomogeneousPoint::new(0.0, 0.0)
+    }
+
+    /// Line parallel to the given one, going through the left image corner which keeps the whole
+    /// left side of the image on the right of the line.
+    fn left_border(&self, line: &HomogeneousLine) -> HomogeneousLine {
+        let corner = if line.rises() {
+            self.top_left()
+        } else {
+            self.bottom_left()
+        };
+        line.parallel_through(corner)
+    }
+
+    /// Line parallel to the given one, going through the right image corner which keeps the whole
+    /// right side of the image on the left of the line.
+    fn right_border(&self, line: &HomogeneousLine) -> HomogeneousLine {
+        let corner = if line.rises() {
+            self.bottom_right()
+        } else {
+            self.top_right()
+        };
+        line.parallel_through(corner)
+    }
 
-fn compute_lines_intersection_point(
-    slope1: f32,
-    intercept1: f32,
-    slope2: f32,
-    intercept2: f32,
-) -> (f32, f32) {
-    // Given two lines 'y = a1 * x + b2 and 'y = a2 * x + b2', finds point of intersection (x, y).
-    //
-    // a2 * x + b2 = a1 * x + b1
-    // a2 * x - a1 * x = b1 - b2
-    // (a2 - a1) * x = b1 - b2
-    // x = (b1 - b2) / (a2 - a1)
+    /// Line at the vertical image side, just outside the image.
+    fn image_side(&self, side: Side) -> HomogeneousLine {
+        let (outer_x, inner_x) = match side {
+            Side::Left => (-1.0, 0.0),
+            Side::Right => (self.max_x + 1.0, self.max_x),
+        };
+        HomogeneousLine::through(
+            HomogeneousPoint::new(outer_x, 0.0),
+            HomogeneousPoint::new(inner_x, self.max_y),
+        )
+    }
+
+    /// Invisible ceiling (or floor) line at the given image side must not cross the image,
+    /// so it is moved to go through the image corner if it would.
+    fn keep_outside(&self, line: HomogeneousLine, side: Side, level: Level) -> HomogeneousLine {
+        match (side, level, line.rises()) {
+            (Side::Left, Level::Top, true) => line.parallel_through(self.top_left()),
+            (Side::Left, Level::Bottom, false) => line.parallel_through(self.bottom_left()),
+            (Side::Right, Level::Top, false) => line.parallel_through(self.top_right()),
+            (Side::Right, Level::Bottom, true) => line.parallel_through(self.bottom_right()),
+            _ => line,
+        }
+    }
+
+    /// Point where wall edge meets border line. If they are parallel, or meet too far away from
+    /// the image, the edge is cut by the vertical image side closest to the border instead.
+    fn corner(&self, edge: &HomogeneousLine, border: &HomogeneousLine) -> Point {
+        if let Some(point) = edge.intersection(border) {
+            if let Some((x, y)) = point.to_cartesian() {
+                let max_distance_x = self.max_x * MAX_CORNER_DISTANCE_IMAGE_SIZES;
+                let max_distance_y = self.max_y * MAX_CORNER_DISTANCE_IMAGE_SIZES;
+                if x >= -max_distance_x
+                    && x <= self.max_x + max_distance_x
+                    && y >= -max_distance_y
+                    && y <= self.max_y + max_distance_y
+                {
+                    return (x as i32, y as i32);
+                }
+            }
+        }
+
+        let border_x = border
+            .project(HomogeneousPoint::new(self.max_x / 2.0, self.max_y / 2.0))
+            .to_cartesian()
+            .unwrap()
+            .0;
+        let side_x = if border_x < self.max_x / 2.0 {
+            0.0
+        } else {
+            self.max_x
+        };
+        let side = HomogeneousLine::through(
+            HomogeneousPoint::new(side_x, 0.0),
+            HomogeneousPoint::new(side_x, self.max_y),
+        );
+        match edge.intersection(&side) {
+            Some(point) => point.to_point().unwrap(),
+            // Vertical edge never reaches vertical side, so take its point closest to the side
+            None => edge
+                .project(HomogeneousPoint::new(side_x, self.max_y / 2.0))
+                .to_point()
+                .unwrap(),
+        }
+    }
+}
 
-    // Computations might overflow, so calculate using 64-bit precision
-    let slope1 = slope1 as f64;
-    let slope2 = slope2 as f64;
-    let intercept1 = intercept1 as f64;
-    let intercept2 = intercept2 as f64;
+/// Line through the point with direction of `line` mirrored about `axis`.
+fn mirrored_line(
+    line: &HomogeneousLine,
+    axis: &HomogeneousLine,
+    point: HomogeneousPoint,
+) -> HomogeneousLine {
+    HomogeneousLine::with_direction_angle(point, line.mirrored_direction_angle(axis))
+}
 
-    let x = (intercept1 - intercept2) / (slope2 - slope1);
-    let y = slope1 * x + intercept1; // or y = slope2 * x + intercept2, doesn't matter
-    (x as f32, y as f32)
+/// Direction angle of a line perpendicular to `line`, tilted by the given extra angle.
+fn tilted_perpendicular_angle(line: &HomogeneousLine, extra_angle_degrees: f64) -> f64 {
+    line.direction_angle() + 90f64.to_radians() + extra_angle_degrees.to_radians()
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct WallPolygon {
     pub top_left: Point,
     pub top_right: Point,
@@ -101,1679 +208,498 @@ impl WallPolygon {
     }
 }
 
+/// Computes wall polygons of the LSUN room type. `None` if the room type is unknown or there are
+/// fewer lines than it needs.
 pub fn compute_wall_polygons(
-    lines: &Vec<Line>,
+    lines: &[Line],
     image_width: i32,
     image_height: i32,
     room_type: u8,
-) -> Vec<WallPolygon> {
-    match room_type {
-        0 => compute_wall_polygons_for_room_type_0(lines, image_height).to_vec(),
-        1 => compute_wall_polygons_for_room_type_1(lines, image_height).to_vec(),
-        2 => compute_wall_polygons_for_room_type_2(lines, image_height).to_vec(),
-        3 => compute_wall_polygons_for_room_type_3(lines, image_height).to_vec(),
-        4 => compute_wall_polygons_for_room_type_4(lines, image_height).to_vec(),
-        5 => compute_wall_polygons_for_room_type_5(lines, image_height).to_vec(),
-        6 => vec![compute_wall_polygon_for_room_type_6(
-            lines,
-            image_width,
-            image_height,
-        )],
-        7 => compute_wall_polygons_for_room_type_7(lines, image_height).to_vec(),
-        8 => vec![compute_wall_polygon_for_room_type_8(lines, image_height)],
-        9 => vec![compute_wall_polygon_for_room_type_9(lines, image_height)],
-        10 => compute_wall_polygons_for_room_type_10(lines, image_height).to_vec(),
-
-        _ => {
-            // TODO: better return Result with error
-            panic!("Unknown room type: {room_type}")
-        }
-    }
-}
-
-fn compute_wall_polygons_for_room_type_0(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 3] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    let line_center_left = lines_geo[4];
-    let line_params = compute_line_params(line_center_left);
-
-    let line_left_border_slope = line_params.slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Left top, needs to be extended to intersect with left border line
-    let line_left_top = lines_geo[0];
-    let line_params = compute_line_params(line_left_top);
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left_top.0,
-    );
-
-    // Left botton, needs to be extended to intersect with left border line
-    let line_left_bottom = lines_geo[1];
-    let line_params = compute_line_params(line_left_bottom);
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left_bottom.0,
-    );
-
-    let line_center_right = lines_geo[6];
-
-    // Right border
-    let line_params = compute_line_params(line_center_right);
-    let line_right_border_slope = line_params.slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Right top
-    let line_right_top = lines_geo[3];
-    let line_params = compute_line_params(line_right_top);
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_right_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_right_top.0,
-    );
-
-    let line_right_bottom = lines_geo[2];
-    let line_params = compute_line_params(line_right_bottom);
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_right_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_right_bottom.0,
-    );
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_center_left.0,
-        bottom_right: line_center_left.1,
-        bottom_left: line_left_bottom.0,
-    };
-
-    let center_wall_polygon = WallPolygon {
-        top_left: line_center_left.0,
-        top_right: line_center_right.1,
-        bottom_right: line_center_right.0,
-        bottom_left: line_center_left.1,
-    };
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_center_right.1,
-        top_right: line_right_top.0,
-        bottom_right: line_right_bottom.0,
-        bottom_left: line_center_right.0,
-    };
-
-    [left_wall_polygon, center_wall_polygon, right_wall_polygon]
-}
-
-fn compute_wall_polygons_for_room_type_1(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 3] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    // Top left
-    let line_center_left = lines_geo[0];
-    let line_params = compute_line_params(line_center_left);
-    let line_center_left_slope = line_params.slope;
-    let line_center_left_theta = line_center_left_slope.atan();
-
-    let line_left_bottom = lines_geo[1];
-    let line_params = compute_line_params(line_left_bottom);
-    let line_left_bottom_slope = line_params.slope;
-    let line_left_bottom_intercept = line_params.intercept;
-    let line_left_bottom_theta = line_left_bottom_slope.atan();
-
-    let line_left_top_theta = 2. * line_center_left_theta - line_left_bottom_theta;
-    let line_left_top_slope = line_left_top_theta.tan();
-    let corner_point = if line_left_top_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (line_center_left.1 .0 as f32, line_center_left.1 .1 as f32)
-    };
-    let line_left_top_intercept =
-        compute_line_intercept_at_point(line_left_top_slope, corner_point);
-
-    // Left border
-    let line_left_border_slope = line_center_left_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Right top
-    let line_center_right = lines_geo[3];
-    let line_params = compute_line_params(line_center_right);
-    let line_center_right_slope = line_params.slope;
-    let line_center_right_theta = line_center_right_slope.atan();
-
-    let line_right_bottom = lines_geo[4];
-    let line_params = compute_line_params(line_right_bottom);
-    let line_right_bottom_slope = line_params.slope;
-    let line_right_bottom_intercept = line_params.intercept;
-    let line_right_bottom_theta = line_right_bottom_slope.atan();
-
-    let line_right_top_theta = 2. * line_center_right_theta - line_right_bottom_theta;
-    let line_right_top_slope = line_right_top_theta.tan();
-    let corner_point = if line_right_top_slope >= 0. {
-        (line_center_right.1 .0 as f32, line_center_right.1 .1 as f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_top_intercept =
-        compute_line_intercept_at_point(line_right_top_slope, corner_point);
-
-    // Right border
-    let line_right_border_slope = line_center_right_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Left polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_top_slope,
-        line_left_top_intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center_left.1,
-    );
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_bottom_slope,
-        line_left_bottom_intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center_left.0,
-    );
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_left_top.1,
-        bottom_right: line_left_bottom.1,
-        bottom_left: line_left_bottom.0,
-    };
-
-    // Right polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_top_slope,
-        line_right_top_intercept,
-    );
-    let line_right_top = (
-        line_center_right.1,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_bottom_slope,
-        line_right_bottom_intercept,
-    );
-    let line_right_bottom = (
-        line_center_right.0,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_right_top.0,
-        top_right: line_right_top.1,
-        bottom_right: line_right_bottom.1,
-        bottom_left: line_right_bottom.0,
-    };
-
-    // Center polygon
-    let line_center_left = convert_line_coords_image_geo(line_center_left, image_height);
-    let line_center_right = convert_line_coords_image_geo(line_center_right, image_height);
-
-    let center_wall_polygon = WallPolygon {
-        top_left: line_center_left.1,
-        top_right: line_center_right.1,
-        bottom_right: line_center_right.0,
-        bottom_left: line_center_left.0,
-    };
-
-    [left_wall_polygon, center_wall_polygon, right_wall_polygon]
-}
-
-fn compute_wall_polygons_for_room_type_2(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 3] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    let line_left_top = lines_geo[0];
-    let line_center_left = lines_geo[1];
-    let line_right_top = lines_geo[3];
-    let line_center_right = lines_geo[4];
-
-    // Bottom left
-    let line_params = compute_line_params(line_left_top);
-    let line_left_top_slope = line_params.slope;
-    let line_left_top_intercept = line_params.intercept;
-    let line_left_top_theta = line_left_top_slope.atan();
-
-    let line_params = compute_line_params(line_center_left);
-    let line_center_left_slope = line_params.slope;
-    let line_center_left_theta = line_center_left_slope.atan();
-
-    let line_left_bottom_theta = 2. * line_center_left_theta - line_left_top_theta;
-    let line_left_bottom_slope = line_left_bottom_theta.tan();
-
-    let corner_point = if line_left_bottom_slope >= 0. {
-        (line_center_left.1 .0 as f32, line_center_left.1 .1 as f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_bottom_intercept =
-        compute_line_intercept_at_point(line_left_bottom_slope, corner_point);
-
-    // Left border
-    let line_left_border_slope = line_center_left_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Right bottom
-    let line_params = compute_line_params(line_right_top);
-    let line_right_top_slope = line_params.slope;
-    let line_right_top_intercept = line_params.intercept;
-    let line_right_top_theta = line_right_top_slope.atan();
-
-    let line_params = compute_line_params(line_center_right);
-    let line_center_right_slope = line_params.slope;
-    let line_center_right_theta = line_center_right_slope.atan();
-
-    let line_right_bottom_theta = 2. * line_center_right_theta - line_right_top_theta;
-    let line_right_bottom_slope = line_right_bottom_theta.tan();
-
-    let corner_point = if line_right_bottom_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (line_center_right.1 .0 as f32, line_center_right.1 .1 as f32)
-    };
-    let line_right_bottom_intercept =
-        compute_line_intercept_at_point(line_right_bottom_slope, corner_point);
-
-    // Right border
-    let line_right_border_slope = line_center_right_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
+) -> Option<Vec<WallPolygon>> {
+    let Some(topology) = room_topology(room_type) else {
+        println!("Unknown room type: {room_type}");
+        return None;
     };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Left polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_top_slope,
-        line_left_top_intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center_left.0,
-    );
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_bottom_slope,
-        line_left_bottom_intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center_left.1,
-    );
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_left_top.1,
-        bottom_right: line_left_bottom.1,
-        bottom_left: line_left_bottom.0,
-    };
-
-    // Right polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_top_slope,
-        line_right_top_intercept,
-    );
-    let line_right_top = (
-        line_center_right.0,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_bottom_slope,
-        line_right_bottom_intercept,
-    );
-    let line_right_bottom = (
-        line_center_right.1,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_right_top.0,
-        top_right: line_right_top.1,
-        bottom_right: line_right_bottom.1,
-        bottom_left: line_right_bottom.0,
-    };
-
-    // Center polygon
-    let line_center_left = convert_line_coords_image_geo(line_center_left, image_height);
-    let line_center_right = convert_line_coords_image_geo(line_center_right, image_height);
-
-    let center_wall_polygon = WallPolygon {
-        top_left: line_center_left.0,
-        top_right: line_center_right.0,
-        bottom_right: line_center_right.1,
-        bottom_left: line_center_left.1,
-    };
-
-    [left_wall_polygon, center_wall_polygon, right_wall_polygon]
+    solve_wall_polygons(lines, image_width, image_height, &topology)
 }
 
-fn compute_wall_polygons_for_room_type_3(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 2] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    let line_left_top = lines_geo[0];
-    let line_center = lines_geo[1];
-    let line_right_top = lines_geo[2];
-
-    // Left bottom
-    let line_params = compute_line_params(line_left_top);
-    let line_left_top_slope = line_params.slope;
-    let line_left_top_intercept = line_params.intercept;
-    let line_params = compute_line_params(line_center);
-    let line_center_slope = line_params.slope;
-    let line_center_theta = line_center_slope.atan();
-    let line_left_top_theta = line_left_top_slope.atan();
-    let line_left_bottom_theta = 2. * line_center_theta - line_left_top_theta;
-    let line_left_bottom_slope = line_left_bottom_theta.tan();
-    let corner_point = if line_left_bottom_slope >= 0. {
-        (line_center.1 .0 as f32, line_center.1 .1 as f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_bottom_intercept =
-        compute_line_intercept_at_point(line_left_bottom_slope, corner_point);
-
-    // Left border
-    let line_left_border_slope = line_center_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Right bottom
-    let line_params = compute_line_params(line_right_top);
-    let line_right_top_slope = line_params.slope;
-    let line_right_top_intercept = line_params.intercept;
-    let line_right_top_theta = line_right_top_slope.atan();
-    let line_right_bottom_theta = 2. * line_center_theta - line_right_top_theta;
-    let line_right_bottom_slope = line_right_bottom_theta.tan();
-    let corner_point = if line_right_bottom_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (line_center.1 .0 as f32, line_center.1 .1 as f32)
-    };
-    let line_right_bottom_intercept =
-        compute_line_intercept_at_point(line_right_bottom_slope, corner_point);
-
-    // Right border
-    let line_right_border_slope = line_center_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Left top line
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_top_slope,
-        line_left_top_intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left_top.0,
-    );
-
-    // Left bottom line
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_bottom_slope,
-        line_left_bottom_intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center.1,
-    );
-
-    // Right top line
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_top_slope,
-        line_right_top_intercept,
-    );
-    let line_right_top = (
-        line_center.0,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-
-    // Right bottom line
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_bottom_slope,
-        line_right_bottom_intercept,
-    );
-    let line_right_bottom = (
-        line_center.1,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_left_top.1,
-        bottom_right: line_left_bottom.1,
-        bottom_left: line_left_bottom.0,
-    };
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_right_top.0,
-        top_right: line_right_top.1,
-        bottom_right: line_right_bottom.1,
-        bottom_left: line_right_bottom.0,
-    };
-
-    [left_wall_polygon, right_wall_polygon]
-}
-
-fn compute_wall_polygons_for_room_type_4(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 2] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    // 0 - bottom left
-    // 1 - vertical
-    // 2 - bottom right
-
-    // Left border line
-    let line_center = lines_geo[1];
-    let line_params = compute_line_params(line_center);
-    let line_center_slope = line_params.slope;
-    let line_left_border_slope = line_center_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Left top
-    let line_bottom_left = lines_geo[0];
-    let line_params = compute_line_params(line_bottom_left);
-    let line_bottom_left_slope = line_params.slope;
-    let line_bottom_left_intercept = line_params.intercept;
-    let line_center_theta = line_center_slope.atan();
-    let line_bottom_left_theta = line_bottom_left_slope.atan();
-    let line_top_left_theta = 2. * line_center_theta - line_bottom_left_theta;
-    let line_top_left_slope = line_top_left_theta.tan();
-    let corner_point = if line_top_left_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (line_center.1 .0 as f32, line_center.1 .1 as f32)
-    };
-    let line_top_left_intercept =
-        compute_line_intercept_at_point(line_top_left_slope, corner_point);
-
-    // Right border
-    let line_right_border_slope = line_center_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Right top
-    let line_bottom_right = lines_geo[2];
-    let line_params = compute_line_params(line_bottom_right);
-    let line_bottom_right_slope = line_params.slope;
-    let line_bottom_right_intercept = line_params.intercept;
-    let line_bottom_right_theta = line_bottom_right_slope.atan();
-    let line_top_right_theta = 2. * line_center_theta - line_bottom_right_theta;
-    let line_top_right_slope = line_top_right_theta.tan();
-    let corner_point = if line_top_right_slope >= 0. {
-        (line_center.1 .0 as f32, line_center.1 .1 as f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_top_right_intercept =
-        compute_line_intercept_at_point(line_top_right_slope, corner_point);
-
-    // Left polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_top_left_slope,
-        line_top_left_intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center.1,
-    );
-
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_bottom_left_slope,
-        line_bottom_left_intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center.0,
-    );
-
-    // Convert to image coords
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-    let line_center = convert_line_coords_image_geo(line_center, image_height);
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_center.1,
-        bottom_right: line_center.0,
-        bottom_left: line_left_bottom.0,
-    };
-
-    // Right polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_top_right_slope,
-        line_top_right_intercept,
-    );
-    let line_right_top = (
-        line_center.1,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_bottom_right_slope,
-        line_bottom_right_intercept,
-    );
-    let line_right_bottom = (
-        line_center.0,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-
-    // Convert to image coords
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_center.1,
-        top_right: line_right_top.1,
-        bottom_right: line_right_bottom.1,
-        bottom_left: line_center.0,
-    };
-
-    [left_wall_polygon, right_wall_polygon]
-}
-
-fn compute_wall_polygons_for_room_type_5(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 2] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    let line_center = lines_geo[2];
-    let line_center_params = compute_line_params(line_center);
-    let line_center_slope = line_center_params.slope;
-
-    let line_left_border_slope = line_center_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Line 3 - right border
-    let line_right_border_slope = line_center_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Left top, just needs to be extend to intersection point with left border line
-    let line_left_top = lines_geo[0];
-    let line_params = compute_line_params(line_left_top);
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left_top.0,
-    );
-
-    // Left bottom line, also needs to be extended to intersection with left border line
-    let line_left_bottom = lines_geo[3];
-    let line_params = compute_line_params(line_left_bottom);
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left_bottom.0,
-    );
-
-    // Right top line
-    let line_right_top = lines_geo[1];
-    let line_params = compute_line_params(line_right_top);
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_right_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_right_top.0,
-    );
-
-    // Right bottom line
-    let line_right_bottom = lines_geo[4];
-    let line_params = compute_line_params(line_right_bottom);
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_params.slope,
-        line_params.intercept,
-    );
-    let line_right_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_right_bottom.0,
-    );
-
-    // Convert back to image coords from geo coords
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-    let line_center = convert_line_coords_image_geo(line_center, image_height);
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_center.0,
-        bottom_right: line_center.1,
-        bottom_left: line_left_bottom.0,
-    };
-    let right_wall_polygon = WallPolygon {
-        top_left: line_center.0,
-        top_right: line_right_top.0,
-        bottom_right: line_right_bottom.0,
-        bottom_left: line_center.1,
-    };
-
-    [left_wall_polygon, right_wall_polygon]
-}
-
-fn compute_wall_polygon_for_room_type_6(
-    lines: &Vec<Line>,
+/// Computes wall polygons, bounded by detected lines as described by the room topology. `None`
+/// if there are fewer lines than the topology needs.
+pub fn solve_wall_polygons(
+    lines: &[Line],
     image_width: i32,
     image_height: i32,
-) -> WallPolygon {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    let line_top = lines_geo[0];
-    let line_bottom = lines_geo[1];
-
-    let line_params = compute_line_params(line_top);
-    let line_top_slope = line_params.slope;
-    let line_top_intercept = line_params.intercept;
-
-    let line_params = compute_line_params(line_bottom);
-    let line_bottom_slope = line_params.slope;
-    let line_bottom_intercept = line_params.intercept;
-
-    // FIXME: we assume borders to be vertical lines placed at image corners
-    //   Ideally, we would also compute mean angle to rotate them correspondingly,
-    //   if top and bottom lines are parallel or have common slope
-    // Left border
-    let line_params = compute_line_params(((-1, 0), (0, image_height - 1)));
-    let line_left_border_slope = line_params.slope;
-    let line_left_border_intercept = line_params.intercept;
-
-    // Right border
-    let line_params = compute_line_params(((image_width, 0), (image_width - 1, image_height - 1)));
-    let line_right_border_slope = line_params.slope;
-    let line_right_border_intercept = line_params.intercept;
-
-    let top_left_intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_top_slope,
-        line_top_intercept,
-    );
-    let top_right_intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_top_slope,
-        line_top_intercept,
-    );
-    let bottom_left_intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_bottom_slope,
-        line_bottom_intercept,
-    );
-    let bottom_right_intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_bottom_slope,
-        line_bottom_intercept,
-    );
-
-    let line_top = (
-        (
-            top_left_intersection_point.0 as i32,
-            top_left_intersection_point.1 as i32,
-        ),
-        (
-            top_right_intersection_point.0 as i32,
-            top_right_intersection_point.1 as i32,
-        ),
-    );
-    let line_top = convert_line_coords_image_geo(line_top, image_height);
-
-    let line_bottom = (
-        (
-            bottom_left_intersection_point.0 as i32,
-            bottom_left_intersection_point.1 as i32,
-        ),
-        (
-            bottom_right_intersection_point.0 as i32,
-            bottom_right_intersection_point.1 as i32,
-        ),
-    );
-    let line_bottom = convert_line_coords_image_geo(line_bottom, image_height);
-
-    WallPolygon {
-        top_left: line_top.0,
-        top_right: line_top.1,
-        bottom_right: line_bottom.1,
-        bottom_left: line_bottom.0,
+    topology: &RoomTopology,
+) -> Option<Vec<WallPolygon>> {
+    let num_lines = topology.num_lines();
+    if lines.len() < num_lines {
+        println!(
+            "Room topology requires {num_lines} lines, but only {} were detected",
+            lines.len()
+        );
+        return None;
     }
-}
-
-fn compute_wall_polygons_for_room_type_7(lines: &Vec<Line>, image_height: i32) -> [WallPolygon; 3] {
-    let lines_geo = convert_lines_coords_image_geo(&lines, image_height);
-
-    let line_left = lines_geo[0];
-    let line_right = lines_geo[1];
-
-    // Left border
-    let line_params = compute_line_params(line_left);
-    let line_left_slope = line_params.slope;
-
-    let line_left_border_slope = line_left_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // Left top line
-    // We assume it to be perpendicular to left line with some extra degrees added.
-    // Nothing much we can do other than applying our best assumptions.
-    // TODO: We selected 30 degrees because it gave most realistic results on parsed predictions
-    //   results of LSUN val and train datasets.
-    //   As well as after parsing other room types as type 7 and comparing results to actual lines (where these are visible)
-    //   Bascially, the same technique as other papers used to generate missing room types form existing rooms.
-    // TODO: alternatively, we could also take into account line X position to adjust extra degrees
-    //   as they most oftern are sharper the close line is to either border.
-    //   However, since we anyways require user to keep floor and ceiling visible, so that
-    //   edges could be estimated correctly, we won't perform any more advanced workarounds here
-    //   in the current implementation.
-    let extra_angle_rads = 30f32.to_radians();
-    let line_left_perpendicular_slope = -1.0 / line_left_slope;
-    let line_left_top_slope = (line_left_perpendicular_slope.atan() - extra_angle_rads).tan();
-
-    let corner_point = if line_left_top_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (line_left.1 .0 as f32, line_left.1 .1 as f32)
-    };
-    let line_left_top_intercept =
-        compute_line_intercept_at_point(line_left_top_slope, corner_point);
-
-    // Left bottom line
-    let line_left_bottom_slope = (line_left_perpendicular_slope.atan() + extra_angle_rads).tan();
-    let corner_point = if line_left_bottom_slope >= 0. {
-        (line_left.0 .0 as f32, line_left.0 .1 as f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_bottom_intercept =
-        compute_line_intercept_at_point(line_left_bottom_slope, corner_point);
-
-    // Right border
-    let line_params = compute_line_params(line_right);
-    let line_right_slope = line_params.slope;
-
-    let line_right_border_slope = line_right_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Right top line
-    let line_right_perpendicular_slope = -1.0 / line_right_slope;
-    let line_right_top_slope = (line_right_perpendicular_slope.atan() + extra_angle_rads).tan();
-    let corner_point = if line_right_top_slope >= 0. {
-        (line_right.1 .0 as f32, line_right.1 .1 as f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_top_intercept =
-        compute_line_intercept_at_point(line_right_top_slope, corner_point);
-
-    // Right bottom line
-    let line_right_bottom_slope = (line_right_perpendicular_slope.atan() - extra_angle_rads).tan();
-    let corner_point = if line_right_bottom_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (line_right.0 .0 as f32, line_right.0 .1 as f32)
-    };
-    let line_right_bottom_intercept =
-        compute_line_intercept_at_point(line_right_bottom_slope, corner_point);
-
-    // Left polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_top_slope,
-        line_left_top_intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left.1,
-    );
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_bottom_slope,
-        line_left_bottom_intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_left.0,
-    );
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_left_top.1,
-        bottom_right: line_left_bottom.1,
-        bottom_left: line_left_bottom.0,
-    };
-
-    // Right wall polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_top_slope,
-        line_right_top_intercept,
-    );
-    let line_right_top = (
-        line_right.1,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_bottom_slope,
-        line_right_bottom_intercept,
-    );
-    let line_right_bottom = (
-        line_right.0,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_right_top.0,
-        top_right: line_right_top.1,
-        bottom_right: line_right_bottom.1,
-        bottom_left: line_right_bottom.0,
-    };
-
-    // Center wall polygon
-    let center_wall_polygon = WallPolygon {
-        top_left: left_wall_polygon.top_right,
-        top_right: right_wall_polygon.top_left,
-        bottom_right: right_wall_polygon.bottom_left,
-        bottom_left: left_wall_polygon.bottom_right,
-    };
-
-    [left_wall_polygon, center_wall_polygon, right_wall_polygon]
-}
-
-fn compute_wall_polygon_for_room_type_8(lines: &Vec<Line>, image_height: i32) -> WallPolygon {
-    let line_top = convert_line_coords_image_geo(lines[0], image_height);
-    let line_params = compute_line_params(line_top);
-    let line_top_slope = line_params.slope;
-    let line_top_intercept = line_params.intercept;
-
-    let line_bottom_slope = line_top_slope;
-    let corner_point = if line_bottom_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_bottom_intercept = compute_line_intercept_at_point(line_bottom_slope, corner_point);
-
-    // Left and right border lines
-    // Assume it is perpendicular to top (and bottom) line
-    let line_perpendicular_slope = -1.0 / line_top_slope;
-
-    let line_left_border_slope = line_perpendicular_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
 
-    let line_right_border_slope = line_perpendicular_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
+    let solver = TopologySolver {
+        lines_geo: convert_lines_coords_image_geo(lines, image_height),
+        frame: ImageFrame::new(image_width, image_height),
     };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Wall polygon points
-    let top_left = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_top_slope,
-        line_top_intercept,
-    );
-    let top_right = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_top_slope,
-        line_top_intercept,
-    );
-    let bottom_right = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_bottom_slope,
-        line_bottom_intercept,
-    );
-    let bottom_left = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_bottom_slope,
-        line_bottom_intercept,
-    );
-
-    let line_top = (
-        (top_left.0 as i32, top_left.1 as i32),
-        (top_right.0 as i32, top_right.1 as i32),
-    );
-    let line_top = convert_line_coords_image_geo(line_top, image_height);
-    let line_bottom = (
-        (bottom_left.0 as i32, bottom_left.1 as i32),
-        (bottom_right.0 as i32, bottom_right.1 as i32),
-    );
-    let line_bottom = convert_line_coords_image_geo(line_bottom, image_height);
-
-    WallPolygon {
-        top_left: line_top.0,
-        top_right: line_top.1,
-        bottom_right: line_bottom.1,
-        bottom_left: line_bottom.0,
-    }
+    let polygons = topology
+        .walls
+        .iter()
+        .map(|wall| {
+            let wall_polygon = WallPolygon {
+                top_left: solver.corner(wall.top_left, Level::Top),
+                top_right: solver.corner(wall.top_right, Level::Top),
+                bottom_right: solver.corner(wall.bottom_right, Level::Bottom),
+                bottom_left: solver.corner(wall.bottom_left, Level::Bottom),
+            };
+            convert_polygon_coords_image_geo(wall_polygon, image_height)
+        })
+        .collect();
+    Some(polygons)
 }
 
-fn compute_wall_polygon_for_room_type_9(lines: &Vec<Line>, image_height: i32) -> WallPolygon {
-    let line_bottom = convert_line_coords_image_geo(lines[0], image_height);
-    let line_params = compute_line_params(line_bottom);
-    let line_bottom_slope = line_params.slope;
-    let line_bottom_intercept = line_params.intercept;
-
-    let line_top_slope = line_bottom_slope;
-    let corner_point = if line_bottom_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_top_intercept = compute_line_intercept_at_point(line_top_slope, corner_point);
-
-    // Left and right border lines
-    // Assume it is perpendicular to top (and bottom) line
-    let line_perpendicular_slope = -1.0 / line_top_slope;
-
-    let line_left_border_slope = line_perpendicular_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    let line_right_border_slope = line_perpendicular_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Wall polygon points
-    let top_left = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_top_slope,
-        line_top_intercept,
-    );
-    let top_right = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_top_slope,
-        line_top_intercept,
-    );
-    let bottom_right = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_bottom_slope,
-        line_bottom_intercept,
-    );
-    let bottom_left = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_bottom_slope,
-        line_bottom_intercept,
-    );
-
-    let line_top = (
-        (top_left.0 as i32, top_left.1 as i32),
-        (top_right.0 as i32, top_right.1 as i32),
-    );
-    let line_top = convert_line_coords_image_geo(line_top, image_height);
-    let line_bottom = (
-        (bottom_left.0 as i32, bottom_left.1 as i32),
-        (bottom_right.0 as i32, bottom_right.1 as i32),
-    );
-    let line_bottom = convert_line_coords_image_geo(line_bottom, image_height);
-
-    WallPolygon {
-        top_left: line_top.0,
-        top_right: line_top.1,
-        bottom_right: line_bottom.1,
-        bottom_left: line_bottom.0,
-    }
+/// Whether corner is at the ceiling or at the floor.
+#[derive(Clone, Copy)]
+enum Level {
+    Top,
+    Bottom,
 }
 
-fn compute_wall_polygons_for_room_type_10(
-    lines: &Vec<Line>,
-    image_height: i32,
-) -> [WallPolygon; 2] {
-    let line_center = convert_line_coords_image_geo(lines[0], image_height);
-
-    let line_params = compute_line_params(line_center);
-    let line_center_slope = line_params.slope;
-
-    // Left border
-    let line_left_border_slope = line_center_slope;
-    let corner_point = if line_left_border_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_border_intercept =
-        compute_line_intercept_at_point(line_left_border_slope, corner_point);
-
-    // TODO: same assumption as for room type 7
-    let extra_angle_rads = 30f32.to_radians();
-    let line_perpendicular_slope = -1.0 / line_center_slope;
-
-    // Left top
-    let line_left_top_slope = (line_perpendicular_slope.atan() - extra_angle_rads).tan();
-    let corner_point = if line_left_top_slope >= 0. {
-        (0f32, 511f32)
-    } else {
-        (line_center.0 .0 as f32, line_center.0 .1 as f32)
-    };
-    let line_left_top_intercept =
-        compute_line_intercept_at_point(line_left_top_slope, corner_point);
-
-    // Left bottom
-    let line_left_bottom_slope = (line_perpendicular_slope.atan() + extra_angle_rads).tan();
-    let corner_point = if line_left_bottom_slope >= 0. {
-        (line_center.1 .0 as f32, line_center.1 .1 as f32)
-    } else {
-        (0f32, 0f32)
-    };
-    let line_left_bottom_intercept =
-        compute_line_intercept_at_point(line_left_bottom_slope, corner_point);
-
-    // Right border
-    let line_right_border_slope = line_center_slope;
-    let corner_point = if line_right_border_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_border_intercept =
-        compute_line_intercept_at_point(line_right_border_slope, corner_point);
-
-    // Right top
-    let line_right_top_slope = (line_perpendicular_slope.atan() + extra_angle_rads).tan();
-    let corner_point = if line_right_top_slope >= 0. {
-        (line_center.0 .0 as f32, line_center.0 .1 as f32)
-    } else {
-        (511f32, 511f32)
-    };
-    let line_right_top_intercept =
-        compute_line_intercept_at_point(line_right_top_slope, corner_point);
-
-    // Right bottom
-    let line_right_bottom_slope = (line_perpendicular_slope.atan() - extra_angle_rads).tan();
-    let corner_point = if line_right_bottom_slope >= 0. {
-        (511f32, 0f32)
-    } else {
-        (line_center.1 .0 as f32, line_center.1 .1 as f32)
-    };
-    let line_right_bottom_intercept =
-        compute_line_intercept_at_point(line_right_bottom_slope, corner_point);
-
-    // Left polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_top_slope,
-        line_left_top_intercept,
-    );
-    let line_left_top = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center.0,
-    );
-    let line_left_top = convert_line_coords_image_geo(line_left_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_left_border_slope,
-        line_left_border_intercept,
-        line_left_bottom_slope,
-        line_left_bottom_intercept,
-    );
-    let line_left_bottom = (
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-        line_center.1,
-    );
-    let line_left_bottom = convert_line_coords_image_geo(line_left_bottom, image_height);
-
-    let left_wall_polygon = WallPolygon {
-        top_left: line_left_top.0,
-        top_right: line_left_top.1,
-        bottom_right: line_left_bottom.1,
-        bottom_left: line_left_bottom.0,
-    };
-
-    // Right wall polygon
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_top_slope,
-        line_right_top_intercept,
-    );
-    let line_right_top = (
-        line_center.0,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_top = convert_line_coords_image_geo(line_right_top, image_height);
-
-    let intersection_point = compute_lines_intersection_point(
-        line_right_border_slope,
-        line_right_border_intercept,
-        line_right_bottom_slope,
-        line_right_bottom_intercept,
-    );
-    let line_right_bottom = (
-        line_center.1,
-        (intersection_point.0 as i32, intersection_point.1 as i32),
-    );
-    let line_right_bottom = convert_line_coords_image_geo(line_right_bottom, image_height);
-
-    let right_wall_polygon = WallPolygon {
-        top_left: line_right_top.0,
-        top_right: line_right_top.1,
-        bottom_right: line_right_bottom.1,
-        bottom_left: line_right_bottom.0,
-    };
-
-    [left_wall_polygon, right_wall_polygon]
+struct TopologySolver {
+    lines_geo: Vec<Line>,
+    frame: ImageFrame,
 }
 
-#[cfg(test)]
-pub(crate) mod tests {
-    use crate::polygons::{
-        compute_wall_polygon_for_room_type_6, compute_wall_polygon_for_room_type_8,
-        compute_wall_polygon_for_room_type_9, compute_wall_polygons,
-        compute_wall_polygons_for_room_type_0, compute_wall_polygons_for_room_type_1,
-        compute_wall_polygons_for_room_type_10, compute_wall_polygons_for_room_type_2,
-        compute_wall_polygons_for_room_type_3, compute_wall_polygons_for_room_type_4,
-        compute_wall_polygons_for_room_type_5, compute_wall_polygons_for_room_type_7,
-        convert_lines_coords_image_geo,
-    };
-    use image::{Rgb, RgbImage};
-    use imageproc::definitions::HasBlack;
-    use imageproc::drawing;
-    use lsun_res_parser::Line;
-    use ndarray::{Array1, Array2, Axis};
-    use ndarray_stats::QuantileExt;
-    use serde::{Deserialize, Serialize};
-    use std::{
-        fs::File,
-        io::{BufReader, BufWriter},
-        path::PathBuf,
-    };
-
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct LinesData {
-        pub lines: Vec<((i32, i32), (i32, i32))>,
+impl TopologySolver {
+    fn corner(&self, corner: Corner, level: Level) -> Point {
+        match corner {
+            Corner::Endpoint(line_idx, end) => self.endpoint(line_idx, end),
+            Corner::Meet(edge, border) => {
+                let edge_line = self.edge_line(edge, border.side, level);
+                let border_line = self.border_line(border);
+                self.frame.corner(&edge_line, &border_line)
+            }
+        }
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct RoomLayoutData {
-        #[serde(rename = "roomType")]
-        pub room_type: u8,
-        pub edges: Vec<LineData>,
-        #[serde(rename = "wallPolygons")]
-        pub wall_polygons: Vec<PolygonData>,
+    fn endpoint(&self, line_idx: usize, end: LineEnd) -> Point {
+        let line = self.lines_geo[line_idx];
+        match end {
+            LineEnd::Start => line.0,
+            LineEnd::End => line.1,
+        }
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct PointData {
-        pub x: i32,
-        pub y: i32,
+    fn line(&self, line_idx: usize) -> HomogeneousLine {
+        HomogeneousLine::from_segment(self.lines_geo[line_idx])
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct LineData {
-        pub start: PointData,
-        pub end: PointData,
+    fn edge_line(&self, edge: EdgeLine, side: Side, level: Level) -> HomogeneousLine {
+        match edge {
+            EdgeLine::Detected(line_idx) => self.line(line_idx),
+            EdgeLine::Mirrored {
+                line,
+                axis,
+                through,
+            } => {
+                let line = mirrored_line(
+                    &self.line(line),
+                    &self.line(axis),
+                    self.endpoint(axis, through).into(),
+                );
+                self.frame.keep_outside(line, side, level)
+            }
+            EdgeLine::Tilted { axis, through } => {
+                // Neither ceiling nor floor lines are visible. We assume them to be perpendicular
+                // to wall corner lines with some extra degrees added, opening towards image side.
+                // Nothing much we can do other than applying our best assumptions.
+                let extra_angle_degrees = match (side, level) {
+                    (Side::Left, Level::Top) | (Side::Right, Level::Bottom) => {
+                        -INVISIBLE_LINE_EXTRA_ANGLE_DEGREES
+                    }
+                    (Side::Left, Level::Bottom) | (Side::Right, Level::Top) => {
+                        INVISIBLE_LINE_EXTRA_ANGLE_DEGREES
+                    }
+                };
+                let line = HomogeneousLine::with_direction_angle(
+                    self.endpoint(axis, through).into(),
+                    tilted_perpendicular_angle(&self.line(axis), extra_angle_degrees),
+                );
+                self.frame.keep_outside(line, side, level)
+            }
+            EdgeLine::Shifted(line_idx) => {
+                let line = self.line(line_idx);
+                let corner = match (level, line.rises()) {
+                    (Level::Top, true) => self.frame.top_left(),
+                    (Level::Top, false) => self.frame.top_right(),
+                    (Level::Bottom, true) => self.frame.bottom_right(),
+                    (Level::Bottom, false) => self.frame.bottom_left(),
+                };
+                line.parallel_through(corner)
+            }
+        }
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct PolygonData {
-        #[serde(rename = "topLeft")]
-        pub top_left: PointData,
-        #[serde(rename = "topRight")]
-        pub top_right: PointData,
-        #[serde(rename = "bottomRight")]
-        pub bottom_right: PointData,
-        #[serde(rename = "bottomLeft")]
-        pub bottom_left: PointData,
+    fn border_line(&self, border: Border) -> HomogeneousLine {
+        let direction_line = match border.direction {
+            BorderDirection::Along(line_idx) => self.line(line_idx),
+            BorderDirection::Across(line_idx) => HomogeneousLine::with_direction_angle(
+                HomogeneousPoint::new(0.0, 0.0),
+                tilted_perpendicular_angle(&self.line(line_idx), 0.0),
+            ),
+            BorderDirection::ImageSide => return self.frame.image_side(border.side),
+        };
+        match border.side {
+            Side::Left => self.frame.left_border(&direction_line),
+            Side::Right => self.frame.right_border(&direction_line),
+        }
     }
+}
 
-    #[test]
-    fn prepare_seed_data() {
-        let image_height = 512;
-        let image_width = 512;
-
-        let results_dir = PathBuf::from("/path/to//res_lsun_tr_gt_npy");
-
-        let indices_file_path = results_dir.join("indices.npy");
-        let indices: Array1<i32> = ndarray_npy::read_npy(indices_file_path).unwrap();
-        println!("Indices: {indices:?}");
-
-        for i in indices.iter() {
-            let lines_file_name = format!("lines_{i}.json");
-            let lines_file_path = results_dir.join(lines_file_name);
-
-            println!("Reading {lines_file_path:?}");
-
-            let lines_file = File::open(lines_file_path).unwrap();
-            let mut reader = BufReader::new(lines_file);
-            let lines_data: LinesData = serde_json::from_reader(&mut reader).unwrap();
-
-            let room_type_file_name = format!("type_{i}.npy");
-            let room_type_file_path = results_dir.join(room_type_file_name);
-            let room_type: Array2<f32> = ndarray_npy::read_npy(room_type_file_path).unwrap();
-            let room_type = room_type.mean_axis(Axis(0)).unwrap();
-            let room_type = room_type.argmax().unwrap() as u8;
-
-            let polygons =
-                compute_wall_polygons(&lines_data.lines, image_width, image_height, room_type);
-            println!("{polygons:?}");
-
-            // TODO: save JSON file compatible with out Swift class
-
-            // FIXME: for debug
-            let edges: Vec<LineData> = lines_data
-                .lines
-                .iter()
-                .map(|line| {
-                    let start = line.0;
-                    let start = PointData {
-                        x: start.0,
-                        y: start.1,
-                    };
-                    let end = line.1;
-                    let end = PointData { x: end.0, y: end.1 };
-                    LineData { start, end }
-                })
-                .collect();
-
-            let wall_polygons: Vec<PolygonData> = polygons
-                .iter()
-                .map(|polygon| {
-                    let top_left = PointData {
-                        x: polygon.top_left.0,
-                        y: polygon.top_left.1,
-                    };
-                    let top_right = PointData {
-                        x: polygon.top_right.0,
-                        y: polygon.top_right.1,
-                    };
-                    let bottom_right = PointData {
-                        x: polygon.bottom_right.0,
-                        y: polygon.bottom_right.1,
-                    };
-                    let bottom_left = PointData {
-                        x: polygon.bottom_left.0,
-                        y: polygon.bottom_left.1,
-                    };
-                    PolygonData {
-                        top_left,
-                        top_right,
-                        bottom_right,
-                        bottom_left,
-                    }
-                })
-                .collect();
-
-            let room_layout = RoomLayoutData {
-                room_type,
-                edges,
-                wall_polygons,
-            };
-
-            let room_layout_file_name = format!("layout_{i}.json");
-            let room_layout_file_path = results_dir.join(room_layout_file_name);
-            let room_layout_file = File::create(room_layout_file_path).unwrap();
-            let writer = BufWriter::new(room_layout_file);
-            serde_json::to_writer(writer, &room_layout).unwrap();
-
-            // let images_dir = PathBuf::from(
-            //     "/path/to/lsun_tr_gt/img",
-            // );
-            // let image_path = images_dir.join(format!("{i}.png"));
-            // let src_image = image::open(image_path).unwrap().into_rgb8();
-
-            // let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-            // let output_dir = PathBuf::from("./out");
-            // let output_image_path = output_dir.join(format!("{i}.png"));
-            // overlay_image.save(output_image_path).unwrap();
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::evaluation::evaluate_layout;
+    use crate::layout::lsun::{Line, Point};
+    use crate::layout::{RoomLayout, RoomType};
+    use crate::polygons::{
+        compute_wall_polygons, convert_lines_coords_image_geo, solve_wall_polygons, Border,
+        BorderDirection, Corner, EdgeLine, LineEnd, RoomTopology, WallPolygon, WallTopology,
+    };
+    use crate::synthetic::SyntheticRoom;
+
+    fn wall_polygon(corners: [Point; 4]) -> WallPolygon {
+        let [top_left, top_right, bottom_right, bottom_left] = corners;
+        WallPolygon {
+            top_left,
+            top_right,
+            bottom_right,
+            bottom_left,
         }
     }
 
+    /// Lines estimated for LSUN training images, and polygons computed for them by the
+    /// hand-written solvers replaced by room topologies
     #[test]
-    fn compute_and_draw_polygons_for_room_type_0() {
-        let image_height = 512;
-        let i = 40;
-        let lines = vec![
-            ((116, 100), (72, 0)),
-            ((116, 396), (64, 511)),
-            ((344, 370), (511, 479)),
-            ((342, 133), (496, 0)),
-            ((116, 100), (116, 396)),
-            ((116, 396), (344, 370)),
-            ((344, 370), (342, 133)),
-            ((342, 133), (116, 100)),
+    fn computes_polygons_of_lsun_training_lines() {
+        let cases: Vec<(u8, Vec<Line>, Vec<WallPolygon>)> = vec![
+            // LSUN training image 40
+            (
+                0,
+                vec![
+                    ((116, 100), (72, 0)),
+                    ((116, 396), (64, 511)),
+                    ((344, 370), (511, 479)),
+                    ((342, 133), (496, 0)),
+                    ((116, 100), (116, 396)),
+                    ((116, 396), (344, 370)),
+                    ((344, 370), (342, 133)),
+                    ((342, 133), (116, 100)),
+                ],
+                vec![
+                    wall_polygon([(0, -163), (116, 100), (116, 396), (0, 652)]),
+                    wall_polygon([(116, 100), (342, 133), (344, 370), (116, 396)]),
+                    wall_polygon([(342, 133), (510, -12), (515, 482), (344, 370)]),
+                ],
+            ),
+            // LSUN training image 135
+            (
+                1,
+                vec![
+                    ((153, 365), (154, 0)),
+                    ((153, 365), (24, 511)),
+                    ((153, 365), (441, 375)),
+                    ((441, 375), (446, 1)),
+                    ((441, 375), (510, 439)),
+                ],
+                vec![
+                    wall_polygon([(0, -175), (154, 0), (153, 365), (-1, 539)]),
+                    wall_polygon([(154, 0), (446, 1), (441, 375), (153, 365)]),
+                    wall_polygon([(446, 1), (518, -62), (511, 441), (441, 375)]),
+                ],
+            ),
+            // LSUN training image 396
+            (
+                2,
+                vec![
+                    ((101, 35), (86, 1)),
+                    ((101, 35), (93, 510)),
+                    ((101, 35), (336, 42)),
+                    ((336, 42), (365, 1)),
+                    ((336, 42), (336, 510)),
+                ],
+                vec![
+                    wall_polygon([(3, -186), (101, 35), (93, 510), (-12, 728)]),
+                    wall_polygon([(101, 35), (336, 42), (336, 510), (93, 510)]),
+                    wall_polygon([(336, 42), (510, -205), (511, 757), (336, 510)]),
+                ],
+            ),
+            // LSUN training image 555
+            (
+                3,
+                vec![
+                    ((31, 110), (0, 97)),
+                    ((31, 110), (20, 511)),
+                    ((31, 110), (511, 0)),
+                ],
+                vec![
+                    wall_polygon([(-2, 96), (31, 110), (20, 511), (-14, 523)]),
+                    wall_polygon([(31, 110), (525, -3), (507, 651), (20, 511)]),
+                ],
+            ),
+            // LSUN training image 13
+            (
+                4,
+                vec![
+                    ((482, 471), (1, 422)),
+                    ((482, 471), (504, 1)),
+                    ((482, 471), (491, 512)),
+                ],
+                vec![
+                    wall_polygon([(0, 0), (504, 1), (482, 471), (-19, 420)]),
+                    wall_polygon([(504, 1), (540, -112), (507, 586), (482, 471)]),
+                ],
+            ),
+            // LSUN training image 2
+            (
+                5,
+                vec![
+                    ((294, 167), (13, 0)),
+                    ((294, 167), (511, 85)),
+                    ((294, 167), (306, 343)),
+                    ((306, 343), (0, 491)),
+                    ((306, 343), (511, 410)),
+                ],
+                vec![
+                    wall_polygon([(-36, -29), (294, 167), (306, 343), (-1, 492)]),
+                    wall_polygon([(294, 167), (516, 83), (539, 420), (306, 343)]),
+                ],
+            ),
+            // LSUN training image 188
+            (
+                6,
+                vec![((142, 0), (511, 75)), ((2, 511), (511, 346))],
+                vec![wall_polygon([(0, -28), (511, 76), (511, 346), (-1, 511)])],
+            ),
+            // LSUN training image 206
+            (
+                7,
+                vec![((51, 510), (0, 0)), ((417, 510), (419, 1))],
+                vec![
+                    wall_polygon([(-53, -24), (0, 0), (51, 510), (3, 544)]),
+                    wall_polygon([(0, 0), (419, 1), (417, 510), (51, 510)]),
+                    wall_polygon([(419, 1), (513, -52), (510, 564), (417, 510)]),
+                ],
+            ),
+            // LSUN training image 1583
+            (
+                8,
+                vec![((0, 127), (511, 95))],
+                vec![wall_polygon([(-23, 129), (516, 95), (542, 510), (1, 542)])],
+            ),
+            // LSUN training image 11
+            (
+                9,
+                vec![((0, 311), (511, 305))],
+                vec![wall_polygon([(-5, 1), (510, -5), (514, 305), (-2, 312)])],
+            ),
+            // LSUN training image 279
+            (
+                10,
+                vec![((292, 0), (312, 511))],
+                vec![
+                    wall_polygon([(-26, -167), (292, 0), (312, 511), (7, 703)]),
+                    wall_polygon([(292, 0), (505, -134), (535, 628), (312, 511)]),
+                ],
+            ),
         ];
 
-        let [left_wall_polygon, center_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_0(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(center_wall_polygon.lines());
-        lines.extend(right_wall_polygon.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+        for (room_type, lines, expected_polygons) in cases {
+            let polygons = compute_wall_polygons(&lines, 512, 512, room_type).unwrap();
+            assert_eq!(polygons, expected_polygons, "Room type {room_type}");
+        }
     }
 
+    /// Lines of synthetic rooms of every room type, and polygons computed for them by the
+    /// hand-written solvers replaced by room topologies
     #[test]
-    fn compute_and_draw_polygons_for_room_type_1() {
-        let image_height = 512;
-        let i = 135;
-        let lines = vec![
-            ((153, 365), (154, 0)),
-            ((153, 365), (24, 511)),
-            ((153, 365), (441, 375)),
-            ((441, 375), (446, 1)),
-            ((441, 375), (510, 439)),
+    fn computes_polygons_of_synthetic_room_lines() {
+        let cases: Vec<(u8, Vec<Line>, Vec<WallPolygon>)> = vec![
+            (
+                0,
+                vec![
+                    ((125, 183), (0, 102)),
+                    ((125, 329), (0, 410)),
+                    ((344, 325), (511, 433)),
+                    ((344, 187), (511, 79)),
+                    ((125, 183), (125, 329)),
+                    ((125, 329), (344, 325)),
+                    ((344, 325), (344, 187)),
+                    ((344, 187), (125, 183)),
+                ],
+                vec![
+                    wall_polygon([(0, 102), (125, 183), (125, 329), (0, 410)]),
+                    wall_polygon([(125, 183), (344, 187), (344, 325), (125, 329)]),
+                    wall_polygon([(344, 187), (511, 79), (511, 433), (344, 325)]),
+                ],
+            ),
+            (
+                1,
+                vec![
+                    ((47, 224), (10, 0)),
+                    ((47, 224), (0, 256)),
+                    ((47, 224), (413, 218)),
+                    ((413, 218), (440, 0)),
+                    ((413, 218), (511, 285)),
+                ],
+                vec![
+                    wall_polygon([(-88, -27), (10, 0), (47, 224), (-37, 282)]),
+                    wall_polygon([(10, 0), (440, 0), (413, 218), (47, 224)]),
+                    wall_polygon([(440, 0), (580, -51), (536, 303), (413, 218)]),
+                ],
+            ),
+            (
+                2,
+                vec![
+                    ((99, 294), (0, 226)),
+                    ((99, 294), (72, 511)),
+                    ((99, 294), (465, 288)),
+                    ((465, 288), (511, 256)),
+                    ((465, 288), (502, 511)),
+                ],
+                vec![
+                    wall_polygon([(-25, 209), (99, 294), (72, 511), (-70, 563)]),
+                    wall_polygon([(99, 294), (465, 288), (502, 511), (72, 511)]),
+                    wall_polygon([(465, 288), (549, 230), (600, 539), (502, 511)]),
+                ],
+            ),
+            (
+                3,
+                vec![
+                    ((256, 293), (0, 205)),
+                    ((256, 293), (256, 511)),
+                    ((256, 293), (511, 205)),
+                ],
+                vec![
+                    wall_polygon([(0, 205), (256, 293), (256, 511), (0, 598)]),
+                    wall_polygon([(256, 293), (511, 205), (511, 598), (256, 511)]),
+                ],
+            ),
+            (
+                4,
+                vec![
+                    ((256, 219), (0, 307)),
+                    ((256, 219), (256, 0)),
+                    ((256, 219), (511, 307)),
+                ],
+                vec![
+                    wall_polygon([(0, -87), (256, 0), (256, 219), (0, 307)]),
+                    wall_polygon([(256, 0), (511, -87), (511, 307), (256, 219)]),
+                ],
+            ),
+            (
+                5,
+                vec![
+                    ((217, 92), (0, 6)),
+                    ((217, 92), (493, 0)),
+                    ((217, 92), (217, 420)),
+                    ((217, 420), (0, 506)),
+                    ((217, 420), (490, 511)),
+                ],
+                vec![
+                    wall_polygon([(0, 6), (217, 92), (217, 420), (0, 506)]),
+                    wall_polygon([(217, 92), (511, -5), (511, 517), (217, 420)]),
+                ],
+            ),
+            (
+                6,
+                vec![((0, 60), (511, 72)), ((0, 452), (511, 440))],
+                vec![wall_polygon([(0, 60), (511, 73), (511, 440), (0, 453)])],
+            ),
+            (
+                7,
+                vec![((20, 511), (20, 0)), ((443, 511), (443, 0))],
+                vec![
+                    wall_polygon([(0, -11), (20, 0), (20, 511), (0, 522)]),
+                    wall_polygon([(20, 0), (443, 0), (443, 511), (20, 511)]),
+                    wall_polygon([(443, 0), (511, -39), (511, 550), (443, 511)]),
+                ],
+            ),
+            (
+                8,
+                vec![((0, 187), (511, 199))],
+                vec![wall_polygon([
+                    (-4, 187),
+                    (518, 200),
+                    (510, 522),
+                    (-11, 511),
+                ])],
+            ),
+            (
+                9,
+                vec![((0, 313), (511, 325))],
+                vec![wall_polygon([(0, -11), (522, 1), (515, 326), (-7, 313)])],
+            ),
+            (
+                10,
+                vec![((256, 0), (256, 511))],
+                vec![
+                    wall_polygon([(0, -147), (256, 0), (256, 511), (0, 658)]),
+                    wall_polygon([(256, 0), (511, -147), (511, 658), (256, 511)]),
+                ],
+            ),
         ];
 
-        let [left_wall_polygon, center_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_1(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(center_wall_polygon.lines());
-        lines.extend(right_wall_polygon.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+        for (room_type, lines, expected_polygons) in cases {
+            let polygons = compute_wall_polygons(&lines, 512, 512, room_type).unwrap();
+            assert_eq!(polygons, expected_polygons, "Room type {room_type}");
+        }
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_2() {
-        let image_height = 512;
-        let i = 396;
-        let lines = vec![
-            ((101, 35), (86, 1)),
-            ((101, 35), (93, 510)),
-            ((101, 35), (336, 42)),
-            ((336, 42), (365, 1)),
-            ((336, 42), (336, 510)),
-        ];
-
-        let [left_wall_polygon, center_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_2(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(center_wall_polygon.lines());
-        lines.extend(right_wall_polygon.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
+    fn rejects_unknown_room_type_and_missing_lines() {
+        let lines = vec![((0, 311), (511, 305))];
 
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+        assert_eq!(compute_wall_polygons(&lines, 512, 512, 11), None);
+        assert_eq!(compute_wall_polygons(&lines, 512, 512, 6), None);
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_3() {
-        let image_height = 512;
-        let i = 555;
-        let lines = vec![
-            ((31, 110), (0, 97)),
-            ((31, 110), (20, 511)),
-            ((31, 110), (511, 0)),
+    fn converts_line_coordinates_between_image_and_geo_spaces() {
+        let lines_image = vec![
+            ((294, 167), (13, 0)),
+            ((294, 167), (511, 85)),
+            ((294, 167), (306, 343)),
+            ((306, 343), (0, 491)),
+            ((306, 343), (511, 410)),
         ];
-
-        let [left_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_3(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(right_wall_polygon.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
-    }
-
-    #[test]
-    fn compute_and_draw_polygons_for_room_type_4() {
         let image_height = 512;
-        let i = 13;
-        let lines = vec![
-            ((482, 471), (1, 422)),
-            ((482, 471), (504, 1)),
-            ((482, 471), (491, 512)),
-        ];
-
-        let [left_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_4(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(right_wall_polygon.lines());
 
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
+        let lines_geo = convert_lines_coords_image_geo(&lines_image, image_height);
+        let expected_lines_geo = vec![
+            ((294, 344), (13, 511)),
+            ((294, 344), (511, 426)),
+            ((294, 344), (306, 168)),
+            ((306, 168), (0, 20)),
+            ((306, 168), (511, 101)),
+        ];
+        assert_eq!(lines_geo, expected_lines_geo);
 
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
+        let lines_image_from_geo = convert_lines_coords_image_geo(&lines_geo, image_height);
+        assert_eq!(lines_image_from_geo, lines_image);
+    }
 
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+    fn assert_points_close(actual: Point, expected: Point, tolerance: i32) {
+        assert!(
+            (actual.0 - expected.0).abs() <= tolerance
+                && (actual.1 - expected.1).abs() <= tolerance,
+            "{actual:?} is not close to {expected:?}"
+        );
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_5() {
-        let image_height = 512;
-        let i = 2;
+    fn computes_polygons_for_non_square_layout() {
         let lines = vec![
             ((294, 167), (13, 0)),
             ((294, 167), (511, 85)),
@@ -1781,194 +707,133 @@ pub(crate) mod tests {
             ((306, 343), (0, 491)),
             ((306, 343), (511, 410)),
         ];
-
-        let [left_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_5(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(right_wall_polygon.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
-    }
-
-    #[test]
-    fn compute_and_draw_polygons_for_room_type_6() {
-        let image_height = 512;
-        let image_width = 512;
-        let i = 188;
-        let lines = vec![((142, 0), (511, 75)), ((2, 511), (511, 346))];
-
-        let wall_polygon = compute_wall_polygon_for_room_type_6(&lines, image_width, image_height);
-
-        let lines = wall_polygon.lines().to_vec();
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+        let square_polygons = compute_wall_polygons(&lines, 512, 512, 5).unwrap();
+
+        // Same layout estimated by portrait model
+        let (image_width, image_height) = (384, 640);
+        let scale_point = |point: Point| {
+            (
+                (point.0 as f32 * (image_width - 1) as f32 / 511.0).round() as i32,
+                (point.1 as f32 * (image_height - 1) as f32 / 511.0).round() as i32,
+            )
+        };
+        let portrait_lines: Vec<Line> = lines
+            .iter()
+            .map(|(start, end)| (scale_point(*start), scale_point(*end)))
+            .collect();
+        let portrait_polygons =
+            compute_wall_polygons(&portrait_lines, image_width, image_height, 5).unwrap();
+
+        assert_eq!(portrait_polygons.len(), square_polygons.len());
+        for (portrait_polygon, square_polygon) in
+            portrait_polygons.iter().zip(square_polygons.iter())
+        {
+            let portrait_corners = [
+                portrait_polygon.top_left,
+                portrait_polygon.top_right,
+                portrait_polygon.bottom_right,
+                portrait_polygon.bottom_left,
+            ];
+            let square_corners = [
+                square_polygon.top_left,
+                square_polygon.top_right,
+                square_polygon.bottom_right,
+                square_polygon.bottom_left,
+            ];
+            for (portrait_corner, square_corner) in portrait_corners.iter().zip(square_corners) {
+                assert_points_close(*portrait_corner, scale_point(square_corner), 3);
+            }
+        }
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_7() {
-        let image_height = 512;
-        let i = 206;
-        let lines = vec![((51, 510), (0, 0)), ((417, 510), (419, 1))];
-
-        let [left_wall_polygon, center_wall_polygon, right_wall_polygon] =
-            compute_wall_polygons_for_room_type_7(&lines, image_height);
-
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(center_wall_polygon.lines());
-        lines.extend(right_wall_polygon.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
+    fn solves_custom_room_topology() {
+        // Single wall with visible ceiling and floor, closed by wall corner on the right
+        let topology = RoomTopology {
+            walls: vec![WallTopology {
+                top_left: Corner::Meet(
+                    EdgeLine::Detected(0),
+                    Border::left(BorderDirection::Along(2)),
+                ),
+                top_right: Corner::Endpoint(2, LineEnd::Start),
+                bottom_right: Corner::Endpoint(2, LineEnd::End),
+                bottom_left: Corner::Meet(
+                    EdgeLine::Detected(1),
+                    Border::left(BorderDirection::Along(2)),
+                ),
+            }],
+        };
+        let lines = vec![
+            ((300, 100), (100, 50)),
+            ((300, 400), (100, 450)),
+            ((300, 100), (300, 400)),
+        ];
 
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
+        let polygons = solve_wall_polygons(&lines, 512, 512, &topology).unwrap();
 
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert_points_close(polygons[0].top_left, (0, 25), 1);
+        assert_points_close(polygons[0].top_right, (300, 100), 0);
+        assert_points_close(polygons[0].bottom_right, (300, 400), 0);
+        assert_points_close(polygons[0].bottom_left, (0, 475), 1);
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_8() {
-        let image_height = 512;
-        let i = 1583;
-        let lines = vec![((0, 127), (511, 95))];
-
-        let lines = compute_wall_polygon_for_room_type_8(&lines, image_height)
-            .lines()
-            .to_vec();
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
+    fn computes_polygons_for_vertical_wall_corner() {
+        let lines = vec![
+            ((300, 167), (13, 0)),
+            ((300, 167), (511, 85)),
+            ((300, 167), (300, 343)),
+            ((300, 343), (0, 491)),
+            ((300, 343), (511, 410)),
+        ];
 
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
+        let polygons = compute_wall_polygons(&lines, 512, 512, 5).unwrap();
 
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+        assert_eq!(polygons.len(), 2);
+        assert_points_close(polygons[0].top_left, (0, -7), 1);
+        assert_points_close(polygons[0].bottom_left, (0, 491), 1);
+        assert_points_close(polygons[1].top_right, (511, 85), 1);
+        assert_points_close(polygons[1].bottom_right, (511, 410), 1);
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_9() {
-        let image_height = 512;
-        let i = 11;
-        let lines = vec![((0, 311), (511, 305))];
-
-        let lines = compute_wall_polygon_for_room_type_9(&lines, image_height)
-            .lines()
-            .to_vec();
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
+    fn extends_walls_to_borders_of_wide_layout() {
+        let (image_width, image_height) = (640, 360);
+        let lines = vec![((0, 100), (639, 110))];
+
+        let wall_polygon = compute_wall_polygons(&lines, image_width, image_height, 8)
+            .unwrap()
+            .remove(0);
+
+        // Borders are perpendicular to the slightly tilted ceiling line, so corners might be
+        // a few pixels outside of the image
+        let tolerance = 8;
+        assert!(wall_polygon.top_left.0.abs() <= tolerance);
+        assert_points_close(wall_polygon.bottom_left, (0, 359), tolerance);
+        assert!((wall_polygon.top_right.0 - 639).abs() <= tolerance);
+        assert!((wall_polygon.bottom_right.0 - 639).abs() <= tolerance);
     }
 
     #[test]
-    fn compute_and_draw_polygons_for_room_type_10() {
-        let image_height = 512;
-        let i = 279;
-        let lines = vec![((292, 0), (312, 511))];
-
-        let [left_wall_polygon, right_wall_polygons] =
-            compute_wall_polygons_for_room_type_10(&lines, image_height);
-        let mut lines = left_wall_polygon.lines().to_vec();
-        lines.extend(right_wall_polygons.lines());
-
-        let images_dir =
-            PathBuf::from("/path/to/lsun_tr_gt/img");
-        let image_path = images_dir.join(format!("{i}.png"));
-        let src_image = image::open(image_path).unwrap().into_rgb8();
-
-        let overlay_image = draw_lines_on_padded_image(&src_image, &lines, 200);
-
-        let output_dir = PathBuf::from("./out");
-        let output_image_path = output_dir.join(format!("{i}.png"));
-        overlay_image.save(output_image_path).unwrap();
-    }
-
-    pub(crate) fn draw_lines_on_padded_image(
-        src_image: &RgbImage,
-        lines: &[Line],
-        padding: u32,
-    ) -> RgbImage {
-        let new_width = src_image.width() + 2 * padding;
-        let new_height = src_image.height() + 2 * padding;
+    fn solves_wall_polygons_of_synthetic_rooms() {
+        for room_type in RoomType::ALL {
+            let room = SyntheticRoom::new(room_type).render();
 
-        let mut padded_image = RgbImage::from_pixel(new_width, new_height, Rgb::black());
-
-        image::imageops::overlay(&mut padded_image, src_image, padding as i64, padding as i64);
-
-        let padding = padding as f32;
-        for line in lines {
-            let (p1, p2) = line;
-
-            let x1 = p1.0 as f32 + padding;
-            let y1 = p1.1 as f32 + padding;
-            let x2 = p2.0 as f32 + padding;
-            let y2 = p2.1 as f32 + padding;
-
-            drawing::draw_line_segment_mut(
-                &mut padded_image,
-                (x1, y1),
-                (x2, y2),
-                Rgb::from([255, 0, 0]),
+            let solved_layout = RoomLayout::from_lines(
+                room_type,
+                room.layout.lines.clone(),
+                room.layout.layout_width,
+                room.layout.layout_height,
+            )
+            .unwrap();
+
+            let evaluation = evaluate_layout(&solved_layout, &room.layout);
+            assert!(
+                evaluation.pixel_error < 0.005,
+                "{room_type:?}: {}",
+                evaluation.pixel_error
             );
         }
-
-        padded_image
-    }
-
-    #[test]
-    fn converts_line_coordinates_between_image_and_geo_spaces() {
-        let lines_image = vec![
-            ((294, 167), (13, 0)),
-            ((294, 167), (511, 85)),
-            ((294, 167), (306, 343)),
-            ((306, 343), (0, 491)),
-            ((306, 343), (511, 410)),
-        ];
-        let image_height = 512;
-
-        let lines_geo = convert_lines_coords_image_geo(&lines_image, image_height);
-        let expected_lines_geo = vec![
-            ((294, 344), (13, 511)),
-            ((294, 344), (511, 426)),
-            ((294, 344), (306, 168)),
-            ((306, 168), (0, 20)),
-            ((306, 168), (511, 101)),
-        ];
-        assert_eq!(lines_geo, expected_lines_geo);
-
-        let lines_image_from_geo = convert_lines_coords_image_geo(&lines_geo, image_height);
-        assert_eq!(lines_image_from_geo, lines_image);
     }
 }
@@ -0,0 +1,209 @@
+use crate::layout::lsun::{Line, Point};
+
+/// Lines whose directions differ by less than this angle (in radians) are treated as parallel
+const PARALLEL_ANGLE_EPSILON: f64 = 1e-6;
+/// Lines with normal X or Y component smaller than this are treated as vertical or horizontal
+const AXIS_ALIGNED_EPSILON: f64 = 1e-9;
+
+/// 2D point in homogeneous coordinates `(x, y, w)`. Points with `w = 0` lie at infinity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct HomogeneousPoint {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+}
+
+impl HomogeneousPoint {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y, w: 1.0 }
+    }
+
+    /// Cartesian coordinates of the point, `None` if it lies at infinity.
+    pub fn to_cartesian(self) -> Option<(f64, f64)> {
+        if self.w.abs() < f64::EPSILON {
+            return None;
+        }
+        Some((self.x / self.w, self.y / self.w))
+    }
+
+    /// Integer point, truncating coordinates. `None` if the point lies at infinity.
+    pub fn to_point(self) -> Option<Point> {
+        self.to_cartesian().map(|(x, y)| (x as i32, y as i32))
+    }
+}
+
+impl From<Point> for HomogeneousPoint {
+    fn from(point: Point) -> Self {
+        Self::new(point.0 as f64, point.1 as f64)
+    }
+}
+
+/// 2D line `a * x + b * y + c = 0` in homogeneous coordinates. Lines are normalized, so that
+/// `(a, b)` is a unit normal and `|c|` is the distance from the origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct HomogeneousLine {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+}
+
+impl HomogeneousLine {
+    /// Line through two points, which is their cross product. Line through two equal points is
+    /// undefined, so horizontal line through the point is returned instead.
+    pub fn through(p1: HomogeneousPoint, p2: HomogeneousPoint) -> Self {
+        let line = Self {
+            a: p1.y * p2.w - p1.w * p2.y,
+            b: p1.w * p2.x - p1.x * p2.w,
+            c: p1.x * p2.y - p1.y * p2.x,
+        };
+        if line.a.hypot(line.b) < f64::EPSILON {
+            return Self::with_direction_angle(p1, 0.0);
+        }
+        line.normalized()
+    }
+
+    pub fn from_segment(line: Line) -> Self {
+        Self::through(line.0.into(), line.1.into())
+    }
+
+    /// Line through the point with the given direction angle, measured counterclockwise from
+    /// X axis.
+    pub fn with_direction_angle(point: HomogeneousPoint, angle: f64) -> Self {
+        let (point_x, point_y) = point
+            .to_cartesian()
+            .expect("Line direction through point at infinity is undefined");
+        let a = -angle.sin();
+        let b = angle.cos();
+        Self {
+            a,
+            b,
+            c: -(a * point_x + b * point_y),
+        }
+    }
+
+    /// Line parallel to this one, going through the given point.
+    pub fn parallel_through(&self, point: HomogeneousPoint) -> Self {
+        Self::with_direction_angle(point, self.direction_angle())
+    }
+
+    /// Direction angle of the line in `(-π; π]`. Angles differing by π describe the same line.
+    pub fn direction_angle(&self) -> f64 {
+        f64::atan2(-self.a, self.b)
+    }
+
+    /// Angle of this line mirrored about the `axis` line.
+    pub fn mirrored_direction_angle(&self, axis: &HomogeneousLine) -> f64 {
+        2.0 * axis.direction_angle() - self.direction_angle()
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        self.b.abs() < AXIS_ALIGNED_EPSILON
+    }
+
+    /// Whether the line has non-negative slope in Y-up coordinates. Vertical lines rise too.
+    pub fn rises(&self) -> bool {
+        self.is_vertical() || self.a * self.b <= 0.0
+    }
+
+    /// Whether the lines are parallel or so close to parallel that their intersection is
+    /// numerically meaningless.
+    pub fn is_parallel_to(&self, other: &HomogeneousLine) -> bool {
+        // Normals are unit vectors, so this is the sine of the angle between the lines
+        (self.a * other.b - self.b * other.a).abs() < PARALLEL_ANGLE_EPSILON
+    }
+
+    /// Intersection point of two lines, which is their cross product. `None` for parallel lines.
+    pub fn intersection(&self, other: &HomogeneousLine) -> Option<HomogeneousPoint> {
+        if self.is_parallel_to(other) {
+            return None;
+        }
+        Some(HomogeneousPoint {
+            x: self.b * other.c - self.c * other.b,
+            y: self.c * other.a - self.a * other.c,
+            w: self.a * other.b - self.b * other.a,
+        })
+    }
+
+    /// Point of the line closest to the given point.
+    pub fn project(&self, point: HomogeneousPoint) -> HomogeneousPoint {
+        let (x, y) = point
+            .to_cartesian()
+            .expect("Can not project point at infinity");
+        let distance = self.a * x + self.b * y + self.c;
+        HomogeneousPoint::new(x - self.a * distance, y - self.b * distance)
+    }
+
+    fn normalized(&self) -> Self {
+        let norm = self.a.hypot(self.b);
+        Self {
+            a: self.a / norm,
+            b: self.b / norm,
+            c: self.c / norm,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::polygons::projective::{HomogeneousLine, HomogeneousPoint};
+
+    #[test]
+    fn intersects_vertical_lines() {
+        let vertical_line = HomogeneousLine::from_segment(((100, 0), (100, 511)));
+        let sloped_line = HomogeneousLine::from_segment(((0, 0), (200, 100)));
+
+        assert!(vertical_line.is_vertical());
+        assert!(vertical_line.rises());
+        let (x, y) = vertical_line
+            .intersection(&sloped_line)
+            .unwrap()
+            .to_cartesian()
+            .unwrap();
+        assert!((x - 100.0).abs() < 1e-6);
+        assert!((y - 50.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn does_not_intersect_parallel_lines() {
+        let line = HomogeneousLine::from_segment(((0, 0), (0, 511)));
+        let parallel_line = line.parallel_through(HomogeneousPoint::new(511.0, 0.0));
+        let nearly_parallel_line = HomogeneousLine::from_segment(((511, 0), (512, 100_000_000)));
+
+        assert!(line.intersection(&parallel_line).is_none());
+        assert!(line.intersection(&nearly_parallel_line).is_none());
+    }
+
+    #[test]
+    fn intersects_nearly_vertical_lines_precisely() {
+        // Wall corner which is 1 pixel off vertical
+        let corner_line = HomogeneousLine::from_segment(((300, 0), (301, 511)));
+        let border_line = corner_line.parallel_through(HomogeneousPoint::new(0.0, 511.0));
+        let ceiling_line = HomogeneousLine::from_segment(((300, 0), (100, 100)));
+
+        let (x, y) = border_line
+            .intersection(&ceiling_line)
+            .unwrap()
+            .to_cartesian()
+            .unwrap();
+        assert!((x - 0.0).abs() < 1.0);
+        assert!((y - 150.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn mirrors_direction_about_axis() {
+        let axis = HomogeneousLine::from_segment(((0, 0), (0, 100)));
+        let line = HomogeneousLine::from_segment(((0, 0), (100, 100)));
+
+        let mirrored_line = HomogeneousLine::with_direction_angle(
+            HomogeneousPoint::new(0.0, 0.0),
+            line.mirrored_direction_angle(&axis),
+        );
+
+        let (x, y) = mirrored_line
+            .project(HomogeneousPoint::new(-100.0, 100.0))
+            .to_cartesian()
+            .unwrap();
+        assert!((x + 100.0).abs() < 1e-6);
+        assert!((y - 100.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,436 @@
+//! Declarative description of how detected layout lines bound walls of each room type.
+//!
+//! Line indices refer to lines in the order they are returned by LSUN results parser.
+//! Line start and end are in geo space, where Y axis points up.
+
+/// End of a detected layout line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnd {
+    Start,
+    End,
+}
+
+/// Image side, at which wall is closed by a border line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Line along which ceiling or floor edge of the wall runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeLine {
+    /// Detected layout line.
+    Detected(usize),
+    /// Invisible line, which is assumed to be the mirror image of `line` about wall corner
+    /// `axis` line, going through the `through` end of the axis.
+    Mirrored {
+        line: usize,
+        axis: usize,
+        through: LineEnd,
+    },
+    /// Invisible line, which is assumed to be perpendicular to wall corner `axis` line tilted by
+    /// extra angle towards the image side, going through the `through` end of the axis.
+    Tilted { axis: usize, through: LineEnd },
+    /// Invisible line, which is assumed to be parallel to detected line and touch the image
+    /// from the top (for top corners) or bottom (for bottom corners).
+    Shifted(usize),
+}
+
+/// Direction of the border line, which closes the wall at the image side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderDirection {
+    /// Parallel to detected wall corner line.
+    Along(usize),
+    /// Perpendicular to detected ceiling or floor line, for walls spanning the whole image.
+    Across(usize),
+    /// Vertical image side.
+    ImageSide,
+}
+
+/// Border line, which touches the image at the given side without crossing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Border {
+    pub side: Side,
+    pub direction: BorderDirection,
+}
+
+impl Border {
+    pub fn left(direction: BorderDirection) -> Self {
+        Self {
+            side: Side::Left,
+            direction,
+        }
+    }
+
+    pub fn right(direction: BorderDirection) -> Self {
+        Self {
+            side: Side::Right,
+            direction,
+        }
+    }
+}
+
+/// How wall polygon corner is computed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corner {
+    /// End of detected layout line.
+    Endpoint(usize, LineEnd),
+    /// Point where edge line meets border line.
+    Meet(EdgeLine, Border),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WallTopology {
+    pub top_left: Corner,
+    pub top_right: Corner,
+    pub bottom_right: Corner,
+    pub bottom_left: Corner,
+}
+
+/// Walls of the room from left to right.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomTopology {
+    pub walls: Vec<WallTopology>,
+}
+
+impl RoomTopology {
+    /// Number of detected lines required by the topology.
+    pub fn num_lines(&self) -> usize {
+        self.walls
+            .iter()
+            .flat_map(|wall| {
+                [
+                    wall.top_left,
+                    wall.top_right,
+                    wall.bottom_right,
+                    wall.bottom_left,
+                ]
+            })
+            .flat_map(corner_line_indices)
+            .map(|line_idx| line_idx + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn corner_line_indices(corner: Corner) -> Vec<usize> {
+    match corner {
+        Corner::Endpoint(line_idx, _) => vec![line_idx],
+        Corner::Meet(edge, border) => {
+            let mut indices = match edge {
+                EdgeLine::Detected(line_idx) | EdgeLine::Shifted(line_idx) => vec![line_idx],
+                EdgeLine::Mirrored { line, axis, .. } => vec![line, axis],
+                EdgeLine::Tilted { axis, .. } => vec![axis],
+            };
+            match border.direction {
+                BorderDirection::Along(line_idx) | BorderDirection::Across(line_idx) => {
+                    indices.push(line_idx)
+                }
+                BorderDirection::ImageSide => {}
+            }
+            indices
+        }
+    }
+}
+
+/// Topology of the given LSUN room type, `None` if room type is unknown.
+pub fn room_topology(room_type: u8) -> Option<RoomTopology> {
+    use BorderDirection::*;
+    use Corner::*;
+    use EdgeLine::*;
+    use LineEnd::*;
+
+    let walls = match room_type {
+        // Three walls with visible ceiling and floor
+        0 => vec![
+            WallTopology {
+                top_left: Meet(Detected(0), Border::left(Along(4))),
+                top_right: Endpoint(4, Start),
+                bottom_right: Endpoint(4, End),
+                bottom_left: Meet(Detected(1), Border::left(Along(4))),
+            },
+            WallTopology {
+                top_left: Endpoint(4, Start),
+                top_right: Endpoint(6, End),
+                bottom_right: Endpoint(6, Start),
+                bottom_left: Endpoint(4, End),
+            },
+            WallTopology {
+                top_left: Endpoint(6, End),
+                top_right: Meet(Detected(3), Border::right(Along(6))),
+                bottom_right: Meet(Detected(2), Border::right(Along(6))),
+                bottom_left: Endpoint(6, Start),
+            },
+        ],
+        // Three walls with visible floor
+        1 => vec![
+            WallTopology {
+                top_left: Meet(
+                    Mirrored {
+                        line: 1,
+                        axis: 0,
+                        through: End,
+                    },
+                    Border::left(Along(0)),
+                ),
+                top_right: Endpoint(0, End),
+                bottom_right: Endpoint(0, Start),
+                bottom_left: Meet(Detected(1), Border::left(Along(0))),
+            },
+            WallTopology {
+                top_left: Endpoint(0, End),
+                top_right: Endpoint(3, End),
+                bottom_right: Endpoint(3, Start),
+                bottom_left: Endpoint(0, Start),
+            },
+            WallTopology {
+                top_left: Endpoint(3, End),
+                top_right: Meet(
+                    Mirrored {
+                        line: 4,
+                        axis: 3,
+                        through: End,
+                    },
+                    Border::right(Along(3)),
+                ),
+                bottom_right: Meet(Detected(4), Border::right(Along(3))),
+                bottom_left: Endpoint(3, Start),
+            },
+        ],
+        // Three walls with visible ceiling
+        2 => vec![
+            WallTopology {
+                top_left: Meet(Detected(0), Border::left(Along(1))),
+                top_right: Endpoint(1, Start),
+                bottom_right: Endpoint(1, End),
+                bottom_left: Meet(
+                    Mirrored {
+                        line: 0,
+                        axis: 1,
+                        through: End,
+                    },
+                    Border::left(Along(1)),
+                ),
+            },
+            WallTopology {
+                top_left: Endpoint(1, Start),
+                top_right: Endpoint(4, Start),
+                bottom_right: Endpoint(4, End),
+                bottom_left: Endpoint(1, End),
+            },
+            WallTopology {
+                top_left: Endpoint(4, Start),
+                top_right: Meet(Detected(3), Border::right(Along(4))),
+                bottom_right: Meet(
+                    Mirrored {
+                        line: 3,
+                        axis: 4,
+                        through: End,
+                    },
+                    Border::right(Along(4)),
+                ),
+                bottom_left: Endpoint(4, End),
+            },
+        ],
+        // Two walls with visible ceiling
+        3 => vec![
+            WallTopology {
+                top_left: Meet(Detected(0), Border::left(Along(1))),
+                top_right: Endpoint(0, Start),
+                bottom_right: Endpoint(1, End),
+                bottom_left: Meet(
+                    Mirrored {
+                        line: 0,
+                        axis: 1,
+                        through: End,
+                    },
+                    Border::left(Along(1)),
+                ),
+            },
+            WallTopology {
+                top_left: Endpoint(1, Start),
+                top_right: Meet(Detected(2), Border::right(Along(1))),
+                bottom_right: Meet(
+                    Mirrored {
+                        line: 2,
+                        axis: 1,
+                        through: End,
+                    },
+                    Border::right(Along(1)),
+                ),
+                bottom_left: Endpoint(1, End),
+            },
+        ],
+        // Two walls with visible floor
+        4 => vec![
+            WallTopology {
+                top_left: Meet(
+                    Mirrored {
+                        line: 0,
+                        axis: 1,
+                        through: End,
+                    },
+                    Border::left(Along(1)),
+                ),
+                top_right: Endpoint(1, End),
+                bottom_right: Endpoint(1, Start),
+                bottom_left: Meet(Detected(0), Border::left(Along(1))),
+            },
+            WallTopology {
+                top_left: Endpoint(1, End),
+                top_right: Meet(
+                    Mirrored {
+                        line: 2,
+                        axis: 1,
+                        through: End,
+                    },
+                    Border::right(Along(1)),
+                ),
+                bottom_right: Meet(Detected(2), Border::right(Along(1))),
+                bottom_left: Endpoint(1, Start),
+            },
+        ],
+        // Two walls with visible ceiling and floor
+        5 => vec![
+            WallTopology {
+                top_left: Meet(Detected(0), Border::left(Along(2))),
+                top_right: Endpoint(2, Start),
+                bottom_right: Endpoint(2, End),
+                bottom_left: Meet(Detected(3), Border::left(Along(2))),
+            },
+            WallTopology {
+                top_left: Endpoint(2, Start),
+                top_right: Meet(Detected(1), Border::right(Along(2))),
+                bottom_right: Meet(Detected(4), Border::right(Along(2))),
+                bottom_left: Endpoint(2, End),
+            },
+        ],
+        // Single wall with visible ceiling and floor
+        // FIXME: we assume borders to be vertical lines placed at image corners
+        //   Ideally, we would also compute mean angle to rotate them correspondingly,
+        //   if top and bottom lines are parallel or have common slope
+        6 => vec![WallTopology {
+            top_left: Meet(Detected(0), Border::left(ImageSide)),
+            top_right: Meet(Detected(0), Border::right(ImageSide)),
+            bottom_right: Meet(Detected(1), Border::right(ImageSide)),
+            bottom_left: Meet(Detected(1), Border::left(ImageSide)),
+        }],
+        // Three walls with neither ceiling nor floor visible
+        7 => vec![
+            WallTopology {
+                top_left: Meet(
+                    Tilted {
+                        axis: 0,
+                        through: End,
+                    },
+                    Border::left(Along(0)),
+                ),
+                top_right: Endpoint(0, End),
+                bottom_right: Endpoint(0, Start),
+                bottom_left: Meet(
+                    Tilted {
+                        axis: 0,
+                        through: Start,
+                    },
+                    Border::left(Along(0)),
+                ),
+            },
+            WallTopology {
+                top_left: Endpoint(0, End),
+                top_right: Endpoint(1, End),
+                bottom_right: Endpoint(1, Start),
+                bottom_left: Endpoint(0, Start),
+            },
+            WallTopology {
+                top_left: Endpoint(1, End),
+                top_right: Meet(
+                    Tilted {
+                        axis: 1,
+                        through: End,
+                    },
+                    Border::right(Along(1)),
+                ),
+                bottom_right: Meet(
+                    Tilted {
+                        axis: 1,
+                        through: Start,
+                    },
+                    Border::right(Along(1)),
+                ),
+                bottom_left: Endpoint(1, Start),
+            },
+        ],
+        // Single wall with visible ceiling
+        8 => vec![WallTopology {
+            top_left: Meet(Detected(0), Border::left(Across(0))),
+            top_right: Meet(Detected(0), Border::right(Across(0))),
+            bottom_right: Meet(Shifted(0), Border::right(Across(0))),
+            bottom_left: Meet(Shifted(0), Border::left(Across(0))),
+        }],
+        // Single wall with visible floor
+        9 => vec![WallTopology {
+            top_left: Meet(Shifted(0), Border::left(Across(0))),
+            top_right: Meet(Shifted(0), Border::right(Across(0))),
+            bottom_right: Meet(Detected(0), Border::right(Across(0))),
+            bottom_left: Meet(Detected(0), Border::left(Across(0))),
+        }],
+        // Two walls with neither ceiling nor floor visible
+        10 => vec![
+            WallTopology {
+                top_left: Meet(
+                    Tilted {
+                        axis: 0,
+                        through: Start,
+                    },
+                    Border::left(Along(0)),
+                ),
+                top_right: Endpoint(0, Start),
+                bottom_right: Endpoint(0, End),
+                bottom_left: Meet(
+                    Tilted {
+                        axis: 0,
+                        through: End,
+                    },
+                    Border::left(Along(0)),
+                ),
+            },
+            WallTopology {
+                top_left: Endpoint(0, Start),
+                top_right: Meet(
+                    Tilted {
+                        axis: 0,
+                        through: Start,
+                    },
+                    Border::right(Along(0)),
+                ),
+                bottom_right: Meet(
+                    Tilted {
+                        axis: 0,
+                        through: End,
+                    },
+                    Border::right(Along(0)),
+                ),
+                bottom_left: Endpoint(0, End),
+            },
+        ],
+        _ => return None,
+    };
+
+    Some(RoomTopology { walls })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::polygons::topology::room_topology;
+
+    #[test]
+    fn counts_lines_required_by_room_types() {
+        let num_lines: Vec<usize> = (0..=10)
+            .map(|room_type| room_topology(room_type).unwrap().num_lines())
+            .collect();
+
+        assert_eq!(num_lines, vec![7, 5, 5, 3, 3, 5, 2, 2, 1, 1, 1]);
+        assert!(room_topology(11).is_none());
+    }
+}
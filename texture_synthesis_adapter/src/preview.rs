@@ -1,30 +1,334 @@
+use crate::layout::lsun::Point;
+use crate::material::{
+    decode_normal, orient_normal, relief_shading_factor, LightGradients, WallpaperMaterial,
+};
 use crate::polygons::WallPolygon;
-use geo::geometry::Polygon as GeoPolygon;
-use geo::{Contains, Coord, LineString};
-use image::imageops::{overlay, FilterType};
-use image::{DynamicImage, GrayImage, Luma, RgbImage, Rgba, RgbaImage};
-use imageproc::geometric_transformations::{warp_into, Interpolation, Projection};
-use lsun_res_parser::Point;
+use image::imageops::FilterType;
+use image::{GrayImage, Luma, Rgb, RgbImage};
+use imageproc::geometric_transformations::Projection;
 use rgb_hsv::{hsv_to_rgb, rgb_to_hsv};
 
 const WALL_WIDTH_METERS: f32 = 3.6;
-const WALL_HEIGHT_METERS: f32 = 2.4;
-const WALLPAPER_TILE_WIDTH_METERS: f32 = 0.53;
-const WALLPAPER_TILE_HEIGHT_METERS: f32 = 0.53;
+pub(crate) const WALL_HEIGHT_METERS: f32 = 2.4;
+pub(crate) const WALLPAPER_TILE_WIDTH_METERS: f32 = 0.53;
+pub(crate) const WALLPAPER_TILE_HEIGHT_METERS: f32 = 0.53;
 const VISIBLE_WALLS: f32 = 1.2;
 const MIN_ROOM_IMAGE_SIDE: u32 = 2056;
-const POLYGON_IMAGE_SCALE: f32 = 512.0;
 static VISIBLE_WIDTH_METERS: f32 = WALL_WIDTH_METERS * VISIBLE_WALLS;
 static VISIBLE_HORIZONTAL_TILE_COUNT: f32 = VISIBLE_WIDTH_METERS / WALLPAPER_TILE_WIDTH_METERS;
-static VISIBLE_VERTICAL_TILES_COUNT: f32 = WALL_HEIGHT_METERS / WALLPAPER_TILE_HEIGHT_METERS;
-
+pub(crate) static VISIBLE_VERTICAL_TILES_COUNT: f32 =
+    WALL_HEIGHT_METERS / WALLPAPER_TILE_HEIGHT_METERS;
+const MASK_THRESHOLD: u8 = 50;
+pub(crate) const WALL_PIXEL: Luma<u8> = Luma([255]);
+/// Wall id value of pixels which do not belong to any wall
+pub const NO_WALL_ID: u8 = 255;
+/// Width of the band around shared wall edges, in which neighbouring walls are blended
+const CORNER_BLEND_BAND: f32 = 2.0;
+/// Corners of neighbouring walls closer than this distance (in layout space) are welded together
+const CORNER_WELD_DISTANCE: f32 = 3.0;
+/// How far outside of quad edges points are still considered to be inside, in pixels
+const QUAD_EDGE_TOLERANCE: f32 = 0.5;
+/// Default memory budget of preview generation, in bytes
+pub const DEFAULT_PREVIEW_MEMORY_BUDGET: usize = 512 * 1024 * 1024;
+/// Share of the memory budget which can be taken by the preview image itself
+const PREVIEW_IMAGE_BUDGET_SHARE: f32 = 0.75;
+const RGB_BYTES_PER_PIXEL: usize = 3;
+/// Compositing strips keep wall id and HSV value of each pixel
+const STRIP_BYTES_PER_PIXEL: usize = 2;
+
+/// Creates preview using at most [DEFAULT_PREVIEW_MEMORY_BUDGET] bytes of memory. Polygons are
+/// expected in layout estimation space of `layout_width` x `layout_height` size.
 pub(crate) fn create_preview(
-    mut room_image: RgbImage,
-    mut mask_image: GrayImage,
-    tile_image: RgbImage,
-    mut polygons: Vec<WallPolygon>,
+    room_image: RgbImage,
+    mask_image: GrayImage,
+    material: WallpaperMaterial,
+    polygons: Vec<WallPolygon>,
+    layout_width: u32,
+    layout_height: u32,
+) -> RgbImage {
+    create_preview_with_memory_budget(
+        room_image,
+        mask_image,
+        material,
+        polygons,
+        layout_width,
+        layout_height,
+        DEFAULT_PREVIEW_MEMORY_BUDGET,
+    )
+}
+
+/// Creates preview keeping memory used by the preview image and compositing buffers within the
+/// given budget in bytes. Preview image gets downscaled if it does not fit into the budget,
+/// and the rest of the budget bounds the height of strips the preview is composited in.
+///
+/// Memory of input images and of the material is not included in the budget. Room image is
+/// returned unchanged if the layout has no walls.
+pub(crate) fn create_preview_with_memory_budget(
+    room_image: RgbImage,
+    mask_image: GrayImage,
+    material: WallpaperMaterial,
+    polygons: Vec<WallPolygon>,
+    layout_width: u32,
+    layout_height: u32,
+    memory_budget: usize,
 ) -> RgbImage {
     // Compute width shares using top line since we start applying wallpaper tiles from [0; 0]
+    let Some((polygon_width_shares, visible_horizontal_tile_count)) =
+        compute_polygon_width_shares(&polygons)
+    else {
+        return room_image;
+    };
+
+    let preview_image_budget = (memory_budget as f32 * PREVIEW_IMAGE_BUDGET_SHARE) as usize;
+    let max_preview_pixels = preview_image_budget / RGB_BYTES_PER_PIXEL;
+    let mut preview_image = resize_room_image(room_image, max_preview_pixels);
+
+    let row_bytes = preview_image.width() as usize * STRIP_BYTES_PER_PIXEL;
+    let strip_budget = memory_budget.saturating_sub(
+        preview_image.width() as usize * preview_image.height() as usize * RGB_BYTES_PER_PIXEL,
+    );
+    let strip_rows = usize::max(strip_budget / row_bytes, 1) as u32;
+
+    let quads = scale_polygons(
+        &polygons,
+        layout_width,
+        layout_height,
+        preview_image.width(),
+        preview_image.height(),
+    );
+    composite_wallpaper(
+        &mut preview_image,
+        &mask_image,
+        &material,
+        &quads,
+        &polygon_width_shares,
+        visible_horizontal_tile_count,
+        strip_rows,
+    );
+
+    preview_image
+}
+
+/// Replaces walls of the room image with wallpaper, processing `strip_rows` rows at a time.
+/// Wallpaper tile is sampled procedurally, so the whole wallpaper strip is never assembled.
+fn composite_wallpaper(
+    room_image: &mut RgbImage,
+    mask_image: &GrayImage,
+    material: &WallpaperMaterial,
+    quads: &[WallQuad],
+    polygon_width_shares: &[f32],
+    visible_horizontal_tile_count: f32,
+    strip_rows: u32,
+) {
+    let room_image_height = room_image.height();
+    let shared_edges = find_shared_edges(quads);
+
+    // Wallpaper strip covering all the walls, in tile pixels
+    let wallpaper_width = material.color.width() as f32 * visible_horizontal_tile_count;
+    let wallpaper_height = material.color.height() as f32 * VISIBLE_VERTICAL_TILES_COUNT;
+
+    // Projections from room image onto wallpaper strip, one per wall. Wall sections are
+    // adjacent in the wallpaper strip, so pattern continues across shared wall edges.
+    let mut projections = vec![];
+    let mut inverse_projections = vec![];
+    let mut wallpaper_section_start_x: f32 = 0.0;
+    for (quad, width_share) in quads.iter().zip(polygon_width_shares.iter()) {
+        let wallpaper_section_width = wallpaper_width * width_share;
+        let wallpaper_section_end_x = wallpaper_section_start_x + wallpaper_section_width;
+
+        let projection = compute_wall_section_projection(
+            quad,
+            wallpaper_section_start_x,
+            wallpaper_section_end_x,
+            wallpaper_height,
+        );
+        projections.push(projection);
+        inverse_projections.push(projection.invert());
+
+        wallpaper_section_start_x = wallpaper_section_end_x;
+    }
+
+    // First pass collects wall statistics, which are needed before any pixel is replaced
+    let mut wall_blackness = WallBlackness::new(quads.len());
+    let mut light_gradients = LightGradients::new(quads.len());
+    for strip_start in (0..room_image_height).step_by(strip_rows as usize) {
+        let strip_end = u32::min(strip_start + strip_rows, room_image_height);
+        // Light estimation looks at neighbouring rows, so take one extra row on both sides
+        let extended_start = strip_start.saturating_sub(1);
+        let extended_end = u32::min(strip_end + 1, room_image_height);
+        let strip = compute_wall_strip(room_image, mask_image, quads, extended_start, extended_end);
+
+        let rows = (strip_start - extended_start)..(strip_end - extended_start);
+        for y in rows.clone() {
+            for x in 0..strip.wall_ids.width() {
+                let wall = strip.wall_ids.get_pixel(x, y).0[0];
+                if wall == NO_WALL_ID {
+                    continue;
+                }
+                wall_blackness.add(wall as usize, strip.hsv_values_image.get_pixel(x, y).0[0]);
+            }
+        }
+
+        if material.normal_map.is_some() {
+            // Image borders have no neighbouring rows
+            let gradient_rows = u32::max(rows.start, 1)
+                ..u32::min(rows.end, strip.wall_ids.height().saturating_sub(1));
+            light_gradients.accumulate(&strip.hsv_values_image, &strip.wall_ids, gradient_rows);
+        }
+    }
+
+    let average_blackness_pixel_values = wall_blackness.average_pixel_values();
+    let light_directions = material
+        .normal_map
+        .as_ref()
+        .map(|_| light_gradients.light_directions());
+
+    for strip_start in (0..room_image_height).step_by(strip_rows as usize) {
+        let strip_end = u32::min(strip_start + strip_rows, room_image_height);
+        let strip = compute_wall_strip(room_image, mask_image, quads, strip_start, strip_end);
+
+        for (x, strip_y, wall_id) in strip.wall_ids.enumerate_pixels() {
+            let wall = wall_id.0[0];
+            if wall == NO_WALL_ID {
+                continue;
+            }
+            let wall = wall as usize;
+            let y = strip_start + strip_y;
+
+            let point = (x as f32, y as f32);
+            let wallpaper_point = inverse_projections[wall] * point;
+            let mut tile_pixel_rgb = sample_bilinear_wrapped(&material.color, wallpaper_point);
+
+            // Blend with the neighbouring wall near the shared edge to hide rasterisation seams
+            for shared_edge in shared_edges.iter() {
+                let Some(other_wall) = shared_edge.other_wall(wall) else {
+                    continue;
+                };
+                let distance = shared_edge.distance(point);
+                if distance >= CORNER_BLEND_BAND {
+                    continue;
+                }
+
+                let other_weight = 0.5 * (1.0 - distance / CORNER_BLEND_BAND);
+                let other_tile_pixel_rgb = sample_bilinear_wrapped(
+                    &material.color,
+                    inverse_projections[other_wall] * point,
+                );
+                tile_pixel_rgb = (
+                    tile_pixel_rgb.0 * (1.0 - other_weight) + other_tile_pixel_rgb.0 * other_weight,
+                    tile_pixel_rgb.1 * (1.0 - other_weight) + other_tile_pixel_rgb.1 * other_weight,
+                    tile_pixel_rgb.2 * (1.0 - other_weight) + other_tile_pixel_rgb.2 * other_weight,
+                );
+            }
+
+            let blackness_delta = compute_blackness_delta(
+                strip.hsv_values_image.get_pixel(x, strip_y).0[0],
+                average_blackness_pixel_values[wall],
+            );
+
+            let mut tile_pixel_hsv = rgb_to_hsv(tile_pixel_rgb);
+
+            // Add relief of embossed wallpapers, lit from the same direction as the wall
+            if let (Some(normal_map), Some(light_directions)) =
+                (&material.normal_map, &light_directions)
+            {
+                let normal_rgb = sample_bilinear_wrapped(normal_map, wallpaper_point);
+                let (u_axis, v_axis) = wallpaper_axes(&projections[wall], wallpaper_point);
+                let shading_factor = relief_shading_factor(
+                    orient_normal(decode_normal(normal_rgb), u_axis, v_axis),
+                    light_directions[wall],
+                    material.relief_strength,
+                );
+                tile_pixel_hsv.2 = (tile_pixel_hsv.2 * shading_factor).clamp(0.0, 1.0);
+            }
+
+            shade_wallpaper_pixel(
+                room_image.get_pixel_mut(x, y),
+                tile_pixel_hsv,
+                blackness_delta,
+            );
+        }
+    }
+}
+
+/// Directions of wallpaper X and Y axes in the room image around the wallpaper point, from the
+/// local Jacobian of the projection from wallpaper onto the wall.
+fn wallpaper_axes(
+    projection: &Projection,
+    wallpaper_point: (f32, f32),
+) -> ((f32, f32), (f32, f32)) {
+    let origin = *projection * wallpaper_point;
+    let u_end = *projection * (wallpaper_point.0 + 1.0, wallpaper_point.1);
+    let v_end = *projection * (wallpaper_point.0, wallpaper_point.1 + 1.0);
+    (
+        (u_end.0 - origin.0, u_end.1 - origin.1),
+        (v_end.0 - origin.0, v_end.1 - origin.1),
+    )
+}
+
+/// Wall ids and HSV values of room image rows `[y_start; y_end)`.
+struct WallStrip {
+    wall_ids: GrayImage,
+    /// HSV values of wall pixels
+    hsv_values_image: GrayImage,
+}
+
+fn compute_wall_strip(
+    room_image: &RgbImage,
+    mask_image: &GrayImage,
+    quads: &[WallQuad],
+    y_start: u32,
+    y_end: u32,
+) -> WallStrip {
+    let width = room_image.width();
+    let height = room_image.height();
+    let mask_x_ratio = mask_image.width() as f32 / width as f32;
+    let mask_y_ratio = mask_image.height() as f32 / height as f32;
+
+    let mut wall_ids = GrayImage::from_pixel(width, y_end - y_start, Luma([NO_WALL_ID]));
+    let mut hsv_values_image = GrayImage::new(width, y_end - y_start);
+    for (x, strip_y, wall_id) in wall_ids.enumerate_pixels_mut() {
+        let y = y_start + strip_y;
+
+        // Nearest neighbour sampling of the mask, same as resizing it to room image size
+        let mask_x = u32::min(
+            ((x as f32 + 0.5) * mask_x_ratio) as u32,
+            mask_image.width() - 1,
+        );
+        let mask_y = u32::min(
+            ((y as f32 + 0.5) * mask_y_ratio) as u32,
+            mask_image.height() - 1,
+        );
+        if mask_image.get_pixel(mask_x, mask_y).0[0] <= MASK_THRESHOLD {
+            continue;
+        }
+
+        let point = (x as f32, y as f32);
+        let Some(wall) = quads.iter().position(|quad| quad.contains(point)) else {
+            continue;
+        };
+        *wall_id = Luma([wall as u8]);
+
+        let room_pixel = room_image.get_pixel(x, y);
+        let rgb = (
+            room_pixel.0[0] as f32 / 255.0,
+            room_pixel.0[1] as f32 / 255.0,
+            room_pixel.0[2] as f32 / 255.0,
+        );
+        let value = rgb_to_hsv(rgb).2;
+        *hsv_values_image.get_pixel_mut(x, strip_y) = Luma([(value * 255.0) as u8]);
+    }
+
+    WallStrip {
+        wall_ids,
+        hsv_values_image,
+    }
+}
+
+/// Computes which share of the assembled wallpaper strip goes to each wall polygon, as well as
+/// how many tiles are visible horizontally across all the walls. Returns `None` unless there
+/// are one to three walls.
+pub(crate) fn compute_polygon_width_shares(polygons: &[WallPolygon]) -> Option<(Vec<f32>, f32)> {
     let mut polygon_width_shares: Vec<f32> = vec![];
     let num_polygons = polygons.len();
     let mut visible_horizontal_tile_count = VISIBLE_HORIZONTAL_TILE_COUNT;
@@ -77,209 +381,382 @@ pub(crate) fn create_preview(
             polygon_width_shares.push(center_polygon_width_share);
             polygon_width_shares.push(right_polygon_share);
         }
-        _ => panic!("Unexpected wall polygon count: {num_polygons}"),
+        _ => {
+            println!("Unexpected wall polygon count: {num_polygons}");
+            return None;
+        }
     }
 
-    let mut room_image_width = room_image.width();
-    let mut room_image_height = room_image.height();
+    Some((polygon_width_shares, visible_horizontal_tile_count))
+}
+
+/// Upscales room image so that its smallest side is at least [MIN_ROOM_IMAGE_SIDE], but keeps it
+/// within `max_pixels`, downscaling it if needed.
+pub(crate) fn resize_room_image(room_image: RgbImage, max_pixels: usize) -> RgbImage {
+    let room_image_width = room_image.width();
+    let room_image_height = room_image.height();
     let smallest_room_image_side = u32::min(room_image_width, room_image_height);
-    if smallest_room_image_side < MIN_ROOM_IMAGE_SIDE {
-        let scale_ratio = MIN_ROOM_IMAGE_SIDE as f32 / smallest_room_image_side as f32;
-        room_image_width = (room_image_width as f32 * scale_ratio) as u32;
-        room_image_height = (room_image_height as f32 * scale_ratio) as u32;
-        room_image = image::imageops::resize(
-            &room_image,
-            room_image_width,
-            room_image_height,
-            FilterType::Lanczos3,
-        );
-    }
 
-    let polygon_width_scale = room_image_width as f32 / POLYGON_IMAGE_SCALE;
-    let polygon_height_scale = room_image_height as f32 / POLYGON_IMAGE_SCALE;
+    let mut scale_ratio = f32::max(
+        MIN_ROOM_IMAGE_SIDE as f32 / smallest_room_image_side as f32,
+        1.0,
+    );
+    let room_image_pixels = room_image_width as f32 * room_image_height as f32;
+    let max_scale_ratio = (max_pixels as f32 / room_image_pixels).sqrt();
+    scale_ratio = f32::min(scale_ratio, max_scale_ratio);
+    if scale_ratio == 1.0 {
+        return room_image;
+    }
 
-    // Rescale polygons to apply to room image size
-    for polygon in polygons.iter_mut() {
-        polygon.top_left.0 = (polygon.top_left.0 as f32 * polygon_width_scale) as i32;
-        polygon.top_left.1 = (polygon.top_left.1 as f32 * polygon_height_scale) as i32;
+    image::imageops::resize(
+        &room_image,
+        u32::max((room_image_width as f32 * scale_ratio) as u32, 1),
+        u32::max((room_image_height as f32 * scale_ratio) as u32, 1),
+        FilterType::Lanczos3,
+    )
+}
 
-        polygon.top_right.0 = (polygon.top_right.0 as f32 * polygon_width_scale) as i32;
-        polygon.top_right.1 = (polygon.top_right.1 as f32 * polygon_height_scale) as i32;
+/// Wall polygon in room image space. Corners are kept as floats, so that edges shared by
+/// neighbouring walls stay exactly the same after rescaling.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct WallQuad {
+    pub top_left: (f32, f32),
+    pub top_right: (f32, f32),
+    pub bottom_right: (f32, f32),
+    pub bottom_left: (f32, f32),
+}
 
-        polygon.bottom_right.0 = (polygon.bottom_right.0 as f32 * polygon_width_scale) as i32;
-        polygon.bottom_right.1 = (polygon.bottom_right.1 as f32 * polygon_height_scale) as i32;
+impl WallQuad {
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        [
+            self.top_left,
+            self.top_right,
+            self.bottom_right,
+            self.bottom_left,
+        ]
+    }
 
-        polygon.bottom_left.0 = (polygon.bottom_left.0 as f32 * polygon_width_scale) as i32;
-        polygon.bottom_left.1 = (polygon.bottom_left.1 as f32 * polygon_height_scale) as i32;
+    /// Checks whether point is inside of the quad or on its edges. Quad is assumed to be convex.
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        let corners = self.corners();
+        let mut has_positive = false;
+        let mut has_negative = false;
+        for i in 0..4 {
+            let (x1, y1) = corners[i];
+            let (x2, y2) = corners[(i + 1) % 4];
+            let edge_length = f32::hypot(x2 - x1, y2 - y1);
+            if edge_length == 0.0 {
+                continue;
+            }
+            // Signed distance of the point from the edge
+            let distance = ((x2 - x1) * (point.1 - y1) - (y2 - y1) * (point.0 - x1)) / edge_length;
+            if distance > QUAD_EDGE_TOLERANCE {
+                has_positive = true;
+            } else if distance < -QUAD_EDGE_TOLERANCE {
+                has_negative = true;
+            }
+        }
+        !(has_positive && has_negative)
     }
+}
 
-    mask_image = image::imageops::resize(
-        &mask_image,
-        room_image_width,
-        room_image_height,
-        FilterType::Nearest,
-    );
-    let mask_threshold = 50;
-    mask_image = imageproc::contrast::threshold(&mask_image, mask_threshold);
+/// Edge shared by two neighbouring walls, going from top to bottom.
+#[derive(Clone, Debug)]
+pub(crate) struct SharedEdge {
+    pub left_wall: usize,
+    pub right_wall: usize,
+    pub top: (f32, f32),
+    pub bottom: (f32, f32),
+}
 
-    let assembled_tile_image = assemble_tiles_image2(
-        &tile_image,
-        visible_horizontal_tile_count,
-        VISIBLE_VERTICAL_TILES_COUNT,
-    );
-    let assembled_tile_image = DynamicImage::from(assembled_tile_image).into_rgba8();
-    let assembled_tile_image_width = assembled_tile_image.width();
-    let assembled_tile_image_height = assembled_tile_image.height();
-
-    let mut preview_image = room_image.clone();
-    let wall_pixel = Luma([255]);
-    let background_mask_pixel = Luma([0]);
-    let warp_default_pixel = Rgba([0, 0, 0, 0]);
-    let mut warped_individual_wall_tiles_image = RgbaImage::from_pixel(
-        preview_image.width(),
-        preview_image.height(),
-        warp_default_pixel,
-    );
-    let mut warped_combined_wall_tiles_image = warped_individual_wall_tiles_image.clone();
-    let mut wallpaper_section_start_x: f32 = 0.0;
-    for (i, (polygon, width_share)) in polygons.iter().zip(polygon_width_shares.iter()).enumerate()
-    {
-        let wallpaper_section_width = assembled_tile_image_width as f32 * width_share;
-        let wallpaper_section_end_x = wallpaper_section_start_x + wallpaper_section_width;
+impl SharedEdge {
+    pub fn other_wall(&self, wall: usize) -> Option<usize> {
+        if wall == self.left_wall {
+            Some(self.right_wall)
+        } else if wall == self.right_wall {
+            Some(self.left_wall)
+        } else {
+            None
+        }
+    }
 
-        let from_points = [
-            (wallpaper_section_start_x, 0f32),
-            (wallpaper_section_end_x, 0f32),
-            (wallpaper_section_end_x, assembled_tile_image_height as f32),
-            (
-                wallpaper_section_start_x,
-                assembled_tile_image_height as f32,
-            ),
-        ];
-        let to_points = [
-            (polygon.top_left.0 as f32, polygon.top_left.1 as f32),
-            (polygon.top_right.0 as f32, polygon.top_right.1 as f32),
-            (polygon.bottom_right.0 as f32, polygon.bottom_right.1 as f32),
-            (polygon.bottom_left.0 as f32, polygon.bottom_left.1 as f32),
-        ];
+    /// Distance from the point to the edge segment.
+    pub fn distance(&self, point: (f32, f32)) -> f32 {
+        let (x1, y1) = self.top;
+        let (x2, y2) = self.bottom;
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let length_squared = dx * dx + dy * dy;
+        if length_squared == 0.0 {
+            return f32::hypot(point.0 - x1, point.1 - y1);
+        }
+        let t = (((point.0 - x1) * dx + (point.1 - y1) * dy) / length_squared).clamp(0.0, 1.0);
+        f32::hypot(point.0 - (x1 + t * dx), point.1 - (y1 + t * dy))
+    }
+}
 
-        let projection = Projection::from_control_points(from_points, to_points).unwrap();
-        warp_into(
-            &assembled_tile_image,
-            &projection,
-            Interpolation::Bilinear,
-            warp_default_pixel,
-            &mut warped_individual_wall_tiles_image,
-        );
+/// Rescales polygons from layout estimation space of `layout_width` x `layout_height` size to
+/// room image of given size, and welds corners of neighbouring walls which are meant to be shared.
+pub(crate) fn scale_polygons(
+    polygons: &[WallPolygon],
+    layout_width: u32,
+    layout_height: u32,
+    room_image_width: u32,
+    room_image_height: u32,
+) -> Vec<WallQuad> {
+    let polygon_width_scale = room_image_width as f32 / layout_width as f32;
+    let polygon_height_scale = room_image_height as f32 / layout_height as f32;
+    let scale_point = |point: (i32, i32)| {
+        (
+            point.0 as f32 * polygon_width_scale,
+            point.1 as f32 * polygon_height_scale,
+        )
+    };
 
-        let mut current_wall_mask = mask_image.clone();
-
-        // Transfer shadows
-        // For that compute HSV values image from room image, compuate average blackness
-        // for each individual wall.
-        let mut hsv_values_image = GrayImage::new(room_image.width(), room_image.height());
-        let mut total_wall_blackness = 0f32;
-        let mut values_count = 0usize;
-
-        let geo_polygon = GeoPolygon::new(
-            LineString::new(vec![
-                Coord::from(polygon.top_left),
-                Coord::from(polygon.top_right),
-                Coord::from(polygon.bottom_right),
-                Coord::from(polygon.bottom_left),
-            ]),
-            vec![],
+    let mut quads: Vec<WallQuad> = polygons
+        .iter()
+        .map(|polygon| WallQuad {
+            top_left: scale_point(polygon.top_left),
+            top_right: scale_point(polygon.top_right),
+            bottom_right: scale_point(polygon.bottom_right),
+            bottom_left: scale_point(polygon.bottom_left),
+        })
+        .collect();
+
+    // Polygon corners are rounded to integers in layout estimation space, so corners shared by
+    // neighbouring walls might be slightly off. Use the same point for both walls.
+    let weld_distance = CORNER_WELD_DISTANCE * f32::max(polygon_width_scale, polygon_height_scale);
+    for i in 1..quads.len() {
+        let (left, right) = quads.split_at_mut(i);
+        let left_quad = &mut left[i - 1];
+        let right_quad = &mut right[0];
+        weld_points(
+            &mut left_quad.top_right,
+            &mut right_quad.top_left,
+            weld_distance,
         );
+        weld_points(
+            &mut left_quad.bottom_right,
+            &mut right_quad.bottom_left,
+            weld_distance,
+        );
+    }
 
-        for (x, y, room_pixel) in room_image.enumerate_pixels() {
-            let is_in_polygon_bounds = geo_polygon.contains(&Coord::from((x as i32, y as i32)));
-            if !is_in_polygon_bounds {
-                // Update current wall mask
-                *current_wall_mask.get_pixel_mut(x, y) = background_mask_pixel;
-                continue;
-            }
+    quads
+}
 
-            let mask_value = mask_image.get_pixel(x, y);
-            let is_wall = mask_value == &wall_pixel;
-            if !is_wall {
-                continue;
-            }
+fn weld_points(a: &mut (f32, f32), b: &mut (f32, f32), weld_distance: f32) {
+    if f32::hypot(a.0 - b.0, a.1 - b.1) > weld_distance {
+        return;
+    }
+    let point = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    *a = point;
+    *b = point;
+}
 
-            values_count += 1;
+/// Finds edges shared by neighbouring walls.
+pub(crate) fn find_shared_edges(quads: &[WallQuad]) -> Vec<SharedEdge> {
+    let mut shared_edges = vec![];
+    for i in 1..quads.len() {
+        let left_quad = &quads[i - 1];
+        let right_quad = &quads[i];
+        if left_quad.top_right == right_quad.top_left
+            && left_quad.bottom_right == right_quad.bottom_left
+        {
+            shared_edges.push(SharedEdge {
+                left_wall: i - 1,
+                right_wall: i,
+                top: left_quad.top_right,
+                bottom: left_quad.bottom_right,
+            });
+        }
+    }
+    shared_edges
+}
 
-            let rgb = (
-                room_pixel.0[0] as f32 / 255.0,
-                room_pixel.0[1] as f32 / 255.0,
-                room_pixel.0[2] as f32 / 255.0,
-            );
-            let hsv = rgb_to_hsv(rgb);
-            let value = hsv.2;
+/// Assigns each wall pixel of the mask to exactly one wall quad. Pixels on edges shared by
+/// two walls go to the first one, so that there are neither gaps nor overlaps between walls.
+pub(crate) fn assign_wall_pixels(quads: &[WallQuad], mask_image: &GrayImage) -> GrayImage {
+    let mut wall_ids =
+        GrayImage::from_pixel(mask_image.width(), mask_image.height(), Luma([NO_WALL_ID]));
+    for (x, y, mask_pixel) in mask_image.enumerate_pixels() {
+        if *mask_pixel != WALL_PIXEL {
+            continue;
+        }
+        let point = (x as f32, y as f32);
+        if let Some(wall) = quads.iter().position(|quad| quad.contains(point)) {
+            *wall_ids.get_pixel_mut(x, y) = Luma([wall as u8]);
+        }
+    }
+    wall_ids
+}
+
+/// Resizes wall segmentation mask to room image size and binarizes it.
+pub(crate) fn prepare_wall_mask(mask_image: &GrayImage, width: u32, height: u32) -> GrayImage {
+    let mask_image = image::imageops::resize(mask_image, width, height, FilterType::Nearest);
+    imageproc::contrast::threshold(&mask_image, MASK_THRESHOLD)
+}
 
-            total_wall_blackness += value;
+/// Computes projection which maps wallpaper section `[start_x; end_x] x [0; height]` onto
+/// the given wall quad.
+pub(crate) fn compute_wall_section_projection(
+    quad: &WallQuad,
+    section_start_x: f32,
+    section_end_x: f32,
+    section_height: f32,
+) -> Projection {
+    let from_points = [
+        (section_start_x, 0f32),
+        (section_end_x, 0f32),
+        (section_end_x, section_height),
+        (section_start_x, section_height),
+    ];
+
+    Projection::from_control_points(from_points, quad.corners()).unwrap()
+}
 
-            let value_pixel = Luma([(value * 255.0) as u8]);
-            *hsv_values_image.get_pixel_mut(x, y) = value_pixel;
-        }
+/// Shading information of walls, used to transfer shadows from room image onto wallpaper.
+pub(crate) struct WallShading {
+    /// HSV values of room image wall pixels
+    pub hsv_values_image: GrayImage,
+    pub average_blackness_pixel_values: Vec<i32>,
+}
 
-        let average_wall_blackness = total_wall_blackness / values_count as f32;
-        let average_wall_blackness_pixel_value = (average_wall_blackness * 255.0) as i32;
-        println!("Average wall {i} blackness: {average_wall_blackness}, pixel: {average_wall_blackness_pixel_value}");
+impl WallShading {
+    /// Difference between HSV value of the wall at given pixel and average wall HSV value.
+    pub fn blackness_delta(&self, x: u32, y: u32, wall: usize) -> i32 {
+        compute_blackness_delta(
+            self.hsv_values_image.get_pixel(x, y).0[0],
+            self.average_blackness_pixel_values[wall],
+        )
+    }
+}
 
-        for (x, y, warped_wall_tile_pixel) in
-            warped_individual_wall_tiles_image.enumerate_pixels_mut()
-        {
-            let is_wall = *current_wall_mask.get_pixel(x, y) == wall_pixel;
-            if !is_wall {
-                *warped_wall_tile_pixel = warp_default_pixel;
-                continue;
-            }
+/// HSV values of wall pixels summed for each wall, so that walls can be averaged strip by strip.
+struct WallBlackness {
+    total_wall_blackness: Vec<f32>,
+    values_count: Vec<usize>,
+}
 
-            let wall_blackness = hsv_values_image.get_pixel(x, y).0[0] as i32;
-            let blackness_delta = wall_blackness - average_wall_blackness_pixel_value;
+impl WallBlackness {
+    fn new(num_walls: usize) -> Self {
+        Self {
+            total_wall_blackness: vec![0f32; num_walls],
+            values_count: vec![0usize; num_walls],
+        }
+    }
 
-            let tile_pixel_rgb = (
-                warped_wall_tile_pixel.0[0] as f32 / 255.0,
-                warped_wall_tile_pixel.0[1] as f32 / 255.0,
-                warped_wall_tile_pixel.0[2] as f32 / 255.0,
-            );
+    fn add(&mut self, wall: usize, hsv_value: u8) {
+        self.values_count[wall] += 1;
+        self.total_wall_blackness[wall] += hsv_value as f32 / 255.0;
+    }
 
-            let tile_pixel_hsv = rgb_to_hsv(tile_pixel_rgb);
-            let tile_pixel_hsv_value = (tile_pixel_hsv.2 * 255.0) as i32;
+    /// Average HSV value of each wall, as a pixel value.
+    fn average_pixel_values(&self) -> Vec<i32> {
+        self.total_wall_blackness
+            .iter()
+            .zip(self.values_count.iter())
+            .enumerate()
+            .map(|(i, (total, count))| {
+                let average_blackness = total / *count as f32;
+                let average_blackness_pixel_value = (average_blackness * 255.0) as i32;
+                println!(
+                    "Average wall {i} blackness: {average_blackness}, pixel: {average_blackness_pixel_value}"
+                );
+                average_blackness_pixel_value
+            })
+            .collect()
+    }
+}
 
-            let shifted_tile_pixel_hsv_value =
-                (tile_pixel_hsv_value + blackness_delta).clamp(0, 255) as f32 / 255.0;
+/// Difference between HSV value of the wall pixel and average HSV value of its wall.
+fn compute_blackness_delta(hsv_value: u8, average_blackness_pixel_value: i32) -> i32 {
+    hsv_value as i32 - average_blackness_pixel_value
+}
 
-            let shifted_tile_pixel_hsv = (
-                tile_pixel_hsv.0,
-                tile_pixel_hsv.1,
-                shifted_tile_pixel_hsv_value,
-            );
-            let shifted_tile_pixel_rgb = hsv_to_rgb(shifted_tile_pixel_hsv);
-
-            *warped_wall_tile_pixel = Rgba([
-                (shifted_tile_pixel_rgb.0 * 255.0) as u8,
-                (shifted_tile_pixel_rgb.1 * 255.0) as u8,
-                (shifted_tile_pixel_rgb.2 * 255.0) as u8,
-                255,
-            ]);
-
-            let preview_pixel = preview_image.get_pixel_mut(x, y);
-            preview_pixel.0[0] = warped_wall_tile_pixel.0[0];
-            preview_pixel.0[1] = warped_wall_tile_pixel.0[1];
-            preview_pixel.0[2] = warped_wall_tile_pixel.0[2];
+/// Writes wallpaper pixel into the preview, with its HSV value shifted by the blackness delta of
+/// the wall under it.
+pub(crate) fn shade_wallpaper_pixel(
+    preview_pixel: &mut Rgb<u8>,
+    tile_pixel_hsv: (f32, f32, f32),
+    blackness_delta: i32,
+) {
+    let tile_pixel_hsv_value = (tile_pixel_hsv.2 * 255.0) as i32;
+    let shifted_tile_pixel_hsv_value =
+        (tile_pixel_hsv_value + blackness_delta).clamp(0, 255) as f32 / 255.0;
+    let shifted_tile_pixel_rgb = hsv_to_rgb((
+        tile_pixel_hsv.0,
+        tile_pixel_hsv.1,
+        shifted_tile_pixel_hsv_value,
+    ));
+
+    preview_pixel.0[0] = (shifted_tile_pixel_rgb.0 * 255.0) as u8;
+    preview_pixel.0[1] = (shifted_tile_pixel_rgb.1 * 255.0) as u8;
+    preview_pixel.0[2] = (shifted_tile_pixel_rgb.2 * 255.0) as u8;
+}
+
+pub(crate) fn compute_wall_shading(
+    room_image: &RgbImage,
+    wall_ids: &GrayImage,
+    num_walls: usize,
+) -> WallShading {
+    // Transfer shadows
+    // For that compute HSV values image from room image, compuate average blackness
+    // for each individual wall.
+    let mut hsv_values_image = GrayImage::new(room_image.width(), room_image.height());
+    let mut wall_blackness = WallBlackness::new(num_walls);
+
+    for (x, y, room_pixel) in room_image.enumerate_pixels() {
+        let wall = wall_ids.get_pixel(x, y).0[0];
+        if wall == NO_WALL_ID {
+            continue;
         }
 
-        overlay(
-            &mut warped_combined_wall_tiles_image,
-            &warped_individual_wall_tiles_image,
-            0,
-            0,
+        let rgb = (
+            room_pixel.0[0] as f32 / 255.0,
+            room_pixel.0[1] as f32 / 255.0,
+            room_pixel.0[2] as f32 / 255.0,
         );
+        let value = (rgb_to_hsv(rgb).2 * 255.0) as u8;
 
-        wallpaper_section_start_x = wallpaper_section_end_x;
+        wall_blackness.add(wall as usize, value);
+        *hsv_values_image.get_pixel_mut(x, y) = Luma([value]);
     }
 
-    preview_image
+    WallShading {
+        hsv_values_image,
+        average_blackness_pixel_values: wall_blackness.average_pixel_values(),
+    }
+}
+
+/// Samples image with bilinear interpolation, wrapping coordinates around image borders.
+pub(crate) fn sample_bilinear_wrapped(image: &RgbImage, point: (f32, f32)) -> (f32, f32, f32) {
+    let width = image.width() as i64;
+    let height = image.height() as i64;
+
+    let x = point.0 - 0.5;
+    let y = point.1 - 0.5;
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let fx = x - x0;
+    let fy = y - y0;
+
+    let pixel = |dx: i64, dy: i64| {
+        let px = (x0 as i64 + dx).rem_euclid(width) as u32;
+        let py = (y0 as i64 + dy).rem_euclid(height) as u32;
+        image.get_pixel(px, py).0
+    };
+    let top_left = pixel(0, 0);
+    let top_right = pixel(1, 0);
+    let bottom_left = pixel(0, 1);
+    let bottom_right = pixel(1, 1);
+
+    let channel = |c: usize| {
+        let top = top_left[c] as f32 * (1.0 - fx) + top_right[c] as f32 * fx;
+        let bottom = bottom_left[c] as f32 * (1.0 - fx) + bottom_right[c] as f32 * fx;
+        (top * (1.0 - fy) + bottom * fy) / 255.0
+    };
+
+    (channel(0), channel(1), channel(2))
 }
 
 fn pythagorean_distance(from: Point, to: Point) -> i32 {
@@ -288,196 +765,367 @@ fn pythagorean_distance(from: Point, to: Point) -> i32 {
     f32::sqrt(((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32) as i32
 }
 
-fn assemble_tiles_image2(
-    tile_image: &RgbImage,
-    width_tile_count: f32,
-    height_tile_count: f32,
-) -> RgbImage {
-    let tile_width = tile_image.width();
-    let tile_height = tile_image.height();
-
-    let assembled_image_width = (tile_width as f32 * width_tile_count).ceil() as u32;
-    let assembled_image_height = (tile_height as f32 * height_tile_count).ceil() as u32;
-
-    let mut assembled_tiles_image = RgbImage::new(assembled_image_width, assembled_image_height);
-    for (dst_x, dst_y, dst_pixel) in assembled_tiles_image.enumerate_pixels_mut() {
-        let src_tile_x = dst_x % tile_width;
-        let src_tile_y = dst_y % tile_height;
-        *dst_pixel = *tile_image.get_pixel(src_tile_x, src_tile_y);
-    }
-
-    assembled_tiles_image
-}
-
 #[cfg(test)]
 mod tests {
-    use geo::geometry::LineString;
-    use geo::geometry::Polygon as GeoPolygon;
-    use geo::{Contains, Coord};
-    use image::imageops::{overlay, FilterType};
-    use image::{DynamicImage, GrayImage, Luma, RgbImage, Rgba, RgbaImage};
-    use imageproc::geometric_transformations::{warp_into, Interpolation, Projection};
-    use lsun_res_parser::Point;
-    use rgb_hsv::{hsv_to_rgb, rgb_to_hsv};
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+    use rgb_hsv::rgb_to_hsv;
     use std::path::PathBuf;
 
     use crate::ffi::{LayoutLine, LayoutPoint, LayoutWallPolygon, RoomLayoutData};
-    use crate::polygons::{tests::draw_lines_on_padded_image, WallPolygon};
+    use crate::layout::lsun::Point;
+    use crate::layout::{RoomLayout, RoomType};
+    use crate::material::WallpaperMaterial;
+    use crate::polygons::WallPolygon;
+    use crate::preview::compute_polygon_width_shares;
     use crate::preview::{
-        create_preview, VISIBLE_HORIZONTAL_TILE_COUNT, VISIBLE_VERTICAL_TILES_COUNT, VISIBLE_WALLS,
-        WALLPAPER_TILE_WIDTH_METERS, WALL_WIDTH_METERS,
+        assign_wall_pixels, composite_wallpaper, compute_wall_section_projection, create_preview,
+        create_preview_with_memory_budget, find_shared_edges, resize_room_image, scale_polygons,
+        wallpaper_axes, WallQuad, NO_WALL_ID,
     };
+    use crate::synthetic::{RenderedRoom, Surface, SyntheticRoom};
 
-    #[test]
-    fn preview_generation_works() {
-        let sample_idx = 2;
-
-        // Image 2.jpg
-        // TODO: resize polygons to match input image size
-        let room_layout_data = RoomLayoutData {
-            lines: [
-                LayoutLine {
-                    start: LayoutPoint { x: 300, y: 165 },
-                    end: LayoutPoint { x: 18, y: 0 },
-                },
-                LayoutLine {
-                    start: LayoutPoint { x: 300, y: 165 },
-                    end: LayoutPoint { x: 511, y: 85 },
-                },
-                LayoutLine {
-                    start: LayoutPoint { x: 300, y: 165 },
-                    end: LayoutPoint { x: 302, y: 344 },
-                },
-                LayoutLine {
-                    start: LayoutPoint { x: 302, y: 344 },
-                    end: LayoutPoint { x: 0, y: 499 },
-                },
-                LayoutLine {
-                    start: LayoutPoint { x: 302, y: 344 },
-                    end: LayoutPoint { x: 511, y: 385 },
-                },
-                blank_line(),
-                blank_line(),
-                blank_line(),
-            ],
-            num_lines: 5,
-            room_type: 5,
-            wall_polygons: [
-                LayoutWallPolygon {
-                    top_left: LayoutPoint { x: -5, y: -13 },
-                    top_right: LayoutPoint { x: 300, y: 165 },
-                    bottom_right: LayoutPoint { x: 302, y: 344 },
-                    bottom_left: LayoutPoint { x: 0, y: 500 },
-                },
-                LayoutWallPolygon {
-                    top_left: LayoutPoint { x: 300, y: 165 },
-                    top_right: LayoutPoint { x: 511, y: 85 },
-                    bottom_right: LayoutPoint { x: 515, y: 386 },
-                    bottom_left: LayoutPoint { x: 302, y: 344 },
-                },
-                blank_polygon(),
-            ],
-            num_wall_polygons: 2,
+    fn layout_point((x, y): Point) -> LayoutPoint {
+        LayoutPoint { x, y }
+    }
+
+    /// Layout of the synthetic room as the app passes it over FFI
+    fn room_layout_data(layout: &RoomLayout) -> RoomLayoutData {
+        let mut room_layout_data = RoomLayoutData {
+            num_lines: layout.lines.len() as u8,
+            room_type: layout.room_type.into(),
+            num_wall_polygons: layout.wall_polygons.len() as u8,
+            layout_width: layout.layout_width,
+            layout_height: layout.layout_height,
+            confidence: 1.0,
+            ..Default::default()
         };
+        for (layout_line, (start, end)) in room_layout_data.lines.iter_mut().zip(&layout.lines) {
+            *layout_line = LayoutLine {
+                start: layout_point(*start),
+                end: layout_point(*end),
+            };
+        }
+        for (layout_polygon, polygon) in room_layout_data
+            .wall_polygons
+            .iter_mut()
+            .zip(&layout.wall_polygons)
+        {
+            *layout_polygon = LayoutWallPolygon {
+                top_left: layout_point(polygon.top_left),
+                top_right: layout_point(polygon.top_right),
+                bottom_right: layout_point(polygon.bottom_right),
+                bottom_left: layout_point(polygon.bottom_left),
+            };
+        }
+        room_layout_data
+    }
 
-        let mut polygons: Vec<WallPolygon> = (0..room_layout_data.num_wall_polygons)
-            .map(|i| {
-                let wall_polygon = room_layout_data.wall_polygons[i as usize];
-                wall_polygon.into()
-            })
-            .collect();
+    fn hsv_value(pixel: &Rgb<u8>) -> f32 {
+        let [r, g, b] = pixel.0.map(|channel| channel as f32 / 255.0);
+        rgb_to_hsv((r, g, b)).2
+    }
+
+    /// Surface of the synthetic room seen at the preview pixel, sampled the same way as the
+    /// wall mask.
+    fn room_surface(room: &RenderedRoom, preview_image: &RgbImage, x: u32, y: u32) -> Surface {
+        let (width, height) = room.photo.dimensions();
+        let room_x = ((x as f32 + 0.5) * width as f32 / preview_image.width() as f32) as u32;
+        let room_y = ((y as f32 + 0.5) * height as f32 / preview_image.height() as f32) as u32;
+        room.surfaces[(room_y * width + room_x) as usize]
+    }
+
+    #[test]
+    fn preview_generation_works() {
+        let room = SyntheticRoom::new(RoomType::Type5).render();
+        let room_layout_data = room_layout_data(&room.layout);
+        let polygons = room_layout_data.wall_polygons();
+        assert_eq!(polygons, room.layout.wall_polygons);
+
+        let tile_image = image::open(PathBuf::from("./fixtures/wallpaper1.jpg"))
+            .unwrap()
+            .into_rgb8();
+        let tile_pixels = tile_image.pixels().len() as f32;
+        let tile_value = tile_image.pixels().map(hsv_value).sum::<f32>() / tile_pixels;
+
+        let preview_image = create_preview(
+            room.photo.clone(),
+            room.wall_mask.clone(),
+            tile_image.into(),
+            polygons,
+            room_layout_data.layout_width,
+            room_layout_data.layout_height,
+        );
 
-        let images_dir = PathBuf::from("/path/to/res_lsun_tr_gt_npy");
-        let image_path = images_dir.join(format!("{sample_idx}.png"));
-        println!("Reading image: {image_path:?}");
-        let mut room_image = image::open(image_path).unwrap().into_rgb8();
+        let room_image = resize_room_image(room.photo.clone(), usize::MAX);
+        assert_eq!(preview_image.dimensions(), room_image.dimensions());
+        for wall in [Surface::LeftWall, Surface::FrontWall] {
+            let (mut wall_pixels, mut changed_wall_pixels, mut total_value) = (0, 0, 0.0);
+            for (x, y, preview_pixel) in preview_image.enumerate_pixels() {
+                if room_surface(&room, &preview_image, x, y) != wall {
+                    continue;
+                }
+                wall_pixels += 1;
+                changed_wall_pixels += (preview_pixel != room_image.get_pixel(x, y)) as usize;
+                total_value += hsv_value(preview_pixel);
+            }
+            assert!(
+                changed_wall_pixels as f32 > 0.95 * wall_pixels as f32,
+                "{wall:?}: {changed_wall_pixels} of {wall_pixels}"
+            );
+            // Wall shading only shifts brightness around the wallpaper brightness
+            let average_value = total_value / wall_pixels as f32;
+            assert!(
+                (average_value - tile_value).abs() < 0.05,
+                "{wall:?}: {average_value} vs {tile_value}"
+            );
+        }
+    }
 
-        let mask_image_path = PathBuf::from("./fixtures/masks/2.jpg");
-        let mask_image = image::open(mask_image_path).unwrap().into_luma8();
-        let tile_image_path = PathBuf::from("./fixtures/wallpaper1.jpg");
-        let tile_image = image::open(tile_image_path).unwrap().into_rgb8();
+    #[test]
+    fn wallpaper_axes_follow_wall_perspective() {
+        // Left wall receding to the right, its top edge slopes down
+        let quad = WallQuad {
+            top_left: (0.0, 0.0),
+            top_right: (100.0, 40.0),
+            bottom_right: (100.0, 160.0),
+            bottom_left: (0.0, 200.0),
+        };
+        let projection = compute_wall_section_projection(&quad, 0.0, 10.0, 10.0);
 
-        let preview_image = create_preview(room_image, mask_image, tile_image, polygons);
+        let (u_axis, v_axis) = wallpaper_axes(&projection, (5.0, 0.0));
 
-        preview_image.save("./out/preview.jpg").unwrap();
+        assert!(u_axis.0 > 0.0 && u_axis.1 > 0.0, "{u_axis:?}");
+        assert!((u_axis.1 / u_axis.0 - 0.4).abs() < 0.05, "{u_axis:?}");
+        assert!(v_axis.1 > 0.0 && v_axis.0.abs() < 0.01, "{v_axis:?}");
     }
 
-    impl From<LayoutPoint> for Point {
-        fn from(layout_point: LayoutPoint) -> Self {
-            (layout_point.x, layout_point.y)
-        }
+    #[test]
+    fn returns_room_image_for_layout_without_walls() {
+        let room_image = gradient_room_image(96, 80);
+        let tile_image = RgbImage::from_pixel(16, 16, Rgb([180, 60, 60]));
+
+        let preview_image = create_preview(
+            room_image.clone(),
+            GrayImage::from_pixel(48, 40, Luma([255])),
+            tile_image.into(),
+            vec![],
+            512,
+            512,
+        );
+
+        assert_eq!(preview_image, room_image);
     }
 
-    fn blank_line() -> LayoutLine {
-        LayoutLine {
-            start: blank_point(),
-            end: blank_point(),
+    #[test]
+    fn transferring_shadows() {
+        let room = SyntheticRoom::new(RoomType::Type5).render();
+        let tile_image = RgbImage::from_pixel(16, 16, Rgb([180, 60, 60]));
+
+        let preview_image = create_preview(
+            room.photo.clone(),
+            room.wall_mask.clone(),
+            tile_image.into(),
+            room.layout.wall_polygons.clone(),
+            room.layout.layout_width,
+            room.layout.layout_height,
+        );
+
+        // Brightness of uniform wallpaper follows the brightness of the wall under it, shifted by
+        // the same amount across the whole wall
+        let room_image = resize_room_image(room.photo.clone(), usize::MAX);
+        for wall in [Surface::LeftWall, Surface::FrontWall] {
+            let (mut min_room_value, mut max_room_value) = (f32::MAX, f32::MIN);
+            let (mut min_delta, mut max_delta) = (f32::MAX, f32::MIN);
+            for (x, y, preview_pixel) in preview_image.enumerate_pixels() {
+                if room_surface(&room, &preview_image, x, y) != wall {
+                    continue;
+                }
+                if preview_pixel == room_image.get_pixel(x, y) {
+                    continue;
+                }
+                let room_value = hsv_value(room_image.get_pixel(x, y));
+                let delta = hsv_value(preview_pixel) - room_value;
+                min_room_value = min_room_value.min(room_value);
+                max_room_value = max_room_value.max(room_value);
+                min_delta = min_delta.min(delta);
+                max_delta = max_delta.max(delta);
+            }
+            assert!(
+                max_room_value - min_room_value > 0.2,
+                "{wall:?} is not shaded: {min_room_value}..{max_room_value}"
+            );
+            // Pixels at the corner can be assigned to the neighbouring wall
+            assert!(
+                max_delta - min_delta < 0.03,
+                "{wall:?} brightness is shifted by {min_delta}..{max_delta}"
+            );
         }
     }
 
-    fn blank_point() -> LayoutPoint {
-        LayoutPoint { x: 0, y: 0 }
-    }
+    #[test]
+    fn welds_shared_corners_and_leaves_no_gaps_between_walls() {
+        // Shared corners are 1 pixel off due to rounding in layout estimation space
+        let polygons = vec![
+            WallPolygon {
+                top_left: (0, 0),
+                top_right: (255, 40),
+                bottom_right: (255, 470),
+                bottom_left: (0, 511),
+            },
+            WallPolygon {
+                top_left: (256, 41),
+                top_right: (511, 0),
+                bottom_right: (511, 511),
+                bottom_left: (256, 471),
+            },
+        ];
 
-    fn blank_polygon() -> LayoutWallPolygon {
-        LayoutWallPolygon {
-            top_left: blank_point(),
-            top_right: blank_point(),
-            bottom_right: blank_point(),
-            bottom_left: blank_point(),
+        let quads = scale_polygons(&polygons, 512, 512, 128, 128);
+        assert_eq!(quads[0].top_right, quads[1].top_left);
+        assert_eq!(quads[0].bottom_right, quads[1].bottom_left);
+
+        let shared_edges = find_shared_edges(&quads);
+        assert_eq!(shared_edges.len(), 1);
+
+        let mask_image = GrayImage::from_pixel(128, 128, Luma([255]));
+        let wall_ids = assign_wall_pixels(&quads, &mask_image);
+        for y in 12..116 {
+            for x in 0..128 {
+                assert_ne!(
+                    wall_ids.get_pixel(x, y).0[0],
+                    NO_WALL_ID,
+                    "Pixel [{x}; {y}] is not covered by any wall"
+                );
+            }
         }
+        assert_eq!(wall_ids.get_pixel(10, 64).0[0], 0);
+        assert_eq!(wall_ids.get_pixel(120, 64).0[0], 1);
     }
 
-    fn pythagorean_distance(from: Point, to: Point) -> i32 {
-        let (x1, y1) = from;
-        let (x2, y2) = to;
-        f32::sqrt(((x2 - x1).pow(2) + (y2 - y1).pow(2)) as f32) as i32
+    fn two_wall_polygons() -> Vec<WallPolygon> {
+        vec![
+            WallPolygon {
+                top_left: (0, 0),
+                top_right: (256, 40),
+                bottom_right: (256, 470),
+                bottom_left: (0, 511),
+            },
+            WallPolygon {
+                top_left: (256, 40),
+                top_right: (511, 0),
+                bottom_right: (511, 511),
+                bottom_left: (256, 470),
+            },
+        ]
     }
 
-    fn assemble_tiles_image2(
-        tile_image: &RgbImage,
-        width_tile_count: f32,
-        height_tile_count: f32,
-    ) -> RgbImage {
-        let tile_width = tile_image.width();
-        let tile_height = tile_image.height();
+    fn gradient_room_image(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            let value = (100 + (x + y) % 100) as u8;
+            Rgb([value, value, value])
+        })
+    }
 
-        let assembled_image_width = (tile_width as f32 * width_tile_count).ceil() as u32;
-        let assembled_image_height = (tile_height as f32 * height_tile_count).ceil() as u32;
+    #[test]
+    fn compositing_in_strips_matches_compositing_whole_image() {
+        let polygons = two_wall_polygons();
+        let room_image = gradient_room_image(96, 80);
+        let mut mask_image = GrayImage::from_pixel(48, 40, Luma([255]));
+        mask_image.put_pixel(10, 10, Luma([0]));
+        let tile_image =
+            RgbImage::from_fn(16, 16, |x, y| Rgb([(x * 16) as u8, (y * 16) as u8, 90]));
+        let normal_map = RgbImage::from_fn(16, 16, |x, _| Rgb([(x * 16) as u8, 128, 230]));
+        let material = WallpaperMaterial::with_normal_map(tile_image, normal_map, 1.0);
+
+        let (polygon_width_shares, visible_horizontal_tile_count) =
+            compute_polygon_width_shares(&polygons).unwrap();
+        let quads = scale_polygons(&polygons, 512, 512, room_image.width(), room_image.height());
+        let composite = |strip_rows: u32| {
+            let mut preview_image = room_image.clone();
+            composite_wallpaper(
+                &mut preview_image,
+                &mask_image,
+                &material,
+                &quads,
+                &polygon_width_shares,
+                visible_horizontal_tile_count,
+                strip_rows,
+            );
+            preview_image
+        };
 
-        let mut assembled_tiles_image =
-            RgbImage::new(assembled_image_width, assembled_image_height);
-        for (dst_x, dst_y, dst_pixel) in assembled_tiles_image.enumerate_pixels_mut() {
-            let src_tile_x = dst_x % tile_width;
-            let src_tile_y = dst_y % tile_height;
-            *dst_pixel = *tile_image.get_pixel(src_tile_x, src_tile_y);
+        let whole_image_preview = composite(room_image.height());
+        assert_ne!(whole_image_preview, room_image);
+        for strip_rows in [1, 7, 32] {
+            assert_eq!(composite(strip_rows), whole_image_preview);
         }
+    }
 
-        assembled_tiles_image
+    #[test]
+    fn preview_image_is_downscaled_to_fit_memory_budget() {
+        let room_image = gradient_room_image(300, 200);
+        let mask_image = GrayImage::from_pixel(300, 200, Luma([255]));
+        let tile_image = RgbImage::from_pixel(8, 8, Rgb([200, 10, 10]));
+        let memory_budget = 100 * 1024;
+
+        let preview_image = create_preview_with_memory_budget(
+            room_image,
+            mask_image,
+            tile_image.into(),
+            two_wall_polygons(),
+            512,
+            512,
+            memory_budget,
+        );
+
+        let preview_image_bytes = preview_image.width() * preview_image.height() * 3;
+        assert!((preview_image_bytes as usize) < memory_budget);
+        // Aspect ratio is kept
+        let aspect_ratio = preview_image.width() as f32 / preview_image.height() as f32;
+        assert!((aspect_ratio - 1.5).abs() < 0.02);
     }
 
     #[test]
-    fn transferring_shadows() {
-        let sample_idx = 2;
-        let images_dir = PathBuf::from("/path/to/res_lsun_tr_gt_npy");
-        let image_path = images_dir.join(format!("{sample_idx}.png"));
-        let room_image = image::open(image_path).unwrap();
-
-        let room_image_rgb = room_image.as_rgb8().unwrap();
-        let mut hsv_values_image = GrayImage::new(room_image.width(), room_image.height());
-        // let mut hsv_values: Array2<f32> = Array2::zeros((room_image.height() as usize, room_image.width() as usize));
-        for (x, y, pixel) in room_image_rgb.enumerate_pixels() {
-            let rgb = (
-                pixel.0[0] as f32 / 255.0,
-                pixel.0[1] as f32 / 255.0,
-                pixel.0[2] as f32 / 255.0,
-            );
-            let hsv = rgb_to_hsv(rgb);
-            let value = hsv.2;
-            let value_pixel = Luma([(value * 255.0) as u8]);
-            *hsv_values_image.get_pixel_mut(x, y) = value_pixel;
-        }
+    fn composites_wallpaper_onto_walls_of_synthetic_room() {
+        let room = SyntheticRoom::new(RoomType::Type0)
+            .with_furniture()
+            .render();
+        let tile_image = RgbImage::from_fn(64, 64, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                Rgb([200, 30, 30])
+            } else {
+                Rgb([30, 30, 200])
+            }
+        });
+
+        let preview_image = create_preview(
+            room.photo.clone(),
+            room.wall_mask.clone(),
+            WallpaperMaterial::from(tile_image),
+            room.layout.wall_polygons.clone(),
+            room.layout.layout_width,
+            room.layout.layout_height,
+        );
 
-        hsv_values_image.save("./out/hsv_values.jpg").unwrap();
+        // Preview is upscaled the same way as the room image alone
+        let room_image = resize_room_image(room.photo.clone(), usize::MAX);
+        assert_eq!(preview_image.dimensions(), room_image.dimensions());
+        let (mut wall_pixels, mut changed_wall_pixels, mut changed_other_pixels) = (0, 0, 0);
+        for (x, y, preview_pixel) in preview_image.enumerate_pixels() {
+            let surface = room_surface(&room, &preview_image, x, y);
+            let is_changed = preview_pixel != room_image.get_pixel(x, y);
+            if surface.is_wall() {
+                wall_pixels += 1;
+                changed_wall_pixels += is_changed as usize;
+            } else {
+                changed_other_pixels += is_changed as usize;
+            }
+        }
+        assert!(
+            changed_wall_pixels as f32 > 0.95 * wall_pixels as f32,
+            "{changed_wall_pixels} of {wall_pixels}"
+        );
+        // Furniture, ceiling and floor are kept
+        assert_eq!(changed_other_pixels, 0);
     }
 }
@@ -0,0 +1,660 @@
+use crate::layout::lsun::Point;
+use crate::layout::RoomType;
+use crate::polygons::WallPolygon;
+
+/// Horizontal field of view assumed when focal length can not be estimated
+const DEFAULT_HORIZONTAL_FOV_DEGREES: f64 = 65.0;
+/// Focal lengths giving wider or narrower field of view are estimation errors
+const MIN_HORIZONTAL_FOV_DEGREES: f64 = 30.0;
+const MAX_HORIZONTAL_FOV_DEGREES: f64 = 120.0;
+/// Vanishing points further than this many image half-widths from the image centre are
+/// treated as lying at infinity
+const MAX_FINITE_VANISHING_POINT_DISTANCE: f64 = 100.0;
+/// Ceiling height above the floor in camera heights, used when it can not be measured
+const DEFAULT_CEILING_HEIGHT: f64 = 1.6;
+/// Distance to the walls in camera heights, used when neither floor nor ceiling is visible
+const DEFAULT_WALL_DISTANCE: f64 = 2.0;
+const JACOBI_ITERATIONS: usize = 32;
+
+/// 3D vector in camera coordinates: X to the right, Y down and Z forward.
+pub type Vector3 = [f64; 3];
+
+/// Pinhole camera looking at a Manhattan room, i.e. one whose walls, floor and ceiling are
+/// orthogonal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraCalibration {
+    /// Focal length in layout pixels
+    pub focal_length: f64,
+    /// Principal point in layout pixels, assumed to be the layout centre
+    pub principal_point: (f64, f64),
+    /// Whether focal length was estimated from vanishing points rather than assumed
+    pub is_focal_length_estimated: bool,
+    /// Room axes in camera coordinates: along the first wall, up, and along the second wall.
+    /// These are the rows of the rotation from camera to room coordinates.
+    pub axes: [Vector3; 3],
+}
+
+impl CameraCalibration {
+    pub fn up(&self) -> Vector3 {
+        self.axes[1]
+    }
+
+    /// Unit direction of the camera ray through the layout point.
+    pub fn ray(&self, point: (f64, f64)) -> Vector3 {
+        normalized([
+            point.0 - self.principal_point.0,
+            point.1 - self.principal_point.1,
+            self.focal_length,
+        ])
+    }
+
+    /// Layout point the 3D point projects to, `None` if it is not in front of the camera.
+    pub fn project(&self, point: Vector3) -> Option<(f64, f64)> {
+        (point[2] > f64::EPSILON).then(|| {
+            (
+                self.focal_length * point[0] / point[2] + self.principal_point.0,
+                self.focal_length * point[1] / point[2] + self.principal_point.1,
+            )
+        })
+    }
+
+    /// Layout point where lines of the given 3D direction converge, `None` if it lies at
+    /// infinity.
+    pub fn vanishing_point(&self, direction: Vector3) -> Option<(f64, f64)> {
+        let direction = if direction[2] < 0.0 {
+            scaled(direction, -1.0)
+        } else {
+            direction
+        };
+        self.project(direction)
+    }
+}
+
+/// Plane `normal · X + offset = 0` in camera coordinates. Normal is a unit vector pointing
+/// towards the camera, so `offset` is the distance from the camera to the plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3,
+    pub offset: f64,
+}
+
+impl Plane {
+    /// Plane with normal along `axis` through the point.
+    fn through(axis: Vector3, point: Vector3) -> Self {
+        let normal = if dot(axis, point) > 0.0 {
+            scaled(axis, -1.0)
+        } else {
+            axis
+        };
+        Self {
+            normal,
+            offset: -dot(normal, point),
+        }
+    }
+
+    /// Point where the camera ray hits the plane, `None` if it does not.
+    pub fn intersect_ray(&self, ray: Vector3) -> Option<Vector3> {
+        let denominator = dot(self.normal, ray);
+        if denominator.abs() < f64::EPSILON {
+            return None;
+        }
+        let distance = -self.offset / denominator;
+        (distance > 0.0).then(|| scaled(ray, distance))
+    }
+}
+
+/// Camera and planes of the room, in camera coordinates scaled so that camera is one unit above
+/// the floor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomReconstruction {
+    pub camera: CameraCalibration,
+    pub floor: Plane,
+    pub ceiling: Plane,
+    /// Planes of the wall polygons, in the same order
+    pub walls: Vec<Plane>,
+}
+
+impl RoomReconstruction {
+    /// 3D point of the wall seen at the layout point.
+    pub fn wall_point(&self, wall: usize, point: (f64, f64)) -> Option<Vector3> {
+        self.walls.get(wall)?.intersect_ray(self.camera.ray(point))
+    }
+
+    /// Room coordinates of the camera coordinates point: X along the first room axis, Y up from
+    /// the floor and Z along the third room axis towards the camera, so that the coordinate
+    /// system is right-handed. Camera is at (0, 1, 0).
+    pub fn to_room(&self, point: Vector3) -> Vector3 {
+        let [first_axis, up, second_axis] = self.camera.axes;
+        [
+            dot(first_axis, point),
+            dot(up, point) + 1.0,
+            -dot(second_axis, point),
+        ]
+    }
+
+    /// Camera coordinates of the room coordinates point, see [Self::to_room].
+    pub fn to_camera(&self, point: Vector3) -> Vector3 {
+        let [first_axis, up, second_axis] = self.camera.axes;
+        add(
+            add(scaled(first_axis, point[0]), scaled(up, point[1] - 1.0)),
+            scaled(second_axis, -point[2]),
+        )
+    }
+}
+
+/// Estimates camera focal length and rotation from vanishing points of wall edges. Neighbouring
+/// walls are assumed to be orthogonal, so walls with even index are parallel to the first room
+/// axis and walls with odd index to the third one.
+pub fn calibrate_camera(
+    polygons: &[WallPolygon],
+    room_type: RoomType,
+    layout_width: u32,
+    layout_height: u32,
+) -> CameraCalibration {
+    let frame = NormalizedFrame::new(layout_width, layout_height);
+    let edges = LayoutEdges::new(polygons, room_type);
+    let horizontal_vanishing_points = edges.horizontal.map(|lines| frame.vanishing_point(&lines));
+    let vertical_vanishing_point = frame.vanishing_point(&edges.vertical);
+
+    // Directions of orthogonal vanishing points `v1` and `v2` satisfy `v1 · v2 + f^2 = 0`
+    let min_focal_length = 1.0 / (MAX_HORIZONTAL_FOV_DEGREES.to_radians() / 2.0).tan();
+    let max_focal_length = 1.0 / (MIN_HORIZONTAL_FOV_DEGREES.to_radians() / 2.0).tan();
+    let focal_length_estimates: Vec<f64> = [
+        (
+            horizontal_vanishing_points[0],
+            horizontal_vanishing_points[1],
+        ),
+        (horizontal_vanishing_points[0], vertical_vanishing_point),
+        (horizontal_vanishing_points[1], vertical_vanishing_point),
+    ]
+    .iter()
+    .filter_map(|(first, second)| {
+        let first = finite_point((*first)?)?;
+        let second = finite_point((*second)?)?;
+        let squared_focal_length = -(first.0 * second.0 + first.1 * second.1);
+        (squared_focal_length > 0.0).then(|| squared_focal_length.sqrt())
+    })
+    .filter(|focal_length| (min_focal_length..=max_focal_length).contains(focal_length))
+    .collect();
+    let is_focal_length_estimated = !focal_length_estimates.is_empty();
+    let focal_length = if is_focal_length_estimated {
+        focal_length_estimates.iter().sum::<f64>() / focal_length_estimates.len() as f64
+    } else {
+        1.0 / (DEFAULT_HORIZONTAL_FOV_DEGREES.to_radians() / 2.0).tan()
+    };
+
+    // Vanishing point (x, y, w) is the image of direction (x, y, f * w)
+    let direction = |vanishing_point: Option<Vector3>| {
+        vanishing_point.map(|[x, y, w]| normalized([x, y, focal_length * w]))
+    };
+    let first_direction = direction(horizontal_vanishing_points[0]);
+    let second_direction = direction(horizontal_vanishing_points[1]);
+
+    let up = match (
+        direction(vertical_vanishing_point),
+        first_direction,
+        second_direction,
+    ) {
+        (Some(vertical), _, _) => vertical,
+        (None, Some(first), Some(second)) => cross(first, second),
+        (None, Some(horizontal), None) | (None, None, Some(horizontal))
+            if edges.vertical.len() == 1 =>
+        {
+            // Up lies in the plane through the camera and the single vertical edge
+            let (top, bottom) = edges.vertical[0];
+            let top = frame.normalize(top);
+            let bottom = frame.normalize(bottom);
+            let edge_plane_normal = cross(
+                [top.0, top.1, focal_length],
+                [bottom.0, bottom.1, focal_length],
+            );
+            cross(edge_plane_normal, horizontal)
+        }
+        _ => [0.0, -1.0, 0.0],
+    };
+    let up = normalized(up);
+    let up = if up[1] > 0.0 { scaled(up, -1.0) } else { up };
+
+    let first_axis = match (first_direction, second_direction) {
+        (Some(first), _) => first,
+        (None, Some(second)) => cross(second, up),
+        (None, None) => [1.0, 0.0, 0.0],
+    };
+    let first_axis = normalized(sub(first_axis, scaled(up, dot(first_axis, up))));
+    let first_axis = if first_axis[0] < 0.0 {
+        scaled(first_axis, -1.0)
+    } else {
+        first_axis
+    };
+    let second_axis = cross(up, first_axis);
+
+    CameraCalibration {
+        focal_length: focal_length * frame.scale,
+        principal_point: frame.center,
+        is_focal_length_estimated,
+        axes: [first_axis, up, second_axis],
+    }
+}
+
+/// Recovers planes of the walls, floor and ceiling up to scale. Walls are placed using their
+/// floor edges, or ceiling edges if the floor is not visible, and walls without either are
+/// connected to their neighbours at the shared vertical edges.
+pub fn reconstruct_room(
+    polygons: &[WallPolygon],
+    room_type: RoomType,
+    layout_width: u32,
+    layout_height: u32,
+) -> RoomReconstruction {
+    let camera = calibrate_camera(polygons, room_type, layout_width, layout_height);
+    let up = camera.up();
+    let floor = Plane {
+        normal: up,
+        offset: 1.0,
+    };
+    let wall_axis = |wall: usize| {
+        if wall.is_multiple_of(2) {
+            camera.axes[2]
+        } else {
+            camera.axes[0]
+        }
+    };
+    let ray = |point: Point| camera.ray((point.0 as f64, point.1 as f64));
+    // Plane of the wall through points where rays through its edge corners hit the given plane
+    let wall_through_edge = |wall: usize, corners: [Point; 2], plane: &Plane| {
+        let points: Vec<Vector3> = corners
+            .iter()
+            .filter_map(|corner| plane.intersect_ray(ray(*corner)))
+            .collect();
+        if points.is_empty() {
+            return None;
+        }
+        let mean_point = scaled(
+            points.iter().fold([0.0; 3], |sum, point| add(sum, *point)),
+            1.0 / points.len() as f64,
+        );
+        Some(Plane::through(wall_axis(wall), mean_point))
+    };
+
+    let mut walls: Vec<Option<Plane>> = vec![None; polygons.len()];
+    if room_type.is_floor_visible() {
+        for (wall, polygon) in polygons.iter().enumerate() {
+            walls[wall] =
+                wall_through_edge(wall, [polygon.bottom_left, polygon.bottom_right], &floor);
+        }
+    }
+
+    // Ceiling height is measured where the ceiling edge meets the walls placed on the floor
+    let measured_ceiling_heights: Vec<f64> = if room_type.is_ceiling_visible() {
+        polygons
+            .iter()
+            .zip(&walls)
+            .filter_map(|(polygon, wall)| Some((polygon, (*wall)?)))
+            .flat_map(|(polygon, wall)| {
+                [polygon.top_left, polygon.top_right]
+                    .into_iter()
+                    .filter_map(move |corner| wall.intersect_ray(ray(corner)))
+            })
+            .map(|point| dot(up, point))
+            .filter(|height| *height > 0.0)
+            .collect()
+    } else {
+        vec![]
+    };
+    let ceiling_offset = if measured_ceiling_heights.is_empty() {
+        DEFAULT_CEILING_HEIGHT - 1.0
+    } else {
+        measured_ceiling_heights.iter().sum::<f64>() / measured_ceiling_heights.len() as f64
+    };
+    let ceiling = Plane {
+        normal: scaled(up, -1.0),
+        offset: ceiling_offset,
+    };
+
+    if room_type.is_ceiling_visible() {
+        for (wall, polygon) in polygons.iter().enumerate() {
+            if walls[wall].is_none() {
+                walls[wall] =
+                    wall_through_edge(wall, [polygon.top_left, polygon.top_right], &ceiling);
+            }
+        }
+    }
+
+    while let Some(unplaced_wall) = walls.iter().position(Option::is_none) {
+        let placed_neighbour = (0..polygons.len()).find_map(|wall| {
+            if walls[wall].is_some() {
+                return None;
+            }
+            let left = wall
+                .checked_sub(1)
+                .and_then(|left| walls[left])
+                .map(|plane| (plane, [polygons[wall].top_left, polygons[wall].bottom_left]));
+            let right = walls.get(wall + 1).copied().flatten().map(|plane| {
+                (
+                    plane,
+                    [polygons[wall].top_right, polygons[wall].bottom_right],
+                )
+            });
+            left.or(right).map(|neighbour| (wall, neighbour))
+        });
+        match placed_neighbour {
+            Some((wall, (neighbour_plane, shared_edge))) => {
+                walls[wall] = wall_through_edge(wall, shared_edge, &neighbour_plane).or(Some(
+                    Plane::through(wall_axis(wall), neighbour_plane_point(&neighbour_plane)),
+                ));
+            }
+            None => {
+                let polygon = &polygons[unplaced_wall];
+                let center = [
+                    polygon.top_left,
+                    polygon.top_right,
+                    polygon.bottom_right,
+                    polygon.bottom_left,
+                ]
+                .iter()
+                .fold((0.0, 0.0), |sum, corner| {
+                    (sum.0 + corner.0 as f64 / 4.0, sum.1 + corner.1 as f64 / 4.0)
+                });
+                let point = scaled(camera.ray(center), DEFAULT_WALL_DISTANCE);
+                walls[unplaced_wall] = Some(Plane::through(wall_axis(unplaced_wall), point));
+            }
+        }
+    }
+
+    RoomReconstruction {
+        camera,
+        floor,
+        ceiling,
+        walls: walls.into_iter().flatten().collect(),
+    }
+}
+
+/// Point of the plane closest to the camera.
+fn neighbour_plane_point(plane: &Plane) -> Vector3 {
+    scaled(plane.normal, -plane.offset)
+}
+
+/// Wall edges which are images of straight room edges.
+struct LayoutEdges {
+    /// Ceiling and floor edges of walls along the first and the third room axis
+    horizontal: [Vec<(Point, Point)>; 2],
+    /// Edges shared by neighbouring walls
+    vertical: Vec<(Point, Point)>,
+}
+
+impl LayoutEdges {
+    fn new(polygons: &[WallPolygon], room_type: RoomType) -> Self {
+        let mut horizontal: [Vec<(Point, Point)>; 2] = [vec![], vec![]];
+        for (wall, polygon) in polygons.iter().enumerate() {
+            // Edges of invisible ceiling and floor are guessed by wall polygons
+            if room_type.is_ceiling_visible() {
+                horizontal[wall % 2].push((polygon.top_left, polygon.top_right));
+            }
+            if room_type.is_floor_visible() {
+                horizontal[wall % 2].push((polygon.bottom_left, polygon.bottom_right));
+            }
+        }
+        let vertical = polygons
+            .iter()
+            .skip(1)
+            .map(|polygon| (polygon.top_left, polygon.bottom_left))
+            .collect();
+        Self {
+            horizontal,
+            vertical,
+        }
+    }
+}
+
+/// Layout coordinates centred at the principal point and scaled by the image half-width, in
+/// which vanishing points are estimated for numerical stability.
+struct NormalizedFrame {
+    center: (f64, f64),
+    scale: f64,
+}
+
+impl NormalizedFrame {
+    fn new(layout_width: u32, layout_height: u32) -> Self {
+        Self {
+            center: (layout_width as f64 / 2.0, layout_height as f64 / 2.0),
+            scale: layout_width as f64 / 2.0,
+        }
+    }
+
+    fn normalize(&self, point: Point) -> (f64, f64) {
+        (
+            (point.0 as f64 - self.center.0) / self.scale,
+            (point.1 as f64 - self.center.1) / self.scale,
+        )
+    }
+
+    /// Homogeneous point closest to all the lines in least squares sense, `None` if there are
+    /// less than two lines.
+    fn vanishing_point(&self, lines: &[(Point, Point)]) -> Option<Vector3> {
+        let lines: Vec<Vector3> = lines
+            .iter()
+            .filter(|(start, end)| start != end)
+            .map(|(start, end)| {
+                let start = self.normalize(*start);
+                let end = self.normalize(*end);
+                normalized(cross([start.0, start.1, 1.0], [end.0, end.1, 1.0]))
+            })
+            .collect();
+        if lines.len() < 2 {
+            return None;
+        }
+
+        let mut moments = [[0.0; 3]; 3];
+        for line in &lines {
+            for row in 0..3 {
+                for column in 0..3 {
+                    moments[row][column] += line[row] * line[column];
+                }
+            }
+        }
+        Some(smallest_eigenvector(moments))
+    }
+}
+
+/// Cartesian coordinates of the homogeneous point, `None` if it is too far to be finite.
+fn finite_point([x, y, w]: Vector3) -> Option<(f64, f64)> {
+    let is_finite = x.hypot(y) < MAX_FINITE_VANISHING_POINT_DISTANCE * w.abs();
+    is_finite.then(|| (x / w, y / w))
+}
+
+/// Eigenvector of the symmetric matrix with the smallest eigenvalue, found with Jacobi
+/// eigenvalue algorithm.
+pub(crate) fn smallest_eigenvector(mut matrix: [[f64; 3]; 3]) -> Vector3 {
+    let mut eigenvectors = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..JACOBI_ITERATIONS {
+        // Largest off-diagonal element is zeroed by rotation in its plane
+        let (p, q) = [(0, 1), (0, 2), (1, 2)]
+            .into_iter()
+            .max_by(|a, b| matrix[a.0][a.1].abs().total_cmp(&matrix[b.0][b.1].abs()))
+            .unwrap();
+        if matrix[p][q].abs() < f64::EPSILON {
+            break;
+        }
+        let theta = 0.5 * (2.0 * matrix[p][q]).atan2(matrix[q][q] - matrix[p][p]);
+        let (sin, cos) = theta.sin_cos();
+
+        let mut rotation = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        rotation[p][p] = cos;
+        rotation[q][q] = cos;
+        rotation[p][q] = sin;
+        rotation[q][p] = -sin;
+        matrix = multiply(transposed(rotation), multiply(matrix, rotation));
+        eigenvectors = multiply(eigenvectors, rotation);
+    }
+
+    let smallest = (0..3)
+        .min_by(|a, b| matrix[*a][*a].total_cmp(&matrix[*b][*b]))
+        .unwrap();
+    [
+        eigenvectors[0][smallest],
+        eigenvectors[1][smallest],
+        eigenvectors[2][smallest],
+    ]
+}
+
+fn multiply(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut product = [[0.0; 3]; 3];
+    for row in 0..3 {
+        for column in 0..3 {
+            product[row][column] = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    product
+}
+
+fn transposed(matrix: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut transposed = matrix;
+    for row in 0..3 {
+        for column in 0..3 {
+            transposed[row][column] = matrix[column][row];
+        }
+    }
+    transposed
+}
+
+pub(crate) fn dot(a: Vector3, b: Vector3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: Vector3, b: Vector3) -> Vector3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn add(a: Vector3, b: Vector3) -> Vector3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub(crate) fn sub(a: Vector3, b: Vector3) -> Vector3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn scaled(a: Vector3, factor: f64) -> Vector3 {
+    [a[0] * factor, a[1] * factor, a[2] * factor]
+}
+
+pub(crate) fn normalized(a: Vector3) -> Vector3 {
+    let length = dot(a, a).sqrt();
+    if length < f64::EPSILON {
+        return a;
+    }
+    scaled(a, 1.0 / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::RoomType;
+    use crate::polygons::compute_wall_polygons;
+    use crate::reconstruction::{calibrate_camera, dot, reconstruct_room, Vector3};
+
+    const FOCAL_LENGTH: f64 = 400.0;
+    const YAW_DEGREES: f64 = 18.0;
+    const PITCH_DEGREES: f64 = 8.0;
+
+    /// Room coordinates (X right, Y up, Z forward) to coordinates of the camera, which is
+    /// turned right and looks down.
+    fn to_camera(point: Vector3) -> Vector3 {
+        let (x, y, z) = (point[0], -point[1], point[2]);
+        let (yaw_sin, yaw_cos) = YAW_DEGREES.to_radians().sin_cos();
+        let (x, z) = (x * yaw_cos - z * yaw_sin, x * yaw_sin + z * yaw_cos);
+        let (pitch_sin, pitch_cos) = PITCH_DEGREES.to_radians().sin_cos();
+        let (y, z) = (y * pitch_cos - z * pitch_sin, y * pitch_sin + z * pitch_cos);
+        [x, y, z]
+    }
+
+    fn project(point: Vector3) -> (i32, i32) {
+        let point = to_camera(point);
+        (
+            (FOCAL_LENGTH * point[0] / point[2] + 256.0).round() as i32,
+            (FOCAL_LENGTH * point[1] / point[2] + 256.0).round() as i32,
+        )
+    }
+
+    /// Room type 5 layout of the corner at X = 1, Z = 3 between floor at Y = -1 and ceiling at
+    /// Y = 0.6, seen from the origin.
+    fn corner_layout() -> Vec<((i32, i32), (i32, i32))> {
+        let top_corner = project([1.0, 0.6, 3.0]);
+        let bottom_corner = project([1.0, -1.0, 3.0]);
+        vec![
+            (top_corner, project([-2.0, 0.6, 3.0])),
+            (top_corner, project([1.0, 0.6, 1.5])),
+            (top_corner, bottom_corner),
+            (bottom_corner, project([-2.0, -1.0, 3.0])),
+            (bottom_corner, project([1.0, -1.0, 1.5])),
+        ]
+    }
+
+    fn assert_parallel(actual: Vector3, expected: Vector3) {
+        let cosine = dot(actual, expected).abs();
+        assert!(cosine > 0.999, "{actual:?} is not parallel to {expected:?}");
+    }
+
+    #[test]
+    fn calibrates_camera_from_vanishing_points() {
+        let polygons = compute_wall_polygons(&corner_layout(), 512, 512, 5).unwrap();
+
+        let camera = calibrate_camera(&polygons, RoomType::Type5, 512, 512);
+
+        assert!(camera.is_focal_length_estimated);
+        assert!(
+            (camera.focal_length - FOCAL_LENGTH).abs() < 0.03 * FOCAL_LENGTH,
+            "{camera:?}"
+        );
+        assert_parallel(camera.up(), to_camera([0.0, 1.0, 0.0]));
+        assert!(dot(camera.up(), to_camera([0.0, 1.0, 0.0])) > 0.0);
+        assert_parallel(camera.axes[0], to_camera([1.0, 0.0, 0.0]));
+        assert_parallel(camera.axes[2], to_camera([0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn reconstructs_room_planes_up_to_scale() {
+        let polygons = compute_wall_polygons(&corner_layout(), 512, 512, 5).unwrap();
+
+        let room = reconstruct_room(&polygons, RoomType::Type5, 512, 512);
+
+        assert_eq!(room.walls.len(), 2);
+        assert_parallel(room.walls[0].normal, to_camera([0.0, 0.0, 1.0]));
+        assert!((room.walls[0].offset - 3.0).abs() < 0.1, "{room:?}");
+        assert_parallel(room.walls[1].normal, to_camera([1.0, 0.0, 0.0]));
+        assert!((room.walls[1].offset - 1.0).abs() < 0.05, "{room:?}");
+        assert!((room.ceiling.offset - 0.6).abs() < 0.05, "{room:?}");
+        assert_eq!(room.floor.offset, 1.0);
+        // Walls meet at the corner
+        let corner = room
+            .wall_point(
+                0,
+                (
+                    polygons[0].bottom_right.0 as f64,
+                    polygons[0].bottom_right.1 as f64,
+                ),
+            )
+            .unwrap();
+        let expected_corner = to_camera([1.0, -1.0, 3.0]);
+        for axis in 0..3 {
+            assert!(
+                (corner[axis] - expected_corner[axis]).abs() < 0.1,
+                "{corner:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn assumes_upright_camera_without_vanishing_points() {
+        let polygons = compute_wall_polygons(&[((0, 270), (511, 270))], 512, 512, 9).unwrap();
+
+        let room = reconstruct_room(&polygons, RoomType::Type9, 512, 512);
+
+        assert!(!room.camera.is_focal_length_estimated);
+        assert_parallel(room.camera.up(), [0.0, -1.0, 0.0]);
+        assert_parallel(room.walls[0].normal, [0.0, 0.0, 1.0]);
+        // Floor edge is just below the horizon, so the wall is far away
+        assert!(room.walls[0].offset > 10.0, "{room:?}");
+    }
+}
@@ -0,0 +1,550 @@
+use image::{GrayImage, ImageBuffer, Luma};
+use imageproc::gradients::{horizontal_sobel, vertical_sobel};
+
+use crate::editing::is_same_junction;
+use crate::layout::lsun::{Line, Point};
+use crate::polygons::projective::{HomogeneousLine, HomogeneousPoint};
+use crate::polygons::{compute_wall_polygons, room_topology, Corner, EdgeLine, WallPolygon};
+
+/// Half-width of the band searched around each line, in layout pixels
+const SEARCH_BAND_LAYOUT_PIXELS: f64 = 4.0;
+/// Share of the line length at both ends which is not sampled, since other edges meet there
+const LINE_END_MARGIN: f64 = 0.1;
+/// Lines are sampled every this many image pixels, but at most [MAX_LINE_SAMPLES] times
+const LINE_SAMPLE_STEP: f64 = 2.0;
+const MAX_LINE_SAMPLES: usize = 256;
+/// At least this share of line samples should be within the image
+const MIN_SAMPLES_IN_IMAGE_SHARE: f64 = 0.5;
+/// Gradient should be within this angle from the line normal to belong to the line edge
+const MAX_GRADIENT_ANGLE_DEGREES: f64 = 20.0;
+/// Mean Sobel response along the edge which the line is snapped to
+const MIN_EDGE_STRENGTH: f64 = 40.0;
+
+type ImagePoint = (f64, f64);
+
+/// Moves layout lines estimated at layout resolution onto the strongest straight edges of the
+/// full resolution room image nearby, keeping lines meeting at junctions connected. Refinement
+/// of a line is dropped if it makes lines, which should converge to a vanishing point outside
+/// the walls, meet within a wall.
+///
+/// Returned lines are in room image coordinates, i.e. layout size becomes the room image size.
+pub fn refine_layout_lines(
+    room_image: &GrayImage,
+    lines: &[Line],
+    room_type: u8,
+    layout_width: u32,
+    layout_height: u32,
+) -> Vec<Line> {
+    let (width, height) = room_image.dimensions();
+    let scale_x = width as f64 / layout_width as f64;
+    let scale_y = height as f64 / layout_height as f64;
+    let scaled_lines: Vec<(ImagePoint, ImagePoint)> = lines
+        .iter()
+        .map(|(start, end)| {
+            (
+                (start.0 as f64 * scale_x, start.1 as f64 * scale_y),
+                (end.0 as f64 * scale_x, end.1 as f64 * scale_y),
+            )
+        })
+        .collect();
+
+    let gradients = Gradients::new(room_image);
+    let band = SEARCH_BAND_LAYOUT_PIXELS * scale_x.max(scale_y);
+    let mut snapped_lines: Vec<Option<SnappedLine>> = scaled_lines
+        .iter()
+        .map(|line| snap_line(&gradients, *line, band))
+        .collect();
+
+    let rounded_lines: Vec<Line> = lines
+        .iter()
+        .map(|(start, end)| {
+            (
+                scale_point(*start, scale_x, scale_y),
+                scale_point(*end, scale_x, scale_y),
+            )
+        })
+        .collect();
+    // Lines which do not fit the room type are refined without vanishing constraints
+    let scaled_polygons =
+        compute_wall_polygons(&rounded_lines, width as i32, height as i32, room_type)
+            .unwrap_or_default();
+    enforce_vanishing_constraints(
+        &scaled_lines,
+        &mut snapped_lines,
+        &vanishing_constraints(room_type),
+        &scaled_polygons,
+    );
+
+    let infinite_lines: Vec<HomogeneousLine> = scaled_lines
+        .iter()
+        .zip(&snapped_lines)
+        .map(|(line, snapped)| match snapped {
+            Some(snapped) => through(snapped.start, snapped.end),
+            None => through(line.0, line.1),
+        })
+        .collect();
+    lines
+        .iter()
+        .enumerate()
+        .map(|(line_idx, (start, end))| {
+            let start = junction_point(
+                lines,
+                &infinite_lines,
+                line_idx,
+                *start,
+                scaled_lines[line_idx].0,
+            );
+            let end = junction_point(
+                lines,
+                &infinite_lines,
+                line_idx,
+                *end,
+                scaled_lines[line_idx].1,
+            );
+            (start, end)
+        })
+        .collect()
+}
+
+/// Line moved onto an image edge.
+#[derive(Clone, Copy, Debug)]
+struct SnappedLine {
+    start: ImagePoint,
+    end: ImagePoint,
+    /// How much stronger the edge is than the image response along the original line
+    gain: f64,
+}
+
+/// Sobel gradients of the room image.
+pub(crate) struct Gradients {
+    horizontal: ImageBuffer<Luma<i16>, Vec<i16>>,
+    vertical: ImageBuffer<Luma<i16>, Vec<i16>>,
+}
+
+impl Gradients {
+    pub(crate) fn new(image: &GrayImage) -> Self {
+        Self {
+            horizontal: horizontal_sobel(image),
+            vertical: vertical_sobel(image),
+        }
+    }
+
+    /// Gradient at the pixel closest to the point, `None` outside the image.
+    pub(crate) fn at(&self, point: ImagePoint) -> Option<(f64, f64)> {
+        let (x, y) = (point.0.round(), point.1.round());
+        if x < 0.0
+            || y < 0.0
+            || x >= self.horizontal.width() as f64
+            || y >= self.horizontal.height() as f64
+        {
+            return None;
+        }
+        let (x, y) = (x as u32, y as u32);
+        Some((
+            self.horizontal.get_pixel(x, y)[0] as f64,
+            self.vertical.get_pixel(x, y)[0] as f64,
+        ))
+    }
+}
+
+/// Mean gradient across the segment over samples whose gradient is orthogonal to it. Signed
+/// responses are summed, so that only edges between consistently brighter and darker sides
+/// are strong. `None` if the segment is mostly outside the image.
+fn edge_response(gradients: &Gradients, start: ImagePoint, end: ImagePoint) -> Option<f64> {
+    let line = through(start, end);
+    let length = (end.0 - start.0).hypot(end.1 - start.1);
+    let num_samples = ((length / LINE_SAMPLE_STEP) as usize).clamp(2, MAX_LINE_SAMPLES);
+    let min_alignment = MAX_GRADIENT_ANGLE_DEGREES.to_radians().cos();
+
+    let mut response = 0.0;
+    let mut num_samples_in_image = 0;
+    for sample_idx in 0..num_samples {
+        let t = LINE_END_MARGIN
+            + (1.0 - 2.0 * LINE_END_MARGIN) * sample_idx as f64 / (num_samples - 1) as f64;
+        let sample = (
+            start.0 + t * (end.0 - start.0),
+            start.1 + t * (end.1 - start.1),
+        );
+        let Some((gradient_x, gradient_y)) = gradients.at(sample) else {
+            continue;
+        };
+        num_samples_in_image += 1;
+        let across = gradient_x * line.a + gradient_y * line.b;
+        let magnitude = gradient_x.hypot(gradient_y);
+        if magnitude > 0.0 && across.abs() >= min_alignment * magnitude {
+            response += across;
+        }
+    }
+
+    let min_samples = (MIN_SAMPLES_IN_IMAGE_SHARE * num_samples as f64).ceil() as usize;
+    (num_samples_in_image >= min_samples.max(1))
+        .then(|| (response / num_samples_in_image as f64).abs())
+}
+
+/// Strongest edge within `band` pixels from both line ends, `None` if there is no edge.
+fn snap_line(
+    gradients: &Gradients,
+    (start, end): (ImagePoint, ImagePoint),
+    band: f64,
+) -> Option<SnappedLine> {
+    let line = through(start, end);
+    let original_response = edge_response(gradients, start, end)?;
+    let shifted = |point: ImagePoint, offset: i32| {
+        (
+            point.0 + offset as f64 * line.a,
+            point.1 + offset as f64 * line.b,
+        )
+    };
+
+    let max_offset = band.ceil() as i32;
+    let mut best: Option<(ImagePoint, ImagePoint, f64)> = None;
+    for start_offset in -max_offset..=max_offset {
+        for end_offset in -max_offset..=max_offset {
+            let candidate_start = shifted(start, start_offset);
+            let candidate_end = shifted(end, end_offset);
+            let Some(response) = edge_response(gradients, candidate_start, candidate_end) else {
+                continue;
+            };
+            if best.is_none_or(|(_, _, best_response)| response > best_response) {
+                best = Some((candidate_start, candidate_end, response));
+            }
+        }
+    }
+
+    let (start, end, response) = best?;
+    (response >= MIN_EDGE_STRENGTH).then_some(SnappedLine {
+        start,
+        end,
+        gain: response - original_response,
+    })
+}
+
+/// Pair of lines which converge to a vanishing point outside of the walls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VanishingConstraint {
+    /// Ceiling and floor edges of the wall meet beyond its left or right side
+    CeilingFloor {
+        ceiling: usize,
+        floor: usize,
+        wall: usize,
+    },
+    /// Vertical edges between walls meet above or below the walls
+    Vertical(usize, usize),
+}
+
+fn vanishing_constraints(room_type: u8) -> Vec<VanishingConstraint> {
+    let Some(topology) = room_topology(room_type) else {
+        return vec![];
+    };
+    let detected_line = |corners: [Corner; 2]| {
+        corners.iter().find_map(|corner| match corner {
+            Corner::Meet(EdgeLine::Detected(line), _) => Some(*line),
+            _ => None,
+        })
+    };
+
+    let mut constraints = vec![];
+    let mut vertical_lines: Vec<usize> = vec![];
+    for (wall_idx, wall) in topology.walls.iter().enumerate() {
+        let ceiling = detected_line([wall.top_left, wall.top_right]);
+        let floor = detected_line([wall.bottom_left, wall.bottom_right]);
+        if let (Some(ceiling), Some(floor)) = (ceiling, floor) {
+            constraints.push(VanishingConstraint::CeilingFloor {
+                ceiling,
+                floor,
+                wall: wall_idx,
+            });
+        }
+
+        for (top, bottom) in [
+            (wall.top_left, wall.bottom_left),
+            (wall.top_right, wall.bottom_right),
+        ] {
+            if let (Corner::Endpoint(top_line, _), Corner::Endpoint(bottom_line, _)) = (top, bottom)
+            {
+                if top_line == bottom_line && !vertical_lines.contains(&top_line) {
+                    vertical_lines.push(top_line);
+                }
+            }
+        }
+    }
+    for (idx, first) in vertical_lines.iter().enumerate() {
+        for second in &vertical_lines[idx + 1..] {
+            constraints.push(VanishingConstraint::Vertical(*first, *second));
+        }
+    }
+    constraints
+}
+
+/// Drops refinement of lines, with the smaller gain first, until all constraints hold.
+fn enforce_vanishing_constraints(
+    lines: &[(ImagePoint, ImagePoint)],
+    snapped_lines: &mut [Option<SnappedLine>],
+    constraints: &[VanishingConstraint],
+    polygons: &[WallPolygon],
+) {
+    loop {
+        let current_line = |line_idx: usize| match snapped_lines[line_idx] {
+            Some(snapped) => (snapped.start, snapped.end),
+            None => lines[line_idx],
+        };
+        let violated = constraints.iter().find_map(|constraint| {
+            let (first, second) = match *constraint {
+                VanishingConstraint::CeilingFloor { ceiling, floor, .. } => (ceiling, floor),
+                VanishingConstraint::Vertical(first, second) => (first, second),
+            };
+            let holds = |first_line, second_line| {
+                converges_outside(constraint, first_line, second_line, polygons)
+            };
+            // Constraints not satisfied by the estimated layout can not be enforced
+            let is_violated = holds(lines[first], lines[second])
+                && !holds(current_line(first), current_line(second));
+            is_violated.then_some((first, second))
+        });
+        let Some((first, second)) = violated else {
+            return;
+        };
+
+        let gain = |line_idx: usize| snapped_lines[line_idx].map_or(f64::INFINITY, |s| s.gain);
+        let reverted = if gain(first) <= gain(second) {
+            first
+        } else {
+            second
+        };
+        println!("Dropping refinement of layout line {reverted}, which breaks {violated:?}");
+        snapped_lines[reverted] = None;
+    }
+}
+
+fn converges_outside(
+    constraint: &VanishingConstraint,
+    first: (ImagePoint, ImagePoint),
+    second: (ImagePoint, ImagePoint),
+    polygons: &[WallPolygon],
+) -> bool {
+    let vanishing_point = through(first.0, first.1)
+        .intersection(&through(second.0, second.1))
+        .and_then(HomogeneousPoint::to_cartesian);
+    // Parallel lines meet at infinity
+    let Some((x, y)) = vanishing_point else {
+        return true;
+    };
+
+    match *constraint {
+        VanishingConstraint::CeilingFloor { wall, .. } => {
+            let Some(wall) = polygons.get(wall) else {
+                return true;
+            };
+            let corners = [
+                wall.top_left,
+                wall.top_right,
+                wall.bottom_right,
+                wall.bottom_left,
+            ];
+            let min_x = corners.iter().map(|corner| corner.0).min().unwrap() as f64;
+            let max_x = corners.iter().map(|corner| corner.0).max().unwrap() as f64;
+            x <= min_x || x >= max_x
+        }
+        VanishingConstraint::Vertical(..) => {
+            let ys = [first.0 .1, first.1 .1, second.0 .1, second.1 .1];
+            let min_y = ys.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max_y = ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            y <= min_y || y >= max_y
+        }
+    }
+}
+
+/// Refined position of the line end: the point closest to all refined lines meeting at the
+/// junction, or projection onto the refined line if no other line ends there.
+fn junction_point(
+    lines: &[Line],
+    infinite_lines: &[HomogeneousLine],
+    line_idx: usize,
+    layout_point: Point,
+    image_point: ImagePoint,
+) -> Point {
+    let junction_lines: Vec<&HomogeneousLine> = lines
+        .iter()
+        .zip(infinite_lines)
+        .filter(|((start, end), _)| {
+            is_same_junction(*start, layout_point) || is_same_junction(*end, layout_point)
+        })
+        .map(|(_, line)| line)
+        .collect();
+
+    let point = if junction_lines.len() >= 2 {
+        closest_point(&junction_lines)
+    } else {
+        None
+    };
+    let point = point.unwrap_or_else(|| {
+        infinite_lines[line_idx]
+            .project(HomogeneousPoint::new(image_point.0, image_point.1))
+            .to_cartesian()
+            .unwrap()
+    });
+    (point.0.round() as i32, point.1.round() as i32)
+}
+
+/// Point with the least sum of squared distances to the lines, `None` if they are parallel.
+fn closest_point(lines: &[&HomogeneousLine]) -> Option<ImagePoint> {
+    // Normal equations of minimizing sum of (a * x + b * y + c)^2
+    let (mut aa, mut ab, mut bb, mut ac, mut bc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for line in lines {
+        aa += line.a * line.a;
+        ab += line.a * line.b;
+        bb += line.b * line.b;
+        ac += line.a * line.c;
+        bc += line.b * line.c;
+    }
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < f64::EPSILON {
+        return None;
+    }
+    Some((
+        (ab * bc - bb * ac) / determinant,
+        (ab * ac - aa * bc) / determinant,
+    ))
+}
+
+fn through(start: ImagePoint, end: ImagePoint) -> HomogeneousLine {
+    HomogeneousLine::through(
+        HomogeneousPoint::new(start.0, start.1),
+        HomogeneousPoint::new(end.0, end.1),
+    )
+}
+
+fn scale_point(point: Point, scale_x: f64, scale_y: f64) -> Point {
+    (
+        (point.0 as f64 * scale_x).round() as i32,
+        (point.1 as f64 * scale_y).round() as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::refinement::{
+        enforce_vanishing_constraints, refine_layout_lines, SnappedLine, VanishingConstraint,
+    };
+    use image::{GrayImage, Luma};
+    use imageproc::drawing::draw_polygon_mut;
+    use imageproc::point::Point;
+
+    fn fill(image: &mut GrayImage, polygon: &[(i32, i32)], value: u8) {
+        let polygon: Vec<Point<i32>> = polygon.iter().map(|(x, y)| Point::new(*x, *y)).collect();
+        draw_polygon_mut(image, &polygon, Luma([value]));
+    }
+
+    /// Room with two walls meeting at the vertical edge from (600, 330) to (612, 690).
+    fn two_walls_image() -> GrayImage {
+        let mut image = GrayImage::from_pixel(1024, 1024, Luma([150]));
+        fill(
+            &mut image,
+            &[(600, 330), (1023, 170), (1023, 820), (612, 690)],
+            100,
+        );
+        fill(
+            &mut image,
+            &[(0, 0), (1023, 0), (1023, 170), (600, 330), (0, 120)],
+            230,
+        );
+        fill(
+            &mut image,
+            &[(0, 980), (612, 690), (1023, 820), (1023, 1023), (0, 1023)],
+            60,
+        );
+        image
+    }
+
+    fn assert_near(actual: (i32, i32), expected: (i32, i32), tolerance: i32) {
+        assert!(
+            (actual.0 - expected.0).abs() <= tolerance
+                && (actual.1 - expected.1).abs() <= tolerance,
+            "{actual:?} is not near {expected:?}"
+        );
+    }
+
+    #[test]
+    fn snaps_lines_to_image_edges() {
+        let mut image = GrayImage::from_pixel(1024, 1024, Luma([120]));
+        fill(&mut image, &[(0, 0), (1023, 0), (1023, 261), (0, 210)], 230);
+        fill(
+            &mut image,
+            &[(0, 820), (1023, 820), (1023, 1023), (0, 1023)],
+            60,
+        );
+        // Estimated lines are a couple of layout pixels off
+        let lines = vec![((0, 103), (511, 128)), ((0, 412), (511, 408))];
+
+        let refined_lines = refine_layout_lines(&image, &lines, 6, 512, 512);
+
+        assert_near(refined_lines[0].0, (0, 210), 2);
+        assert_near(refined_lines[0].1, (1022, 261), 2);
+        assert_near(refined_lines[1].0, (0, 820), 2);
+        assert_near(refined_lines[1].1, (1022, 820), 2);
+    }
+
+    #[test]
+    fn keeps_junctions_connected() {
+        let lines = vec![
+            ((302, 167), (0, 58)),
+            ((302, 167), (511, 87)),
+            ((302, 167), (308, 343)),
+            ((308, 343), (0, 488)),
+            ((308, 343), (511, 409)),
+        ];
+
+        let refined_lines = refine_layout_lines(&two_walls_image(), &lines, 5, 512, 512);
+
+        assert_eq!(refined_lines[0].0, refined_lines[1].0);
+        assert_eq!(refined_lines[0].0, refined_lines[2].0);
+        assert_eq!(refined_lines[2].1, refined_lines[3].0);
+        assert_eq!(refined_lines[2].1, refined_lines[4].0);
+        assert_near(refined_lines[2].0, (600, 330), 3);
+        assert_near(refined_lines[2].1, (612, 690), 3);
+    }
+
+    #[test]
+    fn keeps_lines_without_nearby_edges() {
+        let image = GrayImage::from_pixel(1024, 1024, Luma([120]));
+        let lines = vec![((0, 103), (511, 128)), ((0, 412), (511, 408))];
+
+        let refined_lines = refine_layout_lines(&image, &lines, 6, 512, 512);
+
+        assert_eq!(
+            refined_lines,
+            vec![((0, 206), (1022, 256)), ((0, 824), (1022, 816))]
+        );
+    }
+
+    #[test]
+    fn drops_refinement_making_vertical_edges_cross_between_walls() {
+        let lines = vec![
+            ((100.0, 100.0), (100.0, 400.0)),
+            ((200.0, 100.0), (200.0, 400.0)),
+        ];
+        let mut snapped_lines = vec![
+            // Strong edge which crosses the other vertical edge
+            Some(SnappedLine {
+                start: (100.0, 100.0),
+                end: (300.0, 400.0),
+                gain: 10.0,
+            }),
+            Some(SnappedLine {
+                start: (202.0, 100.0),
+                end: (201.0, 400.0),
+                gain: 50.0,
+            }),
+        ];
+
+        enforce_vanishing_constraints(
+            &lines,
+            &mut snapped_lines,
+            &[VanishingConstraint::Vertical(0, 1)],
+            &[],
+        );
+
+        assert!(snapped_lines[0].is_none());
+        assert!(snapped_lines[1].is_some());
+    }
+}
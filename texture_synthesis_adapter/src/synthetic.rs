@@ -0,0 +1,642 @@
+//! Procedurally rendered box rooms of every LSUN room type with exact layout, wall mask and
+//! camera, so that tests do not depend on photos and layout estimation results.
+//!
+//! Room coordinates are in meters: X to the right, Y up and Z away from the camera, with the
+//! floor at Y = 0 and the room spanning from the origin to its size.
+
+use image::{GrayImage, Luma, Rgb, RgbImage};
+
+use crate::layout::lsun::{Line, Point};
+use crate::layout::{RoomLayout, RoomType};
+use crate::polygons::WallPolygon;
+use crate::preview::WALL_PIXEL;
+use crate::reconstruction::{add, cross, dot, normalized, scaled, sub, Vector3};
+
+/// Points closer than this to the camera plane are clipped
+const NEAR_PLANE_DISTANCE: f64 = 1e-3;
+/// Share of the light surfaces get even when facing away from the ceiling light
+const AMBIENT_LIGHT: f64 = 0.35;
+/// Distance in meters at which the ceiling light is half as bright
+const LIGHT_FALLOFF_DISTANCE: f64 = 4.0;
+/// Ceiling light hangs this share of the room height above the floor
+const LIGHT_HEIGHT_SHARE: f64 = 0.8;
+/// Visible part of a wall is first looked for at this many places along the wall
+const WALL_SAMPLES: usize = 256;
+const BISECTION_STEPS: usize = 24;
+const WALL_COLOR: [f64; 3] = [210.0, 196.0, 170.0];
+const CEILING_COLOR: [f64; 3] = [245.0, 245.0, 240.0];
+const FLOOR_COLOR: [f64; 3] = [140.0, 100.0, 70.0];
+const FURNITURE_COLOR: [f64; 3] = [60.0, 90.0, 130.0];
+const NOT_WALL_PIXEL: Luma<u8> = Luma([0]);
+/// Size of the layout estimation output, which synthetic rooms are rendered at by default
+pub(crate) const SYNTHETIC_IMAGE_SIZE: u32 = 512;
+
+/// Surface of the room a camera ray hits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Surface {
+    Ceiling,
+    Floor,
+    /// Wall at X = 0
+    LeftWall,
+    /// Wall at Z = depth
+    FrontWall,
+    /// Wall at X = width
+    RightWall,
+    /// Wall at Z = 0
+    BackWall,
+    Furniture,
+}
+
+impl Surface {
+    pub(crate) fn is_wall(&self) -> bool {
+        matches!(
+            self,
+            Surface::LeftWall | Surface::FrontWall | Surface::RightWall | Surface::BackWall
+        )
+    }
+}
+
+/// Pinhole camera without lens distortion and with the principal point in the image centre.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Camera {
+    pub position: Vector3,
+    /// Turn from the Z axis towards the X axis
+    pub yaw_degrees: f64,
+    /// Tilt up from horizontal
+    pub pitch_degrees: f64,
+    pub horizontal_fov_degrees: f64,
+}
+
+/// Box standing in the room, e.g. a cabinet, which occludes walls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Furniture {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+/// Box shaped room seen by a camera posed so that exactly the surfaces of the room type are
+/// visible. Surfaces are flat shaded and lit by a ceiling light, so that they have gradients
+/// like in photos.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SyntheticRoom {
+    pub room_type: RoomType,
+    /// Width (X), height (Y) and depth (Z) of the room
+    pub size: Vector3,
+    pub camera: Camera,
+    pub furniture: Vec<Furniture>,
+    pub image_width: u32,
+    pub image_height: u32,
+}
+
+/// Rendered room with its exact layout in image coordinates.
+pub(crate) struct RenderedRoom {
+    pub photo: RgbImage,
+    /// Visible wall pixels are [WALL_PIXEL], the rest are black
+    pub wall_mask: GrayImage,
+    /// Layout lines in LSUN order and wall polygons from left to right
+    pub layout: RoomLayout,
+    /// Surface seen at each pixel, row by row
+    pub surfaces: Vec<Surface>,
+}
+
+impl SyntheticRoom {
+    /// Empty room of the given type, rendered at the layout estimation output size.
+    pub(crate) fn new(room_type: RoomType) -> Self {
+        let camera =
+            |position: Vector3, yaw_degrees, pitch_degrees, horizontal_fov_degrees| Camera {
+                position,
+                yaw_degrees,
+                pitch_degrees,
+                horizontal_fov_degrees,
+            };
+        // Frontal views look at the front wall from the back of the room, corner views look at
+        // the front left corner from the middle of the room
+        let (size, camera) = match room_type {
+            RoomType::Type0 => ([4.0, 2.6, 5.0], camera([2.0, 1.3, 0.3], 4.0, 0.0, 90.0)),
+            RoomType::Type1 => ([4.0, 2.6, 5.0], camera([2.0, 1.3, 0.3], 3.0, -20.0, 60.0)),
+            RoomType::Type2 => ([4.0, 2.6, 5.0], camera([2.0, 1.3, 0.3], -3.0, 20.0, 60.0)),
+            RoomType::Type3 => ([5.0, 2.6, 5.0], camera([2.5, 1.3, 2.5], -45.0, 25.0, 60.0)),
+            RoomType::Type4 => ([5.0, 2.6, 5.0], camera([2.5, 1.3, 2.5], -45.0, -25.0, 60.0)),
+            RoomType::Type5 => ([5.0, 2.6, 5.0], camera([2.5, 1.3, 2.5], -40.0, 0.0, 60.0)),
+            RoomType::Type6 => ([6.0, 2.6, 5.0], camera([3.0, 1.3, 0.2], 5.0, 0.0, 40.0)),
+            RoomType::Type7 => ([3.0, 4.4, 5.0], camera([1.5, 2.2, 0.5], 2.0, 0.0, 44.0)),
+            RoomType::Type8 => ([6.0, 2.6, 5.0], camera([3.0, 1.3, 0.2], 5.0, 10.0, 40.0)),
+            RoomType::Type9 => ([6.0, 2.6, 5.0], camera([3.0, 1.3, 0.2], -5.0, -10.0, 40.0)),
+            RoomType::Type10 => ([5.0, 4.4, 5.0], camera([2.5, 2.2, 2.5], -45.0, 0.0, 40.0)),
+        };
+        Self {
+            room_type,
+            size,
+            camera,
+            furniture: vec![],
+            image_width: SYNTHETIC_IMAGE_SIZE,
+            image_height: SYNTHETIC_IMAGE_SIZE,
+        }
+    }
+
+    /// Adds a cabinet against the wall the camera looks at.
+    pub(crate) fn with_furniture(mut self) -> Self {
+        let [width, _, depth] = self.size;
+        let cabinet = if self.is_corner_view() {
+            Furniture {
+                min: [0.0, 0.0, depth - 1.6],
+                max: [0.5, 1.0, depth - 0.9],
+            }
+        } else {
+            Furniture {
+                min: [width * 0.35, 0.0, depth - 0.6],
+                max: [width * 0.65, 1.4, depth],
+            }
+        };
+        self.furniture.push(cabinet);
+        self
+    }
+
+    fn is_corner_view(&self) -> bool {
+        matches!(
+            self.room_type,
+            RoomType::Type3 | RoomType::Type4 | RoomType::Type5 | RoomType::Type10
+        )
+    }
+
+    pub(crate) fn render(&self) -> RenderedRoom {
+        let view = View::new(&self.camera, self.image_width, self.image_height);
+        let mut photo = RgbImage::new(self.image_width, self.image_height);
+        let mut wall_mask = GrayImage::new(self.image_width, self.image_height);
+        let mut surfaces = Vec::with_capacity((self.image_width * self.image_height) as usize);
+        for y in 0..self.image_height {
+            for x in 0..self.image_width {
+                let ray = view.ray((x as f64 + 0.5, y as f64 + 0.5));
+                let (surface, distance, normal) = self.trace(ray);
+                let point = add(self.camera.position, scaled(ray, distance));
+                let color = match surface {
+                    Surface::Ceiling => CEILING_COLOR,
+                    Surface::Floor => FLOOR_COLOR,
+                    Surface::Furniture => FURNITURE_COLOR,
+                    _ => WALL_COLOR,
+                };
+                photo.put_pixel(x, y, self.shade(point, normal, color));
+                let mask_pixel = if surface.is_wall() {
+                    WALL_PIXEL
+                } else {
+                    NOT_WALL_PIXEL
+                };
+                wall_mask.put_pixel(x, y, mask_pixel);
+                surfaces.push(surface);
+            }
+        }
+
+        let layout = RoomLayout {
+            room_type: self.room_type,
+            lines: self.layout_lines(&view),
+            wall_polygons: self.wall_polygons(&view),
+            layout_width: self.image_width,
+            layout_height: self.image_height,
+            confidence: None,
+            issues: None,
+        };
+        RenderedRoom {
+            photo,
+            wall_mask,
+            layout,
+            surfaces,
+        }
+    }
+
+    /// Surface the ray from the camera hits first, distance to it and its normal.
+    fn trace(&self, ray: Vector3) -> (Surface, f64, Vector3) {
+        let origin = self.camera.position;
+        let mut hit = (Surface::Floor, f64::MAX, [0.0, 1.0, 0.0]);
+        for axis in 0..3 {
+            if ray[axis].abs() < f64::EPSILON {
+                continue;
+            }
+            let bound = if ray[axis] > 0.0 {
+                self.size[axis]
+            } else {
+                0.0
+            };
+            let distance = (bound - origin[axis]) / ray[axis];
+            if distance >= hit.1 {
+                continue;
+            }
+            let mut normal = [0.0; 3];
+            normal[axis] = -ray[axis].signum();
+            let surface = match (axis, ray[axis] > 0.0) {
+                (0, false) => Surface::LeftWall,
+                (0, true) => Surface::RightWall,
+                (1, false) => Surface::Floor,
+                (1, true) => Surface::Ceiling,
+                (_, true) => Surface::FrontWall,
+                (_, false) => Surface::BackWall,
+            };
+            hit = (surface, distance, normal);
+        }
+
+        for furniture in &self.furniture {
+            if let Some((distance, normal)) = intersect_box(origin, ray, furniture) {
+                if distance < hit.1 {
+                    hit = (Surface::Furniture, distance, normal);
+                }
+            }
+        }
+        hit
+    }
+
+    fn shade(&self, point: Vector3, normal: Vector3, color: [f64; 3]) -> Rgb<u8> {
+        let light = [
+            self.size[0] / 2.0,
+            self.size[1] * LIGHT_HEIGHT_SHARE,
+            self.size[2] / 2.0,
+        ];
+        let to_light = sub(light, point);
+        let distance = dot(to_light, to_light).sqrt();
+        let diffuse = dot(normal, normalized(to_light)).max(0.0)
+            / (1.0 + (distance / LIGHT_FALLOFF_DISTANCE).powi(2));
+        let intensity = AMBIENT_LIGHT + (1.0 - AMBIENT_LIGHT) * diffuse;
+        Rgb(color.map(|channel| (channel * intensity).round().clamp(0.0, 255.0) as u8))
+    }
+
+    /// Room corners, named by the walls they are at: front or back, left or right, top or bottom.
+    fn corner(&self, is_right: bool, is_top: bool, is_front: bool) -> Vector3 {
+        [
+            if is_right { self.size[0] } else { 0.0 },
+            if is_top { self.size[1] } else { 0.0 },
+            if is_front { self.size[2] } else { 0.0 },
+        ]
+    }
+
+    /// Visible parts of room edges in the order LSUN results parser returns them.
+    fn layout_lines(&self, view: &View) -> Vec<Line> {
+        let flt = self.corner(false, true, true);
+        let flb = self.corner(false, false, true);
+        let frt = self.corner(true, true, true);
+        let frb = self.corner(true, false, true);
+        let blt = self.corner(false, true, false);
+        let blb = self.corner(false, false, false);
+        let brt = self.corner(true, true, false);
+        let brb = self.corner(true, false, false);
+        let edges = match self.room_type {
+            RoomType::Type0 => vec![
+                (flt, blt),
+                (flb, blb),
+                (frb, brb),
+                (frt, brt),
+                (flt, flb),
+                (flb, frb),
+                (frb, frt),
+                (frt, flt),
+            ],
+            RoomType::Type1 => vec![(flb, flt), (flb, blb), (flb, frb), (frb, frt), (frb, brb)],
+            RoomType::Type2 => vec![(flt, blt), (flt, flb), (flt, frt), (frt, brt), (frt, frb)],
+            RoomType::Type3 => vec![(flt, blt), (flt, flb), (flt, frt)],
+            RoomType::Type4 => vec![(flb, blb), (flb, flt), (flb, frb)],
+            RoomType::Type5 => vec![(flt, blt), (flt, frt), (flt, flb), (flb, blb), (flb, frb)],
+            RoomType::Type6 => vec![(flt, frt), (flb, frb)],
+            RoomType::Type7 => vec![(flb, flt), (frb, frt)],
+            RoomType::Type8 => vec![(flt, frt)],
+            RoomType::Type9 => vec![(flb, frb)],
+            RoomType::Type10 => vec![(flt, flb)],
+        };
+
+        let lines: Vec<Line> = edges
+            .into_iter()
+            .filter_map(|(start, end)| view.clip_segment(start, end))
+            .map(|(start, end)| (rounded(start), rounded(end)))
+            .collect();
+        assert_eq!(
+            lines.len(),
+            self.room_type.num_lines(),
+            "Camera does not see all the edges of {:?}",
+            self.room_type
+        );
+        lines
+    }
+
+    /// Visible parts of the walls from left to right, cut at the image sides by vertical lines
+    /// in the room.
+    fn wall_polygons(&self, view: &View) -> Vec<WallPolygon> {
+        let [width, height, depth] = self.size;
+        // Bottom start of the wall and its direction along the floor
+        let walls = [
+            ([0.0, 0.0, 0.0], [0.0, 0.0, depth]),
+            ([0.0, 0.0, depth], [width, 0.0, 0.0]),
+            ([width, 0.0, depth], [0.0, 0.0, -depth]),
+            ([width, 0.0, 0.0], [-width, 0.0, 0.0]),
+        ];
+
+        let mut polygons: Vec<(f64, WallPolygon)> = vec![];
+        for (start, direction) in walls {
+            let bottom = |position: f64| add(start, scaled(direction, position));
+            let top = |position: f64| add(bottom(position), [0.0, height, 0.0]);
+            let is_visible =
+                |position: f64| view.clip_segment(bottom(position), top(position)).is_some();
+
+            let visible: Vec<usize> = (0..=WALL_SAMPLES)
+                .filter(|sample| is_visible(*sample as f64 / WALL_SAMPLES as f64))
+                .collect();
+            let (Some(first), Some(last)) = (visible.first(), visible.last()) else {
+                continue;
+            };
+            let refine = |visible_sample: usize, step: isize| {
+                let mut inside = visible_sample as f64 / WALL_SAMPLES as f64;
+                let outside_sample = visible_sample as isize + step;
+                if !(0..=WALL_SAMPLES as isize).contains(&outside_sample) {
+                    return inside;
+                }
+                let mut outside = outside_sample as f64 / WALL_SAMPLES as f64;
+                for _ in 0..BISECTION_STEPS {
+                    let middle = (inside + outside) / 2.0;
+                    if is_visible(middle) {
+                        inside = middle;
+                    } else {
+                        outside = middle;
+                    }
+                }
+                inside
+            };
+            let (start_position, end_position) = (refine(*first, -1), refine(*last, 1));
+
+            let project = |point: Vector3| view.project(point).map(rounded);
+            let (Some(start_top), Some(start_bottom), Some(end_top), Some(end_bottom)) = (
+                project(top(start_position)),
+                project(bottom(start_position)),
+                project(top(end_position)),
+                project(bottom(end_position)),
+            ) else {
+                println!("Skipping wall crossing the camera plane: {start:?}");
+                continue;
+            };
+            let polygon = if start_top.0 + start_bottom.0 <= end_top.0 + end_bottom.0 {
+                WallPolygon {
+                    top_left: start_top,
+                    top_right: end_top,
+                    bottom_right: end_bottom,
+                    bottom_left: start_bottom,
+                }
+            } else {
+                WallPolygon {
+                    top_left: end_top,
+                    top_right: start_top,
+                    bottom_right: start_bottom,
+                    bottom_left: end_bottom,
+                }
+            };
+            let center_x = (start_top.0 + start_bottom.0 + end_top.0 + end_bottom.0) as f64 / 4.0;
+            polygons.push((center_x, polygon));
+        }
+
+        polygons.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let polygons: Vec<WallPolygon> = polygons.into_iter().map(|(_, polygon)| polygon).collect();
+        assert_eq!(
+            polygons.len(),
+            self.room_type.num_walls(),
+            "Camera does not see all the walls of {:?}",
+            self.room_type
+        );
+        polygons
+    }
+}
+
+/// Camera basis in room coordinates and intrinsics for the image size.
+struct View {
+    position: Vector3,
+    right: Vector3,
+    up: Vector3,
+    forward: Vector3,
+    focal_length: f64,
+    width: u32,
+    height: u32,
+}
+
+impl View {
+    fn new(camera: &Camera, width: u32, height: u32) -> Self {
+        let (yaw, pitch) = (
+            camera.yaw_degrees.to_radians(),
+            camera.pitch_degrees.to_radians(),
+        );
+        let forward = [
+            yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            yaw.cos() * pitch.cos(),
+        ];
+        let right = normalized(cross([0.0, 1.0, 0.0], forward));
+        let up = cross(forward, right);
+        let focal_length =
+            width as f64 / 2.0 / (camera.horizontal_fov_degrees.to_radians() / 2.0).tan();
+        Self {
+            position: camera.position,
+            right,
+            up,
+            forward,
+            focal_length,
+            width,
+            height,
+        }
+    }
+
+    /// Unit direction of the camera ray through the image point.
+    fn ray(&self, point: (f64, f64)) -> Vector3 {
+        let x = (point.0 - self.width as f64 / 2.0) / self.focal_length;
+        let y = (point.1 - self.height as f64 / 2.0) / self.focal_length;
+        normalized(add(
+            self.forward,
+            add(scaled(self.right, x), scaled(self.up, -y)),
+        ))
+    }
+
+    fn depth(&self, point: Vector3) -> f64 {
+        dot(sub(point, self.position), self.forward)
+    }
+
+    /// Image point the room point projects to, `None` if it is behind the camera.
+    fn project(&self, point: Vector3) -> Option<(f64, f64)> {
+        let depth = self.depth(point);
+        if depth < NEAR_PLANE_DISTANCE {
+            return None;
+        }
+        let offset = sub(point, self.position);
+        Some((
+            self.width as f64 / 2.0 + self.focal_length * dot(offset, self.right) / depth,
+            self.height as f64 / 2.0 - self.focal_length * dot(offset, self.up) / depth,
+        ))
+    }
+
+    /// Part of the room segment visible in the image, in image coordinates and in the same
+    /// direction.
+    fn clip_segment(&self, start: Vector3, end: Vector3) -> Option<((f64, f64), (f64, f64))> {
+        let (start_depth, end_depth) = (self.depth(start), self.depth(end));
+        if start_depth < NEAR_PLANE_DISTANCE && end_depth < NEAR_PLANE_DISTANCE {
+            return None;
+        }
+        // Just in front of the near plane, so that rounding does not put it behind
+        let on_near_plane = |behind: Vector3, behind_depth: f64, front_depth: f64, front| {
+            let share = (2.0 * NEAR_PLANE_DISTANCE - behind_depth) / (front_depth - behind_depth);
+            add(behind, scaled(sub(front, behind), share))
+        };
+        let (start, end) = if start_depth < NEAR_PLANE_DISTANCE {
+            (on_near_plane(start, start_depth, end_depth, end), end)
+        } else if end_depth < NEAR_PLANE_DISTANCE {
+            (start, on_near_plane(end, end_depth, start_depth, start))
+        } else {
+            (start, end)
+        };
+
+        clip_to_image(
+            self.project(start)?,
+            self.project(end)?,
+            (self.width - 1) as f64,
+            (self.height - 1) as f64,
+        )
+    }
+}
+
+/// Liang-Barsky clipping of the image segment to `[0, max_x] x [0, max_y]`.
+fn clip_to_image(
+    start: (f64, f64),
+    end: (f64, f64),
+    max_x: f64,
+    max_y: f64,
+) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let (mut entering, mut leaving) = (0.0f64, 1.0f64);
+    for (p, q) in [
+        (-dx, start.0),
+        (dx, max_x - start.0),
+        (-dy, start.1),
+        (dy, max_y - start.1),
+    ] {
+        if p.abs() < f64::EPSILON {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let share = q / p;
+        if p < 0.0 {
+            entering = entering.max(share);
+        } else {
+            leaving = leaving.min(share);
+        }
+    }
+    if entering > leaving {
+        return None;
+    }
+    let at = |share: f64| (start.0 + dx * share, start.1 + dy * share);
+    Some((at(entering), at(leaving)))
+}
+
+/// Distance along the ray to the box and the normal of the box side it enters through.
+fn intersect_box(origin: Vector3, ray: Vector3, furniture: &Furniture) -> Option<(f64, Vector3)> {
+    let mut entering = (f64::MIN, [0.0; 3]);
+    let mut leaving = f64::MAX;
+    for axis in 0..3 {
+        if ray[axis].abs() < f64::EPSILON {
+            if origin[axis] < furniture.min[axis] || origin[axis] > furniture.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let near_bound = if ray[axis] > 0.0 {
+            furniture.min[axis]
+        } else {
+            furniture.max[axis]
+        };
+        let far_bound = if ray[axis] > 0.0 {
+            furniture.max[axis]
+        } else {
+            furniture.min[axis]
+        };
+        let near = (near_bound - origin[axis]) / ray[axis];
+        let far = (far_bound - origin[axis]) / ray[axis];
+        if near > entering.0 {
+            let mut normal = [0.0; 3];
+            normal[axis] = -ray[axis].signum();
+            entering = (near, normal);
+        }
+        leaving = leaving.min(far);
+    }
+    (entering.0 <= leaving && entering.0 > 0.0).then_some(entering)
+}
+
+fn rounded(point: (f64, f64)) -> Point {
+    (point.0.round() as i32, point.1.round() as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{surface_labels, SURFACE_LABEL_FIRST_WALL};
+    use crate::layout::RoomType;
+    use crate::preview::WALL_PIXEL;
+    use crate::synthetic::{Surface, SyntheticRoom};
+
+    #[test]
+    fn renders_layouts_of_all_room_types() {
+        for room_type in RoomType::ALL {
+            let room = SyntheticRoom::new(room_type).render();
+            let (width, height) = room.wall_mask.dimensions();
+
+            assert_eq!(room.layout.lines.len(), room_type.num_lines());
+            assert_eq!(room.layout.wall_polygons.len(), room_type.num_walls());
+            // Room corners are inside the image
+            let ends: Vec<_> = room
+                .layout
+                .lines
+                .iter()
+                .flat_map(|(start, end)| [*start, *end])
+                .collect();
+            for end in &ends {
+                let is_shared = ends.iter().filter(|other| *other == end).count() > 1;
+                let is_inside =
+                    end.0 > 0 && end.1 > 0 && end.0 < width as i32 - 1 && end.1 < height as i32 - 1;
+                assert_eq!(is_shared, is_inside, "{room_type:?}: {end:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn wall_mask_matches_wall_polygons() {
+        for room_type in RoomType::ALL {
+            let room = SyntheticRoom::new(room_type).render();
+            let (width, height) = room.wall_mask.dimensions();
+            let labels = surface_labels(&room.layout.wall_polygons, width, height);
+
+            let mismatches = labels
+                .pixels()
+                .zip(room.wall_mask.pixels())
+                .filter(|(label, mask_pixel)| {
+                    (label.0[0] >= SURFACE_LABEL_FIRST_WALL) != (**mask_pixel == WALL_PIXEL)
+                })
+                .count();
+
+            let mismatch_share = mismatches as f32 / (width * height) as f32;
+            assert!(mismatch_share < 0.01, "{room_type:?}: {mismatch_share}");
+        }
+    }
+
+    #[test]
+    fn furniture_occludes_walls() {
+        for room_type in [RoomType::Type0, RoomType::Type5] {
+            let empty_room = SyntheticRoom::new(room_type).render();
+            let furnished_room = SyntheticRoom::new(room_type).with_furniture().render();
+
+            let furniture_pixels: Vec<usize> = (0..furnished_room.surfaces.len())
+                .filter(|idx| furnished_room.surfaces[*idx] == Surface::Furniture)
+                .collect();
+            assert!(furniture_pixels.len() > 1000, "{room_type:?}");
+            let occluded_wall_pixels = furniture_pixels
+                .iter()
+                .filter(|idx| empty_room.surfaces[**idx].is_wall())
+                .count();
+            assert!(occluded_wall_pixels > 0, "{room_type:?}");
+            assert_eq!(empty_room.layout, furnished_room.layout);
+            let wall_pixels = |mask: &image::GrayImage| {
+                mask.pixels().filter(|pixel| **pixel == WALL_PIXEL).count()
+            };
+            assert_eq!(
+                wall_pixels(&empty_room.wall_mask) - wall_pixels(&furnished_room.wall_mask),
+                occluded_wall_pixels
+            );
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use imageproc::geometric_transformations::Projection;
+
+use crate::layout::{RoomLayout, RoomType};
+use crate::mask_layout::lsun_layout_from_polygons;
+use crate::polygons::WallPolygon;
+
+/// Corner of the wall polygon in layout space
+type Corner = (f32, f32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayoutTrackerOptions {
+    /// How far corners are expected to move between frames, in layout pixels, beyond what the
+    /// homography explains
+    pub process_noise: f32,
+    /// How far estimated corners are expected to be from the true ones, in layout pixels
+    pub measurement_noise: f32,
+    /// Corners which jump further than this, in layout pixels, are reset instead of filtered, or
+    /// ignored while the room type is not trusted
+    pub reset_distance: f32,
+    /// Number of consecutive frames a new room type has to be estimated in before it is trusted
+    pub room_type_switch_frames: u32,
+    /// Number of consecutive frames a wall is kept for while it is not estimated
+    pub max_missed_frames: u32,
+    /// Walls of consecutive frames are the same wall if their horizontal spans overlap by at
+    /// least this share of their union
+    pub min_wall_overlap: f32,
+}
+
+impl Default for LayoutTrackerOptions {
+    fn default() -> Self {
+        Self {
+            process_noise: 2.0,
+            measurement_noise: 6.0,
+            reset_distance: 48.0,
+            room_type_switch_frames: 3,
+            max_missed_frames: 5,
+            min_wall_overlap: 0.3,
+        }
+    }
+}
+
+/// Wall of the tracked layout, with identity kept across frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackedWall {
+    pub id: u32,
+    pub polygon: WallPolygon,
+}
+
+/// Smoothed room layout of the current frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackedLayout {
+    /// Room type estimated in the last `room_type_switch_frames` frames
+    pub room_type: RoomType,
+    /// Walls from left to right
+    pub walls: Vec<TrackedWall>,
+    pub layout_width: u32,
+    pub layout_height: u32,
+}
+
+impl TrackedLayout {
+    pub fn wall_polygons(&self) -> Vec<WallPolygon> {
+        self.walls.iter().map(|wall| wall.polygon.clone()).collect()
+    }
+
+    /// Room layout with the smoothed walls, with room type and lines describing them instead of
+    /// the tracked room type. `None` if there are no walls.
+    pub fn room_layout(&self) -> Option<RoomLayout> {
+        let wall_polygons = self.wall_polygons();
+        let (room_type, lines) = lsun_layout_from_polygons(&wall_polygons)?;
+        Some(RoomLayout {
+            room_type: RoomType::try_from(room_type).ok()?,
+            lines,
+            wall_polygons,
+            layout_width: self.layout_width,
+            layout_height: self.layout_height,
+            confidence: None,
+            issues: None,
+        })
+    }
+}
+
+/// Corner position filtered with a scalar Kalman filter, with the same variance along both axes.
+#[derive(Clone, Copy, Debug)]
+struct CornerFilter {
+    position: Corner,
+    variance: f32,
+}
+
+impl CornerFilter {
+    fn new(position: Corner, options: &LayoutTrackerOptions) -> Self {
+        Self {
+            position,
+            variance: options.measurement_noise.powi(2),
+        }
+    }
+
+    fn predict(&mut self, homography: Option<&Projection>, options: &LayoutTrackerOptions) {
+        if let Some(homography) = homography {
+            self.position = *homography * self.position;
+        }
+        self.variance += options.process_noise.powi(2);
+    }
+
+    /// Corners jumping further than `reset_distance` are reset to the measurement if it is
+    /// trusted, and ignored otherwise.
+    fn update(&mut self, measurement: Corner, is_trusted: bool, options: &LayoutTrackerOptions) {
+        let innovation = (
+            measurement.0 - self.position.0,
+            measurement.1 - self.position.1,
+        );
+        if innovation.0.hypot(innovation.1) > options.reset_distance {
+            if is_trusted {
+                *self = Self::new(measurement, options);
+            }
+            return;
+        }
+        let gain = self.variance / (self.variance + options.measurement_noise.powi(2));
+        self.position.0 += gain * innovation.0;
+        self.position.1 += gain * innovation.1;
+        self.variance *= 1.0 - gain;
+    }
+}
+
+#[derive(Clone, Debug)]
+struct WallTrack {
+    id: u32,
+    /// Top left, top right, bottom right and bottom left corners
+    corners: [CornerFilter; 4],
+    /// Number of consecutive frames the wall was not estimated in
+    missed_frames: u32,
+    /// Whether the wall was estimated in a frame with trusted room type
+    is_confirmed: bool,
+}
+
+impl WallTrack {
+    fn positions(&self) -> [Corner; 4] {
+        self.corners.map(|corner| corner.position)
+    }
+
+    fn polygon(&self) -> WallPolygon {
+        let [top_left, top_right, bottom_right, bottom_left] = self
+            .positions()
+            .map(|(x, y)| (x.round() as i32, y.round() as i32));
+        WallPolygon {
+            top_left,
+            top_right,
+            bottom_right,
+            bottom_left,
+        }
+    }
+}
+
+/// Smooths room layouts estimated independently for each frame of the camera view or video.
+///
+/// Corners of each wall are filtered over time, optionally following the camera motion given by
+/// frame-to-frame homographies. Walls are matched between frames by their horizontal spans, so a
+/// wall keeps its id while the estimated room type flickers, and walls missing from a few frames
+/// are kept until the new room type is trusted.
+#[derive(Clone, Debug, Default)]
+pub struct LayoutTracker {
+    options: LayoutTrackerOptions,
+    tracks: Vec<WallTrack>,
+    next_id: u32,
+    /// Trusted room type and layout size
+    state: Option<(RoomType, u32, u32)>,
+    /// Room type of the last frames which differs from the trusted one, and number of frames
+    room_type_candidate: Option<(RoomType, u32)>,
+}
+
+impl LayoutTracker {
+    pub fn new(options: LayoutTrackerOptions) -> Self {
+        Self {
+            options,
+            ..Default::default()
+        }
+    }
+
+    /// Forgets tracked walls, e.g. after a cut in the video.
+    pub fn reset(&mut self) {
+        self.tracks.clear();
+        self.state = None;
+        self.room_type_candidate = None;
+    }
+
+    /// Adds the layout estimated for the next frame and returns the smoothed layout.
+    /// `homography` maps layout space of the previous frame onto layout space of this one.
+    pub fn update(
+        &mut self,
+        layout: &RoomLayout,
+        homography: Option<&Projection>,
+    ) -> TrackedLayout {
+        let options = self.options;
+        let frame_size = (layout.layout_width, layout.layout_height);
+        let room_type = match self.state {
+            Some((room_type, width, height)) if (width, height) == frame_size => room_type,
+            _ => {
+                self.reset();
+                layout.room_type
+            }
+        };
+        let room_type = self.update_room_type(room_type, layout.room_type);
+        let is_trusted = room_type == layout.room_type;
+        self.state = Some((room_type, layout.layout_width, layout.layout_height));
+
+        for track in &mut self.tracks {
+            for corner in &mut track.corners {
+                corner.predict(homography, &options);
+            }
+        }
+
+        let measured: Vec<[Corner; 4]> = layout.wall_polygons.iter().map(corners).collect();
+        let tracked: Vec<[Corner; 4]> = self.tracks.iter().map(WallTrack::positions).collect();
+        let matches = match_walls(&tracked, &measured, options.min_wall_overlap);
+
+        let mut is_matched = vec![false; self.tracks.len()];
+        // Track of each measured wall
+        let mut measured_tracks = Vec::with_capacity(measured.len());
+        for (wall_corners, track_index) in measured.iter().zip(matches) {
+            let track_index = match track_index {
+                Some(track_index) => {
+                    let track = &mut self.tracks[track_index];
+                    for (corner, measurement) in track.corners.iter_mut().zip(wall_corners) {
+                        corner.update(*measurement, is_trusted, &options);
+                    }
+                    track.missed_frames = 0;
+                    track.is_confirmed |= is_trusted;
+                    is_matched[track_index] = true;
+                    track_index
+                }
+                None => {
+                    self.tracks.push(WallTrack {
+                        id: self.next_id,
+                        corners: wall_corners.map(|corner| CornerFilter::new(corner, &options)),
+                        missed_frames: 0,
+                        is_confirmed: is_trusted,
+                    });
+                    self.next_id += 1;
+                    is_matched.push(true);
+                    self.tracks.len() - 1
+                }
+            };
+            measured_tracks.push(track_index);
+        }
+
+        // Corners shared by neighbouring walls stay shared
+        for (idx, pair) in layout.wall_polygons.windows(2).enumerate() {
+            let (left, right) = (measured_tracks[idx], measured_tracks[idx + 1]);
+            for (left_corner, right_corner, is_shared) in [
+                (1, 0, pair[0].top_right == pair[1].top_left),
+                (2, 3, pair[0].bottom_right == pair[1].bottom_left),
+            ] {
+                if !is_shared {
+                    continue;
+                }
+                let (a, b) = (
+                    self.tracks[left].corners[left_corner],
+                    self.tracks[right].corners[right_corner],
+                );
+                let welded = CornerFilter {
+                    position: (
+                        (a.position.0 + b.position.0) / 2.0,
+                        (a.position.1 + b.position.1) / 2.0,
+                    ),
+                    variance: a.variance.max(b.variance),
+                };
+                self.tracks[left].corners[left_corner] = welded;
+                self.tracks[right].corners[right_corner] = welded;
+            }
+        }
+
+        let mut tracks = std::mem::take(&mut self.tracks);
+        for (track, is_matched) in tracks.iter_mut().zip(&is_matched) {
+            if !is_matched {
+                track.missed_frames += 1;
+            }
+        }
+        // Walls missing from a trusted frame are gone
+        tracks.retain(|track| {
+            track.missed_frames == 0
+                || (!is_trusted && track.missed_frames <= options.max_missed_frames)
+        });
+        tracks.sort_by(|a, b| center_x(&a.positions()).total_cmp(&center_x(&b.positions())));
+        self.tracks = tracks;
+
+        TrackedLayout {
+            room_type,
+            walls: self
+                .tracks
+                .iter()
+                .filter(|track| track.is_confirmed)
+                .map(|track| TrackedWall {
+                    id: track.id,
+                    polygon: track.polygon(),
+                })
+                .collect(),
+            layout_width: layout.layout_width,
+            layout_height: layout.layout_height,
+        }
+    }
+
+    /// Trusted room type after the frame with `frame_room_type`.
+    fn update_room_type(&mut self, room_type: RoomType, frame_room_type: RoomType) -> RoomType {
+        if frame_room_type == room_type {
+            self.room_type_candidate = None;
+            return room_type;
+        }
+        let num_frames = match self.room_type_candidate {
+            Some((candidate, num_frames)) if candidate == frame_room_type => num_frames + 1,
+            _ => 1,
+        };
+        if num_frames >= self.options.room_type_switch_frames {
+            self.room_type_candidate = None;
+            frame_room_type
+        } else {
+            self.room_type_candidate = Some((frame_room_type, num_frames));
+            room_type
+        }
+    }
+}
+
+fn corners(polygon: &WallPolygon) -> [Corner; 4] {
+    [
+        polygon.top_left,
+        polygon.top_right,
+        polygon.bottom_right,
+        polygon.bottom_left,
+    ]
+    .map(|(x, y)| (x as f32, y as f32))
+}
+
+fn horizontal_span(corners: &[Corner; 4]) -> (f32, f32) {
+    let xs = corners.map(|corner| corner.0);
+    (
+        xs.iter().cloned().fold(f32::INFINITY, f32::min),
+        xs.iter().cloned().fold(f32::NEG_INFINITY, f32::max),
+    )
+}
+
+fn center_x(corners: &[Corner; 4]) -> f32 {
+    let (left, right) = horizontal_span(corners);
+    (left + right) / 2.0
+}
+
+/// Share of the union of horizontal spans of the walls which they both cover.
+fn horizontal_overlap(a: &[Corner; 4], b: &[Corner; 4]) -> f32 {
+    let (a_left, a_right) = horizontal_span(a);
+    let (b_left, b_right) = horizontal_span(b);
+    let intersection = (a_right.min(b_right) - a_left.max(b_left)).max(0.0);
+    let union = a_right.max(b_right) - a_left.min(b_left);
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+/// Index of the tracked wall matching each measured wall. Both are ordered from left to right,
+/// and so are the matches, which have the largest total overlap.
+fn match_walls(
+    tracked: &[[Corner; 4]],
+    measured: &[[Corner; 4]],
+    min_overlap: f32,
+) -> Vec<Option<usize>> {
+    let (n, m) = (tracked.len(), measured.len());
+    // Largest total overlap of the first `i` tracked and the first `j` measured walls
+    let mut total = vec![vec![0f32; m + 1]; n + 1];
+    for i in 1..=n {
+        for j in 1..=m {
+            let overlap = horizontal_overlap(&tracked[i - 1], &measured[j - 1]);
+            let matched = if overlap >= min_overlap {
+                total[i - 1][j - 1] + overlap
+            } else {
+                0.0
+            };
+            total[i][j] = matched.max(total[i - 1][j]).max(total[i][j - 1]);
+        }
+    }
+
+    let mut matches = vec![None; m];
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        if total[i][j] == total[i - 1][j] {
+            i -= 1;
+        } else if total[i][j] == total[i][j - 1] {
+            j -= 1;
+        } else {
+            matches[j - 1] = Some(i - 1);
+            i -= 1;
+            j -= 1;
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use imageproc::geometric_transformations::Projection;
+
+    use crate::layout::lsun::Line;
+    use crate::layout::{RoomLayout, RoomType};
+    use crate::mask_layout::lsun_layout_from_polygons;
+    use crate::tracking::{LayoutTracker, LayoutTrackerOptions, TrackedLayout};
+
+    const LAYOUT_SIZE: u32 = 512;
+
+    /// Room type 0 layout, shifted by `dx` and `dy`
+    fn room_type_0_layout(dx: i32, dy: i32) -> RoomLayout {
+        let lines: Vec<Line> = vec![
+            ((160, 140), (0, 60)),
+            ((160, 380), (0, 470)),
+            ((360, 370), (512, 480)),
+            ((360, 150), (512, 70)),
+            ((160, 140), (160, 380)),
+            ((160, 380), (360, 370)),
+            ((360, 370), (360, 150)),
+            ((360, 150), (160, 140)),
+        ];
+        let lines = lines
+            .into_iter()
+            .map(|(start, end)| ((start.0 + dx, start.1 + dy), (end.0 + dx, end.1 + dy)))
+            .collect();
+        RoomLayout::from_lines(RoomType::Type0, lines, LAYOUT_SIZE, LAYOUT_SIZE).unwrap()
+    }
+
+    /// Same room with the left wall missing, as room type 5
+    fn without_left_wall(layout: &RoomLayout) -> RoomLayout {
+        let (room_type, lines) = lsun_layout_from_polygons(&layout.wall_polygons[1..]).unwrap();
+        RoomLayout::from_lines(
+            RoomType::try_from(room_type).unwrap(),
+            lines,
+            LAYOUT_SIZE,
+            LAYOUT_SIZE,
+        )
+        .unwrap()
+    }
+
+    fn ids(layout: &TrackedLayout) -> Vec<u32> {
+        layout.walls.iter().map(|wall| wall.id).collect()
+    }
+
+    /// Largest distance between corresponding corners of the walls
+    fn max_corner_distance(tracked: &TrackedLayout, layout: &RoomLayout) -> f32 {
+        assert_eq!(tracked.walls.len(), layout.wall_polygons.len());
+        tracked
+            .wall_polygons()
+            .iter()
+            .zip(&layout.wall_polygons)
+            .flat_map(|(a, b)| a.lines().into_iter().zip(b.lines()))
+            .map(|((a, _), (b, _))| ((a.0 - b.0) as f32).hypot((a.1 - b.1) as f32))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn smooths_jittering_corners() {
+        let mut tracker = LayoutTracker::new(LayoutTrackerOptions::default());
+        let mut tracked = None;
+        for frame in 0..30 {
+            let jitter = if frame % 2 == 0 { 5 } else { -5 };
+            tracked = Some(tracker.update(&room_type_0_layout(jitter, -jitter), None));
+        }
+
+        let tracked = tracked.unwrap();
+        assert_eq!(tracked.room_type, RoomType::Type0);
+        assert_eq!(ids(&tracked), vec![0, 1, 2]);
+        assert!(max_corner_distance(&tracked, &room_type_0_layout(0, 0)) <= 3.0);
+    }
+
+    #[test]
+    fn keeps_wall_ids_when_room_type_flickers() {
+        let mut tracker = LayoutTracker::new(LayoutTrackerOptions::default());
+        let layout = room_type_0_layout(0, 0);
+        for _ in 0..5 {
+            tracker.update(&layout, None);
+        }
+
+        let flickered = tracker.update(&without_left_wall(&layout), None);
+        assert_eq!(flickered.room_type, RoomType::Type0);
+        assert_eq!(ids(&flickered), vec![0, 1, 2]);
+        assert_eq!(flickered.wall_polygons(), layout.wall_polygons);
+
+        let recovered = tracker.update(&layout, None);
+        assert_eq!(ids(&recovered), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn switches_room_type_after_consistent_frames() {
+        let options = LayoutTrackerOptions::default();
+        let mut tracker = LayoutTracker::new(options);
+        let layout = room_type_0_layout(0, 0);
+        tracker.update(&layout, None);
+
+        let two_walls = without_left_wall(&layout);
+        for _ in 1..options.room_type_switch_frames {
+            let tracked = tracker.update(&two_walls, None);
+            assert_eq!(tracked.room_type, RoomType::Type0);
+        }
+        let tracked = tracker.update(&two_walls, None);
+        assert_eq!(tracked.room_type, RoomType::Type5);
+        assert_eq!(ids(&tracked), vec![1, 2]);
+
+        // New wall is only shown once its room type is trusted
+        for _ in 1..options.room_type_switch_frames {
+            let tracked = tracker.update(&layout, None);
+            assert_eq!(ids(&tracked), vec![1, 2]);
+        }
+        let tracked = tracker.update(&layout, None);
+        assert_eq!(tracked.room_type, RoomType::Type0);
+        assert_eq!(ids(&tracked), vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn follows_camera_motion_with_homography() {
+        let mut tracker = LayoutTracker::new(LayoutTrackerOptions::default());
+        let mut lagging_tracker = LayoutTracker::new(LayoutTrackerOptions::default());
+        let step = Projection::translate(0.0, 3.0);
+        let mut tracked = None;
+        let mut lagging = None;
+        for frame in 0..10 {
+            let layout = room_type_0_layout(0, 3 * frame);
+            tracked = Some(tracker.update(&layout, Some(&step)));
+            lagging = Some(lagging_tracker.update(&layout, None));
+        }
+
+        let layout = room_type_0_layout(0, 27);
+        assert!(max_corner_distance(&tracked.unwrap(), &layout) <= 1.5);
+        assert!(max_corner_distance(&lagging.unwrap(), &layout) > 3.0);
+    }
+}
@@ -0,0 +1,543 @@
+use ndarray::{s, Array3};
+
+use crate::layout::lsun::Point;
+use crate::polygons::WallPolygon;
+
+/// Walls smaller than this share of the layout image are considered degenerate
+const MIN_WALL_AREA_SHARE: f64 = 0.01;
+/// Walls with smaller share of their area inside the layout image are considered off-screen
+const MIN_VISIBLE_AREA_SHARE: f64 = 0.5;
+/// How far apart (as a share of layout image diagonal) corners of neighbouring walls may be
+const SHARED_EDGE_TOLERANCE: f64 = 0.02;
+/// Wall corner lines of photos taken upright are close to vertical
+const MAX_VERTICAL_EDGE_TILT_DEGREES: f64 = 25.0;
+/// Vertical edges converge to a vanishing point, which is at least this many image heights
+/// away from the image top or bottom for photos taken upright
+const MIN_VERTICAL_VANISHING_POINT_DISTANCE: f64 = 0.5;
+/// Corners closer than this to the layout image side are cut by it rather than detected
+const IMAGE_SIDE_MARGIN: f64 = 2.0;
+/// Radius (as a share of heatmap size) around a corner where heatmap peak is looked for
+const CORNER_SEARCH_RADIUS: f64 = 0.02;
+/// Corners with weaker heatmap peak are not supported by the layout estimation
+const MIN_CORNER_HEATMAP_PEAK: f32 = 0.2;
+
+pub const LAYOUT_ISSUE_NO_WALLS: u32 = 1 << 0;
+pub const LAYOUT_ISSUE_SELF_INTERSECTING: u32 = 1 << 1;
+pub const LAYOUT_ISSUE_INVERTED: u32 = 1 << 2;
+pub const LAYOUT_ISSUE_NOT_CONVEX: u32 = 1 << 3;
+pub const LAYOUT_ISSUE_TOO_SMALL: u32 = 1 << 4;
+pub const LAYOUT_ISSUE_OFF_SCREEN: u32 = 1 << 5;
+pub const LAYOUT_ISSUE_DISCONNECTED_WALLS: u32 = 1 << 6;
+pub const LAYOUT_ISSUE_IMPLAUSIBLE_VERTICAL_EDGES: u32 = 1 << 7;
+pub const LAYOUT_ISSUE_WEAK_CORNER_EVIDENCE: u32 = 1 << 8;
+
+/// Problem found in wall polygons of the estimated room layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayoutIssue {
+    NoWalls,
+    /// Wall polygon edges cross each other
+    SelfIntersecting {
+        wall: usize,
+    },
+    /// Wall polygon vertices are not in top left, top right, bottom right, bottom left order,
+    /// e.g. top is below bottom
+    Inverted {
+        wall: usize,
+    },
+    NotConvex {
+        wall: usize,
+    },
+    /// Wall polygon has (close to) zero area
+    TooSmall {
+        wall: usize,
+    },
+    /// Most of the wall polygon is outside the image
+    OffScreen {
+        wall: usize,
+    },
+    /// Neighbouring walls do not share the wall corner edge
+    DisconnectedWalls {
+        left_wall: usize,
+        right_wall: usize,
+    },
+    /// Vertical wall edges are tilted too much or converge within the image
+    ImplausibleVerticalEdges {
+        wall: usize,
+    },
+    /// Layout estimation heatmap has no peak at the wall corner
+    WeakCornerEvidence {
+        wall: usize,
+        corner: Point,
+    },
+}
+
+impl LayoutIssue {
+    /// Bit flag of the issue kind, see `LAYOUT_ISSUE_*` constants.
+    pub fn flag(&self) -> u32 {
+        match self {
+            LayoutIssue::NoWalls => LAYOUT_ISSUE_NO_WALLS,
+            LayoutIssue::SelfIntersecting { .. } => LAYOUT_ISSUE_SELF_INTERSECTING,
+            LayoutIssue::Inverted { .. } => LAYOUT_ISSUE_INVERTED,
+            LayoutIssue::NotConvex { .. } => LAYOUT_ISSUE_NOT_CONVEX,
+            LayoutIssue::TooSmall { .. } => LAYOUT_ISSUE_TOO_SMALL,
+            LayoutIssue::OffScreen { .. } => LAYOUT_ISSUE_OFF_SCREEN,
+            LayoutIssue::DisconnectedWalls { .. } => LAYOUT_ISSUE_DISCONNECTED_WALLS,
+            LayoutIssue::ImplausibleVerticalEdges { .. } => LAYOUT_ISSUE_IMPLAUSIBLE_VERTICAL_EDGES,
+            LayoutIssue::WeakCornerEvidence { .. } => LAYOUT_ISSUE_WEAK_CORNER_EVIDENCE,
+        }
+    }
+
+    /// Factor by which the issue reduces layout confidence.
+    fn confidence_factor(&self) -> f32 {
+        match self {
+            LayoutIssue::NoWalls => 0.0,
+            LayoutIssue::SelfIntersecting { .. }
+            | LayoutIssue::Inverted { .. }
+            | LayoutIssue::TooSmall { .. } => 0.2,
+            LayoutIssue::NotConvex { .. } | LayoutIssue::OffScreen { .. } => 0.5,
+            LayoutIssue::DisconnectedWalls { .. }
+            | LayoutIssue::ImplausibleVerticalEdges { .. } => 0.7,
+            LayoutIssue::WeakCornerEvidence { .. } => 0.9,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LayoutValidation {
+    /// How much the layout can be trusted, from 0 (garbage) to 1
+    pub confidence: f32,
+    pub issues: Vec<LayoutIssue>,
+}
+
+impl LayoutValidation {
+    /// Bit flags of all found issue kinds.
+    pub fn issue_flags(&self) -> u32 {
+        self.issues
+            .iter()
+            .fold(0, |flags, issue| flags | issue.flag())
+    }
+}
+
+/// Validates wall polygons in layout image coordinates. `corners_heatmap` is the
+/// `(channels, height, width)` corners output of layout estimation, used to check whether
+/// detected wall corners are supported by heatmap peaks.
+pub fn validate_layout(
+    polygons: &[WallPolygon],
+    layout_width: u32,
+    layout_height: u32,
+    corners_heatmap: Option<&Array3<f32>>,
+) -> LayoutValidation {
+    if polygons.is_empty() {
+        return LayoutValidation {
+            confidence: 0.0,
+            issues: vec![LayoutIssue::NoWalls],
+        };
+    }
+
+    let (width, height) = (layout_width as f64, layout_height as f64);
+    let mut issues = vec![];
+
+    for (wall, polygon) in polygons.iter().enumerate() {
+        issues.extend(validate_polygon_shape(wall, polygon, width, height));
+        if !vertical_edges_plausible(polygon, width, height) {
+            issues.push(LayoutIssue::ImplausibleVerticalEdges { wall });
+        }
+    }
+
+    let shared_edge_tolerance = width.hypot(height) * SHARED_EDGE_TOLERANCE;
+    for right_wall in 1..polygons.len() {
+        let left_polygon = &polygons[right_wall - 1];
+        let right_polygon = &polygons[right_wall];
+        if distance(left_polygon.top_right, right_polygon.top_left) > shared_edge_tolerance
+            || distance(left_polygon.bottom_right, right_polygon.bottom_left)
+                > shared_edge_tolerance
+        {
+            issues.push(LayoutIssue::DisconnectedWalls {
+                left_wall: right_wall - 1,
+                right_wall,
+            });
+        }
+    }
+
+    let mut evidence = 1.0;
+    if let Some(corners_heatmap) = corners_heatmap {
+        let mut peaks = vec![];
+        for (wall, polygon) in polygons.iter().enumerate() {
+            for corner in polygon_vertices(polygon) {
+                if !is_detected_corner(corner, width, height) {
+                    continue;
+                }
+                let peak = heatmap_peak(corners_heatmap, corner, width, height);
+                if peak < MIN_CORNER_HEATMAP_PEAK {
+                    issues.push(LayoutIssue::WeakCornerEvidence { wall, corner });
+                }
+                peaks.push(peak.clamp(0.0, 1.0));
+            }
+        }
+        if !peaks.is_empty() {
+            // Heatmap evidence can halve the confidence at most, since even good corners
+            // rarely get peaks close to 1
+            let mean_peak = peaks.iter().sum::<f32>() / peaks.len() as f32;
+            evidence = 0.5 + 0.5 * mean_peak;
+        }
+    }
+
+    let confidence = issues
+        .iter()
+        .map(LayoutIssue::confidence_factor)
+        .product::<f32>()
+        * evidence;
+
+    LayoutValidation { confidence, issues }
+}
+
+fn validate_polygon_shape(
+    wall: usize,
+    polygon: &WallPolygon,
+    width: f64,
+    height: f64,
+) -> Vec<LayoutIssue> {
+    let vertices = polygon_vertices(polygon).map(to_f64);
+    let area = signed_area(&vertices);
+    let min_area = width * height * MIN_WALL_AREA_SHARE;
+    let mut issues = vec![];
+
+    if segments_intersect(vertices[0], vertices[1], vertices[2], vertices[3])
+        || segments_intersect(vertices[1], vertices[2], vertices[3], vertices[0])
+    {
+        issues.push(LayoutIssue::SelfIntersecting { wall });
+    } else if area.abs() >= min_area
+        && (area < 0.0
+            || polygon.top_left.1 >= polygon.bottom_left.1
+            || polygon.top_right.1 >= polygon.bottom_right.1
+            || polygon.top_left.0 >= polygon.top_right.0
+            || polygon.bottom_left.0 >= polygon.bottom_right.0)
+    {
+        issues.push(LayoutIssue::Inverted { wall });
+    } else if !is_convex(&vertices) {
+        issues.push(LayoutIssue::NotConvex { wall });
+    }
+
+    if area.abs() < min_area {
+        issues.push(LayoutIssue::TooSmall { wall });
+    } else if issues.is_empty() {
+        let visible_area = signed_area(&clip_to_image(&vertices, width, height)).abs();
+        if visible_area / area.abs() < MIN_VISIBLE_AREA_SHARE {
+            issues.push(LayoutIssue::OffScreen { wall });
+        }
+    }
+
+    issues
+}
+
+/// Vertical edges of a wall photographed upright are close to vertical and converge to a
+/// vanishing point far above or below the image.
+fn vertical_edges_plausible(polygon: &WallPolygon, width: f64, height: f64) -> bool {
+    let edges = [
+        (polygon.top_left, polygon.bottom_left),
+        (polygon.top_right, polygon.bottom_right),
+    ];
+    // Edges along image sides are borders of the image rather than wall corners
+    let edges: Vec<_> = edges
+        .into_iter()
+        .filter(|(top, bottom)| {
+            !(is_at_image_side(*top, width) && is_at_image_side(*bottom, width))
+        })
+        .map(|(top, bottom)| (to_f64(top), to_f64(bottom)))
+        .collect();
+
+    let max_tilt = MAX_VERTICAL_EDGE_TILT_DEGREES.to_radians();
+    for (top, bottom) in &edges {
+        let tilt = (top.0 - bottom.0).atan2(bottom.1 - top.1);
+        if tilt.abs() > max_tilt {
+            return false;
+        }
+    }
+
+    if let [(top_a, bottom_a), (top_b, bottom_b)] = edges[..] {
+        if let Some((_, y)) = lines_intersection(top_a, bottom_a, top_b, bottom_b) {
+            let min_distance = height * MIN_VERTICAL_VANISHING_POINT_DISTANCE;
+            if y > -min_distance && y < height - 1.0 + min_distance {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+fn polygon_vertices(polygon: &WallPolygon) -> [Point; 4] {
+    [
+        polygon.top_left,
+        polygon.top_right,
+        polygon.bottom_right,
+        polygon.bottom_left,
+    ]
+}
+
+fn to_f64(point: Point) -> (f64, f64) {
+    (point.0 as f64, point.1 as f64)
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((a.0 - b.0) as f64).hypot((a.1 - b.1) as f64)
+}
+
+fn is_at_image_side(point: Point, width: f64) -> bool {
+    let x = point.0 as f64;
+    x <= IMAGE_SIDE_MARGIN || x >= width - 1.0 - IMAGE_SIDE_MARGIN
+}
+
+/// Whether the corner is inside the image and is not cut by the image side.
+fn is_detected_corner(corner: Point, width: f64, height: f64) -> bool {
+    let (x, y) = to_f64(corner);
+    x > IMAGE_SIDE_MARGIN
+        && x < width - 1.0 - IMAGE_SIDE_MARGIN
+        && y > IMAGE_SIDE_MARGIN
+        && y < height - 1.0 - IMAGE_SIDE_MARGIN
+}
+
+/// Strongest response of any heatmap channel around the layout point.
+fn heatmap_peak(heatmap: &Array3<f32>, point: Point, width: f64, height: f64) -> f32 {
+    let (_, heatmap_height, heatmap_width) = heatmap.dim();
+    let x = (point.0 as f64 * heatmap_width as f64 / width) as usize;
+    let y = (point.1 as f64 * heatmap_height as f64 / height) as usize;
+    let radius =
+        ((heatmap_width.max(heatmap_height) as f64 * CORNER_SEARCH_RADIUS).ceil() as usize).max(1);
+
+    let window = heatmap.slice(s![
+        ..,
+        y.saturating_sub(radius)..(y + radius + 1).min(heatmap_height),
+        x.saturating_sub(radius)..(x + radius + 1).min(heatmap_width)
+    ]);
+    window.iter().cloned().fold(f32::MIN, f32::max)
+}
+
+/// Shoelace area, which is positive for vertices going clockwise in image coordinates.
+fn signed_area(vertices: &[(f64, f64)]) -> f64 {
+    let mut area = 0.0;
+    for i in 0..vertices.len() {
+        let (x1, y1) = vertices[i];
+        let (x2, y2) = vertices[(i + 1) % vertices.len()];
+        area += x1 * y2 - x2 * y1;
+    }
+    area / 2.0
+}
+
+fn cross(o: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
+
+fn is_convex(vertices: &[(f64, f64)]) -> bool {
+    let num_vertices = vertices.len();
+    let turns: Vec<f64> = (0..num_vertices)
+        .map(|i| {
+            cross(
+                vertices[i],
+                vertices[(i + 1) % num_vertices],
+                vertices[(i + 2) % num_vertices],
+            )
+        })
+        .collect();
+    turns.iter().all(|turn| *turn >= 0.0) || turns.iter().all(|turn| *turn <= 0.0)
+}
+
+/// Whether segments cross at a point interior to both of them.
+fn segments_intersect(a1: (f64, f64), a2: (f64, f64), b1: (f64, f64), b2: (f64, f64)) -> bool {
+    let d1 = cross(b1, b2, a1);
+    let d2 = cross(b1, b2, a2);
+    let d3 = cross(a1, a2, b1);
+    let d4 = cross(a1, a2, b2);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+fn lines_intersection(
+    a1: (f64, f64),
+    a2: (f64, f64),
+    b1: (f64, f64),
+    b2: (f64, f64),
+) -> Option<(f64, f64)> {
+    let denominator = (a1.0 - a2.0) * (b1.1 - b2.1) - (a1.1 - a2.1) * (b1.0 - b2.0);
+    if denominator.abs() < f64::EPSILON {
+        return None;
+    }
+    let a = a1.0 * a2.1 - a1.1 * a2.0;
+    let b = b1.0 * b2.1 - b1.1 * b2.0;
+    Some((
+        (a * (b1.0 - b2.0) - (a1.0 - a2.0) * b) / denominator,
+        (a * (b1.1 - b2.1) - (a1.1 - a2.1) * b) / denominator,
+    ))
+}
+
+/// Part of the polygon inside the image (Sutherland-Hodgman clipping).
+fn clip_to_image(vertices: &[(f64, f64)], width: f64, height: f64) -> Vec<(f64, f64)> {
+    let (max_x, max_y) = (width - 1.0, height - 1.0);
+    // Each clip edge is given by a function which is non-negative inside the image
+    let clip_edges: [&dyn Fn((f64, f64)) -> f64; 4] =
+        [&|(x, _)| x, &|(x, _)| max_x - x, &|(_, y)| y, &|(_, y)| {
+            max_y - y
+        }];
+
+    let mut clipped = vertices.to_vec();
+    for inside in clip_edges {
+        let input = std::mem::take(&mut clipped);
+        for i in 0..input.len() {
+            let current = input[i];
+            let next = input[(i + 1) % input.len()];
+            let (current_inside, next_inside) = (inside(current), inside(next));
+            if current_inside >= 0.0 {
+                clipped.push(current);
+            }
+            if (current_inside >= 0.0) != (next_inside >= 0.0) {
+                let t = current_inside / (current_inside - next_inside);
+                clipped.push((
+                    current.0 + (next.0 - current.0) * t,
+                    current.1 + (next.1 - current.1) * t,
+                ));
+            }
+        }
+    }
+    clipped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::polygons::WallPolygon;
+    use crate::validation::{
+        validate_layout, LayoutIssue, LAYOUT_ISSUE_INVERTED, LAYOUT_ISSUE_NO_WALLS,
+    };
+    use ndarray::Array3;
+
+    fn two_wall_layout() -> Vec<WallPolygon> {
+        vec![
+            WallPolygon {
+                top_left: (0, 60),
+                top_right: (250, 100),
+                bottom_right: (250, 420),
+                bottom_left: (0, 470),
+            },
+            WallPolygon {
+                top_left: (250, 100),
+                top_right: (511, 40),
+                bottom_right: (511, 480),
+                bottom_left: (250, 420),
+            },
+        ]
+    }
+
+    #[test]
+    fn accepts_plausible_layout() {
+        let validation = validate_layout(&two_wall_layout(), 512, 512, None);
+
+        assert_eq!(validation.issues, vec![]);
+        assert_eq!(validation.confidence, 1.0);
+    }
+
+    #[test]
+    fn detects_broken_wall_polygons() {
+        let mut polygons = two_wall_layout();
+        // Top below bottom
+        polygons[0] = WallPolygon {
+            top_left: (0, 470),
+            top_right: (250, 420),
+            bottom_right: (250, 100),
+            bottom_left: (0, 60),
+        };
+        // Bow tie
+        polygons[1] = WallPolygon {
+            top_left: (250, 100),
+            top_right: (511, 480),
+            bottom_right: (511, 40),
+            bottom_left: (250, 420),
+        };
+
+        let validation = validate_layout(&polygons, 512, 512, None);
+
+        assert!(validation
+            .issues
+            .contains(&LayoutIssue::Inverted { wall: 0 }));
+        assert!(validation
+            .issues
+            .contains(&LayoutIssue::SelfIntersecting { wall: 1 }));
+        assert_ne!(validation.issue_flags() & LAYOUT_ISSUE_INVERTED, 0);
+        assert!(validation.confidence < 0.1);
+    }
+
+    #[test]
+    fn detects_degenerate_and_off_screen_walls() {
+        let polygons = vec![
+            WallPolygon {
+                top_left: (100, 100),
+                top_right: (101, 100),
+                bottom_right: (101, 400),
+                bottom_left: (100, 400),
+            },
+            WallPolygon {
+                top_left: (400, -2000),
+                top_right: (900, -2000),
+                bottom_right: (900, 100),
+                bottom_left: (400, 100),
+            },
+        ];
+
+        let validation = validate_layout(&polygons, 512, 512, None);
+
+        assert!(validation
+            .issues
+            .contains(&LayoutIssue::TooSmall { wall: 0 }));
+        assert!(validation
+            .issues
+            .contains(&LayoutIssue::OffScreen { wall: 1 }));
+        assert!(validation.issues.contains(&LayoutIssue::DisconnectedWalls {
+            left_wall: 0,
+            right_wall: 1
+        }));
+    }
+
+    #[test]
+    fn detects_vertical_edges_converging_within_image() {
+        let polygons = vec![WallPolygon {
+            top_left: (200, 100),
+            top_right: (300, 100),
+            bottom_right: (400, 400),
+            bottom_left: (100, 400),
+        }];
+
+        let validation = validate_layout(&polygons, 512, 512, None);
+
+        assert_eq!(
+            validation.issues,
+            vec![LayoutIssue::ImplausibleVerticalEdges { wall: 0 }]
+        );
+    }
+
+    #[test]
+    fn uses_corner_heatmap_peaks_as_evidence() {
+        let polygons = two_wall_layout();
+        let mut heatmap = Array3::<f32>::zeros((2, 256, 256));
+        // Only top corner of the shared edge is detected, at half resolution
+        heatmap[[1, 50, 126]] = 0.9;
+
+        let validation = validate_layout(&polygons, 512, 512, Some(&heatmap));
+
+        let weak_corners: Vec<_> = validation
+            .issues
+            .iter()
+            .filter_map(|issue| match issue {
+                LayoutIssue::WeakCornerEvidence { corner, .. } => Some(*corner),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(weak_corners, vec![(250, 420), (250, 420)]);
+        assert!(validation.confidence < 0.9);
+        assert!(validation.confidence > 0.5);
+    }
+
+    #[test]
+    fn rejects_layout_without_walls() {
+        let validation = validate_layout(&[], 512, 512, None);
+
+        assert_eq!(validation.issue_flags(), LAYOUT_ISSUE_NO_WALLS);
+        assert_eq!(validation.confidence, 0.0);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::polygons::WallPolygon;
+use crate::preview::{
+    assign_wall_pixels, compute_polygon_width_shares, compute_wall_section_projection,
+    compute_wall_shading, prepare_wall_mask, scale_polygons, VISIBLE_VERTICAL_TILES_COUNT,
+    WALLPAPER_TILE_HEIGHT_METERS, WALLPAPER_TILE_WIDTH_METERS, WALL_PIXEL,
+};
+use image::{GrayImage, Luma, RgbImage};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const UV_MAP_FILE_NAME: &str = "uv.f32";
+const WALL_ID_MAP_FILE_NAME: &str = "wall_ids.png";
+const COVERAGE_MASK_FILE_NAME: &str = "coverage.png";
+const SHADING_MAP_FILE_NAME: &str = "shading.png";
+const DESCRIPTOR_FILE_NAME: &str = "descriptor.json";
+
+pub use crate::preview::NO_WALL_ID;
+
+/// Shading map stores HSV value delta of each wall pixel shifted by this bias
+pub const SHADING_BIAS: i32 = 128;
+
+/// Per-pixel mapping of the room image onto wall planes, which lets other renderers apply any
+/// wallpaper without running the whole preview pipeline again.
+pub struct WallMaps {
+    pub width: u32,
+    pub height: u32,
+    /// Interleaved `(u, v)` wall plane coordinates in meters, `NaN` for non-wall pixels
+    pub uv: Vec<f32>,
+    /// Index of the wall polygon each pixel belongs to, [NO_WALL_ID] if none
+    pub wall_ids: GrayImage,
+    /// 255 for pixels which should be covered by wallpaper, 0 otherwise
+    pub coverage: GrayImage,
+    /// HSV value difference between wall pixel and average wall value, shifted by [SHADING_BIAS]
+    pub shading: GrayImage,
+    pub walls: Vec<WallDescriptor>,
+    /// Width of the whole visible wallpaper strip in meters
+    pub strip_width_meters: f32,
+    /// Height of the whole visible wallpaper strip in meters
+    pub strip_height_meters: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WallDescriptor {
+    pub id: u8,
+    /// Horizontal offset of the wall within the wallpaper strip
+    pub u_offset_meters: f32,
+    pub width_meters: f32,
+    pub height_meters: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WallMapsDescriptor {
+    width: u32,
+    height: u32,
+    uv_map: UvMapDescriptor,
+    wall_id_map: WallIdMapDescriptor,
+    coverage_mask: String,
+    shading_map: ShadingMapDescriptor,
+    tile_width_meters: f32,
+    tile_height_meters: f32,
+    strip_width_meters: f32,
+    strip_height_meters: f32,
+    walls: Vec<WallDescriptor>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UvMapDescriptor {
+    file: String,
+    /// Little-endian 32-bit floats, row-major
+    format: String,
+    channels: u8,
+    units: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WallIdMapDescriptor {
+    file: String,
+    no_wall_value: u8,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShadingMapDescriptor {
+    file: String,
+    bias: i32,
+}
+
+/// Computes wall maps for the given room image. Polygons are expected in layout estimation space
+/// of `layout_width` x `layout_height` size and are rescaled to room image size, same as in
+/// [crate::preview::create_preview].
+pub fn compute_wall_maps(
+    room_image: &RgbImage,
+    mask_image: &GrayImage,
+    polygons: Vec<WallPolygon>,
+    layout_width: u32,
+    layout_height: u32,
+) -> WallMaps {
+    let width = room_image.width();
+    let height = room_image.height();
+
+    // Layout without walls maps no pixels
+    let (polygon_width_shares, visible_horizontal_tile_count) =
+        compute_polygon_width_shares(&polygons).unwrap_or_default();
+    let quads = scale_polygons(&polygons, layout_width, layout_height, width, height);
+    let mask_image = prepare_wall_mask(mask_image, width, height);
+    let wall_ids = assign_wall_pixels(&quads, &mask_image);
+    let wall_shading = compute_wall_shading(room_image, &wall_ids, quads.len());
+
+    let strip_width_meters = visible_horizontal_tile_count * WALLPAPER_TILE_WIDTH_METERS;
+    let strip_height_meters = VISIBLE_VERTICAL_TILES_COUNT * WALLPAPER_TILE_HEIGHT_METERS;
+
+    // Maps image pixels to wall plane meters of each wall
+    let mut inverse_projections = vec![];
+    let mut walls = vec![];
+    let mut section_start_x = 0f32;
+    for (i, (quad, width_share)) in quads.iter().zip(polygon_width_shares.iter()).enumerate() {
+        let section_end_x = section_start_x + strip_width_meters * width_share;
+
+        let projection = compute_wall_section_projection(
+            quad,
+            section_start_x,
+            section_end_x,
+            strip_height_meters,
+        );
+        inverse_projections.push(projection.invert());
+
+        walls.push(WallDescriptor {
+            id: i as u8,
+            u_offset_meters: section_start_x,
+            width_meters: section_end_x - section_start_x,
+            height_meters: strip_height_meters,
+        });
+
+        section_start_x = section_end_x;
+    }
+
+    let mut uv = vec![f32::NAN; width as usize * height as usize * 2];
+    let mut coverage = GrayImage::new(width, height);
+    let mut shading = GrayImage::from_pixel(width, height, Luma([SHADING_BIAS as u8]));
+    for (x, y, wall_id) in wall_ids.enumerate_pixels() {
+        let wall = wall_id.0[0];
+        if wall == NO_WALL_ID {
+            continue;
+        }
+        let wall = wall as usize;
+
+        let (u, v) = inverse_projections[wall] * (x as f32, y as f32);
+        let uv_idx = (y as usize * width as usize + x as usize) * 2;
+        uv[uv_idx] = u;
+        uv[uv_idx + 1] = v;
+
+        *coverage.get_pixel_mut(x, y) = WALL_PIXEL;
+
+        let shading_value = (SHADING_BIAS + wall_shading.blackness_delta(x, y, wall)).clamp(0, 255);
+        *shading.get_pixel_mut(x, y) = Luma([shading_value as u8]);
+    }
+
+    WallMaps {
+        width,
+        height,
+        uv,
+        wall_ids,
+        coverage,
+        shading,
+        walls,
+        strip_width_meters,
+        strip_height_meters,
+    }
+}
+
+impl WallMaps {
+    /// Wall plane coordinates in meters at the given pixel, `None` if pixel is not on a wall.
+    pub fn uv_at(&self, x: u32, y: u32) -> Option<(f32, f32)> {
+        let uv_idx = (y as usize * self.width as usize + x as usize) * 2;
+        let (u, v) = (self.uv[uv_idx], self.uv[uv_idx + 1]);
+        if u.is_nan() || v.is_nan() {
+            None
+        } else {
+            Some((u, v))
+        }
+    }
+
+    /// Writes all maps along with JSON descriptor into the given directory.
+    pub fn save(&self, output_dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+
+        let mut uv_writer = BufWriter::new(File::create(output_dir.join(UV_MAP_FILE_NAME))?);
+        for value in self.uv.iter() {
+            uv_writer.write_all(&value.to_le_bytes())?;
+        }
+        uv_writer.flush()?;
+
+        self.wall_ids
+            .save(output_dir.join(WALL_ID_MAP_FILE_NAME))
+            .map_err(to_io_error)?;
+        self.coverage
+            .save(output_dir.join(COVERAGE_MASK_FILE_NAME))
+            .map_err(to_io_error)?;
+        self.shading
+            .save(output_dir.join(SHADING_MAP_FILE_NAME))
+            .map_err(to_io_error)?;
+
+        let descriptor = WallMapsDescriptor {
+            width: self.width,
+            height: self.height,
+            uv_map: UvMapDescriptor {
+                file: UV_MAP_FILE_NAME.to_string(),
+                format: "f32le".to_string(),
+                channels: 2,
+                units: "meters".to_string(),
+            },
+            wall_id_map: WallIdMapDescriptor {
+                file: WALL_ID_MAP_FILE_NAME.to_string(),
+                no_wall_value: NO_WALL_ID,
+            },
+            coverage_mask: COVERAGE_MASK_FILE_NAME.to_string(),
+            shading_map: ShadingMapDescriptor {
+                file: SHADING_MAP_FILE_NAME.to_string(),
+                bias: SHADING_BIAS,
+            },
+            tile_width_meters: WALLPAPER_TILE_WIDTH_METERS,
+            tile_height_meters: WALLPAPER_TILE_HEIGHT_METERS,
+            strip_width_meters: self.strip_width_meters,
+            strip_height_meters: self.strip_height_meters,
+            walls: self.walls.clone(),
+        };
+        let descriptor_file = File::create(output_dir.join(DESCRIPTOR_FILE_NAME))?;
+        serde_json::to_writer_pretty(BufWriter::new(descriptor_file), &descriptor)?;
+
+        Ok(())
+    }
+}
+
+fn to_io_error(error: image::ImageError) -> std::io::Error {
+    std::io::Error::other(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::polygons::WallPolygon;
+    use crate::wall_maps::{compute_wall_maps, NO_WALL_ID};
+    use image::{GrayImage, Luma, Rgb, RgbImage};
+
+    #[test]
+    fn maps_single_wall_to_wall_plane_meters() {
+        let room_image = RgbImage::from_pixel(64, 64, Rgb([128, 128, 128]));
+        let mut mask_image = GrayImage::from_pixel(64, 64, Luma([255]));
+        // Something in front of the wall
+        for x in 0..8 {
+            for y in 0..8 {
+                mask_image.put_pixel(x, y, Luma([0]));
+            }
+        }
+        let polygons = vec![WallPolygon {
+            top_left: (0, 0),
+            top_right: (256, 0),
+            bottom_right: (256, 128),
+            bottom_left: (0, 128),
+        }];
+
+        let wall_maps = compute_wall_maps(&room_image, &mask_image, polygons, 256, 128);
+
+        assert_eq!(wall_maps.walls.len(), 1);
+        assert_eq!(*wall_maps.wall_ids.get_pixel(2, 2), Luma([NO_WALL_ID]));
+        assert!(wall_maps.uv_at(2, 2).is_none());
+
+        assert_eq!(*wall_maps.wall_ids.get_pixel(32, 32), Luma([0]));
+        assert_eq!(*wall_maps.coverage.get_pixel(32, 32), Luma([255]));
+        // Uniform wall color means there is no shading, apart from rounding
+        let shading = wall_maps.shading.get_pixel(32, 32).0[0] as i32;
+        assert!((shading - 128).abs() <= 1);
+
+        let (u, v) = wall_maps.uv_at(32, 32).unwrap();
+        assert!((u - wall_maps.strip_width_meters / 2.0).abs() < 0.01);
+        assert!((v - wall_maps.strip_height_meters / 2.0).abs() < 0.01);
+    }
+}